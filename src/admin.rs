@@ -0,0 +1,141 @@
+use crate::notifications::notify;
+use log::{error, info, warn};
+use std::{
+    fs::{DirBuilder, Permissions},
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+use tokio_util::sync::CancellationToken;
+
+/// Set while an operator paused the submissions
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether an operator paused the submissions, the accounts
+/// are still tracked meanwhile so resuming is instant
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Serves the admin commands on a unix socket until cancelled, one per line:
+/// `pause` and `resume` the submissions, `status` returns the status as JSON
+pub async fn serve_admin_socket(path: PathBuf, cancellation_token: CancellationToken) {
+    // A socket left by a previous run is removed, any other file fails the bind
+    remove_socket(&path);
+
+    let listener = match bind_private(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind the admin socket {:?}: {:?}", path, e);
+            return;
+        }
+    };
+
+    info!("Serving admin commands on {:?}", path);
+
+    loop {
+        let stream = tokio::select! {
+            _ = cancellation_token.cancelled() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    error!("Failed to accept an admin connection: {:?}", e);
+                    continue;
+                }
+            },
+        };
+
+        tokio::task::spawn(async move {
+            if let Err(e) = handle_connection(stream).await {
+                warn!("Admin connection failed: {:?}", e);
+            }
+        });
+    }
+
+    remove_socket(&path);
+}
+
+/// Binds the socket so only the user running the liquidator can send commands.
+/// It is bound and restricted in a private directory, then moved to the path,
+/// so it is never reachable by other users, even before its permissions are set
+fn bind_private(path: &Path) -> anyhow::Result<UnixListener> {
+    // Moving the socket would replace any file left at the path
+    if std::fs::symlink_metadata(path).is_ok() {
+        anyhow::bail!("{:?} already exists", path);
+    }
+
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let private_dir = parent.join(format!(".eva01-admin-{}", std::process::id()));
+    DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+    let staged_path = private_dir.join("admin.sock");
+    let listener = UnixListener::bind(&staged_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged_path, Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged_path, path)?;
+            Ok(listener)
+        });
+
+    if let Err(e) = std::fs::remove_dir_all(&private_dir) {
+        warn!("Failed to remove {:?}: {:?}", private_dir, e);
+    }
+
+    listener
+}
+
+/// Removes the socket at the path, leaving any other kind of file untouched
+fn remove_socket(path: &Path) {
+    let is_socket = std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Failed to remove the admin socket {:?}: {:?}", path, e);
+        }
+    }
+}
+
+async fn handle_connection(stream: UnixStream) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = run_command(line.trim());
+        writer.write_all(response.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+
+    Ok(())
+}
+
+fn run_command(command: &str) -> String {
+    match command {
+        "pause" => {
+            if !PAUSED.swap(true, Ordering::Relaxed) {
+                warn!("Submissions paused by an operator");
+                notify("Submissions paused by an operator".to_string());
+            }
+            "paused".to_string()
+        }
+        "resume" => {
+            if PAUSED.swap(false, Ordering::Relaxed) {
+                info!("Submissions resumed by an operator");
+                notify("Submissions resumed by an operator".to_string());
+            }
+            "resumed".to_string()
+        }
+        "status" => crate::status::status().to_string(),
+        _ => format!(
+            "Unknown command `{}`, expected `pause`, `resume` or `status`",
+            command
+        ),
+    }
+}
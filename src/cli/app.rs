@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
 use solana_sdk::pubkey::Pubkey;
 
 #[derive(Parser, Debug)]
@@ -16,9 +17,50 @@ pub enum Commands {
     Run {
         #[arg(required = true)]
         path: PathBuf,
+        #[arg(long, help = "Records the geyser updates to this file, to be replayed")]
+        record: Option<PathBuf>,
     },
     #[command(about = "Setups a new configuration file, by the user preferences")]
     Setup,
+    #[command(about = "Lists the accounts that can currently be liquidated, then exits")]
+    Scan {
+        #[arg(required = true)]
+        path: PathBuf,
+    },
+    #[command(
+        about = "Creates or extends the address lookup table of the marginfi group accounts"
+    )]
+    SetupLookupTable {
+        #[arg(required = true)]
+        path: PathBuf,
+    },
+    #[command(
+        about = "Replays recorded geyser updates in dry run, listing the liquidations that would have fired"
+    )]
+    Replay {
+        #[arg(required = true)]
+        path: PathBuf,
+        #[arg(required = true, help = "Recording made with `run --record`")]
+        recording: PathBuf,
+        #[arg(
+            long,
+            help = "Waits between the updates as long as when they were recorded"
+        )]
+        realtime: bool,
+    },
+    #[command(
+        about = "Exports the transactions of the transaction log confirmed between two dates, for the accounting"
+    )]
+    Export {
+        #[arg(required = true)]
+        path: PathBuf,
+        #[arg(long, help = "First day exported, as YYYY-MM-DD (UTC)")]
+        from: NaiveDate,
+        #[arg(long, help = "Last day exported, as YYYY-MM-DD (UTC)")]
+        to: NaiveDate,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+    },
     #[command(
         hide = true,
         about = "Setups a new configuration file, by the user preferences"
@@ -26,6 +68,11 @@ pub enum Commands {
     SetupFromCli(SetupFromCliOpts),
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Csv,
+}
+
 #[derive(Parser, Debug)]
 pub struct SetupFromCliOpts {
     #[arg(short = 'u', long, help = "RPC endpoint url")]
@@ -1,95 +1,705 @@
 use crate::{
-    config::Eva01Config,
-    geyser::{GeyserService, GeyserUpdate},
+    cli::app::ExportFormat,
+    config::{Eva01Config, PriceSource, RestartPolicy},
+    cooldown::LiquidationCooldowns,
+    geyser::{latest_update_channel, GeyserService, GeyserUpdate},
+    jupiter::JupiterSwapper,
     liquidator::Liquidator,
     rebalancer::Rebalancer,
-    transaction_manager::{BatchTransactions, TransactionManager},
+    rpc_pool::RpcPool,
+    state::StateSnapshot,
+    transaction_manager::{BatchTransactions, PendingLiquidations, TransactionManager},
+    wrappers::liquidator_account::LiquidatorAccount,
+};
+use chrono::{NaiveDate, NaiveTime};
+use futures::{future::BoxFuture, FutureExt};
+use log::{error, info, warn};
+use marginfi::state::marginfi_group::BankVaultType;
+use solana_address_lookup_table_program::{
+    instruction::{create_lookup_table, extend_lookup_table},
+    state::AddressLookupTable,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
 };
-use log::{error, info};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
     sync::{atomic::AtomicBool, Arc},
+    time::Duration,
 };
+use tokio_util::sync::CancellationToken;
+
+/// Addresses an address lookup table can hold
+const LOOKUP_TABLE_MAX_ADDRESSES: usize = 256;
+
+/// Addresses added to a lookup table per transaction, to fit in a packet
+const LOOKUP_TABLE_EXTEND_CHUNK: usize = 20;
+
+/// First wait before a failed service is restarted
+const MIN_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait before a failed service is restarted
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+pub async fn run_liquidator(
+    config: Eva01Config,
+    config_path: PathBuf,
+    record_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    config.validate()?;
+
+    if let Some(log_filter) = &config.general_config.log_filter {
+        crate::logger::set_filter(Some(log_filter));
+    }
 
-pub async fn run_liquidator(config: Eva01Config) -> anyhow::Result<()> {
-    info!("Starting eva01 liquidator! {:#?}", &config);
+    info!(
+        "Starting eva01 liquidator! {:#}",
+        crate::status::redacted_config(&config)
+    );
+    crate::status::set_config(&config);
 
-    // Create two channels
+    // Cancelled on Ctrl-C, every service observes it to shut down gracefully
+    let cancellation_token = CancellationToken::new();
+
+    // Rpc clients shared by the services, switched to a healthy endpoint when needed
+    let rpc_pool = Arc::new(RpcPool::new(&config.general_config));
+
+    // Shared by the liquidators of every signer, so an account is never
+    // liquidated by two of them at once
+    let pending_liquidations = PendingLiquidations::default();
+    let liquidation_cooldowns = LiquidationCooldowns::new(&config.liquidator_config);
+
+    let mut snapshot = config.general_config.state_path.as_ref().and_then(|path| {
+        StateSnapshot::load(
+            path,
+            &config.general_config.marginfi_group_address,
+            config.general_config.max_state_age_secs,
+        )
+    });
+
+    // Every signer, of the main marginfi group or of an additional one, runs its own
+    // liquidator, rebalancer and transaction manager, fed by the same geyser service.
+    // Each one gets its channels
     // Geyser -> Liquidator
     // Geyser -> Rebalancer
     // Liquidator/Rebalancer -> TransactionManager
-    let (liquidator_tx, liquidator_rx) = crossbeam::channel::unbounded::<GeyserUpdate>();
-    let (rebalancer_tx, rebalancer_rx) = crossbeam::channel::unbounded::<GeyserUpdate>();
-    let (transaction_tx, transaction_rx) = crossbeam::channel::unbounded::<BatchTransactions>();
+    let mut liquidators = vec![];
+    let mut rebalancers = vec![];
+    let mut transaction_managers = vec![];
+    let mut liquidator_txs = vec![];
+    let mut rebalancer_txs = vec![];
+    let mut accounts_to_track = HashMap::new();
+    let mut signer_pubkeys = HashSet::new();
+    // Index of the liquidator that loaded the marginfi accounts of each group
+    let mut loaded_groups = HashMap::new();
+    for mut general_config in config.get_liquidator_configs() {
+        let group = general_config.marginfi_group_address;
+        signer_pubkeys.insert(general_config.signer_pubkey);
+
+        // Resolved once, so the liquidator and the rebalancer of a signer
+        // without a marginfi account share the one created for it
+        general_config.liquidator_account = Some(LiquidatorAccount::resolve_address(
+            &rpc_pool,
+            &general_config,
+            true,
+        )?);
+
+        // Only the latest pending update of each account is kept for the liquidator,
+        // so it catches up on the freshest state when it falls behind
+        let (liquidator_tx, liquidator_rx) = latest_update_channel();
+        let (rebalancer_tx, rebalancer_rx) = crossbeam::channel::unbounded::<GeyserUpdate>();
+        let (transaction_tx, transaction_rx) = crossbeam::channel::unbounded::<BatchTransactions>();
+
+        // Creates an atomicbool that will be shared between the liquidator and the rebalancer
+        // to stop the liquidator when the rebalancer ask for it
+        let stop_liquidator = Arc::new(AtomicBool::new(false));
+
+        let transaction_manager =
+            TransactionManager::new(transaction_rx, general_config.clone(), rpc_pool.clone())
+                .await?;
+
+        let mut liquidator = Liquidator::new(
+            general_config.clone(),
+            config.liquidator_config.clone(),
+            liquidator_rx,
+            transaction_tx.clone(),
+            stop_liquidator.clone(),
+            cancellation_token.clone(),
+            rpc_pool.clone(),
+            pending_liquidations.clone(),
+            liquidation_cooldowns.clone(),
+        )
+        .await;
+        if config.liquidator_config.value_collateral_via_jupiter {
+            liquidator = liquidator.with_jupiter_swapper(JupiterSwapper::from_config(
+                &config.rebalancer_config,
+                rpc_pool.clone(),
+            ));
+        }
+
+        let mut rebalancer = Rebalancer::new(
+            general_config,
+            config.rebalancer_config.clone(),
+            transaction_tx,
+            rebalancer_rx,
+            stop_liquidator,
+            cancellation_token.clone(),
+            rpc_pool.clone(),
+        )
+        .await?
+        .with_liquidation_dependencies(liquidator.in_flight_liquidations());
+
+        // The marginfi accounts of a group are loaded once, by its first liquidator,
+        // the state snapshot being the one of the main group
+        match loaded_groups.get(&group) {
+            Some(&index) => liquidator.copy_data_from(&liquidators[index]),
+            None => {
+                liquidator.load_data(snapshot.take()).await?;
+                loaded_groups.insert(group, liquidators.len());
+            }
+        }
+        rebalancer.load_data(liquidator.get_banks_and_map()).await?;
+
+        accounts_to_track.extend(liquidator.get_accounts_to_track());
+        accounts_to_track.extend(rebalancer.get_accounts_to_track());
+
+        liquidators.push(liquidator);
+        rebalancers.push(rebalancer);
+        transaction_managers.push(transaction_manager);
+        liquidator_txs.push((group, liquidator_tx));
+        rebalancer_txs.push((group, rebalancer_tx));
+    }
+    info!(
+        "Liquidating {} marginfi groups with {} signers",
+        loaded_groups.len(),
+        liquidators.len()
+    );
+
+    if config.general_config.price_source == PriceSource::PythHermesWs {
+        // The feeds of the banks of every group
+        let feed_ids = liquidators
+            .iter()
+            .flat_map(|liquidator| liquidator.get_banks_and_map().0.into_values())
+            .filter_map(|bank| bank.get_pyth_push_oracle_feed())
+            .map(|(_, feed_id)| feed_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        tokio::task::spawn(crate::pyth::serve_hermes_price_stream(
+            config.general_config.hermes_ws_url.clone(),
+            feed_ids,
+            cancellation_token.clone(),
+        ));
+    }
+
+    let mut liquidators = liquidators.into_iter();
+    let mut liquidator = liquidators
+        .next()
+        .expect("the main signer is always configured");
+
+    if let Some(record_path) = record_path {
+        tokio::task::spawn(crate::replay::serve_recording(
+            record_path,
+            cancellation_token.clone(),
+        ));
+    }
+
+    // The geyser service, the rebalancer and the transaction manager are supervised,
+    // so the liquidator doesn't keep running half alive when one of them stops
+    let restart_policy = config.general_config.restart_policy;
+    let mut services = tokio::task::JoinSet::new();
+
+    let geyser_configs = config.general_config.get_geyser_service_configs();
+    let marginfi_program_id = config.general_config.marginfi_program_id;
+    let geyser_max_connect_attempts = config.general_config.geyser_max_connect_attempts;
+    let geyser_max_accounts_per_subscription =
+        config.general_config.geyser_max_accounts_per_subscription;
+    let geyser_idle_timeout = Duration::from_secs(config.general_config.geyser_idle_timeout_secs);
+    let geyser_cancellation_token = cancellation_token.clone();
+    services.spawn(supervise(
+        "geyser service",
+        (),
+        move |_| {
+            GeyserService::connect(
+                geyser_configs.clone(),
+                accounts_to_track.clone(),
+                marginfi_program_id,
+                geyser_max_connect_attempts,
+                geyser_max_accounts_per_subscription,
+                geyser_idle_timeout,
+                liquidator_txs.clone(),
+                rebalancer_txs.clone(),
+                geyser_cancellation_token.clone(),
+            )
+            .boxed()
+        },
+        restart_policy,
+        cancellation_token.clone(),
+    ));
+
+    tokio::task::spawn(rpc_pool.serve_health_checks(
+        std::time::Duration::from_secs(config.general_config.rpc_health_check_interval_secs),
+        cancellation_token.clone(),
+    ));
+
+    if let Some(tx_log_path) = config.general_config.tx_log_path.clone() {
+        tokio::task::spawn(crate::tx_log::serve_tx_log(
+            tx_log_path,
+            rpc_pool.clone(),
+            cancellation_token.clone(),
+        ));
+    }
+
+    tokio::task::spawn(crate::logger::serve_log_filter_reload(
+        config_path,
+        cancellation_token.clone(),
+    ));
+
+    crate::spend_budget::set_max_spend_per_hour(config.general_config.max_spend_per_hour);
+
+    if let Some(admin_socket_path) = config.general_config.admin_socket_path.clone() {
+        tokio::task::spawn(crate::admin::serve_admin_socket(
+            admin_socket_path,
+            cancellation_token.clone(),
+        ));
+    }
+
+    // A signer liquidating several groups is watched once
+    for signer_pubkey in signer_pubkeys {
+        tokio::task::spawn(crate::sol_balance::serve_sol_balance_watch(
+            rpc_pool.clone(),
+            signer_pubkey,
+            config.general_config.min_sol_balance,
+            std::time::Duration::from_secs(config.general_config.sol_balance_check_interval_secs),
+            cancellation_token.clone(),
+        ));
+    }
+
+    tokio::task::spawn(crate::pnl::serve_pnl_summary(
+        std::time::Duration::from_secs(config.general_config.pnl_summary_interval_secs),
+        cancellation_token.clone(),
+    ));
+
+    let notifications_config = config.general_config.clone();
+    tokio::task::spawn(async move {
+        crate::notifications::serve_notifications(&notifications_config).await;
+    });
+
+    if let Some(metrics_port) = config.general_config.metrics_port {
+        tokio::task::spawn(async move {
+            if let Err(e) = crate::metrics::serve_metrics(metrics_port).await {
+                error!("Failed to serve metrics: {:?}", e);
+            }
+        });
+    }
+
+    for transaction_manager in transaction_managers {
+        services.spawn(supervise(
+            "transaction manager",
+            transaction_manager,
+            |transaction_manager| {
+                async move {
+                    transaction_manager.start().await;
+                    Ok(())
+                }
+                .boxed()
+            },
+            restart_policy,
+            cancellation_token.clone(),
+        ));
+    }
+
+    for rebalancer in rebalancers {
+        services.spawn(supervise(
+            "rebalancer",
+            rebalancer,
+            |rebalancer| rebalancer.start().boxed(),
+            restart_policy,
+            cancellation_token.clone(),
+        ));
+    }
+
+    // The liquidators of the additional signers are supervised as well,
+    // the one of the main signer runs on this task
+    for additional_liquidator in liquidators {
+        services.spawn(supervise(
+            "liquidator",
+            additional_liquidator,
+            |liquidator| liquidator.start().boxed(),
+            restart_policy,
+            cancellation_token.clone(),
+        ));
+    }
+
+    let signal_cancellation_token = cancellation_token.clone();
+    tokio::task::spawn(async move {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {:?}", e);
+            return;
+        }
+        info!("Received Ctrl-C, shutting down");
+        signal_cancellation_token.cancel();
+    });
+
+    let liquidator_result = liquidator.start().await;
+
+    // Stops the other services as well when the liquidator fails
+    cancellation_token.cancel();
+    drop(liquidator);
+
+    while let Some(result) = services.join_next().await {
+        result?;
+    }
 
-    // Creates an atomicbool that will be shared between the liquidator and the rebalancer
-    // to stop the liquidator when the rebalancer ask for it
+    info!("Eva01 liquidator stopped");
 
-    let stop_liquidator = Arc::new(AtomicBool::new(false));
+    liquidator_result
+}
+
+/// Runs a service until the cancellation token is cancelled. A service stopping
+/// before, failed or not, is restarted with an exponential backoff under the
+/// `Restart` policy, or shuts every service down under the `Shutdown` policy
+async fn supervise<S>(
+    name: &'static str,
+    mut service: S,
+    run: impl for<'a> Fn(&'a mut S) -> BoxFuture<'a, anyhow::Result<()>>,
+    restart_policy: RestartPolicy,
+    cancellation_token: CancellationToken,
+) {
+    let mut backoff = MIN_RESTART_BACKOFF;
+    loop {
+        let started_at = std::time::Instant::now();
+        let result = run(&mut service).await;
 
-    // Creates the transaction manager
-    // a channel is shared between the liquidator/rebalancer
-    // and the transaction manager
-    let mut transaction_manager =
-        TransactionManager::new(transaction_rx, config.general_config.clone()).await;
+        if let Err(e) = &result {
+            error!("The {} failed: {:?}", name, e);
+        }
+        if cancellation_token.is_cancelled() {
+            return;
+        }
+        if result.is_ok() {
+            error!("The {} stopped unexpectedly", name);
+        }
+
+        match restart_policy {
+            RestartPolicy::Shutdown => {
+                error!("Shutting down, the {} is not running anymore", name);
+                cancellation_token.cancel();
+                return;
+            }
+            RestartPolicy::Restart => {
+                // The backoff only grows while the service keeps failing right away
+                if started_at.elapsed() > MAX_RESTART_BACKOFF {
+                    backoff = MIN_RESTART_BACKOFF;
+                }
+                warn!("Restarting the {} in {:?}", name, backoff);
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => return,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Loads the marginfi group and prints the accounts that can currently be
+/// liquidated, with the same evaluation as the liquidator, without running it
+pub async fn scan_opportunities(config: Eva01Config) -> anyhow::Result<()> {
+    config.validate()?;
+
+    let (_, geyser_rx) = latest_update_channel();
+    let (transaction_tx, _) = crossbeam::channel::unbounded();
+    let rpc_pool = Arc::new(RpcPool::new(&config.general_config));
 
-    // Create the liquidator
     let mut liquidator = Liquidator::new(
         config.general_config.clone(),
         config.liquidator_config.clone(),
-        liquidator_rx.clone(),
-        transaction_tx.clone(),
-        stop_liquidator.clone(),
+        geyser_rx,
+        transaction_tx,
+        Arc::new(AtomicBool::new(false)),
+        CancellationToken::new(),
+        rpc_pool,
+        PendingLiquidations::default(),
+        LiquidationCooldowns::new(&config.liquidator_config),
     )
     .await;
+    liquidator.load_data(None).await?;
+
+    let opportunities = liquidator.scan().await;
+
+    println!(
+        "{:<46} {:>8} {:<46} {:<46} {:>16} {:>12}",
+        "Account", "Health", "Asset bank", "Liability bank", "Liquidatable", "Profit ($)"
+    );
+    for opportunity in &opportunities {
+        println!(
+            "{:<46} {:>8.4} {:<46} {:<46} {:>16.4} {:>12.4}",
+            opportunity.address.to_string(),
+            opportunity.health_factor.to_num::<f64>(),
+            opportunity.asset_bank.to_string(),
+            opportunity.liab_bank.to_string(),
+            opportunity.max_liquidation_amount.to_num::<f64>(),
+            opportunity.profit.to_num::<f64>()
+        );
+    }
+    println!("{} accounts can be liquidated", opportunities.len());
+
+    Ok(())
+}
 
-    // Create the rebalancer
-    let mut rebalancer = Rebalancer::new(
+/// Adds the accounts read by the liquidations of the marginfi group (banks, oracles,
+/// vaults and mints) to an address lookup table, so the long observation account
+/// lists are referenced by index. The first configured table owned by the signer is
+/// extended, a new one is created otherwise and has to be added to `address_lookup_tables`
+pub async fn setup_lookup_table(config: Eva01Config) -> anyhow::Result<()> {
+    config.validate()?;
+
+    let (_, geyser_rx) = latest_update_channel();
+    let (transaction_tx, _) = crossbeam::channel::unbounded();
+    let rpc_pool = Arc::new(RpcPool::new(&config.general_config));
+    let rpc = rpc_pool.rpc();
+    let keypair = config.general_config.keypair.load()?;
+
+    let mut liquidator = Liquidator::new(
         config.general_config.clone(),
-        config.rebalancer_config.clone(),
-        transaction_tx.clone(),
-        rebalancer_rx.clone(),
-        stop_liquidator.clone(),
+        config.liquidator_config.clone(),
+        geyser_rx,
+        transaction_tx,
+        Arc::new(AtomicBool::new(false)),
+        CancellationToken::new(),
+        rpc_pool.clone(),
+        PendingLiquidations::default(),
+        LiquidationCooldowns::new(&config.liquidator_config),
     )
-    .await?;
+    .await;
+    liquidator.load_banks().await?;
 
-    liquidator.load_data().await?;
-    rebalancer.load_data(liquidator.get_banks_and_map()).await?;
+    let program_id = config.general_config.marginfi_program_id;
+    let mut addresses = vec![
+        program_id,
+        config.general_config.marginfi_group_address,
+        spl_token::ID,
+        anchor_spl::token_2022::ID,
+    ];
+    for bank in liquidator.get_banks_and_map().0.values() {
+        addresses.extend([
+            bank.address,
+            bank.oracle_adapter.address,
+            bank.bank.mint,
+            bank.bank.liquidity_vault,
+            bank.bank.insurance_vault,
+            crate::utils::find_bank_vault_authority_pda(
+                &bank.address,
+                BankVaultType::Liquidity,
+                &program_id,
+            )
+            .0,
+        ]);
+    }
 
-    let mut accounts_to_track = HashMap::new();
-    for (key, value) in liquidator.get_accounts_to_track() {
-        accounts_to_track.insert(key, value);
+    // The addresses already in one of the configured tables are skipped
+    let mut table_address = None;
+    for configured_table in &config.general_config.address_lookup_tables {
+        let account = rpc.get_account(configured_table)?;
+        let table = AddressLookupTable::deserialize(&account.data)?;
+        addresses.retain(|address| !table.addresses.contains(address));
+        if table_address.is_none()
+            && table.meta.authority == Some(keypair.pubkey())
+            && table.addresses.len() < LOOKUP_TABLE_MAX_ADDRESSES
+        {
+            table_address = Some(*configured_table);
+        }
     }
-    for (key, value) in rebalancer.get_accounts_to_track() {
-        accounts_to_track.insert(key, value);
+    addresses.sort();
+    addresses.dedup();
+
+    if addresses.is_empty() {
+        println!("The configured lookup tables already hold every account");
+        return Ok(());
     }
 
-    tokio::task::spawn(async move {
-        if let Err(e) = GeyserService::connect(
-            config.general_config.get_geyser_service_config(),
-            accounts_to_track,
-            config.general_config.marginfi_program_id,
-            config.general_config.marginfi_group_address,
-            liquidator_tx,
-            rebalancer_tx,
-        )
-        .await
-        {
-            error!("Failed to connect to geyser service: {:?}", e);
+    let table_address = match table_address {
+        Some(table_address) => table_address,
+        None => {
+            let (create_ix, table_address) = create_lookup_table(
+                keypair.pubkey(),
+                keypair.pubkey(),
+                rpc.get_slot_with_commitment(CommitmentConfig::finalized())?,
+            );
+            send_setup_transaction(&rpc, &keypair, create_ix)?;
+            println!("Created the lookup table {}", table_address);
+            table_address
         }
-    });
+    };
 
-    tokio::task::spawn(async move {
-        transaction_manager.start().await;
-    });
+    for chunk in addresses.chunks(LOOKUP_TABLE_EXTEND_CHUNK) {
+        let extend_ix = extend_lookup_table(
+            table_address,
+            keypair.pubkey(),
+            Some(keypair.pubkey()),
+            chunk.to_vec(),
+        );
+        send_setup_transaction(&rpc, &keypair, extend_ix)?;
+    }
 
-    tokio::task::spawn(async move {
-        if let Err(e) = rebalancer.start().await {
-            error!("Failed to start rebalancer: {:?}", e);
+    println!(
+        "Added {} accounts to the lookup table {}, make sure it is listed in `address_lookup_tables`",
+        addresses.len(),
+        table_address
+    );
+
+    Ok(())
+}
+
+fn send_setup_transaction(
+    rpc: &RpcClient,
+    keypair: &Keypair,
+    instruction: Instruction,
+) -> anyhow::Result<()> {
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&keypair.pubkey()),
+        &[keypair],
+        rpc.get_latest_blockhash()?,
+    );
+    rpc.send_and_confirm_transaction(&transaction)?;
+    Ok(())
+}
+
+/// Loads the marginfi group, then applies the recorded geyser updates slot by slot
+/// and prints the first liquidation each account would have triggered, nothing is sent
+pub async fn replay_recording(
+    config: Eva01Config,
+    recording_path: PathBuf,
+    realtime: bool,
+) -> anyhow::Result<()> {
+    config.validate()?;
+
+    let records = crate::replay::read_recording(&recording_path)?;
+    info!(
+        "Replaying {} geyser updates from {:?}",
+        records.len(),
+        recording_path
+    );
+
+    let (_, geyser_rx) = latest_update_channel();
+    let (transaction_tx, _) = crossbeam::channel::unbounded();
+    let rpc_pool = Arc::new(RpcPool::new(&config.general_config));
+
+    let mut liquidator = Liquidator::new(
+        config.general_config.clone(),
+        config.liquidator_config.clone(),
+        geyser_rx,
+        transaction_tx,
+        Arc::new(AtomicBool::new(false)),
+        CancellationToken::new(),
+        rpc_pool,
+        PendingLiquidations::default(),
+        LiquidationCooldowns::new(&config.liquidator_config),
+    )
+    .await;
+    liquidator.load_data(None).await?;
+
+    // First liquidation of each account, and the slot it would have fired at
+    let mut fired = HashMap::new();
+    let mut records = records.into_iter().peekable();
+    let mut last_timestamp_ms = None;
+    while let Some(record) = records.next() {
+        let slot = record.slot;
+
+        if realtime {
+            if let Some(last_timestamp_ms) = last_timestamp_ms {
+                tokio::time::sleep(Duration::from_millis(
+                    record.timestamp_ms.saturating_sub(last_timestamp_ms),
+                ))
+                .await;
+            }
+            last_timestamp_ms = Some(record.timestamp_ms);
         }
-    });
+        liquidator.apply_update(record.into());
+
+        // The accounts are evaluated once every update of the slot is applied
+        if records.peek().is_some_and(|next| next.slot == slot) {
+            continue;
+        }
+        for opportunity in liquidator.scan().await {
+            if opportunity.profit < config.liquidator_config.min_profit_usd {
+                continue;
+            }
+            fired.entry(opportunity.address).or_insert_with(|| {
+                info!(
+                    "Slot {}: would liquidate {} for an estimated profit of ${:.4}",
+                    slot,
+                    opportunity.address,
+                    opportunity.profit.to_num::<f64>()
+                );
+                (slot, opportunity)
+            });
+        }
+    }
+
+    let mut fired = fired.into_values().collect::<Vec<_>>();
+    fired.sort_by_key(|(slot, _)| *slot);
+
+    println!(
+        "{:>12} {:<46} {:>8} {:<46} {:<46} {:>12}",
+        "Slot", "Account", "Health", "Asset bank", "Liability bank", "Profit ($)"
+    );
+    for (slot, opportunity) in &fired {
+        println!(
+            "{:>12} {:<46} {:>8.4} {:<46} {:<46} {:>12.4}",
+            slot,
+            opportunity.address.to_string(),
+            opportunity.health_factor.to_num::<f64>(),
+            opportunity.asset_bank.to_string(),
+            opportunity.liab_bank.to_string(),
+            opportunity.profit.to_num::<f64>()
+        );
+    }
+    println!(
+        "{} liquidations would have fired, for an estimated profit of ${:.4}",
+        fired.len(),
+        fired
+            .iter()
+            .map(|(_, opportunity)| opportunity.profit.to_num::<f64>())
+            .sum::<f64>()
+    );
+
+    Ok(())
+}
+
+/// Writes the transactions of the transaction log confirmed between the `from`
+/// and `to` days, both included, to the standard output
+pub fn export_tx_log(
+    config: Eva01Config,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: ExportFormat,
+) -> anyhow::Result<()> {
+    let Some(tx_log_path) = config.general_config.tx_log_path else {
+        anyhow::bail!("No transaction log to export, `tx_log_path` isn't set");
+    };
+    if from > to {
+        anyhow::bail!("The export starts on {}, after its end on {}", from, to);
+    }
+
+    let from = from.and_time(NaiveTime::MIN).and_utc().timestamp() as u64;
+    let to = to
+        .succ_opt()
+        .map(|next_day| next_day.and_time(NaiveTime::MIN).and_utc().timestamp() as u64 - 1)
+        .unwrap_or(u64::MAX);
 
-    liquidator.start().await?;
+    let mut stdout = std::io::stdout().lock();
+    let rows = match format {
+        ExportFormat::Csv => crate::tx_log::export_csv(&tx_log_path, from, to, &mut stdout)?,
+    };
+    info!("Exported {} transactions from {:?}", rows, tx_log_path);
 
     Ok(())
 }
@@ -16,13 +16,38 @@ pub async fn main_entry() -> anyhow::Result<()> {
     let args = app::Args::parse();
 
     match args.cmd {
-        app::Commands::Run { path } => {
-            let config = Eva01Config::try_load_from_file(path).unwrap();
-            entrypoints::run_liquidator(config).await?;
+        app::Commands::Run { path, record } => {
+            let config = Eva01Config::try_load_from_file(path.clone()).unwrap();
+            entrypoints::run_liquidator(config, path, record).await?;
         }
         app::Commands::Setup => {
             entrypoints::wizard_setup().await?;
         }
+        app::Commands::Scan { path } => {
+            let config = Eva01Config::try_load_from_file(path).unwrap();
+            entrypoints::scan_opportunities(config).await?;
+        }
+        app::Commands::SetupLookupTable { path } => {
+            let config = Eva01Config::try_load_from_file(path).unwrap();
+            entrypoints::setup_lookup_table(config).await?;
+        }
+        app::Commands::Replay {
+            path,
+            recording,
+            realtime,
+        } => {
+            let config = Eva01Config::try_load_from_file(path).unwrap();
+            entrypoints::replay_recording(config, recording, realtime).await?;
+        }
+        app::Commands::Export {
+            path,
+            from,
+            to,
+            format,
+        } => {
+            let config = Eva01Config::try_load_from_file(path).unwrap();
+            entrypoints::export_tx_log(config, from, to, format)?;
+        }
         app::Commands::SetupFromCli(cfg) => setup_from_cfg(cfg).await?,
     }
 
@@ -7,22 +7,39 @@ use solana_sdk::{
     pubkey::Pubkey,
     signature::Signature,
     signature::{Keypair, Signer},
+    transaction::Transaction,
 };
 use std::sync::Arc;
 
+/// Creates a marginfi account owned by the signer in the group,
+/// returns its address once the transaction is confirmed
 pub fn initialize_marginfi_account(
     rpc_client: Arc<RpcClient>,
     signer: Arc<Keypair>,
     marginfi_program_id: Pubkey,
     marginfi_group_id: Pubkey,
     send_cfg: SenderCfg,
-) -> anyhow::Result<Signature> {
+) -> anyhow::Result<(Pubkey, Signature)> {
     let signer_pk = signer.pubkey();
+    let marginfi_account = Keypair::new();
 
-    let initialize_ix = make_initialize_ix(marginfi_program_id, marginfi_group_id, signer_pk);
+    let initialize_ix = make_initialize_ix(
+        marginfi_program_id,
+        marginfi_group_id,
+        marginfi_account.pubkey(),
+        signer_pk,
+    );
 
-    let sig = TransactionSender::send_ix(rpc_client, initialize_ix, signer, None, send_cfg)
-        .map_err(|e| anyhow::anyhow!("Coulnd't send the transaction: {}", e))?;
+    let recent_blockhash = rpc_client.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(
+        &[initialize_ix],
+        Some(&signer_pk),
+        &[signer.as_ref(), &marginfi_account],
+        recent_blockhash,
+    );
 
-    Ok(sig)
+    let sig = TransactionSender::passive_send_tx(rpc_client, &tx, send_cfg)
+        .map_err(|e| anyhow::anyhow!("Couldn't send the transaction: {}", e))?;
+
+    Ok((marginfi_account.pubkey(), sig))
 }
@@ -1,25 +1,27 @@
 use super::app::SetupFromCliOpts;
 use crate::{
-    config::{Eva01Config, GeneralConfig, LiquidatorCfg, RebalancerCfg},
-    utils::{ask_keypair_until_valid, expand_tilde, is_valid_url, prompt_user},
+    config::{Eva01Config, GeneralConfig, KeypairSource, LiquidatorCfg, RebalancerCfg},
+    sender::SenderCfg,
+    utils::{
+        ask_keypair_until_valid, expand_tilde, is_valid_url, marginfi_accounts_by_authority,
+        prompt_user,
+    },
 };
 
 use anyhow::bail;
 use fixed::types::I80F48;
 use lazy_static::lazy_static;
-use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
-use solana_client::{
-    rpc_client::RpcClient,
-    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
-    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
-};
+use marginfi::state::marginfi_account::MarginfiAccount;
+use solana_client::rpc_client::RpcClient;
 use solana_program::pubkey::Pubkey;
-use solana_sdk::signature::{read_keypair_file, Signer};
-use std::{ops::Not, path::PathBuf, str::FromStr};
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use std::{ops::Not, path::PathBuf, str::FromStr, sync::Arc};
 
 /// Helper for initializing Marginfi Account
 pub mod initialize;
 
+use initialize::initialize_marginfi_account;
+
 lazy_static! {
     static ref DEFAULT_CONFIG_PATH: PathBuf = {
         let mut path = dirs::home_dir().expect("Couldn't find the config directory");
@@ -51,7 +53,7 @@ pub async fn setup() -> anyhow::Result<()> {
     if !is_valid_url(&rpc_url) {
         bail!("Invalid RPC endpoint");
     }
-    let rpc_client = RpcClient::new(rpc_url.clone());
+    let rpc_client = Arc::new(RpcClient::new(rpc_url.clone()));
 
     // Target program/group
     let input_raw = prompt_user(&format!(
@@ -76,12 +78,36 @@ pub async fn setup() -> anyhow::Result<()> {
 
     // Marginfi account discovery/selection
     let (keypair_path, signer_keypair) = ask_keypair_until_valid()?;
-    let accounts = marginfi_account_by_authority(signer_keypair.pubkey(), rpc_client).await?;
-    if accounts.is_empty() {
-        println!("No marginfi account found for the provided signer. Please create one first.");
-        bail!("No marginfi account found");
-        // TODO: initialize a marginfi account programmatically
-    }
+    let signer_pubkey = signer_keypair.pubkey();
+    let accounts = marginfi_accounts_by_authority(
+        &rpc_client,
+        &marginfi_program_id,
+        &marginfi_group_address,
+        &signer_pubkey,
+    )?;
+    let liquidator_account = match accounts.first() {
+        Some(account) => {
+            println!("Using the marginfi account {} of the signer.", account);
+            Some(*account)
+        }
+        None => {
+            let create =
+                prompt_user("No marginfi account found for the signer, create one now? [Y/n] ")?
+                    .to_lowercase()
+                    != "n";
+            if create {
+                Some(create_marginfi_account(
+                    rpc_client,
+                    Arc::new(signer_keypair),
+                    marginfi_program_id,
+                    marginfi_group_address,
+                )?)
+            } else {
+                println!("A marginfi account will be created on the first run.");
+                None
+            }
+        }
+    };
 
     let yellowstone_endpoint = prompt_user("Yellowstone endpoint url [required]: ")?;
     let yellowstone_x_token = {
@@ -96,22 +122,96 @@ pub async fn setup() -> anyhow::Result<()> {
         rpc_url,
         yellowstone_endpoint,
         yellowstone_x_token,
+        rpc_standby_urls: GeneralConfig::default_rpc_standby_urls(),
+        yellowstone_standby_endpoints: GeneralConfig::default_yellowstone_standby_endpoints(),
         block_engine_url: GeneralConfig::default_block_engine_url(),
-        signer_pubkey: signer_keypair.pubkey(),
-        keypair_path,
-        liquidator_account: accounts[0],
+        block_engine_regions: GeneralConfig::default_block_engine_regions(),
+        auth_keypair_path: GeneralConfig::default_auth_keypair_path(),
+        signer_pubkey,
+        keypair: KeypairSource::File(keypair_path),
+        liquidator_account,
+        liquidator_account_index: GeneralConfig::default_liquidator_account_index(),
         compute_unit_price_micro_lamports: GeneralConfig::default_compute_unit_price_micro_lamports(
         ),
+        priority_fee_percentile: GeneralConfig::default_priority_fee_percentile(),
+        min_priority_fee_micro_lamports: GeneralConfig::default_min_priority_fee_micro_lamports(),
+        max_priority_fee_micro_lamports: GeneralConfig::default_max_priority_fee_micro_lamports(),
+        min_compute_unit_price: GeneralConfig::default_min_compute_unit_price(),
+        max_compute_unit_price: GeneralConfig::default_max_compute_unit_price(),
         marginfi_program_id,
         marginfi_group_address,
         account_whitelist: GeneralConfig::default_account_whitelist(),
         address_lookup_tables: GeneralConfig::default_address_lookup_tables(),
+        tip_strategy: GeneralConfig::default_tip_strategy(),
+        tip_funding_strategy: GeneralConfig::default_tip_funding_strategy(),
+        max_leader_wait_ms: GeneralConfig::default_max_leader_wait_ms(),
+        jito_leader_slot_threshold: GeneralConfig::default_jito_leader_slot_threshold(),
+        jito_tip_split: GeneralConfig::default_jito_tip_split(),
+        jito_max_retries: GeneralConfig::default_jito_max_retries(),
+        jito_retry_delay_ms: GeneralConfig::default_jito_retry_delay_ms(),
+        jito_retry_tip_bump_bps: GeneralConfig::default_jito_retry_tip_bump_bps(),
+        fallback_to_rpc: GeneralConfig::default_fallback_to_rpc(),
+        max_in_flight_bundles: GeneralConfig::default_max_in_flight_bundles(),
+        simulate_transactions: GeneralConfig::default_simulate_transactions(),
+        compute_unit_limit: GeneralConfig::default_compute_unit_limit(),
+        tune_compute_unit_limit: GeneralConfig::default_tune_compute_unit_limit(),
+        compute_unit_margin_bps: GeneralConfig::default_compute_unit_margin_bps(),
+        dry_run: GeneralConfig::default_dry_run(),
+        dry_run_json: GeneralConfig::default_dry_run_json(),
+        metrics_port: GeneralConfig::default_metrics_port(),
+        geyser_max_connect_attempts: GeneralConfig::default_geyser_max_connect_attempts(),
+        geyser_max_accounts_per_subscription:
+            GeneralConfig::default_geyser_max_accounts_per_subscription(),
+        geyser_idle_timeout_secs: GeneralConfig::default_geyser_idle_timeout_secs(),
+        commitment: GeneralConfig::default_commitment(),
+        hermes_url: GeneralConfig::default_hermes_url(),
+        state_path: GeneralConfig::default_state_path(),
+        max_state_age_secs: GeneralConfig::default_max_state_age_secs(),
+        discord_webhook_url: GeneralConfig::default_discord_webhook_url(),
+        telegram_bot_token: GeneralConfig::default_telegram_bot_token(),
+        telegram_chat_id: GeneralConfig::default_telegram_chat_id(),
+        notification_interval_secs: GeneralConfig::default_notification_interval_secs(),
+        send_retries: GeneralConfig::default_send_retries(),
+        send_timeout_ms: GeneralConfig::default_send_timeout_ms(),
+        skip_preflight: GeneralConfig::default_skip_preflight(),
+        max_retries: GeneralConfig::default_max_retries(),
+        retry_backoff_ms: GeneralConfig::default_retry_backoff_ms(),
+        rpc_health_check_interval_secs: GeneralConfig::default_rpc_health_check_interval_secs(),
+        max_rpc_slot_lag: GeneralConfig::default_max_rpc_slot_lag(),
+        rpc_requests_per_second: GeneralConfig::default_rpc_requests_per_second(),
+        rpc_burst: GeneralConfig::default_rpc_burst(),
+        tx_log_path: GeneralConfig::default_tx_log_path(),
+        pnl_summary_interval_secs: GeneralConfig::default_pnl_summary_interval_secs(),
+        log_filter: GeneralConfig::default_log_filter(),
+        admin_socket_path: GeneralConfig::default_admin_socket_path(),
+        max_spend_per_hour: GeneralConfig::default_max_spend_per_hour(),
+        price_source: GeneralConfig::default_price_source(),
+        hermes_ws_url: GeneralConfig::default_hermes_ws_url(),
+        restart_policy: GeneralConfig::default_restart_policy(),
+        min_sol_balance: GeneralConfig::default_min_sol_balance(),
+        sol_balance_check_interval_secs: GeneralConfig::default_sol_balance_check_interval_secs(),
+        additional_signers: GeneralConfig::default_additional_signers(),
+        nonce_account: GeneralConfig::default_nonce_account(),
     };
 
     let liquidator_config = LiquidatorCfg {
-        min_profit: LiquidatorCfg::default_min_profit(),
+        min_profit_usd: LiquidatorCfg::default_min_profit_usd(),
         max_liquidation_value: None,
         isolated_banks,
+        swap_slippage_bps: LiquidatorCfg::default_swap_slippage_bps(),
+        health_threshold: LiquidatorCfg::default_health_threshold(),
+        bank_allowlist: LiquidatorCfg::default_bank_allowlist(),
+        bank_blacklist: LiquidatorCfg::default_bank_blacklist(),
+        max_asset_exposure: LiquidatorCfg::default_max_asset_exposure(),
+        ranking_strategy: LiquidatorCfg::default_ranking_strategy(),
+        max_consecutive_failures: LiquidatorCfg::default_max_consecutive_failures(),
+        failure_cooldown_secs: LiquidatorCfg::default_failure_cooldown_secs(),
+        max_evaluation_ms: LiquidatorCfg::default_max_evaluation_ms(),
+        confirm_before_liquidate: LiquidatorCfg::default_confirm_before_liquidate(),
+        scan_interval_ms: LiquidatorCfg::default_scan_interval_ms(),
+        value_collateral_via_jupiter: LiquidatorCfg::default_value_collateral_via_jupiter(),
+        additional_groups: LiquidatorCfg::default_additional_groups(),
+        max_oracle_age_slots: LiquidatorCfg::default_max_oracle_age_slots(),
     };
 
     let rebalancer_config = RebalancerCfg {
@@ -122,6 +222,18 @@ pub async fn setup() -> anyhow::Result<()> {
         compute_unit_price_micro_lamports: RebalancerCfg::default_compute_unit_price_micro_lamports(
         ),
         slippage_bps: RebalancerCfg::default_slippage_bps(),
+        min_swap_usd: RebalancerCfg::default_min_swap_usd(),
+        min_rebalance_usd: RebalancerCfg::default_min_rebalance_usd(),
+        dust_threshold_usd: RebalancerCfg::default_dust_threshold_usd(),
+        claim_emissions: RebalancerCfg::default_claim_emissions(),
+        claim_emissions_interval_secs: RebalancerCfg::default_claim_emissions_interval_secs(),
+        min_claim_emissions_usd: RebalancerCfg::default_min_claim_emissions_usd(),
+        min_health_buffer: RebalancerCfg::default_min_health_buffer(),
+        auto_unwrap_sol: RebalancerCfg::default_auto_unwrap_sol(),
+        seed_deposits: RebalancerCfg::default_seed_deposits(),
+        post_liquidation_strategy: RebalancerCfg::default_post_liquidation_strategy(),
+        max_price_impact_bps: RebalancerCfg::default_max_price_impact_bps(),
+        quote_cache_ttl_ms: RebalancerCfg::default_quote_cache_ttl_ms(),
     };
 
     println!(
@@ -146,6 +258,40 @@ pub async fn setup() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Creates a marginfi account for the signer in the group, once it holds the rent
+fn create_marginfi_account(
+    rpc_client: Arc<RpcClient>,
+    signer_keypair: Arc<Keypair>,
+    marginfi_program_id: Pubkey,
+    marginfi_group_address: Pubkey,
+) -> anyhow::Result<Pubkey> {
+    let rent = rpc_client
+        .get_minimum_balance_for_rent_exemption(8 + std::mem::size_of::<MarginfiAccount>())?;
+    let balance = rpc_client.get_balance(&signer_keypair.pubkey())?;
+    if balance < rent {
+        bail!(
+            "The signer {} holds {} lamports, it needs {} to fund the rent of a marginfi account",
+            signer_keypair.pubkey(),
+            balance,
+            rent
+        );
+    }
+
+    let (marginfi_account, signature) = initialize_marginfi_account(
+        rpc_client,
+        signer_keypair,
+        marginfi_program_id,
+        marginfi_group_address,
+        SenderCfg::DEFAULT,
+    )?;
+    println!(
+        "Created the marginfi account {}: {}",
+        marginfi_account, signature
+    );
+
+    Ok(marginfi_account)
+}
+
 pub async fn setup_from_cfg(
     SetupFromCliOpts {
         rpc_url,
@@ -178,34 +324,112 @@ pub async fn setup_from_cfg(
         }
     };
 
+    // Left to the discovery of the first run when the signer has no account yet
     let marginfi_account = match marginfi_account {
-        Some(account) => account,
-        None => marginfi_account_by_authority(signer_pubkey, RpcClient::new(rpc_url.clone()))
-            .await
-            .expect("Failed to get marginfi account by authority")
-            .pop()
-            .expect("No marginfi account found"),
+        Some(account) => Some(account),
+        None => marginfi_accounts_by_authority(
+            &RpcClient::new(rpc_url.clone()),
+            &marginfi_program_id,
+            &marginfi_group_address,
+            &signer_pubkey,
+        )?
+        .first()
+        .copied(),
     };
 
     let general_config = GeneralConfig {
         rpc_url,
         yellowstone_endpoint,
         yellowstone_x_token,
+        rpc_standby_urls: GeneralConfig::default_rpc_standby_urls(),
+        yellowstone_standby_endpoints: GeneralConfig::default_yellowstone_standby_endpoints(),
         block_engine_url: GeneralConfig::default_block_engine_url(),
+        block_engine_regions: GeneralConfig::default_block_engine_regions(),
+        auth_keypair_path: GeneralConfig::default_auth_keypair_path(),
         signer_pubkey,
-        keypair_path,
+        keypair: KeypairSource::File(keypair_path),
         liquidator_account: marginfi_account,
+        liquidator_account_index: GeneralConfig::default_liquidator_account_index(),
         compute_unit_price_micro_lamports,
+        priority_fee_percentile: GeneralConfig::default_priority_fee_percentile(),
+        min_priority_fee_micro_lamports: GeneralConfig::default_min_priority_fee_micro_lamports(),
+        max_priority_fee_micro_lamports: GeneralConfig::default_max_priority_fee_micro_lamports(),
+        min_compute_unit_price: GeneralConfig::default_min_compute_unit_price(),
+        max_compute_unit_price: GeneralConfig::default_max_compute_unit_price(),
         marginfi_program_id,
         marginfi_group_address,
         account_whitelist: None,
         address_lookup_tables: GeneralConfig::default_address_lookup_tables(),
+        tip_strategy: GeneralConfig::default_tip_strategy(),
+        tip_funding_strategy: GeneralConfig::default_tip_funding_strategy(),
+        max_leader_wait_ms: GeneralConfig::default_max_leader_wait_ms(),
+        jito_leader_slot_threshold: GeneralConfig::default_jito_leader_slot_threshold(),
+        jito_tip_split: GeneralConfig::default_jito_tip_split(),
+        jito_max_retries: GeneralConfig::default_jito_max_retries(),
+        jito_retry_delay_ms: GeneralConfig::default_jito_retry_delay_ms(),
+        jito_retry_tip_bump_bps: GeneralConfig::default_jito_retry_tip_bump_bps(),
+        fallback_to_rpc: GeneralConfig::default_fallback_to_rpc(),
+        max_in_flight_bundles: GeneralConfig::default_max_in_flight_bundles(),
+        simulate_transactions: GeneralConfig::default_simulate_transactions(),
+        compute_unit_limit: GeneralConfig::default_compute_unit_limit(),
+        tune_compute_unit_limit: GeneralConfig::default_tune_compute_unit_limit(),
+        compute_unit_margin_bps: GeneralConfig::default_compute_unit_margin_bps(),
+        dry_run: GeneralConfig::default_dry_run(),
+        dry_run_json: GeneralConfig::default_dry_run_json(),
+        metrics_port: GeneralConfig::default_metrics_port(),
+        geyser_max_connect_attempts: GeneralConfig::default_geyser_max_connect_attempts(),
+        geyser_max_accounts_per_subscription:
+            GeneralConfig::default_geyser_max_accounts_per_subscription(),
+        geyser_idle_timeout_secs: GeneralConfig::default_geyser_idle_timeout_secs(),
+        commitment: GeneralConfig::default_commitment(),
+        hermes_url: GeneralConfig::default_hermes_url(),
+        state_path: GeneralConfig::default_state_path(),
+        max_state_age_secs: GeneralConfig::default_max_state_age_secs(),
+        discord_webhook_url: GeneralConfig::default_discord_webhook_url(),
+        telegram_bot_token: GeneralConfig::default_telegram_bot_token(),
+        telegram_chat_id: GeneralConfig::default_telegram_chat_id(),
+        notification_interval_secs: GeneralConfig::default_notification_interval_secs(),
+        send_retries: GeneralConfig::default_send_retries(),
+        send_timeout_ms: GeneralConfig::default_send_timeout_ms(),
+        skip_preflight: GeneralConfig::default_skip_preflight(),
+        max_retries: GeneralConfig::default_max_retries(),
+        retry_backoff_ms: GeneralConfig::default_retry_backoff_ms(),
+        rpc_health_check_interval_secs: GeneralConfig::default_rpc_health_check_interval_secs(),
+        max_rpc_slot_lag: GeneralConfig::default_max_rpc_slot_lag(),
+        rpc_requests_per_second: GeneralConfig::default_rpc_requests_per_second(),
+        rpc_burst: GeneralConfig::default_rpc_burst(),
+        tx_log_path: GeneralConfig::default_tx_log_path(),
+        pnl_summary_interval_secs: GeneralConfig::default_pnl_summary_interval_secs(),
+        log_filter: GeneralConfig::default_log_filter(),
+        admin_socket_path: GeneralConfig::default_admin_socket_path(),
+        max_spend_per_hour: GeneralConfig::default_max_spend_per_hour(),
+        price_source: GeneralConfig::default_price_source(),
+        hermes_ws_url: GeneralConfig::default_hermes_ws_url(),
+        restart_policy: GeneralConfig::default_restart_policy(),
+        min_sol_balance: GeneralConfig::default_min_sol_balance(),
+        sol_balance_check_interval_secs: GeneralConfig::default_sol_balance_check_interval_secs(),
+        additional_signers: GeneralConfig::default_additional_signers(),
+        nonce_account: GeneralConfig::default_nonce_account(),
     };
 
     let liquidator_config = LiquidatorCfg {
-        min_profit,
+        min_profit_usd: min_profit,
         max_liquidation_value,
         isolated_banks,
+        swap_slippage_bps: LiquidatorCfg::default_swap_slippage_bps(),
+        health_threshold: LiquidatorCfg::default_health_threshold(),
+        bank_allowlist: LiquidatorCfg::default_bank_allowlist(),
+        bank_blacklist: LiquidatorCfg::default_bank_blacklist(),
+        max_asset_exposure: LiquidatorCfg::default_max_asset_exposure(),
+        ranking_strategy: LiquidatorCfg::default_ranking_strategy(),
+        max_consecutive_failures: LiquidatorCfg::default_max_consecutive_failures(),
+        failure_cooldown_secs: LiquidatorCfg::default_failure_cooldown_secs(),
+        max_evaluation_ms: LiquidatorCfg::default_max_evaluation_ms(),
+        confirm_before_liquidate: LiquidatorCfg::default_confirm_before_liquidate(),
+        scan_interval_ms: LiquidatorCfg::default_scan_interval_ms(),
+        value_collateral_via_jupiter: LiquidatorCfg::default_value_collateral_via_jupiter(),
+        additional_groups: LiquidatorCfg::default_additional_groups(),
+        max_oracle_age_slots: LiquidatorCfg::default_max_oracle_age_slots(),
     };
 
     let rebalancer_config = RebalancerCfg {
@@ -215,6 +439,18 @@ pub async fn setup_from_cfg(
         jup_swap_api_url,
         compute_unit_price_micro_lamports,
         slippage_bps: default_slippage_bps,
+        min_swap_usd: RebalancerCfg::default_min_swap_usd(),
+        min_rebalance_usd: RebalancerCfg::default_min_rebalance_usd(),
+        dust_threshold_usd: RebalancerCfg::default_dust_threshold_usd(),
+        claim_emissions: RebalancerCfg::default_claim_emissions(),
+        claim_emissions_interval_secs: RebalancerCfg::default_claim_emissions_interval_secs(),
+        min_claim_emissions_usd: RebalancerCfg::default_min_claim_emissions_usd(),
+        min_health_buffer: RebalancerCfg::default_min_health_buffer(),
+        auto_unwrap_sol: RebalancerCfg::default_auto_unwrap_sol(),
+        seed_deposits: RebalancerCfg::default_seed_deposits(),
+        post_liquidation_strategy: RebalancerCfg::default_post_liquidation_strategy(),
+        max_price_impact_bps: RebalancerCfg::default_max_price_impact_bps(),
+        quote_cache_ttl_ms: RebalancerCfg::default_quote_cache_ttl_ms(),
     };
 
     let config = Eva01Config {
@@ -243,50 +479,3 @@ pub async fn setup_from_cfg(
 
     Ok(())
 }
-
-async fn marginfi_account_by_authority(
-    authority: Pubkey,
-    rpc_client: RpcClient,
-) -> anyhow::Result<Vec<Pubkey>> {
-    let marginfi_account_address = rpc_client.get_program_accounts_with_config(
-        &GeneralConfig::default_marginfi_program_id(),
-        RpcProgramAccountsConfig {
-            account_config: RpcAccountInfoConfig {
-                encoding: Some(UiAccountEncoding::Base64),
-                data_slice: Some(UiDataSliceConfig {
-                    offset: 0,
-                    length: 0,
-                }),
-                ..Default::default()
-            },
-            filters: Some(vec![
-                #[allow(deprecated)]
-                RpcFilterType::Memcmp(Memcmp {
-                    offset: 8,
-                    #[allow(deprecated)]
-                    bytes: MemcmpEncodedBytes::Base58(
-                        GeneralConfig::default_marginfi_group_address().to_string(),
-                    ),
-                    #[allow(deprecated)]
-                    encoding: None,
-                }),
-                #[allow(deprecated)]
-                RpcFilterType::Memcmp(Memcmp {
-                    offset: 8 + 32,
-                    #[allow(deprecated)]
-                    bytes: MemcmpEncodedBytes::Base58(authority.to_string()),
-                    #[allow(deprecated)]
-                    encoding: None,
-                }),
-            ]),
-            with_context: Some(false),
-        },
-    )?;
-
-    let marginfi_account_pubkeys: Vec<Pubkey> = marginfi_account_address
-        .iter()
-        .map(|(pubkey, _)| *pubkey)
-        .collect();
-
-    Ok(marginfi_account_pubkeys)
-}
@@ -1,20 +1,40 @@
 use crate::{
+    fee_estimator::PriorityFee,
     geyser::GeyserServiceConfig,
+    sender::SenderCfg,
+    transaction_manager::{JitoRegion, TipStrategy},
     utils::{
-        fixed_from_float, fixed_to_float, from_option_vec_pubkey_string, from_pubkey_string,
-        from_vec_str_to_pubkey, pubkey_to_str, vec_pubkey_to_option_vec_str, vec_pubkey_to_str,
+        fixed_from_float, fixed_to_float, from_option_pubkey_string, from_option_vec_pubkey_string,
+        from_pubkey_string, from_str_map_to_pubkey_map, from_vec_str_to_pubkey,
+        option_pubkey_to_str, pubkey_map_to_str_map, pubkey_to_str, vec_pubkey_to_option_vec_str,
+        vec_pubkey_to_str,
     },
     wrappers::marginfi_account::TxConfig,
 };
+use anyhow::{anyhow, bail};
 use fixed::types::I80F48;
 use fixed_macro::types::I80F48;
-use solana_sdk::{pubkey, pubkey::Pubkey};
+use solana_sdk::{
+    bs58,
+    commitment_config::CommitmentConfig,
+    pubkey,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair},
+    signer::Signer,
+};
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
     io::{BufWriter, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 use toml::ser::to_string_pretty;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Maximum compute units a transaction can request
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 /// Eva01 configuration strecture
@@ -40,6 +60,285 @@ impl Eva01Config {
         writeln!(file, "{}", toml_str)?;
         Ok(())
     }
+
+    /// Checks the config before the services are started,
+    /// failing with an error naming the offending field
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.general_config.validate()?;
+        self.liquidator_config.validate()?;
+        self.rebalancer_config.validate()?;
+        if self
+            .liquidator_config
+            .additional_groups
+            .iter()
+            .any(|group| group.marginfi_group_address == self.general_config.marginfi_group_address)
+        {
+            bail!(
+                "The marginfi group {} of the general config is also listed in `additional_groups`",
+                self.general_config.marginfi_group_address
+            );
+        }
+
+        // The rebalancer of a signer drains its token accounts, two of them would compete
+        let mut signers = HashSet::new();
+        let mut liquidator_accounts = HashSet::new();
+        let mut nonce_accounts = HashSet::new();
+        for config in self.get_liquidator_configs() {
+            if !signers.insert(config.signer_pubkey) {
+                bail!(
+                    "The signer {} is used by several liquidators",
+                    config.signer_pubkey
+                );
+            }
+            if let Some(liquidator_account) = config.liquidator_account {
+                if !liquidator_accounts.insert(liquidator_account) {
+                    bail!(
+                        "The liquidator account {} is used by several signers",
+                        liquidator_account
+                    );
+                }
+            }
+            if let Some(nonce_account) = config.nonce_account {
+                if !nonce_accounts.insert(nonce_account) {
+                    bail!(
+                        "The nonce account {} is used by several signers",
+                        nonce_account
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Config of each liquidator: the signers of the main marginfi group, the main
+    /// one first, then the signer of each additional group. Only the main one saves
+    /// the state, the others of its group share the tracked accounts it loads
+    pub fn get_liquidator_configs(&self) -> Vec<GeneralConfig> {
+        let mut configs = self.general_config.get_signer_configs();
+        configs.extend(
+            self.liquidator_config
+                .additional_groups
+                .iter()
+                .map(|group| GeneralConfig {
+                    marginfi_group_address: group.marginfi_group_address,
+                    keypair: group.signer.keypair.clone(),
+                    signer_pubkey: group.signer.signer_pubkey,
+                    liquidator_account: group.signer.liquidator_account,
+                    liquidator_account_index: group.signer.liquidator_account_index,
+                    nonce_account: group.signer.nonce_account,
+                    additional_signers: vec![],
+                    state_path: None,
+                    ..self.general_config.clone()
+                }),
+        );
+        configs
+    }
+}
+
+/// Fails when the `field` isn't a valid url
+fn validate_url(field: &str, url: &str) -> anyhow::Result<()> {
+    Url::parse(url).map_err(|e| anyhow!("`{}` {:?} is not a valid url: {}", field, url, e))?;
+    Ok(())
+}
+
+/// Fails when the `field` doesn't point to a readable keypair file
+fn validate_keypair_path(field: &str, path: &Path) -> anyhow::Result<Keypair> {
+    if !path.exists() {
+        bail!("`{}` {:?} doesn't exist", field, path);
+    }
+    read_keypair_file(path)
+        .map_err(|e| anyhow!("`{}` {:?} is not a keypair file: {}", field, path, e))
+}
+
+/// Where the signer keypair is read from: a keypair file, or an environment
+/// variable holding the secret key so containers don't need a key file mounted
+///
+/// ```toml
+/// keypair = "/path/to/keypair.json"
+/// keypair = { env_base58 = "EVA01_KEYPAIR" }
+/// keypair = { env_json_array = "EVA01_KEYPAIR" }
+/// ```
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum KeypairSource {
+    File(PathBuf),
+    EnvBase58 { env_base58: String },
+    EnvJsonArray { env_json_array: String },
+}
+
+impl KeypairSource {
+    /// Reads the keypair, the buffers holding its secret key are zeroized once done
+    pub fn load(&self) -> anyhow::Result<Keypair> {
+        match self {
+            Self::File(path) => validate_keypair_path("keypair", path),
+            Self::EnvBase58 { env_base58 } => {
+                let secret = read_secret_env(env_base58)?;
+                let bytes = Zeroizing::new(
+                    bs58::decode(secret.trim())
+                        .into_vec()
+                        .map_err(|_| anyhow!("`{}` is not a base58 keypair", env_base58))?,
+                );
+                keypair_from_bytes(env_base58, &bytes)
+            }
+            Self::EnvJsonArray { env_json_array } => {
+                let secret = read_secret_env(env_json_array)?;
+                let bytes =
+                    Zeroizing::new(serde_json::from_str::<Vec<u8>>(&secret).map_err(|_| {
+                        anyhow!("`{}` is not a JSON array keypair", env_json_array)
+                    })?);
+                keypair_from_bytes(env_json_array, &bytes)
+            }
+        }
+    }
+}
+
+/// Additional signer of the liquidator, with its own marginfi account
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SignerCfg {
+    #[serde(alias = "keypair_path")]
+    pub keypair: KeypairSource,
+    #[serde(
+        deserialize_with = "from_pubkey_string",
+        serialize_with = "pubkey_to_str"
+    )]
+    pub signer_pubkey: Pubkey,
+    /// Marginfi account of this signer, see `GeneralConfig::liquidator_account`
+    #[serde(
+        default = "GeneralConfig::default_liquidator_account",
+        deserialize_with = "from_option_pubkey_string",
+        serialize_with = "option_pubkey_to_str"
+    )]
+    pub liquidator_account: Option<Pubkey>,
+    /// See `GeneralConfig::liquidator_account_index`
+    #[serde(default = "GeneralConfig::default_liquidator_account_index")]
+    pub liquidator_account_index: usize,
+    /// Durable nonce account of this signer, see `GeneralConfig::nonce_account`
+    #[serde(
+        default = "GeneralConfig::default_nonce_account",
+        deserialize_with = "from_option_pubkey_string",
+        serialize_with = "option_pubkey_to_str"
+    )]
+    pub nonce_account: Option<Pubkey>,
+}
+
+impl SignerCfg {
+    fn validate(&self, field: &str) -> anyhow::Result<()> {
+        let keypair = self.keypair.load()?;
+        if keypair.pubkey() != self.signer_pubkey {
+            bail!(
+                "`{}` keypair {:?} holds the keypair of {}, not of the `signer_pubkey` {}",
+                field,
+                self.keypair,
+                keypair.pubkey(),
+                self.signer_pubkey
+            );
+        }
+        if let Some(liquidator_account) = &self.liquidator_account {
+            validate_pubkey(&format!("{}.liquidator_account", field), liquidator_account)?;
+        }
+        if let Some(nonce_account) = &self.nonce_account {
+            validate_pubkey(&format!("{}.nonce_account", field), nonce_account)?;
+        }
+        Ok(())
+    }
+}
+
+/// Additional marginfi group liquidated, by its own signer and liquidator account
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupCfg {
+    #[serde(
+        deserialize_with = "from_pubkey_string",
+        serialize_with = "pubkey_to_str"
+    )]
+    pub marginfi_group_address: Pubkey,
+    #[serde(flatten)]
+    pub signer: SignerCfg,
+}
+
+fn read_secret_env(var: &str) -> anyhow::Result<Zeroizing<String>> {
+    std::env::var(var)
+        .map(Zeroizing::new)
+        .map_err(|e| anyhow!("Failed to read the keypair from `{}`: {}", var, e))
+}
+
+fn keypair_from_bytes(var: &str, bytes: &[u8]) -> anyhow::Result<Keypair> {
+    Keypair::from_bytes(bytes).map_err(|_| anyhow!("`{}` doesn't hold a valid keypair", var))
+}
+
+/// Fails when the `field` is the default (all zeros) pubkey, left by a missing value
+fn validate_pubkey(field: &str, pubkey: &Pubkey) -> anyhow::Result<()> {
+    if *pubkey == Pubkey::default() {
+        bail!("`{}` is not set", field);
+    }
+    Ok(())
+}
+
+/// Fails when the `field` isn't within 0 and 10000 bps
+fn validate_bps(field: &str, bps: u16) -> anyhow::Result<()> {
+    if bps > 10_000 {
+        bail!("`{}` is {} bps, it can't exceed 10000 bps", field, bps);
+    }
+    Ok(())
+}
+
+/// Source of the oracle prices of the liquidator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum PriceSource {
+    /// The oracle accounts, streamed by geyser
+    GeyserOracleAccounts,
+    /// The pyth prices streamed by the hermes websocket, used while they are more
+    /// recent than the oracle accounts streamed by geyser, so price moves are seen
+    /// even when the geyser updates lag
+    PythHermesWs,
+}
+
+/// What happens when the geyser service, the rebalancer
+/// or the transaction manager stops unexpectedly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum RestartPolicy {
+    /// The service is restarted with an exponential backoff
+    Restart,
+    /// Every service is shut down, for a supervisor to restart the liquidator
+    Shutdown,
+}
+
+/// Order in which the liquidatable accounts are liquidated, when
+/// more of them are found than can be liquidated at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum RankingStrategy {
+    /// The highest estimated net profit first
+    HighestProfit,
+    /// The lowest maintenance health factor first
+    LowestHealth,
+    /// The largest liquidated value first
+    LargestSize,
+}
+
+/// What the rebalancer does with the positions a liquidation leaves in the liquidator
+/// account, the seized collateral and the liability. Below its health buffer the
+/// account is deleveraged with `WithdrawAndSell`, whatever the strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum PostLiquidationStrategy {
+    /// The collateral and the liability are kept as marginfi positions
+    HoldAsMarginfiPosition,
+    /// The collateral is withdrawn and sold for the swap mint, then the liability
+    /// is repaid with the swap mint deposits
+    WithdrawAndSell,
+    /// The liability is repaid with the swap mint deposits first, the collateral is
+    /// only withdrawn and sold once no liability is left, so more of it can be
+    /// withdrawn within the health buffer
+    RepayThenWithdraw,
+}
+
+/// Where the lamports of the jito tips come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum TipFundingStrategy {
+    /// The native SOL balance of the signer
+    NativeSol,
+    /// The native SOL balance of the signer, topped up within the bundle from the
+    /// SOL deposit of the liquidator account when it can't cover the tip
+    MarginfiSolDeposit,
 }
 
 // General Config
@@ -47,23 +346,60 @@ impl Eva01Config {
 /// General config that can be shared by liquidator, rebalancer and geyser
 pub struct GeneralConfig {
     pub rpc_url: String,
+    /// Rpc endpoints taking over from the `rpc_url` when it lags or times out
+    #[serde(default = "GeneralConfig::default_rpc_standby_urls")]
+    pub rpc_standby_urls: Vec<String>,
     pub yellowstone_endpoint: String,
     pub yellowstone_x_token: Option<String>,
+    /// Geyser endpoints kept subscribed as warm standbys of the yellowstone endpoint
+    #[serde(default = "GeneralConfig::default_yellowstone_standby_endpoints")]
+    pub yellowstone_standby_endpoints: Vec<GeyserServiceConfig>,
     #[serde(default = "GeneralConfig::default_block_engine_url")]
     pub block_engine_url: String,
+    /// Jito block engine regions, the one with the lowest latency
+    /// is used instead of the `block_engine_url`
+    #[serde(default = "GeneralConfig::default_block_engine_regions")]
+    pub block_engine_regions: Vec<JitoRegion>,
+    /// Jito approved keypair used to authenticate against the block engine,
+    /// when not provided the searcher client is unauthenticated
+    #[serde(default = "GeneralConfig::default_auth_keypair_path")]
+    pub auth_keypair_path: Option<PathBuf>,
     #[serde(
         deserialize_with = "from_pubkey_string",
         serialize_with = "pubkey_to_str"
     )]
     pub signer_pubkey: Pubkey,
-    pub keypair_path: PathBuf,
+    /// Signer keypair, `keypair_path` is still accepted for a keypair file
+    #[serde(alias = "keypair_path")]
+    pub keypair: KeypairSource,
+    /// Marginfi account liquidating, discovered among the accounts of the signer in
+    /// the group when not set, and created when the signer has none
     #[serde(
-        deserialize_with = "from_pubkey_string",
-        serialize_with = "pubkey_to_str"
+        default = "GeneralConfig::default_liquidator_account",
+        deserialize_with = "from_option_pubkey_string",
+        serialize_with = "option_pubkey_to_str"
     )]
-    pub liquidator_account: Pubkey,
+    pub liquidator_account: Option<Pubkey>,
+    /// Position of the discovered account among the accounts of the signer,
+    /// sorted by address, when `liquidator_account` isn't set
+    #[serde(default = "GeneralConfig::default_liquidator_account_index")]
+    pub liquidator_account_index: usize,
     #[serde(default = "GeneralConfig::default_compute_unit_price_micro_lamports")]
     pub compute_unit_price_micro_lamports: Option<u64>,
+    /// Prices the liquidations at this percentile of the compute unit prices paid
+    /// in the recent slots to write the same accounts, instead of the static price
+    #[serde(default = "GeneralConfig::default_priority_fee_percentile")]
+    pub priority_fee_percentile: Option<u8>,
+    /// Bounds of the dynamic compute unit price, in micro lamports
+    #[serde(default = "GeneralConfig::default_min_priority_fee_micro_lamports")]
+    pub min_priority_fee_micro_lamports: u64,
+    #[serde(default = "GeneralConfig::default_max_priority_fee_micro_lamports")]
+    pub max_priority_fee_micro_lamports: u64,
+    /// Bounds enforced on every compute unit price, static or dynamic, in micro lamports
+    #[serde(default = "GeneralConfig::default_min_compute_unit_price")]
+    pub min_compute_unit_price: u64,
+    #[serde(default = "GeneralConfig::default_max_compute_unit_price")]
+    pub max_compute_unit_price: u64,
     #[serde(
         deserialize_with = "from_pubkey_string",
         serialize_with = "pubkey_to_str",
@@ -88,6 +424,180 @@ pub struct GeneralConfig {
         serialize_with = "vec_pubkey_to_str"
     )]
     pub address_lookup_tables: Vec<Pubkey>,
+    #[serde(default = "GeneralConfig::default_tip_strategy")]
+    pub tip_strategy: TipStrategy,
+    /// Where the lamports of the jito tips come from
+    #[serde(default = "GeneralConfig::default_tip_funding_strategy")]
+    pub tip_funding_strategy: TipFundingStrategy,
+    /// Maximum time to wait for a jito leader before dropping a bundle, in milliseconds
+    #[serde(default = "GeneralConfig::default_max_leader_wait_ms")]
+    pub max_leader_wait_ms: u64,
+    /// Slots before a jito leader from which the bundles are sent to it, higher
+    /// when the block engine is far enough for bundles to miss a tight window
+    #[serde(default = "GeneralConfig::default_jito_leader_slot_threshold")]
+    pub jito_leader_slot_threshold: u64,
+    /// Number of jito tip accounts the tip of a bundle is spread across, falls
+    /// back to a single transfer when the extra transfers don't fit in the transaction
+    #[serde(default = "GeneralConfig::default_jito_tip_split")]
+    pub jito_tip_split: usize,
+    /// Times a bundle is rebuilt with a fresh blockhash and sent again when it loses
+    /// an auction or is dropped, a bundle failing its simulation is never retried
+    #[serde(default = "GeneralConfig::default_jito_max_retries")]
+    pub jito_max_retries: u64,
+    /// Wait before a rejected bundle is sent again, in milliseconds
+    #[serde(default = "GeneralConfig::default_jito_retry_delay_ms")]
+    pub jito_retry_delay_ms: u64,
+    /// Raise of the jito tip on each retry of a bundle, in basis points
+    #[serde(default = "GeneralConfig::default_jito_retry_tip_bump_bps")]
+    pub jito_retry_tip_bump_bps: u16,
+    /// Resend the transactions through the rpc when a jito bundle doesn't land
+    #[serde(default = "GeneralConfig::default_fallback_to_rpc")]
+    pub fallback_to_rpc: bool,
+    /// Batches of transactions in flight at once, bundles or transactions sent through
+    /// the rpc awaiting their outcome. The next batches wait until one of them resolves
+    #[serde(default = "GeneralConfig::default_max_in_flight_bundles")]
+    pub max_in_flight_bundles: usize,
+    /// Simulate liquidation transactions before sending them
+    #[serde(default = "GeneralConfig::default_simulate_transactions")]
+    pub simulate_transactions: bool,
+    /// Compute unit limit of the liquidation transactions, estimated
+    /// from the number of observation accounts when not set
+    #[serde(default = "GeneralConfig::default_compute_unit_limit")]
+    pub compute_unit_limit: Option<u32>,
+    /// Sizes the compute unit limit of the liquidations on their simulated consumption,
+    /// cached per asset bank, liability bank and number of observation accounts.
+    /// Ignored when `compute_unit_limit` is set
+    #[serde(default = "GeneralConfig::default_tune_compute_unit_limit")]
+    pub tune_compute_unit_limit: bool,
+    /// Margin added to the simulated compute units of a liquidation, in basis points
+    #[serde(default = "GeneralConfig::default_compute_unit_margin_bps")]
+    pub compute_unit_margin_bps: u16,
+    /// Logs the transactions instead of sending them
+    #[serde(default = "GeneralConfig::default_dry_run")]
+    pub dry_run: bool,
+    /// Logs the cost estimates of the dry run as JSON, one per line,
+    /// instead of the human readable lines
+    #[serde(default = "GeneralConfig::default_dry_run_json")]
+    pub dry_run_json: bool,
+    /// Port of the prometheus metrics endpoint, disabled when not set
+    #[serde(default = "GeneralConfig::default_metrics_port")]
+    pub metrics_port: Option<u16>,
+    /// Failed attempts to connect to a geyser endpoint before giving up on it, only until
+    /// it first connects. A rejected token or filter is never retried
+    #[serde(default = "GeneralConfig::default_geyser_max_connect_attempts")]
+    pub geyser_max_connect_attempts: u32,
+    /// Accounts subscribed to per geyser subscription, the tracked accounts are split
+    /// across several subscriptions to stay under the cap of the provider
+    #[serde(default = "GeneralConfig::default_geyser_max_accounts_per_subscription")]
+    pub geyser_max_accounts_per_subscription: usize,
+    /// A geyser subscription silent for this long, slots included, is torn down
+    /// and subscribed again, in seconds
+    #[serde(default = "GeneralConfig::default_geyser_idle_timeout_secs")]
+    pub geyser_idle_timeout_secs: u64,
+    /// Commitment used by the rpc clients and awaited when confirming transactions
+    ///
+    /// `processed` has the lowest latency but its state can be rolled back with a fork,
+    /// `finalized` is the safest but lags ~13 seconds behind,
+    /// `confirmed` is voted by a supermajority of the cluster and rarely rolled back
+    #[serde(default = "GeneralConfig::default_commitment")]
+    pub commitment: CommitmentConfig,
+    /// Hermes api serving the price updates of the pyth pull oracles
+    #[serde(default = "GeneralConfig::default_hermes_url")]
+    pub hermes_url: String,
+    /// Where the liquidator reads the oracle prices from
+    #[serde(default = "GeneralConfig::default_price_source")]
+    pub price_source: PriceSource,
+    /// Hermes websocket streaming the pyth prices, with the `PythHermesWs` price source
+    #[serde(default = "GeneralConfig::default_hermes_ws_url")]
+    pub hermes_ws_url: String,
+    /// What happens when a service of the liquidator stops unexpectedly
+    #[serde(default = "GeneralConfig::default_restart_policy")]
+    pub restart_policy: RestartPolicy,
+    /// SOL balance of the signer under which an alert is sent and the jito tips are
+    /// paused, only the most profitable liquidations are sent through the rpc then
+    #[serde(default = "GeneralConfig::default_min_sol_balance")]
+    pub min_sol_balance: f64,
+    /// Interval between two checks of the signer SOL balance, in seconds
+    #[serde(default = "GeneralConfig::default_sol_balance_check_interval_secs")]
+    pub sol_balance_check_interval_secs: u64,
+    /// Signers liquidating in parallel with the main one, each with its own marginfi
+    /// account, transaction manager and rebalancer. The opportunities go to the free ones
+    #[serde(default = "GeneralConfig::default_additional_signers")]
+    pub additional_signers: Vec<SignerCfg>,
+    /// Durable nonce account, with the signer as authority, the single transaction bundles
+    /// are built on so they don't expire while waiting for a jito leader. They use a recent
+    /// blockhash when not set
+    #[serde(
+        default = "GeneralConfig::default_nonce_account",
+        deserialize_with = "from_option_pubkey_string",
+        serialize_with = "option_pubkey_to_str"
+    )]
+    pub nonce_account: Option<Pubkey>,
+    /// File where the tracked accounts are snapshotted to speed up restarts, disabled when not set
+    #[serde(default = "GeneralConfig::default_state_path")]
+    pub state_path: Option<PathBuf>,
+    /// Snapshots older than this are ignored on startup, in seconds
+    #[serde(default = "GeneralConfig::default_max_state_age_secs")]
+    pub max_state_age_secs: u64,
+    /// Discord webhook receiving the liquidation alerts
+    #[serde(default = "GeneralConfig::default_discord_webhook_url")]
+    pub discord_webhook_url: Option<String>,
+    /// Telegram bot sending the liquidation alerts to `telegram_chat_id`
+    #[serde(default = "GeneralConfig::default_telegram_bot_token")]
+    pub telegram_bot_token: Option<String>,
+    #[serde(default = "GeneralConfig::default_telegram_chat_id")]
+    pub telegram_chat_id: Option<String>,
+    /// Minimum interval between two alerts, in seconds, the alerts
+    /// raised in between are batched into a single message
+    #[serde(default = "GeneralConfig::default_notification_interval_secs")]
+    pub notification_interval_secs: u64,
+    /// Times each transaction sent through the rpc is resent while awaiting its confirmation
+    #[serde(default = "GeneralConfig::default_send_retries")]
+    pub send_retries: u64,
+    /// Time after which an unconfirmed transaction sent through the rpc is given up, in milliseconds
+    #[serde(default = "GeneralConfig::default_send_timeout_ms")]
+    pub send_timeout_ms: u64,
+    /// Skips the preflight simulation of the rpc node when sending a transaction
+    #[serde(default = "GeneralConfig::default_skip_preflight")]
+    pub skip_preflight: bool,
+    /// Times a transaction is re-signed with a fresh blockhash when it expires
+    #[serde(default = "GeneralConfig::default_max_retries")]
+    pub max_retries: u64,
+    /// Wait between two re-signing attempts, in milliseconds
+    #[serde(default = "GeneralConfig::default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// Interval between two health checks of the rpc endpoints, in seconds
+    #[serde(default = "GeneralConfig::default_rpc_health_check_interval_secs")]
+    pub rpc_health_check_interval_secs: u64,
+    /// Slots an rpc endpoint can lag behind the most advanced one before it is switched
+    #[serde(default = "GeneralConfig::default_max_rpc_slot_lag")]
+    pub max_rpc_slot_lag: u64,
+    /// Rpc requests sent per second by all the components together, the blockhashes,
+    /// fees and confirmations of the transactions in flight going first. Unlimited when not set
+    #[serde(default = "GeneralConfig::default_rpc_requests_per_second")]
+    pub rpc_requests_per_second: Option<u32>,
+    /// Rpc requests that can be sent at once before `rpc_requests_per_second` applies
+    #[serde(default = "GeneralConfig::default_rpc_burst")]
+    pub rpc_burst: u32,
+    /// JSONL file where the submitted transactions and their outcome are appended,
+    /// disabled when not set
+    #[serde(default = "GeneralConfig::default_tx_log_path")]
+    pub tx_log_path: Option<PathBuf>,
+    /// Interval between two logs of the profit and loss summary, in seconds
+    #[serde(default = "GeneralConfig::default_pnl_summary_interval_secs")]
+    pub pnl_summary_interval_secs: u64,
+    /// `RUST_LOG` style filter, e.g. `info,eva01::geyser=warn`, taking over from
+    /// the environment. Reloaded from the config file on SIGHUP
+    #[serde(default = "GeneralConfig::default_log_filter")]
+    pub log_filter: Option<String>,
+    /// Unix socket accepting the `pause`, `resume` and `status` admin commands,
+    /// disabled when not set
+    #[serde(default = "GeneralConfig::default_admin_socket_path")]
+    pub admin_socket_path: Option<PathBuf>,
+    /// Lamports that can be spent on fees and jito tips over a rolling hour,
+    /// the submissions are paused once they are spent. Uncapped when not set
+    #[serde(default = "GeneralConfig::default_max_spend_per_hour")]
+    pub max_spend_per_hour: Option<u64>,
 }
 
 impl std::fmt::Display for GeneralConfig {
@@ -99,7 +609,7 @@ impl std::fmt::Display for GeneralConfig {
                  - Yellowstone Endpoint: {}\n\
                  - Yellowstone X Token: {}\n\
                  - Signer Pubkey: {}\n\
-                 - Keypair Path: {:?}\n\
+                 - Keypair: {:?}\n\
                  - Liquidator Account: {}\n\
                  - Compute Unit Price Micro Lamports: {}\n\
                  - Marginfi Program ID: {}\n\
@@ -109,8 +619,13 @@ impl std::fmt::Display for GeneralConfig {
             self.yellowstone_endpoint,
             self.yellowstone_x_token.as_deref().unwrap_or("None"),
             self.signer_pubkey,
-            self.keypair_path,
-            self.liquidator_account,
+            self.keypair,
+            self.liquidator_account
+                .map(|account| account.to_string())
+                .unwrap_or(format!(
+                    "Discovered (index {})",
+                    self.liquidator_account_index
+                )),
             self.compute_unit_price_micro_lamports.unwrap_or_default(),
             self.marginfi_program_id,
             self.marginfi_group_address,
@@ -127,11 +642,141 @@ impl std::fmt::Display for GeneralConfig {
 }
 
 impl GeneralConfig {
-    pub fn get_geyser_service_config(&self) -> GeyserServiceConfig {
-        GeyserServiceConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        validate_url("rpc_url", &self.rpc_url)?;
+        for url in &self.rpc_standby_urls {
+            validate_url("rpc_standby_urls", url)?;
+        }
+        if self.rpc_health_check_interval_secs == 0 {
+            bail!("`rpc_health_check_interval_secs` must be greater than 0");
+        }
+        if self.rpc_requests_per_second == Some(0) {
+            bail!("`rpc_requests_per_second` must be greater than 0");
+        }
+        if self.rpc_burst == 0 {
+            bail!("`rpc_burst` must be greater than 0");
+        }
+        if self.geyser_max_connect_attempts == 0 {
+            bail!("`geyser_max_connect_attempts` must be greater than 0");
+        }
+        if self.geyser_max_accounts_per_subscription == 0 {
+            bail!("`geyser_max_accounts_per_subscription` must be greater than 0");
+        }
+        if self.geyser_idle_timeout_secs == 0 {
+            bail!("`geyser_idle_timeout_secs` must be greater than 0");
+        }
+        if self.max_spend_per_hour == Some(0) {
+            bail!("`max_spend_per_hour` must be greater than 0");
+        }
+        if self.jito_tip_split == 0 {
+            bail!("`jito_tip_split` must be greater than 0");
+        }
+        if self.max_in_flight_bundles == 0 {
+            bail!("`max_in_flight_bundles` must be greater than 0");
+        }
+        validate_bps("jito_retry_tip_bump_bps", self.jito_retry_tip_bump_bps)?;
+        if self.pnl_summary_interval_secs == 0 {
+            bail!("`pnl_summary_interval_secs` must be greater than 0");
+        }
+        if !self.min_sol_balance.is_finite() || self.min_sol_balance < 0.0 {
+            bail!(
+                "`min_sol_balance` is {}, it can't be negative",
+                self.min_sol_balance
+            );
+        }
+        if self.sol_balance_check_interval_secs == 0 {
+            bail!("`sol_balance_check_interval_secs` must be greater than 0");
+        }
+        validate_url("yellowstone_endpoint", &self.yellowstone_endpoint)?;
+        for config in &self.yellowstone_standby_endpoints {
+            validate_url("yellowstone_standby_endpoints", &config.endpoint)?;
+        }
+        validate_url("block_engine_url", &self.block_engine_url)?;
+        validate_url("hermes_url", &self.hermes_url)?;
+        validate_url("hermes_ws_url", &self.hermes_ws_url)?;
+        if let Some(webhook_url) = &self.discord_webhook_url {
+            validate_url("discord_webhook_url", webhook_url)?;
+        }
+
+        let keypair = self.keypair.load()?;
+        if keypair.pubkey() != self.signer_pubkey {
+            bail!(
+                "`keypair` {:?} holds the keypair of {}, not of the `signer_pubkey` {}",
+                self.keypair,
+                keypair.pubkey(),
+                self.signer_pubkey
+            );
+        }
+        for signer in &self.additional_signers {
+            signer.validate("additional_signers")?;
+        }
+        if let Some(nonce_account) = &self.nonce_account {
+            validate_pubkey("nonce_account", nonce_account)?;
+        }
+        if let Some(auth_keypair_path) = &self.auth_keypair_path {
+            validate_keypair_path("auth_keypair_path", auth_keypair_path)?;
+        }
+
+        validate_pubkey("marginfi_program_id", &self.marginfi_program_id)?;
+        validate_pubkey("marginfi_group_address", &self.marginfi_group_address)?;
+        if let Some(liquidator_account) = &self.liquidator_account {
+            validate_pubkey("liquidator_account", liquidator_account)?;
+        }
+
+        if let Some(compute_unit_limit) = self.compute_unit_limit {
+            if compute_unit_limit == 0 || compute_unit_limit > MAX_COMPUTE_UNIT_LIMIT {
+                bail!(
+                    "`compute_unit_limit` is {}, it must be within 1 and {}",
+                    compute_unit_limit,
+                    MAX_COMPUTE_UNIT_LIMIT
+                );
+            }
+        }
+        validate_bps("compute_unit_margin_bps", self.compute_unit_margin_bps)?;
+        if let Some(percentile) = self.priority_fee_percentile {
+            if percentile > 100 {
+                bail!(
+                    "`priority_fee_percentile` is {}, it can't exceed 100",
+                    percentile
+                );
+            }
+        }
+        if self.min_priority_fee_micro_lamports > self.max_priority_fee_micro_lamports {
+            bail!(
+                "`min_priority_fee_micro_lamports` ({}) exceeds `max_priority_fee_micro_lamports` ({})",
+                self.min_priority_fee_micro_lamports,
+                self.max_priority_fee_micro_lamports
+            );
+        }
+        if self.min_compute_unit_price > self.max_compute_unit_price {
+            bail!(
+                "`min_compute_unit_price` ({}) exceeds `max_compute_unit_price` ({})",
+                self.min_compute_unit_price,
+                self.max_compute_unit_price
+            );
+        }
+        if self.send_timeout_ms == 0 {
+            bail!("`send_timeout_ms` must be greater than 0");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the rpc endpoints, the primary one first
+    pub fn get_rpc_urls(&self) -> Vec<String> {
+        let mut urls = vec![self.rpc_url.clone()];
+        urls.extend(self.rpc_standby_urls.iter().cloned());
+        urls
+    }
+
+    /// Returns the geyser endpoints, the primary one first
+    pub fn get_geyser_service_configs(&self) -> Vec<GeyserServiceConfig> {
+        let mut configs = vec![GeyserServiceConfig {
             endpoint: self.yellowstone_endpoint.clone(),
             x_token: self.yellowstone_x_token.clone(),
-        }
+        }];
+        configs.extend(self.yellowstone_standby_endpoints.iter().cloned());
+        configs
     }
 
     pub fn default_marginfi_program_id() -> Pubkey {
@@ -154,6 +799,14 @@ impl GeneralConfig {
         String::from("https://ny.mainnet.block-engine.jito.wtf")
     }
 
+    pub fn default_auth_keypair_path() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn default_block_engine_regions() -> Vec<JitoRegion> {
+        vec![]
+    }
+
     pub fn default_address_lookup_tables() -> Vec<Pubkey> {
         vec![
             pubkey!("HGmknUTUmeovMc9ryERNWG6UFZDFDVr9xrum3ZhyL4fC"),
@@ -161,37 +814,534 @@ impl GeneralConfig {
         ]
     }
 
+    pub fn default_tip_strategy() -> TipStrategy {
+        TipStrategy::Fixed(10_000)
+    }
+
+    pub fn default_tip_funding_strategy() -> TipFundingStrategy {
+        TipFundingStrategy::NativeSol
+    }
+
+    pub fn default_max_leader_wait_ms() -> u64 {
+        30_000
+    }
+
+    pub fn default_jito_leader_slot_threshold() -> u64 {
+        2
+    }
+
+    pub fn default_jito_max_retries() -> u64 {
+        2
+    }
+
+    pub fn default_jito_retry_delay_ms() -> u64 {
+        200
+    }
+
+    pub fn default_jito_retry_tip_bump_bps() -> u16 {
+        0
+    }
+
+    pub fn default_jito_tip_split() -> usize {
+        1
+    }
+
+    pub fn default_fallback_to_rpc() -> bool {
+        false
+    }
+
+    pub fn default_max_in_flight_bundles() -> usize {
+        16
+    }
+
+    pub fn default_simulate_transactions() -> bool {
+        false
+    }
+
+    pub fn default_compute_unit_limit() -> Option<u32> {
+        None
+    }
+
+    pub fn default_tune_compute_unit_limit() -> bool {
+        false
+    }
+
+    pub fn default_compute_unit_margin_bps() -> u16 {
+        1_000
+    }
+
+    pub fn default_dry_run() -> bool {
+        false
+    }
+
+    pub fn default_dry_run_json() -> bool {
+        false
+    }
+
+    pub fn default_metrics_port() -> Option<u16> {
+        None
+    }
+
+    pub fn default_geyser_max_connect_attempts() -> u32 {
+        10
+    }
+
+    pub fn default_geyser_max_accounts_per_subscription() -> usize {
+        5_000
+    }
+
+    pub fn default_geyser_idle_timeout_secs() -> u64 {
+        30
+    }
+
+    pub fn default_commitment() -> CommitmentConfig {
+        CommitmentConfig::confirmed()
+    }
+
+    pub fn default_rpc_standby_urls() -> Vec<String> {
+        vec![]
+    }
+
+    pub fn default_rpc_health_check_interval_secs() -> u64 {
+        5
+    }
+
+    pub fn default_max_rpc_slot_lag() -> u64 {
+        30
+    }
+
+    pub fn default_rpc_requests_per_second() -> Option<u32> {
+        None
+    }
+
+    pub fn default_rpc_burst() -> u32 {
+        10
+    }
+
+    pub fn default_tx_log_path() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn default_pnl_summary_interval_secs() -> u64 {
+        3600
+    }
+
+    pub fn default_log_filter() -> Option<String> {
+        None
+    }
+
+    pub fn default_admin_socket_path() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn default_max_spend_per_hour() -> Option<u64> {
+        None
+    }
+
+    pub fn default_yellowstone_standby_endpoints() -> Vec<GeyserServiceConfig> {
+        vec![]
+    }
+
+    pub fn default_hermes_url() -> String {
+        String::from("https://hermes.pyth.network")
+    }
+
+    pub fn default_price_source() -> PriceSource {
+        PriceSource::GeyserOracleAccounts
+    }
+
+    pub fn default_hermes_ws_url() -> String {
+        String::from("wss://hermes.pyth.network/ws")
+    }
+
+    pub fn default_restart_policy() -> RestartPolicy {
+        RestartPolicy::Shutdown
+    }
+
+    pub fn default_min_sol_balance() -> f64 {
+        0.05
+    }
+
+    pub fn default_sol_balance_check_interval_secs() -> u64 {
+        30
+    }
+
+    pub fn default_additional_signers() -> Vec<SignerCfg> {
+        vec![]
+    }
+
+    pub fn default_nonce_account() -> Option<Pubkey> {
+        None
+    }
+
+    pub fn default_liquidator_account() -> Option<Pubkey> {
+        None
+    }
+
+    pub fn default_liquidator_account_index() -> usize {
+        0
+    }
+
+    /// Config of each signer, the main one first. Only the main one
+    /// saves the state, the others share the tracked accounts it loads
+    pub fn get_signer_configs(&self) -> Vec<GeneralConfig> {
+        std::iter::once(self.clone())
+            .chain(self.additional_signers.iter().map(|signer| GeneralConfig {
+                keypair: signer.keypair.clone(),
+                signer_pubkey: signer.signer_pubkey,
+                liquidator_account: signer.liquidator_account,
+                liquidator_account_index: signer.liquidator_account_index,
+                nonce_account: signer.nonce_account,
+                state_path: None,
+                ..self.clone()
+            }))
+            .collect()
+    }
+
+    pub fn default_state_path() -> Option<PathBuf> {
+        None
+    }
+
+    pub fn default_max_state_age_secs() -> u64 {
+        3600
+    }
+
+    pub fn default_discord_webhook_url() -> Option<String> {
+        None
+    }
+
+    pub fn default_telegram_bot_token() -> Option<String> {
+        None
+    }
+
+    pub fn default_telegram_chat_id() -> Option<String> {
+        None
+    }
+
+    pub fn default_notification_interval_secs() -> u64 {
+        10
+    }
+
+    pub fn default_send_retries() -> u64 {
+        SenderCfg::default_spam_times()
+    }
+
+    pub fn default_send_timeout_ms() -> u64 {
+        SenderCfg::default_timeout().as_millis() as u64
+    }
+
+    pub fn default_skip_preflight() -> bool {
+        SenderCfg::default_skip_preflight()
+    }
+
+    pub fn default_max_retries() -> u64 {
+        SenderCfg::default_max_resign_attempts()
+    }
+
+    pub fn default_retry_backoff_ms() -> u64 {
+        SenderCfg::default_resign_backoff().as_millis() as u64
+    }
+
+    pub fn get_sender_config(&self) -> SenderCfg {
+        SenderCfg::DEFAULT
+            .with_spam_times(self.send_retries)
+            .with_timeout(Duration::from_millis(self.send_timeout_ms))
+            .with_skip_preflight(self.skip_preflight)
+            .with_max_resign_attempts(self.max_retries)
+            .with_resign_backoff(Duration::from_millis(self.retry_backoff_ms))
+            .with_commitment(self.commitment)
+    }
+
+    pub fn default_priority_fee_percentile() -> Option<u8> {
+        None
+    }
+
+    pub fn default_min_priority_fee_micro_lamports() -> u64 {
+        0
+    }
+
+    pub fn default_max_priority_fee_micro_lamports() -> u64 {
+        1_000_000
+    }
+
+    pub fn default_min_compute_unit_price() -> u64 {
+        0
+    }
+
+    pub fn default_max_compute_unit_price() -> u64 {
+        10_000_000
+    }
+
     pub fn get_tx_config(&self) -> TxConfig {
+        let priority_fee = match self.priority_fee_percentile {
+            Some(percentile) => Some(PriorityFee::Dynamic {
+                percentile,
+                min: self.min_priority_fee_micro_lamports,
+                max: self.max_priority_fee_micro_lamports,
+            }),
+            None => self
+                .compute_unit_price_micro_lamports
+                .map(PriorityFee::Static),
+        };
+
         TxConfig {
-            compute_unit_price_micro_lamports: self.compute_unit_price_micro_lamports,
+            priority_fee,
+            simulate: self.simulate_transactions,
+            compute_unit_limit: self.compute_unit_limit,
+            tune_compute_unit_limit: self.tune_compute_unit_limit,
+            compute_unit_margin_bps: self.compute_unit_margin_bps,
+            min_compute_unit_price: self.min_compute_unit_price,
+            max_compute_unit_price: self.max_compute_unit_price,
         }
     }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct LiquidatorCfg {
-    /// Minimun profit on a liquidation to be considered, denominated in USD
+    /// Minimun net profit on a liquidation to be considered, denominated in USD,
+    /// after the swap slippage, the jito tip and the transaction fees
     ///
     /// Example:
     /// 0.01 is $0.01
     ///
     /// Default: 0.1
-    #[serde(default = "LiquidatorCfg::default_min_profit")]
-    pub min_profit: f64,
+    #[serde(
+        default = "LiquidatorCfg::default_min_profit_usd",
+        alias = "min_profit"
+    )]
+    pub min_profit_usd: f64,
     /// Maximun liquidation value in USD
     pub max_liquidation_value: Option<f64>,
     #[serde(default = "LiquidatorCfg::default_isolated_banks")]
     pub isolated_banks: bool,
+    /// Expected slippage when swapping the seized asset, in basis points
+    ///
+    /// Default: 50
+    #[serde(default = "LiquidatorCfg::default_swap_slippage_bps")]
+    pub swap_slippage_bps: u16,
+    /// Accounts are only evaluated for liquidation once their maintenance
+    /// health factor drops below this threshold
+    ///
+    /// Accounts are liquidatable below 1.0, a higher threshold
+    /// keeps accounts close to liquidation warm
+    ///
+    /// Default: 1.0
+    #[serde(default = "LiquidatorCfg::default_health_threshold")]
+    pub health_threshold: f64,
+    /// Banks that can be liquidated against, all of them when not set
+    #[serde(
+        deserialize_with = "from_option_vec_pubkey_string",
+        serialize_with = "vec_pubkey_to_option_vec_str",
+        default = "LiquidatorCfg::default_bank_allowlist"
+    )]
+    pub bank_allowlist: Option<Vec<Pubkey>>,
+    /// Banks that are never liquidated against, e.g. illiquid or depegged collateral
+    #[serde(
+        deserialize_with = "from_vec_str_to_pubkey",
+        serialize_with = "vec_pubkey_to_str",
+        default = "LiquidatorCfg::default_bank_blacklist"
+    )]
+    pub bank_blacklist: Vec<Pubkey>,
+    /// Liquidations are skipped when the oracle of the asset or liability bank
+    /// hasn't been published in the last `max_oracle_age_slots` slots
+    ///
+    /// Default: 150 (~60 seconds)
+    #[serde(default = "LiquidatorCfg::default_max_oracle_age_slots")]
+    pub max_oracle_age_slots: u64,
+    /// Maximum value in USD held of an asset, by mint, counting its deposits in the
+    /// liquidator account and its token account. Liquidations seizing more of the
+    /// asset are downsized, or skipped once the cap is reached
+    #[serde(
+        deserialize_with = "from_str_map_to_pubkey_map",
+        serialize_with = "pubkey_map_to_str_map",
+        default = "LiquidatorCfg::default_max_asset_exposure"
+    )]
+    pub max_asset_exposure: HashMap<Pubkey, f64>,
+    /// Order in which the liquidatable accounts are liquidated
+    ///
+    /// Default: HighestProfit
+    #[serde(default = "LiquidatorCfg::default_ranking_strategy")]
+    pub ranking_strategy: RankingStrategy,
+    /// Consecutive failures of the liquidation of an account, against the same
+    /// asset and liability banks, after which it is put on cooldown
+    ///
+    /// Default: 3
+    #[serde(default = "LiquidatorCfg::default_max_consecutive_failures")]
+    pub max_consecutive_failures: u32,
+    /// Time a repeatedly failing liquidation isn't attempted, in seconds
+    ///
+    /// Default: 300
+    #[serde(default = "LiquidatorCfg::default_failure_cooldown_secs")]
+    pub failure_cooldown_secs: u64,
+    /// Time spent building the liquidation of a single account (price updates,
+    /// observation accounts, simulation) after which it is abandoned for the next one,
+    /// in milliseconds
+    ///
+    /// Default: 2000
+    #[serde(default = "LiquidatorCfg::default_max_evaluation_ms")]
+    pub max_evaluation_ms: u64,
+    /// Reads the liquidatable account again at the confirmed commitment before
+    /// liquidating it, so an unhealthy state seen at processed which doesn't
+    /// make it to confirmed isn't paid for, at the cost of a rpc round trip
+    ///
+    /// Default: false
+    #[serde(default = "LiquidatorCfg::default_confirm_before_liquidate")]
+    pub confirm_before_liquidate: bool,
+    /// Evaluates the tracked accounts again at this interval even when no geyser
+    /// update arrived, so an account made liquidatable by a streamed price move
+    /// alone is caught. Disabled when not set
+    ///
+    /// Default: None
+    #[serde(default = "LiquidatorCfg::default_scan_interval_ms")]
+    pub scan_interval_ms: Option<u64>,
+    /// Values the seized collateral with a Jupiter quote for selling it into the
+    /// liability token rather than at its oracle price, so `min_profit_usd` is checked
+    /// against what the collateral can actually be sold for. Uses the Jupiter
+    /// settings of the rebalancer
+    ///
+    /// Default: false
+    #[serde(default = "LiquidatorCfg::default_value_collateral_via_jupiter")]
+    pub value_collateral_via_jupiter: bool,
+    /// Marginfi groups liquidated along with the one of the general config,
+    /// each by its own signer and liquidator account
+    #[serde(default = "LiquidatorCfg::default_additional_groups")]
+    pub additional_groups: Vec<GroupCfg>,
 }
 
 impl LiquidatorCfg {
-    pub fn default_min_profit() -> f64 {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.scan_interval_ms == Some(0) {
+            bail!("`scan_interval_ms` must be greater than 0");
+        }
+        if !self.min_profit_usd.is_finite() || self.min_profit_usd < 0.0 {
+            bail!(
+                "`min_profit_usd` is {}, it can't be negative",
+                self.min_profit_usd
+            );
+        }
+        if let Some(max_liquidation_value) = self.max_liquidation_value {
+            if !max_liquidation_value.is_finite() || max_liquidation_value <= 0.0 {
+                bail!(
+                    "`max_liquidation_value` is {}, it must be positive",
+                    max_liquidation_value
+                );
+            }
+        }
+        validate_bps("swap_slippage_bps", self.swap_slippage_bps)?;
+        if !self.health_threshold.is_finite() || self.health_threshold <= 0.0 {
+            bail!(
+                "`health_threshold` is {}, it must be positive",
+                self.health_threshold
+            );
+        }
+        if matches!(&self.bank_allowlist, Some(allowlist) if allowlist.is_empty()) {
+            bail!("`bank_allowlist` is empty, no bank can be liquidated");
+        }
+        if self.max_consecutive_failures == 0 {
+            bail!("`max_consecutive_failures` must be greater than 0");
+        }
+        if self.max_evaluation_ms == 0 {
+            bail!("`max_evaluation_ms` must be greater than 0");
+        }
+        let mut groups = HashSet::new();
+        for group in &self.additional_groups {
+            validate_pubkey(
+                "additional_groups.marginfi_group_address",
+                &group.marginfi_group_address,
+            )?;
+            if !groups.insert(group.marginfi_group_address) {
+                bail!(
+                    "The marginfi group {} is listed several times in `additional_groups`",
+                    group.marginfi_group_address
+                );
+            }
+            group.signer.validate("additional_groups")?;
+        }
+        for (mint, max_exposure) in &self.max_asset_exposure {
+            if !max_exposure.is_finite() || *max_exposure < 0.0 {
+                bail!(
+                    "`max_asset_exposure` of {} is {}, it can't be negative",
+                    mint,
+                    max_exposure
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn default_min_profit_usd() -> f64 {
         0.1
     }
 
     pub fn default_isolated_banks() -> bool {
         false
     }
+
+    pub fn default_swap_slippage_bps() -> u16 {
+        50
+    }
+
+    pub fn default_health_threshold() -> f64 {
+        1.0
+    }
+
+    pub fn default_bank_allowlist() -> Option<Vec<Pubkey>> {
+        None
+    }
+
+    pub fn default_bank_blacklist() -> Vec<Pubkey> {
+        vec![]
+    }
+
+    pub fn default_max_oracle_age_slots() -> u64 {
+        150
+    }
+
+    pub fn default_max_asset_exposure() -> HashMap<Pubkey, f64> {
+        HashMap::new()
+    }
+
+    pub fn default_ranking_strategy() -> RankingStrategy {
+        RankingStrategy::HighestProfit
+    }
+
+    pub fn default_max_consecutive_failures() -> u32 {
+        3
+    }
+
+    pub fn default_failure_cooldown_secs() -> u64 {
+        300
+    }
+
+    pub fn default_max_evaluation_ms() -> u64 {
+        2000
+    }
+
+    pub fn default_confirm_before_liquidate() -> bool {
+        false
+    }
+
+    pub fn default_scan_interval_ms() -> Option<u64> {
+        None
+    }
+
+    pub fn default_value_collateral_via_jupiter() -> bool {
+        false
+    }
+
+    pub fn default_additional_groups() -> Vec<GroupCfg> {
+        vec![]
+    }
+
+    /// Whether the bank can be used as the asset or liability of a liquidation
+    pub fn is_bank_allowed(&self, bank_pk: &Pubkey) -> bool {
+        !self.bank_blacklist.contains(bank_pk)
+            && self
+                .bank_allowlist
+                .as_ref()
+                .map_or(true, |allowlist| allowlist.contains(bank_pk))
+    }
 }
 
 impl std::fmt::Display for LiquidatorCfg {
@@ -200,9 +1350,13 @@ impl std::fmt::Display for LiquidatorCfg {
             f,
             "Liquidator Config: \n\
                 - Minimun profit: {}$\n\
-                - Max Liquidation Value: {}$\n",
-            self.min_profit,
-            self.max_liquidation_value.unwrap_or_default()
+                - Max Liquidation Value: {}$\n\
+                - Health threshold: {}\n\
+                - Ranking strategy: {:?}\n",
+            self.min_profit_usd,
+            self.max_liquidation_value.unwrap_or_default(),
+            self.health_threshold,
+            self.ranking_strategy
         )
     }
 }
@@ -233,9 +1387,122 @@ pub struct RebalancerCfg {
     pub compute_unit_price_micro_lamports: Option<u64>,
     #[serde(default = "RebalancerCfg::default_slippage_bps")]
     pub slippage_bps: u16,
+    /// Swaps whose quote exceeds this price impact are split in smaller legs
+    #[serde(default = "RebalancerCfg::default_max_price_impact_bps")]
+    pub max_price_impact_bps: u16,
+    /// How long a Jupiter quote is reused for the same swap, in milliseconds
+    #[serde(default = "RebalancerCfg::default_quote_cache_ttl_ms")]
+    pub quote_cache_ttl_ms: u64,
+    #[serde(
+        default = "RebalancerCfg::default_min_swap_usd",
+        deserialize_with = "fixed_from_float",
+        serialize_with = "fixed_to_float"
+    )]
+    pub min_swap_usd: I80F48,
+    #[serde(
+        default = "RebalancerCfg::default_min_rebalance_usd",
+        deserialize_with = "fixed_from_float",
+        serialize_with = "fixed_to_float"
+    )]
+    pub min_rebalance_usd: I80F48,
+    #[serde(
+        default = "RebalancerCfg::default_dust_threshold_usd",
+        deserialize_with = "fixed_from_float",
+        serialize_with = "fixed_to_float"
+    )]
+    pub dust_threshold_usd: I80F48,
+    /// Periodically claims the emissions accrued by the liquidator positions
+    #[serde(default = "RebalancerCfg::default_claim_emissions")]
+    pub claim_emissions: bool,
+    /// Interval between two emissions claims, in seconds
+    #[serde(default = "RebalancerCfg::default_claim_emissions_interval_secs")]
+    pub claim_emissions_interval_secs: u64,
+    /// Emissions worth less than this are left unclaimed, so a claim pays for its fees and tip
+    #[serde(
+        default = "RebalancerCfg::default_min_claim_emissions_usd",
+        deserialize_with = "fixed_from_float",
+        serialize_with = "fixed_to_float"
+    )]
+    pub min_claim_emissions_usd: I80F48,
+    /// Margin kept above a maintenance health factor of 1 when withdrawing,
+    /// the withdrawals are capped so the liquidator account stays above it
+    #[serde(default = "RebalancerCfg::default_min_health_buffer")]
+    pub min_health_buffer: f64,
+    /// Unwraps the SOL deposits withdrawn by the rebalancer to native SOL,
+    /// keeping the signer funded for the Jito tips and the priority fees
+    #[serde(default = "RebalancerCfg::default_auto_unwrap_sol")]
+    pub auto_unwrap_sol: bool,
+    /// Amounts deposited from the signer token accounts into the liquidator
+    /// account on startup, as working capital to repay liabilities
+    #[serde(default = "RebalancerCfg::default_seed_deposits")]
+    pub seed_deposits: Vec<SeedDepositCfg>,
+    /// What is done with the collateral seized and the liability taken over
+    /// by the liquidations
+    ///
+    /// Default: WithdrawAndSell
+    #[serde(default = "RebalancerCfg::default_post_liquidation_strategy")]
+    pub post_liquidation_strategy: PostLiquidationStrategy,
+}
+
+/// Amount of a mint deposited into the liquidator account on startup
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SeedDepositCfg {
+    #[serde(
+        deserialize_with = "from_pubkey_string",
+        serialize_with = "pubkey_to_str"
+    )]
+    pub mint: Pubkey,
+    /// Amount in native units of the mint
+    pub amount: u64,
 }
 
 impl RebalancerCfg {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        validate_url("jup_swap_api_url", &self.jup_swap_api_url)?;
+        validate_bps("slippage_bps", self.slippage_bps)?;
+        validate_bps("max_price_impact_bps", self.max_price_impact_bps)?;
+        if self.max_price_impact_bps == 0 {
+            bail!("`max_price_impact_bps` must be greater than 0");
+        }
+        for (field, value) in [
+            (
+                "token_account_dust_threshold",
+                self.token_account_dust_threshold,
+            ),
+            ("min_swap_usd", self.min_swap_usd),
+            ("min_rebalance_usd", self.min_rebalance_usd),
+            ("dust_threshold_usd", self.dust_threshold_usd),
+            ("min_claim_emissions_usd", self.min_claim_emissions_usd),
+        ] {
+            if value.is_negative() {
+                bail!("`{}` is {}, it can't be negative", field, value);
+            }
+        }
+        for seed_deposit in &self.seed_deposits {
+            if seed_deposit.amount == 0 {
+                bail!(
+                    "`seed_deposits` amount of mint {} must be greater than 0",
+                    seed_deposit.mint
+                );
+            }
+            // The deposits of the other mints are withdrawn and sold by the rebalancer
+            if !self.preferred_mints.contains(&seed_deposit.mint) {
+                bail!(
+                    "`seed_deposits` mint {} is not one of the `preferred_mints`",
+                    seed_deposit.mint
+                );
+            }
+        }
+        if !self.min_health_buffer.is_finite() || self.min_health_buffer < 0.0 {
+            bail!(
+                "`min_health_buffer` is {}, it can't be negative",
+                self.min_health_buffer
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn default_token_account_dust_threshold() -> I80F48 {
         I80F48!(0.01)
     }
@@ -256,9 +1523,57 @@ impl RebalancerCfg {
         250
     }
 
+    pub fn default_max_price_impact_bps() -> u16 {
+        100
+    }
+
+    pub fn default_quote_cache_ttl_ms() -> u64 {
+        2000
+    }
+
     pub fn default_compute_unit_price_micro_lamports() -> Option<u64> {
         Some(10_000)
     }
+
+    pub fn default_min_swap_usd() -> I80F48 {
+        I80F48!(1)
+    }
+
+    pub fn default_min_rebalance_usd() -> I80F48 {
+        I80F48!(1)
+    }
+
+    pub fn default_dust_threshold_usd() -> I80F48 {
+        I80F48!(0.01)
+    }
+
+    pub fn default_claim_emissions() -> bool {
+        false
+    }
+
+    pub fn default_claim_emissions_interval_secs() -> u64 {
+        86400
+    }
+
+    pub fn default_min_claim_emissions_usd() -> I80F48 {
+        I80F48!(1)
+    }
+
+    pub fn default_min_health_buffer() -> f64 {
+        0.1
+    }
+
+    pub fn default_auto_unwrap_sol() -> bool {
+        false
+    }
+
+    pub fn default_seed_deposits() -> Vec<SeedDepositCfg> {
+        vec![]
+    }
+
+    pub fn default_post_liquidation_strategy() -> PostLiquidationStrategy {
+        PostLiquidationStrategy::WithdrawAndSell
+    }
 }
 
 impl std::fmt::Display for RebalancerCfg {
@@ -271,7 +1586,19 @@ impl std::fmt::Display for RebalancerCfg {
                 - Preferred mints: {}\n\
                 - Jup Swap Api URL: {}\n\
                 - Slippabe bps: {}\n\
-                - Compute unit price micro lamports: {}\n",
+                - Compute unit price micro lamports: {}\n\
+                - Min swap USD: {}\n\
+                - Min rebalance USD: {}\n\
+                - Dust threshold USD: {}\n\
+                - Claim emissions: {}\n\
+                - Claim emissions interval secs: {}\n\
+                - Min claim emissions USD: {}\n\
+                - Min health buffer: {}\n\
+                - Max price impact bps: {}\n\
+                - Quote cache TTL ms: {}\n\
+                - Auto unwrap SOL: {}\n\
+                - Seed deposits: {}\n\
+                - Post liquidation strategy: {:?}\n",
             self.token_account_dust_threshold,
             self.swap_mint,
             self.preferred_mints
@@ -284,7 +1611,23 @@ impl std::fmt::Display for RebalancerCfg {
             self.compute_unit_price_micro_lamports
                 .as_ref()
                 .map(|u| u.to_string())
-                .unwrap_or("None".to_string())
+                .unwrap_or("None".to_string()),
+            self.min_swap_usd,
+            self.min_rebalance_usd,
+            self.dust_threshold_usd,
+            self.claim_emissions,
+            self.claim_emissions_interval_secs,
+            self.min_claim_emissions_usd,
+            self.min_health_buffer,
+            self.max_price_impact_bps,
+            self.quote_cache_ttl_ms,
+            self.auto_unwrap_sol,
+            self.seed_deposits
+                .iter()
+                .map(|d| format!("{} of {}", d.amount, d.mint))
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.post_liquidation_strategy
         )
     }
 }
@@ -0,0 +1,68 @@
+use crate::config::LiquidatorCfg;
+use log::warn;
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Liquidatee account, asset bank and liability bank of a liquidation
+pub type LiquidationKey = (Pubkey, Pubkey, Pubkey);
+
+#[derive(Default)]
+struct LiquidationFailures {
+    consecutive: u32,
+    cooldown_until: Option<Instant>,
+}
+
+/// Liquidations failing `max_consecutive_failures` times in a row, e.g. on a
+/// stale oracle or a reverting health check, are not attempted again for the
+/// cooldown, so no more fees are spent on them. Shared by the liquidators of every signer
+#[derive(Clone)]
+pub struct LiquidationCooldowns {
+    failures: Arc<Mutex<HashMap<LiquidationKey, LiquidationFailures>>>,
+    max_consecutive_failures: u32,
+    cooldown: Duration,
+}
+
+impl LiquidationCooldowns {
+    pub fn new(config: &LiquidatorCfg) -> Self {
+        Self {
+            failures: Arc::new(Mutex::new(HashMap::new())),
+            max_consecutive_failures: config.max_consecutive_failures,
+            cooldown: Duration::from_secs(config.failure_cooldown_secs),
+        }
+    }
+
+    /// Time left before the liquidation can be attempted again, none when it can be now
+    pub fn remaining(&self, key: &LiquidationKey) -> Option<Duration> {
+        let mut failures = self.failures.lock().unwrap();
+        let cooldown_until = failures.get(key)?.cooldown_until?;
+        let remaining = cooldown_until.checked_duration_since(Instant::now());
+        if remaining.is_none() {
+            failures.remove(key);
+        }
+        remaining
+    }
+
+    /// Records the outcome of a liquidation, a landed one clears its failures
+    pub fn record_outcome(&self, key: LiquidationKey, landed: bool) {
+        let mut failures = self.failures.lock().unwrap();
+        if landed {
+            failures.remove(&key);
+            return;
+        }
+
+        let liquidation_failures = failures.entry(key).or_default();
+        liquidation_failures.consecutive += 1;
+        if liquidation_failures.consecutive >= self.max_consecutive_failures {
+            warn!(
+                "Liquidation of account {} (asset bank: {}, liability bank: {}) failed {} times in a row, cooling down for {:?}",
+                key.0, key.1, key.2, liquidation_failures.consecutive, self.cooldown
+            );
+            liquidation_failures.consecutive = 0;
+            liquidation_failures.cooldown_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
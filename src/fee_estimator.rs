@@ -0,0 +1,99 @@
+use log::warn;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+    rpc_response::RpcPrioritizationFee,
+};
+use solana_program::pubkey::Pubkey;
+use solana_rpc_client_api::client_error::Result as ClientResult;
+use solana_sdk::instruction::Instruction;
+
+/// Maximum number of accounts accepted by `getRecentPrioritizationFees`
+const MAX_ACCOUNTS: usize = 128;
+
+/// Compute unit price of the transactions
+#[derive(Debug, Clone)]
+pub enum PriorityFee {
+    /// Fixed price, in micro-lamports
+    Static(u64),
+    /// Percentile of the prices paid in the recent slots by the transactions
+    /// writing to the same accounts, bounded by `min` and `max` micro-lamports
+    Dynamic { percentile: u8, min: u64, max: u64 },
+}
+
+impl PriorityFee {
+    /// Resolves the compute unit price of a transaction writing to `writable_accounts`,
+    /// a dynamic fee falls back to its minimum when the rpc request fails
+    pub async fn resolve(&self, rpc: &NonBlockingRpcClient, writable_accounts: &[Pubkey]) -> u64 {
+        match self {
+            Self::Static(price) => *price,
+            Self::Dynamic { .. } => self.price_from_recent_fees(
+                rpc.get_recent_prioritization_fees(fee_market_accounts(writable_accounts))
+                    .await,
+            ),
+        }
+    }
+
+    /// Blocking version of `resolve`
+    pub fn resolve_blocking(&self, rpc: &RpcClient, writable_accounts: &[Pubkey]) -> u64 {
+        match self {
+            Self::Static(price) => *price,
+            Self::Dynamic { .. } => self.price_from_recent_fees(
+                rpc.get_recent_prioritization_fees(fee_market_accounts(writable_accounts)),
+            ),
+        }
+    }
+
+    /// Price out of the recent prioritization fees, shared by both resolutions
+    /// so they can't drift apart
+    fn price_from_recent_fees(&self, fees: ClientResult<Vec<RpcPrioritizationFee>>) -> u64 {
+        match *self {
+            Self::Static(price) => price,
+            Self::Dynamic {
+                percentile,
+                min,
+                max,
+            } => match fees {
+                Ok(fees) => fee_percentile(fees, percentile).clamp(min, max),
+                Err(e) => {
+                    warn!("Failed to fetch the recent prioritization fees: {:?}", e);
+                    min
+                }
+            },
+        }
+    }
+}
+
+/// Writable accounts whose recent fees are fetched, as many as the rpc accepts
+fn fee_market_accounts(writable_accounts: &[Pubkey]) -> &[Pubkey] {
+    &writable_accounts[..writable_accounts.len().min(MAX_ACCOUNTS)]
+}
+
+/// Returns the accounts written by the instructions, which are the ones
+/// whose local fee market sets the price of the transaction
+pub fn writable_accounts(ixs: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts = ixs
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable)
+        .map(|meta| meta.pubkey)
+        .collect::<Vec<_>>();
+    accounts.sort();
+    accounts.dedup();
+    accounts
+}
+
+/// Returns the `percentile` of the fees paid in the recent slots,
+/// the slots without any paid fee are included so an idle market stays cheap
+fn fee_percentile(fees: Vec<RpcPrioritizationFee>, percentile: u8) -> u64 {
+    let mut fees = fees
+        .into_iter()
+        .map(|fee| fee.prioritization_fee)
+        .collect::<Vec<_>>();
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+
+    let index = (fees.len() - 1) * (percentile.min(100) as usize) / 100;
+    fees[index]
+}
@@ -1,12 +1,24 @@
-use crate::utils::account_update_to_account;
+use crate::{
+    metrics::{GEYSER_STALLS, GEYSER_UPDATES, GEYSER_UPDATES_MERGED},
+    utils::account_update_to_account,
+};
 use anchor_lang::AccountDeserialize;
-use crossbeam::channel::Sender;
+use backoff::{backoff::Backoff, ExponentialBackoff};
+use crossbeam::channel::{RecvError, RecvTimeoutError, SendError, Sender};
 use futures::StreamExt;
-use log::{error, info};
+use log::{error, info, warn};
 use marginfi::state::marginfi_account::MarginfiAccount;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::account::Account;
-use std::{collections::HashMap, mem::size_of};
+use std::{
+    collections::{HashMap, VecDeque},
+    mem::size_of,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+use tonic::Code;
 use yellowstone_grpc_client::GeyserGrpcClient;
 use yellowstone_grpc_proto::prelude::*;
 
@@ -26,18 +38,171 @@ pub struct GeyserUpdate {
 /// MarginfiAccount -> Rebalaner and liquidator (Should be moved, so the only account
 ///                    sended to rebalancer is the liquidator account)
 /// TokenAccount -> Rebalancer
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub enum AccountType {
     OracleAccount,
     MarginfiAccount,
     TokenAccount,
 }
 
+/// Updates waiting to be consumed, at most one per account
+struct PendingUpdates {
+    /// Accounts in the order their first pending update was received
+    order: VecDeque<Pubkey>,
+    updates: HashMap<Pubkey, GeyserUpdate>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct UpdateQueue {
+    pending: Mutex<PendingUpdates>,
+    available: Condvar,
+}
+
+/// Creates a channel that keeps only the latest pending update of each account.
+/// A consumer falling behind works on the freshest state of every account, and
+/// no account loses its update the way it would when dropping the oldest ones
+pub fn latest_update_channel() -> (LatestUpdateSender, LatestUpdateReceiver) {
+    let queue = Arc::new(UpdateQueue {
+        pending: Mutex::new(PendingUpdates {
+            order: VecDeque::new(),
+            updates: HashMap::new(),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        available: Condvar::new(),
+    });
+    (
+        LatestUpdateSender {
+            queue: queue.clone(),
+        },
+        LatestUpdateReceiver { queue },
+    )
+}
+
+pub struct LatestUpdateSender {
+    queue: Arc<UpdateQueue>,
+}
+
+impl LatestUpdateSender {
+    /// Queues the update, replacing the pending update of the same account
+    /// while keeping its place in the queue
+    pub fn send(&self, update: GeyserUpdate) -> Result<(), SendError<GeyserUpdate>> {
+        let mut pending = self.queue.pending.lock().unwrap();
+        if !pending.receiver_alive {
+            return Err(SendError(update));
+        }
+        let address = update.address;
+        if pending.updates.insert(address, update).is_some() {
+            GEYSER_UPDATES_MERGED.inc();
+        } else {
+            pending.order.push_back(address);
+        }
+        drop(pending);
+        self.queue.available.notify_one();
+        Ok(())
+    }
+}
+
+impl Clone for LatestUpdateSender {
+    fn clone(&self) -> Self {
+        self.queue.pending.lock().unwrap().senders += 1;
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Drop for LatestUpdateSender {
+    fn drop(&mut self) {
+        self.queue.pending.lock().unwrap().senders -= 1;
+        self.queue.available.notify_all();
+    }
+}
+
+pub struct LatestUpdateReceiver {
+    queue: Arc<UpdateQueue>,
+}
+
+impl LatestUpdateReceiver {
+    /// Waits for the next pending update, fails once every sender is dropped
+    /// and no update is pending
+    pub fn recv(&self) -> Result<GeyserUpdate, RecvError> {
+        let mut pending = self.queue.pending.lock().unwrap();
+        loop {
+            if let Some(update) = Self::pop(&mut pending) {
+                return Ok(update);
+            }
+            if pending.senders == 0 {
+                return Err(RecvError);
+            }
+            pending = self.queue.available.wait(pending).unwrap();
+        }
+    }
+
+    /// Waits for the next pending update at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<GeyserUpdate, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut pending = self.queue.pending.lock().unwrap();
+        loop {
+            if let Some(update) = Self::pop(&mut pending) {
+                return Ok(update);
+            }
+            if pending.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            pending = self
+                .queue
+                .available
+                .wait_timeout(pending, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    fn pop(pending: &mut PendingUpdates) -> Option<GeyserUpdate> {
+        let address = pending.order.pop_front()?;
+        pending.updates.remove(&address)
+    }
+}
+
+impl Drop for LatestUpdateReceiver {
+    fn drop(&mut self) {
+        self.queue.pending.lock().unwrap().receiver_alive = false;
+    }
+}
+
+/// Channel end the geyser service sends the updates of a service to
+pub trait UpdateSender {
+    fn send_update(&self, update: GeyserUpdate) -> Result<(), SendError<GeyserUpdate>>;
+}
+
+impl UpdateSender for LatestUpdateSender {
+    fn send_update(&self, update: GeyserUpdate) -> Result<(), SendError<GeyserUpdate>> {
+        self.send(update)
+    }
+}
+
+impl UpdateSender for Sender<GeyserUpdate> {
+    fn send_update(&self, update: GeyserUpdate) -> Result<(), SendError<GeyserUpdate>> {
+        self.send(update)
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct GeyserServiceConfig {
     pub endpoint: String,
     pub x_token: Option<String>,
 }
 
+/// Sender of a service, along with the marginfi group it liquidates, only
+/// the marginfi accounts of that group are sent to it
+pub type GroupSender<S> = (Pubkey, S);
+
 /// Geyser service is responsible for receiving and distrubute the
 /// messages to the needed services. It already separates the messages by
 /// liquidator or rebalancer to minizime the possible quantity of messages in
@@ -45,164 +210,435 @@ pub struct GeyserServiceConfig {
 pub struct GeyserService {}
 
 impl GeyserService {
+    /// Subscribes to every endpoint, the first one being the primary and the others
+    /// warm standbys. Updates are deduplicated by account and slot, so the first
+    /// endpoint to deliver a slot wins and a failing one is cut over seamlessly.
+    /// The tracked accounts are split across several subscriptions of each endpoint,
+    /// of at most `max_accounts_per_subscription` accounts.
+    /// Fails once every subscription has given up, with the reason of each
+    #[allow(clippy::too_many_arguments)]
     pub async fn connect(
-        config: GeyserServiceConfig,
+        configs: Vec<GeyserServiceConfig>,
         tracked_accounts: HashMap<Pubkey, AccountType>,
         marginfi_program_id: Pubkey,
-        marginfi_group_pk: Pubkey,
-        liquidator_sender: Sender<GeyserUpdate>,
-        rebalancer_sender: Sender<GeyserUpdate>,
+        max_connect_attempts: u32,
+        max_accounts_per_subscription: usize,
+        idle_timeout: Duration,
+        liquidator_senders: Vec<GroupSender<LatestUpdateSender>>,
+        rebalancer_senders: Vec<GroupSender<Sender<GeyserUpdate>>>,
+        cancellation_token: CancellationToken,
+    ) -> anyhow::Result<()> {
+        if configs.is_empty() {
+            return Err(anyhow::anyhow!("No geyser endpoint configured"));
+        }
+
+        let mut tracked_accounts_vec: Vec<Pubkey> = tracked_accounts.keys().cloned().collect();
+        tracked_accounts_vec.sort();
+        tracked_accounts_vec.dedup();
+
+        // Providers cap the accounts of a subscription filter, the marginfi
+        // accounts are only subscribed to by the first chunk
+        let mut chunks = tracked_accounts_vec
+            .chunks(max_accounts_per_subscription)
+            .map(|chunk| chunk.to_vec())
+            .collect::<Vec<_>>();
+        if chunks.is_empty() {
+            chunks.push(vec![]);
+        }
+        if chunks.len() > 1 {
+            info!(
+                "Subscribing to {} accounts in {} subscriptions per geyser endpoint",
+                tracked_accounts_vec.len(),
+                chunks.len()
+            );
+        }
+
+        let (update_tx, mut update_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut subscription_handles = vec![];
+        for (source, config) in configs.into_iter().enumerate() {
+            for (index, chunk) in chunks.iter().enumerate() {
+                subscription_handles.push(tokio::spawn(Self::subscribe(
+                    source,
+                    config.clone(),
+                    chunk.clone(),
+                    (index == 0).then_some(marginfi_program_id),
+                    max_connect_attempts,
+                    idle_timeout,
+                    update_tx.clone(),
+                    cancellation_token.clone(),
+                )));
+            }
+        }
+        drop(update_tx);
+
+        // Latest slot received for each account, and the endpoint it was received from
+        let mut latest_updates: HashMap<Pubkey, (u64, usize)> = HashMap::new();
+        let mut active_source = 0;
+
+        while let Some((source, account_update)) = tokio::select! {
+            _ = cancellation_token.cancelled() => None,
+            update = update_rx.recv() => update,
+        } {
+            let SubscribeUpdateAccount { account, slot, .. } = account_update;
+
+            let Some(update_account) = account else {
+                continue;
+            };
+
+            let Ok(address) = Pubkey::try_from(update_account.pubkey.clone()) else {
+                continue;
+            };
+
+            // Accounts can be written several times within a slot, those writes are only
+            // trusted from the endpoint that delivered the slot first
+            match latest_updates.get(&address) {
+                Some((latest_slot, latest_source))
+                    if slot < *latest_slot
+                        || (slot == *latest_slot && source != *latest_source) =>
+                {
+                    continue;
+                }
+                _ => {}
+            }
+            latest_updates.insert(address, (slot, source));
+            crate::status::set_last_geyser_slot(slot);
+
+            if source != active_source {
+                info!("Geyser updates are now received from endpoint #{}", source);
+                active_source = source;
+            }
+
+            Self::handle_account_update(
+                address,
+                slot,
+                &update_account,
+                &tracked_accounts,
+                &marginfi_program_id,
+                &liquidator_senders,
+                &rebalancer_senders,
+            );
+        }
+
+        // Dropping the senders on return lets the liquidator and rebalancer stop
+        let mut errors = vec![];
+        for handle in subscription_handles {
+            if let Ok(Err(e)) = handle.await {
+                errors.push(format!("{:#}", e));
+            }
+        }
+
+        if !cancellation_token.is_cancelled() {
+            return Err(anyhow::anyhow!(
+                "Every geyser endpoint failed: {}",
+                errors.join("; ")
+            ));
+        }
+
+        info!("Geyser service stopped");
+
+        Ok(())
+    }
+
+    /// Keeps a subscription to a single geyser endpoint alive, reconnecting with
+    /// an exponential backoff, and forwards its account updates. The accounts owned
+    /// by `marginfi_program_id` are subscribed to when set. Gives up on the
+    /// endpoint when it rejects the subscription, or when it can't be reached
+    /// `max_connect_attempts` times in a row before it ever connected.
+    /// A stream that looks connected but stays silent for `idle_timeout` is
+    /// subscribed again, a healthy one receiving at least the slot updates
+    #[allow(clippy::too_many_arguments)]
+    async fn subscribe(
+        source: usize,
+        config: GeyserServiceConfig,
+        tracked_accounts: Vec<Pubkey>,
+        marginfi_program_id: Option<Pubkey>,
+        max_connect_attempts: u32,
+        idle_timeout: Duration,
+        update_tx: UnboundedSender<(usize, SubscribeUpdateAccount)>,
+        cancellation_token: CancellationToken,
     ) -> anyhow::Result<()> {
+        let mut reconnect_backoff = ExponentialBackoff {
+            max_elapsed_time: None,
+            ..Default::default()
+        };
+        let mut connected_once = false;
+        let mut failed_attempts = 0;
+
         loop {
-            info!("Connecting to geyser");
+            if cancellation_token.is_cancelled() {
+                return Ok(());
+            }
 
-            let mut client = GeyserGrpcClient::build_from_shared(config.endpoint.clone())?
-                .x_token(config.x_token.clone())?
-                .connect()
-                .await?;
+            info!("Connecting to geyser {}", config.endpoint);
 
-            info!("Connected to geyser");
+            let sub_req = Self::build_geyser_subscribe_request(
+                &tracked_accounts,
+                marginfi_program_id.as_ref(),
+            );
 
-            let tracked_accounts_vec: Vec<Pubkey> = tracked_accounts.keys().cloned().collect();
+            let subscription = tokio::select! {
+                _ = cancellation_token.cancelled() => continue,
+                subscription = async {
+                    let mut client = GeyserGrpcClient::build_from_shared(config.endpoint.clone())?
+                        .x_token(config.x_token.clone())?
+                        .connect()
+                        .await?;
 
-            let sub_req =
-                Self::build_geyser_subscribe_request(&tracked_accounts_vec, &marginfi_program_id);
+                    let (_, stream) = client.subscribe_with_request(Some(sub_req)).await?;
 
-            let (_, mut stream) = client.subscribe_with_request(Some(sub_req)).await?;
+                    Ok::<_, anyhow::Error>(stream)
+                } => subscription,
+            };
 
-            while let Some(msg) = stream.next().await {
+            let mut stream = match subscription {
+                Ok(stream) => stream,
+                Err(e) => {
+                    if let Some(reason) = Self::rejection_reason(&e, tracked_accounts.len()) {
+                        error!("Giving up on geyser {}: {}", config.endpoint, reason);
+                        return Err(anyhow::anyhow!("{}: {}", config.endpoint, reason));
+                    }
+                    failed_attempts += 1;
+                    if !connected_once && failed_attempts >= max_connect_attempts {
+                        error!(
+                            "Giving up on geyser {} after {} failed connection attempts: {:?}",
+                            config.endpoint, failed_attempts, e
+                        );
+                        return Err(anyhow::anyhow!(
+                            "{}: unreachable after {} attempts, last error: {:#}",
+                            config.endpoint,
+                            failed_attempts,
+                            e
+                        ));
+                    }
+                    let delay = reconnect_backoff
+                        .next_backoff()
+                        .unwrap_or(reconnect_backoff.max_interval);
+                    warn!(
+                        "Failed to connect to geyser {}: {:?}, retrying in {:?}",
+                        config.endpoint, e, delay
+                    );
+                    tokio::select! {
+                        _ = cancellation_token.cancelled() => {}
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                    continue;
+                }
+            };
+
+            info!("Connected to geyser {}", config.endpoint);
+
+            let mut healthy = false;
+            while let Some(msg) = tokio::select! {
+                _ = cancellation_token.cancelled() => None,
+                msg = tokio::time::timeout(idle_timeout, stream.next()) => match msg {
+                    Ok(msg) => msg,
+                    Err(_) => {
+                        warn!(
+                            "No update received from geyser {} in {:?}, the stream is stalled",
+                            config.endpoint, idle_timeout
+                        );
+                        GEYSER_STALLS.inc();
+                        None
+                    }
+                },
+            } {
                 match msg {
                     Ok(msg) => {
-                        if let Some(update_oneof) = msg.update_oneof {
-                            if let subscribe_update::UpdateOneof::Account(account) = update_oneof {
-                                if let Some(update_account) = &account.account {
-                                    if let Ok(address) =
-                                        Pubkey::try_from(update_account.pubkey.clone())
-                                    {
-                                        if let Ok(account) =
-                                            account_update_to_account(update_account)
-                                        {
-                                            if let Ok(account_owner_pk) =
-                                                Pubkey::try_from(account.owner)
-                                            {
-                                                if account_owner_pk == marginfi_program_id
-                                                    && update_account.data.len()
-                                                        == MARGIN_ACCOUNT_SIZE
-                                                {
-                                                    let marginfi_account =
-                                                        MarginfiAccount::try_deserialize(
-                                                            &mut account.data.as_slice(),
-                                                        );
-
-                                                    match marginfi_account {
-                                                        Err(_) => {
-                                                            error!("Error deserializing marginfi account");
-                                                            continue;
-                                                        }
-                                                        Ok(marginfi_account) => {
-                                                            if marginfi_account.group
-                                                                != marginfi_group_pk
-                                                            {
-                                                                continue;
-                                                            }
-                                                        }
-                                                    }
-
-                                                    let update = GeyserUpdate {
-                                                        account_type: AccountType::MarginfiAccount,
-                                                        address,
-                                                        account: account.clone(),
-                                                    };
-                                                    if let Err(e) =
-                                                        liquidator_sender.send(update.clone())
-                                                    {
-                                                        error!("Error sending update to the liquidator sender: {:?}", e);
-                                                    }
-                                                    if let Err(e) =
-                                                        rebalancer_sender.send(update.clone())
-                                                    {
-                                                        error!("Error sending update to the rebalancer sender: {:?}", e);
-                                                    }
-                                                }
-                                            }
-                                            if let Some(account_type) =
-                                                tracked_accounts.get(&address)
-                                            {
-                                                let update = GeyserUpdate {
-                                                    account_type: account_type.clone(),
-                                                    address,
-                                                    account: account.clone(),
-                                                };
-
-                                                match account_type {
-                                                    AccountType::OracleAccount => {
-                                                        if let Err(e) =
-                                                            liquidator_sender.send(update.clone())
-                                                        {
-                                                            error!("Error sending update to the liquidator sender: {:?}", e);
-                                                        }
-                                                        if let Err(e) =
-                                                            rebalancer_sender.send(update.clone())
-                                                        {
-                                                            error!("Error sending update to the rebalancer sender: {:?}", e);
-                                                        }
-                                                    }
-                                                    AccountType::TokenAccount => {
-                                                        if let Err(e) =
-                                                            rebalancer_sender.send(update.clone())
-                                                        {
-                                                            error!("Error sending update to the rebalancer sender: {:?}", e);
-                                                        }
-                                                    }
-                                                    _ => {}
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
+                        // Only reset the backoff once the stream is actually delivering updates
+                        if !healthy {
+                            healthy = true;
+                            connected_once = true;
+                            failed_attempts = 0;
+                            reconnect_backoff.reset();
+                        }
+
+                        GEYSER_UPDATES.inc();
+
+                        if let Some(subscribe_update::UpdateOneof::Account(account)) =
+                            msg.update_oneof
+                        {
+                            if update_tx.send((source, account)).is_err() {
+                                return Ok(());
                             }
                         }
                     }
                     Err(e) => {
-                        error!("Error receiving message from geyser {:?}", e);
+                        // The subscription request is only checked once the stream is open
+                        let e = anyhow::Error::from(e);
+                        if let Some(reason) = Self::rejection_reason(&e, tracked_accounts.len()) {
+                            error!("Giving up on geyser {}: {}", config.endpoint, reason);
+                            return Err(anyhow::anyhow!("{}: {}", config.endpoint, reason));
+                        }
+                        error!(
+                            "Error receiving message from geyser {}: {:?}",
+                            config.endpoint, e
+                        );
                         break;
                     }
                 }
             }
+
+            if cancellation_token.is_cancelled() {
+                continue;
+            }
+
+            let delay = reconnect_backoff
+                .next_backoff()
+                .unwrap_or(reconnect_backoff.max_interval);
+            warn!(
+                "Geyser stream {} dropped, reconnecting in {:?}",
+                config.endpoint, delay
+            );
+            tokio::select! {
+                _ = cancellation_token.cancelled() => {}
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+
+    /// Why the endpoint rejected the subscription for good, none when the
+    /// error is transient and the connection is worth retrying
+    fn rejection_reason(error: &anyhow::Error, tracked_accounts: usize) -> Option<String> {
+        let status = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<tonic::Status>())?;
+        match status.code() {
+            Code::Unauthenticated | Code::PermissionDenied => Some(format!(
+                "authentication rejected ({}), check its x_token",
+                status.message()
+            )),
+            Code::InvalidArgument | Code::ResourceExhausted => Some(format!(
+                "subscription to {} accounts rejected ({}), the provider likely caps \
+                 the accounts of a filter, lower `geyser_max_accounts_per_subscription`",
+                tracked_accounts,
+                status.message()
+            )),
+            _ => None,
+        }
+    }
+
+    /// Distributes an account update to the liquidators and the rebalancers of every signer
+    fn handle_account_update(
+        address: Pubkey,
+        slot: u64,
+        update_account: &SubscribeUpdateAccountInfo,
+        tracked_accounts: &HashMap<Pubkey, AccountType>,
+        marginfi_program_id: &Pubkey,
+        liquidator_senders: &[GroupSender<LatestUpdateSender>],
+        rebalancer_senders: &[GroupSender<Sender<GeyserUpdate>>],
+    ) {
+        let Ok(account) = account_update_to_account(update_account) else {
+            return;
+        };
+
+        if let Ok(account_owner_pk) = Pubkey::try_from(account.owner) {
+            if account_owner_pk == *marginfi_program_id
+                && update_account.data.len() == MARGIN_ACCOUNT_SIZE
+            {
+                let group = match MarginfiAccount::try_deserialize(&mut account.data.as_slice()) {
+                    Err(_) => {
+                        error!("Error deserializing marginfi account");
+                        return;
+                    }
+                    Ok(marginfi_account) => marginfi_account.group,
+                };
+                if !liquidator_senders
+                    .iter()
+                    .any(|(sender_group, _)| *sender_group == group)
+                {
+                    return;
+                }
+
+                let update = GeyserUpdate {
+                    account_type: AccountType::MarginfiAccount,
+                    address,
+                    account: account.clone(),
+                };
+                crate::replay::record_update(slot, &update);
+                Self::send_update(liquidator_senders, &update, "liquidator", Some(&group));
+                Self::send_update(rebalancer_senders, &update, "rebalancer", Some(&group));
+            }
+        }
+
+        if let Some(account_type) = tracked_accounts.get(&address) {
+            let update = GeyserUpdate {
+                account_type: account_type.clone(),
+                address,
+                account,
+            };
+            crate::replay::record_update(slot, &update);
+
+            match account_type {
+                AccountType::OracleAccount => {
+                    Self::send_update(liquidator_senders, &update, "liquidator", None);
+                    Self::send_update(rebalancer_senders, &update, "rebalancer", None);
+                }
+                AccountType::TokenAccount => {
+                    Self::send_update(rebalancer_senders, &update, "rebalancer", None);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends the update to every sender, or only to the ones of the `group` when set
+    fn send_update<S: UpdateSender>(
+        senders: &[GroupSender<S>],
+        update: &GeyserUpdate,
+        consumer: &str,
+        group: Option<&Pubkey>,
+    ) {
+        for (_, sender) in senders
+            .iter()
+            .filter(|(sender_group, _)| group.map_or(true, |group| group == sender_group))
+        {
+            if let Err(e) = sender.send_update(update.clone()) {
+                error!("Error sending update to the {} sender: {:?}", consumer, e);
+            }
         }
     }
 
     /// Builds a geyser subscription request payload
     fn build_geyser_subscribe_request(
         tracked_accounts: &[Pubkey],
-        marginfi_program_id: &Pubkey,
+        marginfi_program_id: Option<&Pubkey>,
     ) -> SubscribeRequest {
         let mut request = SubscribeRequest {
             ..Default::default()
         };
 
-        let subscribe_to_static_account_updates = SubscribeRequestFilterAccounts {
-            account: tracked_accounts.iter().map(|a| a.to_string()).collect(),
-            ..Default::default()
-        };
+        let mut req = HashMap::new();
 
-        let marginfi_account_subscription = SubscribeRequestFilterAccounts {
-            owner: vec![marginfi_program_id.to_string()],
-            ..Default::default()
-        };
+        // An empty filter would match every account
+        if !tracked_accounts.is_empty() {
+            let subscribe_to_static_account_updates = SubscribeRequestFilterAccounts {
+                account: tracked_accounts.iter().map(|a| a.to_string()).collect(),
+                ..Default::default()
+            };
+            req.insert(
+                "static_accounts".to_string(),
+                subscribe_to_static_account_updates,
+            );
+        }
 
-        let mut req = HashMap::new();
-        req.insert(
-            "static_accounts".to_string(),
-            subscribe_to_static_account_updates,
-        );
-        req.insert(
-            "marginfi_accounts".to_string(),
-            marginfi_account_subscription,
-        );
+        if let Some(marginfi_program_id) = marginfi_program_id {
+            let marginfi_account_subscription = SubscribeRequestFilterAccounts {
+                owner: vec![marginfi_program_id.to_string()],
+                ..Default::default()
+            };
+            req.insert(
+                "marginfi_accounts".to_string(),
+                marginfi_account_subscription,
+            );
+        }
 
         request.accounts = req;
 
+        // A slot update is received every slot, so a silent stream is a stalled one
+        request.slots =
+            HashMap::from([("slots".to_string(), SubscribeRequestFilterSlots::default())]);
+
         request
     }
 }
@@ -0,0 +1,253 @@
+use crate::{
+    config::RebalancerCfg,
+    rpc_pool::RpcPool,
+    transaction_manager::RawTransaction,
+    tx_log::{TxMetadata, TxType},
+};
+use jupiter_swap_api_client::{
+    quote::{QuoteRequest, QuoteResponse},
+    swap::SwapRequest,
+    transaction_config::TransactionConfig,
+    JupiterSwapApiClient,
+};
+use log::debug;
+use solana_address_lookup_table_program::state::AddressLookupTable;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    compute_budget::ComputeBudgetInstruction,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Amounts are rounded down to this many significant digits before being
+/// quoted, so the swaps of nearly the same amount share their cached quote
+const QUOTE_AMOUNT_SIGNIFICANT_DIGITS: u32 = 4;
+
+/// A swap with too much price impact is split in at most this many legs
+const MAX_SWAP_LEGS: u64 = 8;
+
+/// Input mint, output mint and rounded amount of a quote
+type QuoteKey = (Pubkey, Pubkey, u64);
+
+/// A swap transaction, along with the amount of input tokens it sells, the quoted
+/// amount of output tokens and the minimum received once the slippage is accounted
+pub struct JupiterSwap {
+    pub transaction: RawTransaction,
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub min_out_amount: u64,
+}
+
+/// Builds swaps through the Jupiter v6 api, as raw transactions
+/// so they are sent along the other transactions of the liquidator
+pub struct JupiterSwapper {
+    client: JupiterSwapApiClient,
+    rpc_pool: Arc<RpcPool>,
+    slippage_bps: u16,
+    compute_unit_price_micro_lamports: Option<u64>,
+    max_price_impact_bps: u16,
+    quote_ttl: Duration,
+    quotes: Mutex<HashMap<QuoteKey, (Instant, QuoteResponse)>>,
+}
+
+impl JupiterSwapper {
+    pub fn new(
+        api_url: String,
+        rpc_pool: Arc<RpcPool>,
+        slippage_bps: u16,
+        compute_unit_price_micro_lamports: Option<u64>,
+        max_price_impact_bps: u16,
+        quote_ttl: Duration,
+    ) -> Self {
+        Self {
+            client: JupiterSwapApiClient::new(api_url),
+            rpc_pool,
+            slippage_bps,
+            compute_unit_price_micro_lamports,
+            max_price_impact_bps,
+            quote_ttl,
+            quotes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates the swapper with the Jupiter settings of the rebalancer
+    pub fn from_config(config: &RebalancerCfg, rpc_pool: Arc<RpcPool>) -> Self {
+        Self::new(
+            config.jup_swap_api_url.clone(),
+            rpc_pool,
+            config.slippage_bps,
+            config.compute_unit_price_micro_lamports,
+            config.max_price_impact_bps,
+            Duration::from_millis(config.quote_cache_ttl_ms),
+        )
+    }
+
+    /// Quotes the swap and returns its transaction. When the price impact of the
+    /// quote exceeds `max_price_impact_bps`, only the first of several smaller legs
+    /// is swapped, the next rebalances swap the remaining tokens once the pools
+    /// recovered from the previous legs
+    pub async fn swap_transaction(
+        &self,
+        user: Pubkey,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+    ) -> anyhow::Result<JupiterSwap> {
+        let mut quote_response = self.quote(input_mint, output_mint, amount).await?;
+
+        let price_impact_bps = price_impact_bps(&quote_response);
+        if price_impact_bps > self.max_price_impact_bps as f64 {
+            let legs = ((price_impact_bps / self.max_price_impact_bps as f64).ceil() as u64)
+                .min(MAX_SWAP_LEGS);
+            debug!(
+                "Swap of {} {} has a price impact of {:.0} bps, swapping it in {} legs",
+                amount, input_mint, price_impact_bps, legs
+            );
+            quote_response = self.quote(input_mint, output_mint, amount / legs).await?;
+        }
+
+        let in_amount = quote_response.in_amount;
+        let out_amount = quote_response.out_amount;
+        let min_out_amount = quote_response.other_amount_threshold;
+
+        let swap_instructions = self
+            .client
+            .swap_instructions(&SwapRequest {
+                user_public_key: user,
+                quote_response,
+                config: TransactionConfig {
+                    wrap_and_unwrap_sol: false,
+                    ..Default::default()
+                },
+            })
+            .await?;
+
+        // The compute unit limit is set by the transaction manager,
+        // so the compute budget instructions of Jupiter are left out
+        let mut ixs = vec![];
+        if let Some(price) = self.compute_unit_price_micro_lamports {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        ixs.extend(swap_instructions.setup_instructions);
+        ixs.push(swap_instructions.swap_instruction);
+        ixs.extend(swap_instructions.cleanup_instruction);
+
+        let lookup_tables =
+            self.load_lookup_tables(&swap_instructions.address_lookup_table_addresses)?;
+
+        Ok(JupiterSwap {
+            transaction: RawTransaction::new(ixs)
+                .with_lookup_tables(lookup_tables)
+                .with_metadata(TxMetadata::new(TxType::Swap, vec![], Some(in_amount))),
+            in_amount,
+            out_amount,
+            min_out_amount,
+        })
+    }
+
+    /// Amount of output tokens quoted for selling the amount of input tokens, before
+    /// any slippage. The quote of the rounded amount is scaled back to the amount
+    pub async fn quote_out_amount(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+    ) -> anyhow::Result<u64> {
+        let quote_response = self.quote(input_mint, output_mint, amount).await?;
+        if quote_response.in_amount == 0 {
+            anyhow::bail!(
+                "Amount {} of {} is too small to be quoted",
+                amount,
+                input_mint
+            );
+        }
+
+        Ok(
+            (quote_response.out_amount as u128 * amount as u128 / quote_response.in_amount as u128)
+                as u64,
+        )
+    }
+
+    /// Quotes the swap of the rounded amount, reusing the quote
+    /// of the same swap when it was fetched less than `quote_ttl` ago
+    async fn quote(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+    ) -> anyhow::Result<QuoteResponse> {
+        let amount = round_amount(amount);
+        let key = (input_mint, output_mint, amount);
+
+        let cached_quote = self
+            .quotes
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(quoted_at, _)| quoted_at.elapsed() < self.quote_ttl)
+            .map(|(_, quote)| quote.clone());
+        if let Some(quote) = cached_quote {
+            return Ok(quote);
+        }
+
+        let quote = self
+            .client
+            .quote(&QuoteRequest {
+                input_mint,
+                output_mint,
+                amount,
+                slippage_bps: self.slippage_bps,
+                ..Default::default()
+            })
+            .await?;
+
+        let mut quotes = self.quotes.lock().unwrap();
+        quotes.retain(|_, (quoted_at, _)| quoted_at.elapsed() < self.quote_ttl);
+        quotes.insert(key, (Instant::now(), quote.clone()));
+
+        Ok(quote)
+    }
+
+    fn load_lookup_tables(
+        &self,
+        addresses: &[Pubkey],
+    ) -> anyhow::Result<Vec<AddressLookupTableAccount>> {
+        let accounts = self.rpc_pool.rpc().get_multiple_accounts(addresses)?;
+
+        addresses
+            .iter()
+            .zip(accounts)
+            .map(|(address, account)| {
+                let account =
+                    account.ok_or_else(|| anyhow::anyhow!("Lookup table {} not found", address))?;
+                let lookup_table = AddressLookupTable::deserialize(&account.data)?;
+
+                Ok(AddressLookupTableAccount {
+                    key: *address,
+                    addresses: lookup_table.addresses.to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Rounds the amount down to `QUOTE_AMOUNT_SIGNIFICANT_DIGITS` significant digits
+fn round_amount(amount: u64) -> u64 {
+    let digits = amount.checked_ilog10().map_or(0, |log| log + 1);
+    let scale = 10u64.pow(digits.saturating_sub(QUOTE_AMOUNT_SIGNIFICANT_DIGITS));
+    amount / scale * scale
+}
+
+/// Price impact of the quote in bps, Jupiter reports it as a fraction
+fn price_impact_bps(quote: &QuoteResponse) -> f64 {
+    quote
+        .price_impact_pct
+        .to_string()
+        .parse::<f64>()
+        .unwrap_or(0.0)
+        * 10_000.0
+}
@@ -1,11 +1,21 @@
 use crate::{
-    config::{GeneralConfig, LiquidatorCfg},
+    config::{GeneralConfig, LiquidatorCfg, PriceSource, RankingStrategy, TipFundingStrategy},
+    cooldown::LiquidationCooldowns,
     crossbar::CrossbarMaintainer,
-    geyser::{AccountType, GeyserUpdate},
-    transaction_manager::BatchTransactions,
+    geyser::{AccountType, GeyserUpdate, LatestUpdateReceiver},
+    jupiter::JupiterSwapper,
+    metrics::{LIQUIDATIONS_ATTEMPTED, LIQUIDATIONS_FAILED, LIQUIDATION_LATENCY, LIQUIDATOR_READY},
+    notifications::notify,
+    pnl::PnlEntry,
+    rpc_pool::RpcPool,
+    state::{SnapshotRefresh, StateSnapshot},
+    transaction_manager::{
+        BatchTransactions, InFlightLiquidations, LiquidationGuard, PendingLiquidations,
+        TransactionManager, MAX_BUNDLE_TRANSACTIONS,
+    },
     utils::{
-        batch_get_multiple_accounts, find_oracle_keys, BankAccountWithPriceFeedEva,
-        BatchLoadingConfig,
+        batch_get_multiple_accounts, find_oracle_keys, get_oracle_publish_slot,
+        get_pyth_push_publish_time, BatchLoadingConfig,
     },
     wrappers::{
         bank::BankWrapper, liquidator_account::LiquidatorAccount,
@@ -14,15 +24,16 @@ use crate::{
 };
 use anchor_client::Program;
 use anchor_lang::Discriminator;
-use crossbeam::channel::{Receiver, Sender};
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use crossbeam::channel::{RecvTimeoutError, Sender};
 use fixed::types::I80F48;
 use fixed_macro::types::I80F48;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use marginfi::{
-    constants::{BANKRUPT_THRESHOLD, EXP_10_I80F48},
+    constants::{BANKRUPT_THRESHOLD, EXP_10_I80F48, LIQUIDATION_LIQUIDATOR_FEE},
     state::{
         marginfi_account::{BalanceSide, MarginfiAccount, RequirementType},
-        marginfi_group::{Bank, BankOperationalState, RiskTier},
+        marginfi_group::{Bank, RiskTier},
         price::{
             OraclePriceFeedAdapter, OraclePriceType, OracleSetup, PriceBias,
             SwitchboardPullPriceFeed,
@@ -38,29 +49,65 @@ use solana_client::{
 };
 use solana_program::pubkey::Pubkey;
 use solana_sdk::{
-    account::Account, account_info::IntoAccountInfo, bs58, clock::Clock, signature::Keypair,
+    account::Account, account_info::IntoAccountInfo, bs58, clock::Clock,
+    commitment_config::CommitmentConfig, rent::Rent, signature::Keypair,
 };
 use std::{
     cmp::min,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{atomic::AtomicBool, Arc},
 };
 use switchboard_on_demand::PullFeedAccountData;
+use tokio_util::sync::CancellationToken;
 
 /// Bank group private key offset
 const BANK_GROUP_PK_OFFSET: usize = 32 + 1 + 8;
 
+/// Maximum number of transactions in a jito bundle
+const MAX_BUNDLE_SIZE: usize = MAX_BUNDLE_TRANSACTIONS;
+
+/// Interval between two snapshots of the tracked accounts
+const STATE_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// Longest wait for every bank to have an oracle price before the liquidator acts,
+/// so a single broken oracle doesn't keep it from liquidating against the other banks
+const WARMUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Signature fees of a liquidation, the crank and the liquidation transactions
+const LIQUIDATION_SIGNATURE_FEES_LAMPORTS: u64 = 2 * 5_000;
+
 pub struct Liquidator {
     liquidator_account: LiquidatorAccount,
     general_config: GeneralConfig,
     config: LiquidatorCfg,
-    geyser_receiver: Receiver<GeyserUpdate>,
+    geyser_receiver: LatestUpdateReceiver,
     transaction_sender: Sender<BatchTransactions>,
     marginfi_accounts: HashMap<Pubkey, MarginfiAccountWrapper>,
     banks: HashMap<Pubkey, BankWrapper>,
     oracle_to_bank: HashMap<Pubkey, Pubkey>,
     stop_liquidation: Arc<AtomicBool>,
     crossbar_client: CrossbarMaintainer,
+    cancellation_token: CancellationToken,
+    last_state_save: std::time::Instant,
+    /// Snapshot being written, a save is skipped while the previous one runs
+    state_save: Option<tokio::task::JoinHandle<()>>,
+    /// Accounts loaded from the snapshot, being refetched in the background
+    snapshot_refresh: Option<SnapshotRefresh>,
+    /// Accounts updated by geyser during the refresh, fresher than the refetched ones
+    updated_during_refresh: HashSet<Pubkey>,
+    /// When the tracked accounts were last evaluated, by an update or the periodic scan
+    last_scan: std::time::Instant,
+    /// Accounts whose liquidation is in flight, not liquidated again until it resolves,
+    /// shared by the liquidators of every signer
+    pending_liquidations: PendingLiquidations,
+    /// Liquidations of this signer still in flight
+    in_flight_liquidations: InFlightLiquidations,
+    /// Liquidations failing repeatedly, not attempted until their cooldown expires
+    liquidation_cooldowns: LiquidationCooldowns,
+    /// Whether the warm-up is over, no account is evaluated before
+    ready: bool,
+    /// Quotes the sale of the seized collateral, when it is valued via Jupiter
+    jupiter_swapper: Option<JupiterSwapper>,
 }
 
 #[derive(Clone)]
@@ -90,13 +137,27 @@ impl<'a> LiquidatableAccount<'a> {
     }
 }
 
+/// An account that can be liquidated, as reported by the `scan` command
+pub struct LiquidationOpportunity {
+    pub address: Pubkey,
+    pub health_factor: I80F48,
+    pub asset_bank: Pubkey,
+    pub liab_bank: Pubkey,
+    /// Asset amount that can be liquidated, in UI units
+    pub max_liquidation_amount: I80F48,
+    pub profit: I80F48,
+}
+
 pub struct PreparedLiquidatableAccount {
     liquidate_account: MarginfiAccountWrapper,
     asset_bank: BankWrapper,
     liab_bank: BankWrapper,
     asset_amount: u64,
     banks: HashMap<Pubkey, BankWrapper>,
-    profit: u64,
+    profit: I80F48,
+    health_factor: I80F48,
+    /// Value of the liquidated asset, in USD
+    value: I80F48,
 }
 
 impl Liquidator {
@@ -104,18 +165,18 @@ impl Liquidator {
     pub async fn new(
         general_config: GeneralConfig,
         liquidator_config: LiquidatorCfg,
-        geyser_receiver: Receiver<GeyserUpdate>,
+        geyser_receiver: LatestUpdateReceiver,
         transaction_sender: Sender<BatchTransactions>,
         stop_liquidation: Arc<AtomicBool>,
+        cancellation_token: CancellationToken,
+        rpc_pool: Arc<RpcPool>,
+        pending_liquidations: PendingLiquidations,
+        liquidation_cooldowns: LiquidationCooldowns,
     ) -> Liquidator {
-        let liquidator_account = LiquidatorAccount::new(
-            RpcClient::new(general_config.rpc_url.clone()),
-            general_config.liquidator_account,
-            transaction_sender.clone(),
-            general_config.clone(),
-        )
-        .await
-        .unwrap();
+        let liquidator_account =
+            LiquidatorAccount::new(rpc_pool, transaction_sender.clone(), general_config.clone())
+                .await
+                .unwrap();
 
         Liquidator {
             general_config,
@@ -128,89 +189,129 @@ impl Liquidator {
             oracle_to_bank: HashMap::new(),
             stop_liquidation,
             crossbar_client: CrossbarMaintainer::new(),
+            cancellation_token,
+            last_state_save: std::time::Instant::now(),
+            state_save: None,
+            snapshot_refresh: None,
+            updated_during_refresh: HashSet::new(),
+            last_scan: std::time::Instant::now(),
+            pending_liquidations,
+            in_flight_liquidations: InFlightLiquidations::default(),
+            liquidation_cooldowns,
+            ready: false,
+            jupiter_swapper: None,
         }
     }
 
-    /// Loads necessary data to the liquidator
-    pub async fn load_data(&mut self) -> anyhow::Result<()> {
-        let rpc_client = Arc::new(RpcClient::new(self.general_config.rpc_url.clone()));
-        self.load_marginfi_accounts(rpc_client.clone()).await?;
-        self.load_oracles_and_banks(rpc_client.clone()).await?;
-        self.liquidator_account
-            .load_initial_data(rpc_client.as_ref(), self.get_all_mints())
+    /// Liquidations of this signer still in flight, which the
+    /// batches of its rebalancer wait for
+    pub fn in_flight_liquidations(&self) -> InFlightLiquidations {
+        self.in_flight_liquidations.clone()
+    }
+
+    /// Values the seized collateral with the quotes of the swapper
+    /// rather than at the oracle prices
+    pub fn with_jupiter_swapper(mut self, jupiter_swapper: JupiterSwapper) -> Self {
+        self.jupiter_swapper = Some(jupiter_swapper);
+        self
+    }
+
+    /// Loads necessary data to the liquidator, starting from the
+    /// snapshot of the accounts when there is one
+    pub async fn load_data(&mut self, snapshot: Option<StateSnapshot>) -> anyhow::Result<()> {
+        let rpc_client = self.liquidator_account.rpc_pool.rpc();
+        self.load_marginfi_accounts(rpc_client.clone(), snapshot)
             .await?;
+        self.load_oracles_and_banks(rpc_client.clone()).await?;
         Ok(())
     }
 
+    /// Takes the tracked accounts and banks loaded by the liquidator of another signer
+    pub fn copy_data_from(&mut self, other: &Liquidator) {
+        self.marginfi_accounts = other.marginfi_accounts.clone();
+        self.banks = other.banks.clone();
+        self.oracle_to_bank = other.oracle_to_bank.clone();
+        self.snapshot_refresh = other.snapshot_refresh.clone();
+    }
+
+    /// Loads the banks of the marginfi group and their oracles only
+    pub async fn load_banks(&mut self) -> anyhow::Result<()> {
+        let rpc_client = self.liquidator_account.rpc_pool.rpc();
+        self.load_oracles_and_banks(rpc_client).await
+    }
+
     /// Liquidator starts, receiving messages and process them,
-    /// a "timeout" is awaiting for accounts to be evaluated
+    /// a "timeout" is awaiting for accounts to be evaluated,
+    /// returns once the cancellation token is cancelled
     pub async fn start(&mut self) -> anyhow::Result<()> {
         let max_duration = std::time::Duration::from_secs(5);
+        let scan_interval = self
+            .config
+            .scan_interval_ms
+            .map(std::time::Duration::from_millis);
+        let warmup_start = std::time::Instant::now();
+        LIQUIDATOR_READY.set(0);
         loop {
+            if self.cancellation_token.is_cancelled() {
+                // The final snapshot is written once the previous one is
+                if let Some(state_save) = self.state_save.take() {
+                    let _ = state_save.await;
+                }
+                self.save_state();
+                if let Some(state_save) = self.state_save.take() {
+                    let _ = state_save.await;
+                }
+                info!("Liquidator stopped");
+                return Ok(());
+            }
+            if self.last_state_save.elapsed() > STATE_SAVE_INTERVAL {
+                self.save_state();
+            }
             let start = std::time::Instant::now();
-            while let Ok(mut msg) = self.geyser_receiver.recv() {
-                debug!("Received message {:?}", msg);
-                match msg.account_type {
-                    AccountType::OracleAccount => {
-                        if let Some(bank_to_update_pk) = self.oracle_to_bank.get(&msg.address) {
-                            let bank_to_update: &mut BankWrapper =
-                                self.banks.get_mut(bank_to_update_pk).unwrap();
-
-                            let oracle_price_adapter = match bank_to_update.bank.config.oracle_setup
-                            {
-                                OracleSetup::SwitchboardPull => {
-                                    let mut offsets_data =
-                                        [0u8; std::mem::size_of::<PullFeedAccountData>()];
-                                    offsets_data.copy_from_slice(
-                                        &msg.account.data
-                                            [8..std::mem::size_of::<PullFeedAccountData>() + 8],
-                                    );
-                                    let swb_feed = crate::utils::load_swb_pull_account_from_bytes(
-                                        &offsets_data,
-                                    )
-                                    .unwrap();
+            // Only the latest update of each account is applied at the end of the tick,
+            // the intermediate states would be evaluated for nothing
+            let mut updates: HashMap<Pubkey, GeyserUpdate> = HashMap::new();
+            loop {
+                // The wait for the next update ends when the periodic scan is due, both
+                // the updates and the scan lead to the same evaluation of every account
+                let msg = match scan_interval {
+                    Some(scan_interval) => match self
+                        .geyser_receiver
+                        .recv_timeout(scan_interval.saturating_sub(self.last_scan.elapsed()))
+                    {
+                        Ok(msg) => Some(msg),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match self.geyser_receiver.recv() {
+                        Ok(msg) => Some(msg),
+                        Err(_) => break,
+                    },
+                };
+                if self.cancellation_token.is_cancelled() {
+                    break;
+                }
+                if let Some(msg) = msg {
+                    debug!("Received message {:?}", msg);
+                    updates.insert(msg.address, msg);
+                }
 
-                                    let feed_hash = hex::encode(swb_feed.feed_hash);
-                                    bank_to_update.oracle_adapter.swb_feed_hash = Some(feed_hash);
-
-                                    OraclePriceFeedAdapter::SwitchboardPull(
-                                        SwitchboardPullPriceFeed {
-                                            feed: Box::new((&swb_feed).into()),
-                                        },
-                                    )
-                                }
-                                _ => {
-                                    let oracle_account_info =
-                                        (&msg.address, &mut msg.account).into_account_info();
-                                    OraclePriceFeedAdapter::try_from_bank_config_with_max_age(
-                                        &bank_to_update.bank.config,
-                                        &[oracle_account_info],
-                                        &Clock::default(),
-                                        i64::MAX as u64,
-                                    )
-                                    .unwrap()
-                                }
-                            };
-
-                            bank_to_update.oracle_adapter.price_adapter = oracle_price_adapter;
-                        }
+                let scan_due =
+                    scan_interval.is_some_and(|interval| self.last_scan.elapsed() >= interval);
+                if start.elapsed() > max_duration || scan_due {
+                    self.last_scan = std::time::Instant::now();
+                    self.apply_snapshot_refresh();
+                    for (_, msg) in updates.drain() {
+                        self.apply_update(msg);
                     }
-                    AccountType::MarginfiAccount => {
-                        let marginfi_account =
-                            bytemuck::from_bytes::<MarginfiAccount>(&msg.account.data[8..]);
-                        self.marginfi_accounts
-                            .entry(msg.address)
-                            .and_modify(|mrgn_account| {
-                                mrgn_account.account = *marginfi_account;
-                            })
-                            .or_insert_with(|| {
-                                MarginfiAccountWrapper::new(msg.address, *marginfi_account)
-                            });
+                    crate::status::set_tracked_accounts(self.marginfi_accounts.len());
+                    if self.general_config.price_source == PriceSource::PythHermesWs {
+                        self.apply_streamed_prices();
+                    }
+                    // The updates keep being applied during the warm-up
+                    if !self.check_ready(warmup_start) {
+                        break;
                     }
-                    _ => {}
-                };
-
-                if start.elapsed() > max_duration {
                     if self
                         .stop_liquidation
                         .load(std::sync::atomic::Ordering::Relaxed)
@@ -218,27 +319,8 @@ impl Liquidator {
                         break;
                     }
                     if let Ok(mut accounts) = self.process_all_accounts().await {
-                        // Accounts are sorted from the highest profit to the lowest
-                        accounts.sort_by(|a, b| a.profit.cmp(&b.profit));
-                        accounts.reverse();
-                        for account in accounts {
-                            if let Err(e) = self
-                                .liquidator_account
-                                .liquidate(
-                                    &account.liquidate_account,
-                                    &account.asset_bank,
-                                    &account.liab_bank,
-                                    account.asset_amount,
-                                    &account.banks,
-                                )
-                                .await
-                            {
-                                info!(
-                                    "Failed to liquidate account {:?}, error: {:?}",
-                                    account.liquidate_account.address, e
-                                );
-                            }
-                        }
+                        self.rank_accounts(&mut accounts);
+                        self.liquidate_batch(accounts).await;
                     }
                     break;
                 }
@@ -246,10 +328,695 @@ impl Liquidator {
         }
     }
 
-    /// Starts processing/evaluate all account, checking
-    /// if a liquidation is necessary/needed
-    async fn process_all_accounts(&mut self) -> anyhow::Result<Vec<PreparedLiquidatableAccount>> {
-        // Update switchboard pull prices with crossbar
+    /// Applies a geyser update to the tracked banks and accounts
+    pub fn apply_update(&mut self, mut msg: GeyserUpdate) {
+        match msg.account_type {
+            AccountType::OracleAccount => {
+                if let Some(bank_to_update_pk) = self.oracle_to_bank.get(&msg.address) {
+                    let bank_to_update: &mut BankWrapper =
+                        self.banks.get_mut(bank_to_update_pk).unwrap();
+
+                    let oracle_price_adapter = match bank_to_update.bank.config.oracle_setup {
+                        OracleSetup::SwitchboardPull => {
+                            let mut offsets_data =
+                                [0u8; std::mem::size_of::<PullFeedAccountData>()];
+                            offsets_data.copy_from_slice(
+                                &msg.account.data
+                                    [8..std::mem::size_of::<PullFeedAccountData>() + 8],
+                            );
+                            let swb_feed =
+                                crate::utils::load_swb_pull_account_from_bytes(&offsets_data)
+                                    .unwrap();
+
+                            let feed_hash = hex::encode(swb_feed.feed_hash);
+                            bank_to_update.oracle_adapter.swb_feed_hash = Some(feed_hash);
+
+                            OraclePriceFeedAdapter::SwitchboardPull(SwitchboardPullPriceFeed {
+                                feed: Box::new((&swb_feed).into()),
+                            })
+                        }
+                        _ => {
+                            let oracle_account_info =
+                                (&msg.address, &mut msg.account).into_account_info();
+                            OraclePriceFeedAdapter::try_from_bank_config_with_max_age(
+                                &bank_to_update.bank.config,
+                                &[oracle_account_info],
+                                &Clock::default(),
+                                i64::MAX as u64,
+                            )
+                            .unwrap()
+                        }
+                    };
+
+                    bank_to_update.oracle_adapter.price_adapter = oracle_price_adapter;
+                    bank_to_update.oracle_adapter.publish_slot = get_oracle_publish_slot(
+                        bank_to_update.bank.config.oracle_setup,
+                        &msg.account.data,
+                    );
+                    if matches!(
+                        bank_to_update.bank.config.oracle_setup,
+                        OracleSetup::PythPushOracle
+                    ) {
+                        bank_to_update.oracle_adapter.publish_time =
+                            get_pyth_push_publish_time(&msg.account.data);
+                    }
+                }
+            }
+            AccountType::MarginfiAccount => {
+                let marginfi_account =
+                    bytemuck::from_bytes::<MarginfiAccount>(&msg.account.data[8..]);
+                // A recording holds the accounts of every group liquidated
+                if marginfi_account.group != self.general_config.marginfi_group_address {
+                    return;
+                }
+                // The liquidator account is kept fresh for the exposures and observation accounts
+                if msg.address == self.liquidator_account.account_wrapper.address {
+                    self.liquidator_account.refresh_account(*marginfi_account);
+                }
+                if self.snapshot_refresh.is_some() {
+                    self.updated_during_refresh.insert(msg.address);
+                }
+                self.marginfi_accounts
+                    .entry(msg.address)
+                    .and_modify(|mrgn_account| {
+                        mrgn_account.account = *marginfi_account;
+                    })
+                    .or_insert_with(|| MarginfiAccountWrapper::new(msg.address, *marginfi_account));
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces the accounts loaded from the snapshot with their refetched state once
+    /// the refresh is over, except the ones geyser updated in the meantime
+    fn apply_snapshot_refresh(&mut self) {
+        let Some(snapshot_refresh) = self.snapshot_refresh.take() else {
+            return;
+        };
+        let Some(accounts) = snapshot_refresh.accounts() else {
+            self.snapshot_refresh = Some(snapshot_refresh);
+            return;
+        };
+
+        for (address, account) in accounts {
+            if !self.updated_during_refresh.contains(address) {
+                self.insert_marginfi_account(*address, account);
+            }
+        }
+        self.updated_during_refresh.clear();
+    }
+
+    /// Snapshots the tracked accounts in the background, when a state path is configured
+    fn save_state(&mut self) {
+        self.last_state_save = std::time::Instant::now();
+
+        let Some(state_path) = self.general_config.state_path.clone() else {
+            return;
+        };
+
+        if self
+            .state_save
+            .as_ref()
+            .is_some_and(|state_save| !state_save.is_finished())
+        {
+            warn!("Skipping the state save, the previous one is still running");
+            return;
+        }
+
+        let snapshot = match StateSnapshot::new(
+            self.general_config.marginfi_group_address,
+            &self.marginfi_accounts,
+        ) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!("Failed to save the state: {:?}", e);
+                return;
+            }
+        };
+
+        self.state_save = Some(tokio::task::spawn_blocking(move || {
+            if let Err(e) = snapshot.save(&state_path) {
+                error!("Failed to save the state: {:?}", e);
+            }
+        }));
+    }
+
+    /// Liquidates the accounts, grouping the liquidations of distinct accounts
+    /// into bundles of up to `MAX_BUNDLE_SIZE` transactions
+    async fn liquidate_batch(&mut self, mut accounts: Vec<PreparedLiquidatableAccount>) {
+        // The accounts are still tracked while paused, so resuming is instant
+        if crate::admin::is_paused() {
+            debug!(
+                "Submissions are paused, skipping {} liquidatable accounts",
+                accounts.len()
+            );
+            return;
+        }
+
+        // The opportunities are still evaluated, so they are logged
+        if crate::spend_budget::is_exhausted() {
+            debug!(
+                "The fee and tip budget is exhausted, skipping {} liquidatable accounts",
+                accounts.len()
+            );
+            return;
+        }
+
+        // The remaining SOL of the signer is kept for the most profitable liquidation
+        if crate::sol_balance::is_sol_balance_low(&self.general_config.signer_pubkey) {
+            if let Some(most_profitable) = accounts
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.profit.cmp(&b.profit))
+                .map(|(index, _)| index)
+            {
+                accounts = vec![accounts.swap_remove(most_profitable)];
+            }
+        }
+
+        if accounts.is_empty() {
+            return;
+        }
+
+        // With several signers, the opportunities are left to the free ones
+        // while the liquidations of this one are in flight
+        let in_flight_liquidations = {
+            let mut in_flight_liquidations = self.in_flight_liquidations.lock().unwrap();
+            in_flight_liquidations.retain(|liquidation_guard| liquidation_guard.strong_count() > 0);
+            in_flight_liquidations.len()
+        };
+        if !self.general_config.additional_signers.is_empty() && in_flight_liquidations > 0 {
+            debug!(
+                "Signer {} is busy with {} liquidations",
+                self.general_config.signer_pubkey, in_flight_liquidations
+            );
+            return;
+        }
+
+        let current_slot = match self
+            .liquidator_account
+            .rpc_pool
+            .non_blocking_rpc()
+            .get_slot()
+            .await
+        {
+            Ok(slot) => slot,
+            Err(e) => {
+                error!("Failed to get the current slot: {:?}", e);
+                return;
+            }
+        };
+
+        // Value held of each capped asset, grown by the liquidations of the batch
+        let mut asset_exposures = match self.get_asset_exposures().await {
+            Ok(asset_exposures) => asset_exposures,
+            Err(e) => {
+                error!("Failed to get the asset exposures: {:?}", e);
+                return;
+            }
+        };
+
+        // Banks the liquidations of the batch open on the liquidator account, the
+        // following liquidations observe them before its state reflects them
+        let mut batch_banks: Vec<Pubkey> = vec![];
+        // Banks opened by the bundles of the batch already sent
+        let mut sent_batch_banks: Vec<Pubkey> = vec![];
+        // A transaction of the bundle is kept for the tip funding, when enabled
+        let max_bundle_size =
+            if self.general_config.tip_funding_strategy == TipFundingStrategy::MarginfiSolDeposit {
+                MAX_BUNDLE_SIZE - 1
+            } else {
+                MAX_BUNDLE_SIZE
+            };
+        let mut bundle: BatchTransactions = vec![];
+        for mut account in accounts {
+            let liquidation_key = (
+                account.liquidate_account.address,
+                account.asset_bank.address,
+                account.liab_bank.address,
+            );
+            if let Some(remaining) = self.liquidation_cooldowns.remaining(&liquidation_key) {
+                debug!(
+                    "Skipping liquidation of account {:?}, it is on cooldown for {:?} after failing repeatedly",
+                    account.liquidate_account.address, remaining
+                );
+                continue;
+            }
+
+            let Some(liquidation_guard) = LiquidationGuard::new(
+                &self.pending_liquidations,
+                account.liquidate_account.address,
+            ) else {
+                debug!(
+                    "Skipping liquidation of account {:?}, one is already in flight",
+                    account.liquidate_account.address
+                );
+                continue;
+            };
+
+            // A liquidation against a stale oracle fails on chain, skip it before paying for it
+            if let Some(stale_bank) = [&account.asset_bank, &account.liab_bank]
+                .into_iter()
+                .find(|bank| bank.is_oracle_stale(current_slot, self.config.max_oracle_age_slots))
+            {
+                info!(
+                    "Skipping liquidation of account {:?}, the oracle {} of bank {} is stale",
+                    account.liquidate_account.address,
+                    stale_bank.oracle_adapter.address,
+                    stale_bank.address
+                );
+                continue;
+            }
+
+            if self.config.confirm_before_liquidate {
+                match self.is_liquidatable_at_confirmed(&account).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        info!(
+                            "Skipping liquidation of account {:?}, it isn't liquidatable at the confirmed commitment",
+                            account.liquidate_account.address
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to confirm the liquidation of account {:?}: {:?}",
+                            account.liquidate_account.address, e
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            match self.fit_asset_exposure(&account, &mut asset_exposures) {
+                Ok(Some(asset_amount)) if asset_amount < account.asset_amount => {
+                    account.profit = account.profit * I80F48::from_num(asset_amount)
+                        / I80F48::from_num(account.asset_amount);
+                    account.asset_amount = asset_amount;
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => continue,
+                Err(e) => {
+                    error!(
+                        "Failed to check the asset exposure of the liquidation of account {:?}: {:?}",
+                        account.liquidate_account.address, e
+                    );
+                    continue;
+                }
+            }
+
+            if let Some(jupiter_swapper) = &self.jupiter_swapper {
+                match self.quote_liquidation_profit(&account, jupiter_swapper).await {
+                    Ok(profit) if profit < I80F48::from_num(self.config.min_profit_usd) => {
+                        debug!(
+                            "Skipping liquidation of account {:?}, its profit of ${:.4} at the oracle price is ${:.4} once the collateral is quoted",
+                            account.liquidate_account.address, account.profit, profit
+                        );
+                        continue;
+                    }
+                    Ok(profit) => account.profit = profit,
+                    // The oracle valuation is kept, rather than missing the liquidation
+                    Err(e) => warn!(
+                        "Failed to quote the collateral of the liquidation of account {:?}, valuing it at the oracle price: {:?}",
+                        account.liquidate_account.address, e
+                    ),
+                }
+            }
+
+            LIQUIDATIONS_ATTEMPTED.inc();
+            let timer = LIQUIDATION_LATENCY.start_timer();
+            // A slow account is abandoned so it doesn't hold back the next ones,
+            // the blocking rpc calls of the build can't be interrupted though
+            let max_evaluation = std::time::Duration::from_millis(self.config.max_evaluation_ms);
+            let Ok(build_result) = tokio::time::timeout(
+                max_evaluation,
+                self.liquidator_account.build_liquidation(
+                    &account.liquidate_account,
+                    &account.asset_bank,
+                    &account.liab_bank,
+                    account.asset_amount,
+                    &account.banks,
+                    account.profit,
+                    &batch_banks,
+                ),
+            )
+            .await
+            else {
+                info!(
+                    "Abandoned the liquidation of account {:?}, it took more than {:?} to build",
+                    account.liquidate_account.address, max_evaluation
+                );
+                continue;
+            };
+            let mut transactions = match build_result {
+                Ok(transactions) => transactions,
+                Err(e) => {
+                    LIQUIDATIONS_FAILED.inc();
+                    self.liquidation_cooldowns
+                        .record_outcome(liquidation_key, false);
+                    info!(
+                        "Failed to liquidate account {:?}, error: {:?}",
+                        account.liquidate_account.address, e
+                    );
+                    notify(format!(
+                        "Failed to liquidate account {}: {}",
+                        account.liquidate_account.address, e
+                    ));
+                    continue;
+                }
+            };
+            timer.observe_duration();
+
+            // The oracle updates can't be split from the liquidation reading them
+            if transactions.len() > max_bundle_size {
+                info!(
+                    "Skipping liquidation of account {:?}, its {} transactions don't fit in a bundle of {}",
+                    account.liquidate_account.address,
+                    transactions.len(),
+                    max_bundle_size
+                );
+                continue;
+            }
+
+            if let Some(mut liquidate_transaction) = transactions.pop() {
+                if let Some(PnlEntry::Liquidation(liquidation)) = liquidate_transaction.pnl.as_mut()
+                {
+                    liquidation.expected_swap_slippage =
+                        self.expected_swap_slippage(liquidation.collateral_value);
+                }
+                let liquidation_guard = Arc::new(liquidation_guard.with_cooldown(
+                    self.liquidation_cooldowns.clone(),
+                    account.asset_bank.address,
+                    account.liab_bank.address,
+                ));
+                self.in_flight_liquidations
+                    .lock()
+                    .unwrap()
+                    .push(Arc::downgrade(&liquidation_guard));
+                transactions.push(liquidate_transaction.with_liquidation_guard(liquidation_guard));
+            }
+
+            if !bundle.is_empty() && bundle.len() + transactions.len() > max_bundle_size {
+                self.send_bundle(std::mem::take(&mut bundle), &sent_batch_banks)
+                    .await;
+                sent_batch_banks = batch_banks.clone();
+            }
+            bundle.extend(transactions);
+
+            for bank_pk in [account.liab_bank.address, account.asset_bank.address] {
+                if !batch_banks.contains(&bank_pk) {
+                    batch_banks.push(bank_pk);
+                }
+            }
+        }
+
+        if !bundle.is_empty() {
+            self.send_bundle(bundle, &sent_batch_banks).await;
+        }
+    }
+
+    /// Tops up the signer from the SOL deposit of the liquidator account when its native
+    /// balance can't cover the tip and the fees of the bundle, at the highest tip of its
+    /// retries. The withdrawal is placed before the first liquidation, the observation
+    /// accounts being those of the liquidator account before any liquidation of the
+    /// bundle, with the `batch_banks` opened by the bundles of the batch sent before
+    async fn fund_tip(
+        &mut self,
+        bundle: &mut BatchTransactions,
+        batch_banks: &[Pubkey],
+    ) -> anyhow::Result<()> {
+        if bundle.len() >= MAX_BUNDLE_SIZE {
+            anyhow::bail!("The bundle has no room left for the tip funding");
+        }
+
+        let expected_profit_lamports = bundle
+            .iter()
+            .filter_map(|raw_transaction| raw_transaction.expected_profit_lamports)
+            .reduce(u64::saturating_add);
+        let tip_lamports = self
+            .general_config
+            .tip_strategy
+            .tip_lamports(expected_profit_lamports);
+        let max_bump_bps = (self.general_config.jito_retry_tip_bump_bps as u64)
+            .saturating_mul(self.general_config.jito_max_retries);
+        let tip_lamports =
+            tip_lamports.saturating_add(tip_lamports.saturating_mul(max_bump_bps) / 10_000);
+        let fees_lamports = bundle
+            .iter()
+            .map(TransactionManager::raw_transaction_fees_lamports)
+            .sum::<u64>()
+            + LIQUIDATION_SIGNATURE_FEES_LAMPORTS;
+        // The signer has to stay rent exempt once the tip and the fees are paid
+        let needed_lamports = tip_lamports
+            .saturating_add(fees_lamports)
+            .saturating_add(Rent::default().minimum_balance(0));
+
+        let balance = self
+            .liquidator_account
+            .rpc_pool
+            .non_blocking_rpc()
+            .get_balance(&self.general_config.signer_pubkey)
+            .await?;
+        if balance >= needed_lamports {
+            return Ok(());
+        }
+
+        let lamports = needed_lamports - balance;
+        let Some(tip_funding) = self
+            .liquidator_account
+            .build_tip_funding(lamports, &self.banks, batch_banks)
+            .await?
+        else {
+            anyhow::bail!(
+                "No SOL deposit of the liquidator account holds the {} lamports missing",
+                lamports
+            );
+        };
+        debug!(
+            "Funding the tip of the bundle with {} lamports of the SOL deposit",
+            lamports
+        );
+
+        let first_liquidation = bundle
+            .iter()
+            .position(|raw_transaction| raw_transaction.expected_profit_lamports.is_some())
+            .unwrap_or(0);
+        bundle.insert(first_liquidation, tip_funding);
+
+        Ok(())
+    }
+
+    /// Whether the account is still below its maintenance requirement once read at
+    /// the confirmed commitment, valued with the banks it was found liquidatable with
+    async fn is_liquidatable_at_confirmed(
+        &self,
+        account: &PreparedLiquidatableAccount,
+    ) -> anyhow::Result<bool> {
+        let address = account.liquidate_account.address;
+        let Some(confirmed_account) = self
+            .liquidator_account
+            .rpc_pool
+            .non_blocking_rpc()
+            .get_account_with_commitment(&address, CommitmentConfig::confirmed())
+            .await?
+            .value
+        else {
+            return Ok(false);
+        };
+
+        let confirmed_account = MarginfiAccountWrapper {
+            address,
+            account: *bytemuck::from_bytes::<MarginfiAccount>(&confirmed_account.data[8..]),
+        };
+        let (assets, liabs) =
+            confirmed_account.calc_health(&account.banks, RequirementType::Maintenance)?;
+
+        Ok(assets < liabs)
+    }
+
+    /// Value in USD held of each asset of `max_asset_exposure`,
+    /// its deposits in the liquidator account and its token account
+    async fn get_asset_exposures(&self) -> anyhow::Result<HashMap<Pubkey, I80F48>> {
+        let mut asset_exposures = HashMap::new();
+        let mut token_accounts = vec![];
+        for mint in self.config.max_asset_exposure.keys() {
+            let mut deposits_value = I80F48::ZERO;
+            for bank in self.banks.values().filter(|bank| bank.bank.mint == *mint) {
+                let (asset_amount, _) = self.get_balance_for_bank(
+                    &self.liquidator_account.account_wrapper,
+                    &bank.address,
+                )?;
+                deposits_value +=
+                    bank.calc_value(asset_amount, BalanceSide::Assets, RequirementType::Equity)?;
+            }
+            asset_exposures.insert(*mint, deposits_value);
+
+            if let Some(bank) = self.banks.values().find(|bank| bank.bank.mint == *mint) {
+                token_accounts.push((
+                    bank,
+                    get_associated_token_address_with_program_id(
+                        &self.general_config.signer_pubkey,
+                        mint,
+                        &bank.token_program,
+                    ),
+                ));
+            }
+        }
+
+        if token_accounts.is_empty() {
+            return Ok(asset_exposures);
+        }
+
+        let addresses = token_accounts
+            .iter()
+            .map(|(_, address)| *address)
+            .collect::<Vec<_>>();
+        let accounts = self
+            .liquidator_account
+            .rpc_pool
+            .non_blocking_rpc()
+            .get_multiple_accounts(&addresses)
+            .await?;
+
+        for ((bank, _), account) in token_accounts.into_iter().zip(accounts) {
+            // The amount follows the mint and the owner, for spl-token and token-2022
+            let Some(amount) = account
+                .and_then(|account| account.data.get(64..72).map(|bytes| bytes.to_vec()))
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            else {
+                continue;
+            };
+
+            *asset_exposures.entry(bank.bank.mint).or_default() += bank.calc_value(
+                I80F48::from_num(amount),
+                BalanceSide::Assets,
+                RequirementType::Equity,
+            )?;
+        }
+
+        Ok(asset_exposures)
+    }
+
+    /// Amount of collateral the liquidation can seize without pushing the value held
+    /// of the asset past its `max_asset_exposure`, none when the cap is already reached
+    fn fit_asset_exposure(
+        &self,
+        account: &PreparedLiquidatableAccount,
+        asset_exposures: &mut HashMap<Pubkey, I80F48>,
+    ) -> anyhow::Result<Option<u64>> {
+        let mint = account.asset_bank.bank.mint;
+        let Some(max_exposure) = self.config.max_asset_exposure.get(&mint) else {
+            return Ok(Some(account.asset_amount));
+        };
+        let max_exposure = I80F48::from_num(*max_exposure);
+
+        let exposure = asset_exposures.entry(mint).or_default();
+        let room = max_exposure - *exposure;
+        if !room.is_positive() {
+            info!(
+                "Skipping liquidation of account {:?}, ${:.2} of {} are already held, the cap is ${:.2}",
+                account.liquidate_account.address, *exposure, mint, max_exposure
+            );
+            return Ok(None);
+        }
+
+        let value = account.asset_bank.calc_value(
+            I80F48::from_num(account.asset_amount),
+            BalanceSide::Assets,
+            RequirementType::Equity,
+        )?;
+        if value <= room {
+            *exposure += value;
+            return Ok(Some(account.asset_amount));
+        }
+
+        let asset_amount = (I80F48::from_num(account.asset_amount) * room / value).to_num::<u64>();
+        info!(
+            "Downsizing liquidation of account {:?} from {} to {} of {}, ${:.2} of it are already held, the cap is ${:.2}",
+            account.liquidate_account.address,
+            account.asset_amount,
+            asset_amount,
+            mint,
+            *exposure,
+            max_exposure
+        );
+        *exposure += room;
+
+        Ok(Some(asset_amount))
+    }
+
+    /// Whether the tracked state is complete enough to act on: the banks are loaded
+    /// and every one of them has an oracle price, or the warm-up timed out
+    fn check_ready(&mut self, warmup_start: std::time::Instant) -> bool {
+        if self.ready {
+            return true;
+        }
+        if self.banks.is_empty() {
+            return false;
+        }
+
+        let banks_without_price = self
+            .banks
+            .values()
+            .filter(|bank| {
+                !bank
+                    .oracle_adapter
+                    .get_price_of_type(OraclePriceType::RealTime, None)
+                    .is_ok_and(|price| price.is_positive())
+            })
+            .map(|bank| bank.address)
+            .collect::<Vec<_>>();
+
+        if banks_without_price.is_empty() {
+            info!(
+                "Liquidator is ready, tracking {} accounts and {} banks",
+                self.marginfi_accounts.len(),
+                self.banks.len()
+            );
+        } else if warmup_start.elapsed() > WARMUP_TIMEOUT {
+            warn!(
+                "Liquidator is ready, without an oracle price for the banks {:?}",
+                banks_without_price
+            );
+        } else {
+            return false;
+        }
+
+        self.ready = true;
+        LIQUIDATOR_READY.set(1);
+        true
+    }
+
+    /// Sends the bundle, `batch_banks` being the banks of the liquidator account
+    /// opened by the bundles of the batch sent before
+    async fn send_bundle(&mut self, mut bundle: BatchTransactions, batch_banks: &[Pubkey]) {
+        if self.general_config.tip_funding_strategy == TipFundingStrategy::MarginfiSolDeposit {
+            if let Err(e) = self.fund_tip(&mut bundle, batch_banks).await {
+                warn!(
+                    "Failed to fund the tip from the SOL deposit, the signer pays it: {:?}",
+                    e
+                );
+            }
+        }
+
+        if let Err(e) = self.transaction_sender.send(bundle) {
+            error!("Failed to send the liquidation bundle: {:?}", e);
+        }
+    }
+
+    /// Hands the pyth prices streamed by hermes to the banks, so price moves are
+    /// seen before the oracle accounts are updated. The liquidation bundles post
+    /// the latest hermes prices on chain, so they are liquidated at these prices
+    fn apply_streamed_prices(&mut self) {
+        for bank in self.banks.values_mut() {
+            if let Some((_, feed_id)) = bank.get_pyth_push_oracle_feed() {
+                bank.oracle_adapter.streamed_price = crate::pyth::get_streamed_price(&feed_id);
+            }
+        }
+    }
+
+    /// Updates the simulated prices of the switchboard pull oracles with crossbar
+    async fn update_swb_simulated_prices(&mut self) {
         let swb_feed_hashes = self
             .banks
             .values()
@@ -268,14 +1035,98 @@ impl Liquidator {
             let bank = self.banks.get_mut(&bank_pk).unwrap();
             bank.oracle_adapter.simulated_price = Some(price);
         }
+    }
+
+    /// Evaluates every account with liabilities below the health threshold, ranked
+    /// from the highest estimated profit to the lowest, without liquidating them
+    pub async fn scan(&mut self) -> Vec<LiquidationOpportunity> {
+        self.update_swb_simulated_prices().await;
+
+        let mut opportunities = self
+            .marginfi_accounts
+            .par_iter()
+            .filter_map(|(_, account)| {
+                let health_factor = Self::liquidatable_health_factor(
+                    account,
+                    &self.liquidator_account.account_wrapper.address,
+                    &self.banks,
+                    self.config.health_threshold,
+                )
+                .ok()??;
+
+                let (asset_bank_pk, liab_bank_pk) =
+                    self.find_liquidation_bank_candidates(account).ok()??;
+
+                let (max_liquidation_amount, profit) = self
+                    .compute_max_liquidatble_asset_amount_with_banks(
+                        account,
+                        &asset_bank_pk,
+                        &liab_bank_pk,
+                    )
+                    .ok()?;
+
+                let asset_decimals = self.banks.get(&asset_bank_pk)?.bank.mint_decimals;
+
+                Some(LiquidationOpportunity {
+                    address: account.address,
+                    health_factor,
+                    asset_bank: asset_bank_pk,
+                    liab_bank: liab_bank_pk,
+                    max_liquidation_amount: max_liquidation_amount
+                        / EXP_10_I80F48[asset_decimals as usize],
+                    profit,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        opportunities.sort_by(|a, b| b.profit.cmp(&a.profit));
+
+        opportunities
+    }
+
+    /// Health factor of the account when it's a liquidation candidate: an account with
+    /// liabilities below the health threshold, other than the liquidator account,
+    /// which is never liquidated by the liquidator itself
+    fn liquidatable_health_factor(
+        account: &MarginfiAccountWrapper,
+        liquidator_account: &Pubkey,
+        banks: &HashMap<Pubkey, BankWrapper>,
+        health_threshold: f64,
+    ) -> anyhow::Result<Option<I80F48>> {
+        if account.address == *liquidator_account || !account.has_liabs() {
+            return Ok(None);
+        }
+
+        let health_factor = account.calc_health_factor(banks)?;
+
+        Ok((health_factor.to_num::<f64>() < health_threshold).then_some(health_factor))
+    }
+
+    /// Starts processing/evaluate all account, checking
+    /// if a liquidation is necessary/needed
+    async fn process_all_accounts(&mut self) -> anyhow::Result<Vec<PreparedLiquidatableAccount>> {
+        self.update_swb_simulated_prices().await;
 
         let accounts = self
             .marginfi_accounts
             .par_iter()
             .filter_map(|(_, account)| {
-                if !account.has_liabs() {
-                    return None;
-                }
+                let health_factor = match Self::liquidatable_health_factor(
+                    account,
+                    &self.liquidator_account.account_wrapper.address,
+                    &self.banks,
+                    self.config.health_threshold,
+                ) {
+                    Ok(Some(health_factor)) => health_factor,
+                    Ok(None) => return None,
+                    Err(e) => {
+                        debug!(
+                            "Failed to compute the health factor of account {}: {:?}",
+                            account.address, e
+                        );
+                        return None;
+                    }
+                };
 
                 let (deposit_shares, liabs_shares) = account.get_deposits_and_liabilities_shares();
 
@@ -306,7 +1157,7 @@ impl Liquidator {
                         }
                     };
 
-                let (max_liquidation_amount, profit) = self
+                let (max_liquidation_amount, _) = self
                     .compute_max_liquidatble_asset_amount_with_banks(
                         account,
                         &asset_bank_pk,
@@ -317,7 +1168,7 @@ impl Liquidator {
                     })
                     .ok()?;
 
-                if max_liquidation_amount.is_zero() || profit < self.config.min_profit {
+                if max_liquidation_amount.is_zero() {
                     return None;
                 }
 
@@ -339,13 +1190,35 @@ impl Liquidator {
 
                 let slippage_adjusted_asset_amount = asset_amount_to_liquidate * I80F48!(0.95);
 
+                let value = asset_bank
+                    .calc_value(
+                        slippage_adjusted_asset_amount,
+                        BalanceSide::Assets,
+                        RequirementType::Equity,
+                    )
+                    .ok()?;
+
+                // The profit is the one of the capped amount actually submitted
+                let profit = self.compute_liquidation_profit(value);
+
+                info!(
+                    "Account {:?} liquidation estimated net profit: {:.4}$",
+                    account.address, profit
+                );
+
+                if profit < self.config.min_profit_usd {
+                    return None;
+                }
+
                 Some(PreparedLiquidatableAccount {
                     liquidate_account: account.clone(),
                     asset_bank: asset_bank.clone(),
                     liab_bank: liab_bank.clone(),
                     asset_amount: slippage_adjusted_asset_amount.to_num(),
                     banks: self.banks.clone(),
-                    profit: profit.to_num(),
+                    profit,
+                    health_factor,
+                    value,
                 })
             })
             .collect::<Vec<_>>();
@@ -353,6 +1226,30 @@ impl Liquidator {
         Ok(accounts)
     }
 
+    /// Sorts the liquidatable accounts in the order they are liquidated,
+    /// following the `ranking_strategy`
+    fn rank_accounts(&self, accounts: &mut [PreparedLiquidatableAccount]) {
+        match self.config.ranking_strategy {
+            RankingStrategy::HighestProfit => accounts.sort_by(|a, b| b.profit.cmp(&a.profit)),
+            RankingStrategy::LowestHealth => {
+                accounts.sort_by(|a, b| a.health_factor.cmp(&b.health_factor))
+            }
+            RankingStrategy::LargestSize => accounts.sort_by(|a, b| b.value.cmp(&a.value)),
+        }
+
+        for (rank, account) in accounts.iter().enumerate() {
+            debug!(
+                "Liquidation rank {} ({:?}): account {}, profit: {:.4}$, health factor: {:.4}, value: {:.2}$",
+                rank,
+                self.config.ranking_strategy,
+                account.liquidate_account.address,
+                account.profit,
+                account.health_factor,
+                account.value
+            );
+        }
+    }
+
     fn get_max_borrow_for_bank(&self, bank_pk: &Pubkey) -> anyhow::Result<I80F48> {
         let free_collateral = self.get_free_collateral()?;
 
@@ -361,6 +1258,11 @@ impl Liquidator {
         let (asset_amount, _) =
             self.get_balance_for_bank(&self.liquidator_account.account_wrapper, bank_pk)?;
 
+        // A reduce-only bank doesn't lend, the liability is only repaid with the deposit
+        if !bank.accepts_increases() {
+            return Ok(asset_amount);
+        }
+
         let untied_collateral_for_bank = min(
             free_collateral,
             bank.calc_value(asset_amount, BalanceSide::Assets, RequirementType::Initial)?,
@@ -397,10 +1299,10 @@ impl Liquidator {
     }
 
     fn get_free_collateral(&self) -> anyhow::Result<I80F48> {
-        let (assets, liabs) = self.calc_health(
-            &self.liquidator_account.account_wrapper,
-            RequirementType::Initial,
-        );
+        let (assets, liabs) = self
+            .liquidator_account
+            .account_wrapper
+            .calc_health(&self.banks, RequirementType::Initial)?;
         if assets > liabs {
             Ok(assets - liabs)
         } else {
@@ -431,34 +1333,66 @@ impl Liquidator {
             return Ok(None);
         }
 
-        let (_, asset_bank) = deposit_values
+        // Banks excluded by the operator can be neither seized nor repaid, an account whose
+        // only candidates are excluded is skipped. The seized collateral is deposited into
+        // the liquidator account, which a reduce-only or paused bank rejects, while a
+        // reduce-only liability bank can still be repaid
+        let (_, asset_bank) = match deposit_values
             .iter()
+            .filter(|(_, bank_pk)| self.config.is_bank_allowed(bank_pk))
+            .filter(|(_, bank_pk)| {
+                self.is_bank_usable(account, bank_pk, "seized", BankWrapper::accepts_increases)
+            })
             .max_by(|a, b| a.0.cmp(&b.0))
-            .ok_or_else(|| anyhow::anyhow!("No asset bank found"))?;
+        {
+            Some(asset_bank) => asset_bank,
+            None => return Ok(None),
+        };
 
-        let (_, liab_bank) = liab_values
+        let (_, liab_bank) = match liab_values
             .iter()
+            .filter(|(_, bank_pk)| self.config.is_bank_allowed(bank_pk))
+            .filter(|(_, bank_pk)| {
+                self.is_bank_usable(account, bank_pk, "repaid", |bank| !bank.is_paused())
+            })
             .max_by(|a, b| a.0.cmp(&b.0))
-            .ok_or_else(|| anyhow::anyhow!("No liability bank found"))?;
+        {
+            Some(liab_bank) => liab_bank,
+            None => return Ok(None),
+        };
 
         Ok(Some((*asset_bank, *liab_bank)))
     }
 
-    /// Computes the max liquidatable asset amount
+    /// Whether the operational state of the bank allows the liquidation, logs the skip otherwise
+    fn is_bank_usable(
+        &self,
+        account: &MarginfiAccountWrapper,
+        bank_pk: &Pubkey,
+        operation: &str,
+        is_allowed: impl Fn(&BankWrapper) -> bool,
+    ) -> bool {
+        let Some(bank) = self.banks.get(bank_pk) else {
+            return false;
+        };
+        if is_allowed(bank) {
+            return true;
+        }
+
+        debug!(
+            "Bank {} of account {} can't be {}, it is {:?}",
+            bank_pk, account.address, operation, bank.bank.config.operational_state
+        );
+        false
+    }
+
+    /// Computes the max liquidatable asset amount and the profit of its liquidation
     fn compute_max_liquidatble_asset_amount_with_banks(
         &self,
         account: &MarginfiAccountWrapper,
         asset_bank_pk: &Pubkey,
         liab_bank_pk: &Pubkey,
     ) -> anyhow::Result<(I80F48, I80F48)> {
-        let (assets, liabs) = self.calc_health(account, RequirementType::Maintenance);
-
-        let maintenance_health = assets - liabs;
-
-        if maintenance_health >= I80F48::ZERO {
-            return Ok((I80F48::ZERO, I80F48::ZERO));
-        }
-
         let asset_bank = self
             .banks
             .get(asset_bank_pk)
@@ -469,76 +1403,86 @@ impl Liquidator {
             .get(liab_bank_pk)
             .ok_or_else(|| anyhow::anyhow!("Liab bank {} not found", liab_bank_pk))?;
 
-        let asset_weight_maint: I80F48 = asset_bank.bank.config.asset_weight_maint.into();
-        let liab_weight_maint: I80F48 = liab_bank.bank.config.asset_weight_maint.into();
-
-        let liquidation_discount = fixed_macro::types::I80F48!(0.95);
+        let (max_liquidatable_asset_amount, max_liquidatable_value) =
+            account.max_liquidatable_amount(asset_bank, liab_bank, &self.banks)?;
 
-        let all = asset_weight_maint - liab_weight_maint * liquidation_discount;
+        let liquidator_profit = self.compute_liquidation_profit(max_liquidatable_value);
 
-        if all == I80F48::ZERO {
+        if liquidator_profit <= I80F48::ZERO {
             return Ok((I80F48::ZERO, I80F48::ZERO));
         }
 
-        let underwater_maint_value =
-            maintenance_health / (asset_weight_maint - liab_weight_maint * liquidation_discount);
+        if liquidator_profit > self.config.min_profit_usd {
+            debug!("Account {:?}", account.address);
+            debug!("Liquidator profit {:?}", liquidator_profit);
+        }
 
-        let (asset_amount, _) = self.get_balance_for_bank(account, asset_bank_pk)?;
-        let (_, liab_amount) = self.get_balance_for_bank(account, liab_bank_pk)?;
+        Ok((max_liquidatable_asset_amount, liquidator_profit))
+    }
 
-        let asset_value = asset_bank.calc_value(
-            asset_amount,
-            BalanceSide::Assets,
-            RequirementType::Maintenance,
-        )?;
+    /// Estimates the net profit of a liquidation, in USD: the liquidator fee
+    /// minus the expected swap slippage of the seized asset, the jito tip
+    /// and the transaction fees
+    fn compute_liquidation_profit(&self, liquidation_value: I80F48) -> I80F48 {
+        let gross_profit = liquidation_value * LIQUIDATION_LIQUIDATOR_FEE;
 
-        let liab_value = liab_bank.calc_value(
-            liab_amount,
-            BalanceSide::Liabilities,
-            RequirementType::Maintenance,
-        )?;
+        let swap_slippage = self.expected_swap_slippage(liquidation_value);
 
-        let max_liquidatable_value = min(min(asset_value, liab_value), underwater_maint_value);
-        let liquidator_profit = max_liquidatable_value * fixed_macro::types::I80F48!(0.025);
+        gross_profit - swap_slippage - self.liquidation_fees(gross_profit)
+    }
 
-        if liquidator_profit <= I80F48::ZERO {
-            return Ok((I80F48::ZERO, I80F48::ZERO));
-        }
+    /// Slippage expected selling the seized collateral, in USD
+    fn expected_swap_slippage(&self, liquidation_value: I80F48) -> I80F48 {
+        liquidation_value * I80F48::from_num(self.config.swap_slippage_bps)
+            / I80F48::from_num(10_000)
+    }
 
-        let max_liquidatable_asset_amount = asset_bank.calc_amount(
-            max_liquidatable_value,
+    /// Estimates the net profit of a liquidation like `compute_liquidation_profit`,
+    /// the seized collateral being valued at the Jupiter quote for selling it
+    /// into the liability token rather than at its oracle price
+    async fn quote_liquidation_profit(
+        &self,
+        account: &PreparedLiquidatableAccount,
+        jupiter_swapper: &JupiterSwapper,
+    ) -> anyhow::Result<I80F48> {
+        let liquidation_value = account.asset_bank.calc_value(
+            I80F48::from_num(account.asset_amount),
             BalanceSide::Assets,
-            RequirementType::Maintenance,
+            RequirementType::Equity,
         )?;
 
-        if liquidator_profit > self.config.min_profit {
-            debug!("Account {:?}", account.address);
-            debug!("Health {:?}", maintenance_health);
-            debug!("Liquidator profit {:?}", liquidator_profit);
-        }
+        let out_amount = jupiter_swapper
+            .quote_out_amount(
+                account.asset_bank.bank.mint,
+                account.liab_bank.bank.mint,
+                account.asset_amount,
+            )
+            .await?;
+        let quoted_value = account.liab_bank.calc_value(
+            I80F48::from_num(out_amount),
+            BalanceSide::Assets,
+            RequirementType::Equity,
+        )?;
 
-        Ok((max_liquidatable_asset_amount, liquidator_profit))
+        // The liability is repaid at the oracle price, discounted by the liquidator fee
+        let gross_profit =
+            quoted_value - liquidation_value * (I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE);
+
+        Ok(gross_profit - self.liquidation_fees(gross_profit))
     }
 
-    /// Calculates the health of a given account
-    fn calc_health(
-        &self,
-        account: &MarginfiAccountWrapper,
-        requirement_type: RequirementType,
-    ) -> (I80F48, I80F48) {
-        let baws =
-            BankAccountWithPriceFeedEva::load(&account.account.lending_account, self.banks.clone())
-                .unwrap();
+    /// Jito tip and transaction fees of a liquidation, in USD
+    fn liquidation_fees(&self, gross_profit: I80F48) -> I80F48 {
+        let tip_lamports = self
+            .general_config
+            .tip_strategy
+            .tip_lamports(crate::utils::usd_to_lamports(gross_profit, &self.banks));
 
-        baws.iter().fold(
-            (I80F48::ZERO, I80F48::ZERO),
-            |(total_assets, total_liabs), baw| {
-                let (assets, liabs) = baw
-                    .calc_weighted_assets_and_liabilities_values(requirement_type)
-                    .unwrap();
-                (total_assets + assets, total_liabs + liabs)
-            },
+        crate::utils::lamports_to_usd(
+            tip_lamports + LIQUIDATION_SIGNATURE_FEES_LAMPORTS,
+            &self.banks,
         )
+        .unwrap_or(I80F48::ZERO)
     }
 
     /// Gets the balance for a given [`MarginfiAccount`] and [`Bank`]
@@ -583,14 +1527,59 @@ impl Liquidator {
     pub async fn load_marginfi_accounts(
         &mut self,
         rpc_client: Arc<RpcClient>,
+        snapshot: Option<StateSnapshot>,
     ) -> anyhow::Result<()> {
-        info!("Loading marginfi accounts, this may take a few minutes, please wait!");
+        info!("Loading marginfi accounts, please wait!");
         let start = std::time::Instant::now();
-        let marginfi_accounts_pubkeys = self
+
+        // Without a snapshot nor a whitelist, every account of the group is needed,
+        // they are bulk loaded with their data in a single `getProgramAccounts` call
+        if snapshot.is_none() && self.general_config.account_whitelist.is_none() {
+            let marginfi_accounts = self
+                .get_marginfi_program_accounts(rpc_client.clone(), false)
+                .await?;
+
+            info!("Fetched {} marginfi accounts", marginfi_accounts.len());
+
+            for (address, account) in marginfi_accounts.iter() {
+                self.insert_marginfi_account(*address, account);
+            }
+
+            info!("Loaded marginfi accounts in {:?}", start.elapsed());
+
+            return Ok(());
+        }
+
+        let mut marginfi_accounts_pubkeys = self
             .load_marginfi_account_addresses(rpc_client.clone())
             .await?;
 
-        let mut marginfi_accounts = batch_get_multiple_accounts(
+        // Only the accounts opened since the snapshot was saved are fetched now and the
+        // closed ones are dropped, the others are refetched in the background as they
+        // may have changed since, and are refreshed by geyser as they change
+        if let Some(snapshot) = snapshot {
+            let mut snapshot_accounts = snapshot.into_accounts();
+            info!(
+                "Loaded {} marginfi accounts from the state",
+                snapshot_accounts.len()
+            );
+
+            for address in marginfi_accounts_pubkeys.iter() {
+                if let Some(account) = snapshot_accounts.remove(address) {
+                    self.marginfi_accounts.insert(*address, account);
+                }
+            }
+
+            marginfi_accounts_pubkeys
+                .retain(|address| !self.marginfi_accounts.contains_key(address));
+
+            self.snapshot_refresh = Some(SnapshotRefresh::spawn(
+                rpc_client.clone(),
+                self.marginfi_accounts.keys().copied().collect(),
+            ));
+        }
+
+        let marginfi_accounts = batch_get_multiple_accounts(
             rpc_client.clone(),
             &marginfi_accounts_pubkeys,
             BatchLoadingConfig::DEFAULT,
@@ -600,22 +1589,26 @@ impl Liquidator {
 
         for (address, account) in marginfi_accounts_pubkeys
             .iter()
-            .zip(marginfi_accounts.iter_mut())
+            .zip(marginfi_accounts.iter())
         {
             let account = account.as_ref().unwrap();
-            let marginfi_account = bytemuck::from_bytes::<MarginfiAccount>(&account.data[8..]);
-            let maw = MarginfiAccountWrapper {
-                address: *address,
-                account: *marginfi_account,
-            };
-            self.marginfi_accounts.insert(*address, maw);
+            self.insert_marginfi_account(*address, account);
         }
 
-        info!("Loaded pubkeys in {:?}", start.elapsed());
+        info!("Loaded marginfi accounts in {:?}", start.elapsed());
 
         Ok(())
     }
 
+    fn insert_marginfi_account(&mut self, address: Pubkey, account: &Account) {
+        let marginfi_account = bytemuck::from_bytes::<MarginfiAccount>(&account.data[8..]);
+        let maw = MarginfiAccountWrapper {
+            address,
+            account: *marginfi_account,
+        };
+        self.marginfi_accounts.insert(address, maw);
+    }
+
     /// Loads all marginfi account address into a [`Vec`]
     async fn load_marginfi_account_addresses(
         &self,
@@ -624,42 +1617,8 @@ impl Liquidator {
         match &self.general_config.account_whitelist {
             Some(account_list) => Ok(account_list.clone()),
             None => {
-                let marginfi_account_addresses = rpc_client.get_program_accounts_with_config(
-                    &self.general_config.marginfi_program_id,
-                    RpcProgramAccountsConfig {
-                        account_config: RpcAccountInfoConfig {
-                            encoding: Some(UiAccountEncoding::Base64),
-                            data_slice: Some(UiDataSliceConfig {
-                                offset: 0,
-                                length: 0,
-                            }),
-                            ..Default::default()
-                        },
-                        filters: Some(vec![
-                            #[allow(deprecated)]
-                            RpcFilterType::Memcmp(Memcmp {
-                                offset: 8,
-                                #[allow(deprecated)]
-                                bytes: MemcmpEncodedBytes::Base58(
-                                    self.general_config.marginfi_group_address.to_string(),
-                                ),
-                                #[allow(deprecated)]
-                                encoding: None,
-                            }),
-                            #[allow(deprecated)]
-                            RpcFilterType::Memcmp(Memcmp {
-                                offset: 0,
-                                #[allow(deprecated)]
-                                bytes: MemcmpEncodedBytes::Base58(
-                                    bs58::encode(MarginfiAccount::DISCRIMINATOR).into_string(),
-                                ),
-                                #[allow(deprecated)]
-                                encoding: None,
-                            }),
-                        ]),
-                        with_context: Some(false),
-                    },
-                )?;
+                let marginfi_account_addresses =
+                    self.get_marginfi_program_accounts(rpc_client, true).await?;
 
                 let marginfi_account_pubkeys: Vec<Pubkey> = marginfi_account_addresses
                     .iter()
@@ -671,10 +1630,69 @@ impl Liquidator {
         }
     }
 
+    /// Loads the marginfi accounts of the group with `getProgramAccounts`,
+    /// their data is left out when only the addresses are needed
+    async fn get_marginfi_program_accounts(
+        &self,
+        rpc_client: Arc<RpcClient>,
+        addresses_only: bool,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        let (encoding, data_slice) = if addresses_only {
+            (
+                UiAccountEncoding::Base64,
+                Some(UiDataSliceConfig {
+                    offset: 0,
+                    length: 0,
+                }),
+            )
+        } else {
+            (UiAccountEncoding::Base64Zstd, None)
+        };
+
+        let accounts = rpc_client.get_program_accounts_with_config(
+            &self.general_config.marginfi_program_id,
+            RpcProgramAccountsConfig {
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(encoding),
+                    data_slice,
+                    ..Default::default()
+                },
+                filters: Some(vec![
+                    #[allow(deprecated)]
+                    RpcFilterType::Memcmp(Memcmp {
+                        offset: 8,
+                        #[allow(deprecated)]
+                        bytes: MemcmpEncodedBytes::Base58(
+                            self.general_config.marginfi_group_address.to_string(),
+                        ),
+                        #[allow(deprecated)]
+                        encoding: None,
+                    }),
+                    #[allow(deprecated)]
+                    RpcFilterType::Memcmp(Memcmp {
+                        offset: 0,
+                        #[allow(deprecated)]
+                        bytes: MemcmpEncodedBytes::Base58(
+                            bs58::encode(MarginfiAccount::DISCRIMINATOR).into_string(),
+                        ),
+                        #[allow(deprecated)]
+                        encoding: None,
+                    }),
+                ]),
+                with_context: Some(false),
+            },
+        )?;
+
+        Ok(accounts)
+    }
+
     /// Loads Oracles and banks into the Liquidator
     async fn load_oracles_and_banks(&mut self, rpc_client: Arc<RpcClient>) -> anyhow::Result<()> {
         let anchor_client = anchor_client::Client::new(
-            anchor_client::Cluster::Custom(self.general_config.rpc_url.clone(), String::from("")),
+            anchor_client::Cluster::Custom(
+                self.liquidator_account.rpc_pool.url(),
+                String::from(""),
+            ),
             Arc::new(Keypair::new()),
         );
 
@@ -696,8 +1714,22 @@ impl Liquidator {
             .flatten()
             .collect::<Vec<_>>();
 
-        let mut oracle_accounts =
-            batch_get_multiple_accounts(rpc_client, &oracle_keys, BatchLoadingConfig::DEFAULT)?;
+        // The token program of each bank is the owner of its mint (spl-token or token-2022)
+        let mints = banks.iter().map(|(_, bank)| bank.mint).collect::<Vec<_>>();
+
+        // The oracles and the mints are fetched concurrently
+        let (oracle_accounts, mint_accounts) = rayon::join(
+            || {
+                batch_get_multiple_accounts(
+                    rpc_client.clone(),
+                    &oracle_keys,
+                    BatchLoadingConfig::DEFAULT,
+                )
+            },
+            || batch_get_multiple_accounts(rpc_client.clone(), &mints, BatchLoadingConfig::DEFAULT),
+        );
+        let mut oracle_accounts = oracle_accounts?;
+        let mint_accounts = mint_accounts?;
 
         let oracle_map: HashMap<Pubkey, Option<Account>> = oracle_keys
             .iter()
@@ -707,6 +1739,12 @@ impl Liquidator {
 
         info!("Found {:?} oracle accounts", oracle_accounts.len());
 
+        let token_program_per_mint: HashMap<Pubkey, Pubkey> = mints
+            .iter()
+            .zip(mint_accounts.iter())
+            .filter_map(|(mint, account)| account.as_ref().map(|account| (*mint, account.owner)))
+            .collect();
+
         for (bank_address, bank) in banks.iter() {
             let (oracle_address, mut oracle_account) = {
                 let oracle_addresses = find_oracle_keys(&bank.config);
@@ -750,13 +1788,20 @@ impl Liquidator {
                 }
             };
 
+            let token_program = *token_program_per_mint
+                .get(&bank.mint)
+                .ok_or_else(|| anyhow::anyhow!("Mint {} not found", bank.mint))?;
+
+            let mut oracle_wrapper = OracleWrapper::new(oracle_address, price_adapter);
+            oracle_wrapper.publish_slot =
+                get_oracle_publish_slot(bank.config.oracle_setup, &oracle_account.data);
+            if matches!(bank.config.oracle_setup, OracleSetup::PythPushOracle) {
+                oracle_wrapper.publish_time = get_pyth_push_publish_time(&oracle_account.data);
+            }
+
             self.banks.insert(
                 *bank_address,
-                BankWrapper::new(
-                    *bank_address,
-                    *bank,
-                    OracleWrapper::new(oracle_address, price_adapter),
-                ),
+                BankWrapper::new(*bank_address, *bank, oracle_wrapper, token_program),
             );
 
             self.oracle_to_bank.insert(oracle_address, *bank_address);
@@ -771,6 +1816,10 @@ impl Liquidator {
         for bank in self.banks.values() {
             tracked_accounts.insert(bank.oracle_adapter.address, AccountType::OracleAccount);
         }
+        tracked_accounts.insert(
+            self.liquidator_account.account_wrapper.address,
+            AccountType::MarginfiAccount,
+        );
 
         tracked_accounts
     }
@@ -801,13 +1850,6 @@ impl Liquidator {
                 continue;
             }
 
-            if !matches!(
-                bank.bank.config.operational_state,
-                BankOperationalState::Operational
-            ) {
-                continue;
-            }
-
             let value = match balance_side {
                 BalanceSide::Liabilities => bank
                     .calc_value(share.0, BalanceSide::Liabilities, requirement_type)
@@ -822,11 +1864,30 @@ impl Liquidator {
 
         Ok(values)
     }
+}
 
-    fn get_all_mints(&self) -> Vec<Pubkey> {
-        self.banks
-            .values()
-            .map(|bank| bank.bank.mint)
-            .collect::<Vec<_>>()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrappers::test_fixtures::{account, bank, banks_map, set_balance};
+
+    #[test]
+    fn liquidator_account_is_never_a_candidate() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let sol = bank(100.0, 0.75, 1.25);
+        let banks = banks_map(&[&usdc, &sol]);
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &sol, 0, 8);
+
+        let health_factor =
+            Liquidator::liquidatable_health_factor(&account, &Pubkey::new_unique(), &banks, 1.0)
+                .unwrap();
+        assert_eq!(health_factor, Some(I80F48::from_num(0.75)));
+
+        let health_factor =
+            Liquidator::liquidatable_health_factor(&account, &account.address, &banks, 1.0)
+                .unwrap();
+        assert_eq!(health_factor, None);
     }
 }
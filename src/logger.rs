@@ -0,0 +1,104 @@
+use crate::config::Eva01Config;
+use env_logger::Builder;
+use lazy_static::lazy_static;
+use log::{error, info, Log, Metadata, Record};
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+/// Filter used when neither the config nor `RUST_LOG` set one
+const DEFAULT_FILTER: &str = "info";
+
+lazy_static! {
+    static ref LOGGER: RwLock<env_logger::Logger> = RwLock::new(build_logger(None));
+}
+
+/// Forwards the records to the env_logger built from the current filter,
+/// so the filter can be replaced while the liquidator runs.
+///
+/// Every module logs under its own target (`eva01::liquidator`, `eva01::geyser`,
+/// `eva01::transaction_manager`...), a filter like `info,eva01::geyser=warn`
+/// silences the geyser service and keeps the other components verbose
+struct ReloadableLogger;
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        LOGGER.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        LOGGER.read().unwrap().log(record);
+    }
+
+    fn flush(&self) {
+        LOGGER.read().unwrap().flush();
+    }
+}
+
+/// Installs the logger, filtered with `RUST_LOG` or at the info level
+pub fn init() {
+    log::set_logger(&ReloadableLogger).expect("The logger is already installed");
+    log::set_max_level(LOGGER.read().unwrap().filter());
+}
+
+/// Replaces the filter with a `RUST_LOG` style directive,
+/// or with the one of the environment when `None`
+pub fn set_filter(filter: Option<&str>) {
+    let logger = build_logger(filter);
+    let max_level = logger.filter();
+    *LOGGER.write().unwrap() = logger;
+    log::set_max_level(max_level);
+}
+
+fn build_logger(filter: Option<&str>) -> env_logger::Logger {
+    match filter {
+        Some(filter) => Builder::new().parse_filters(filter).build(),
+        None => {
+            Builder::from_env(env_logger::Env::default().default_filter_or(DEFAULT_FILTER)).build()
+        }
+    }
+}
+
+/// Reloads the `log_filter` of the config file on SIGHUP, to change
+/// the verbosity during an incident without restarting the liquidator
+pub async fn serve_log_filter_reload(config_path: PathBuf, cancellation_token: CancellationToken) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {:?}", e);
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => return,
+            hangup = hangups.recv() => {
+                if hangup.is_none() {
+                    return;
+                }
+                reload_log_filter(&config_path);
+            }
+        }
+    }
+}
+
+fn reload_log_filter(config_path: &Path) {
+    match Eva01Config::try_load_from_file(config_path.to_path_buf()) {
+        Ok(config) => {
+            let filter = config.general_config.log_filter;
+            set_filter(filter.as_deref());
+            info!(
+                "Reloaded the log filter: {}",
+                filter.as_deref().unwrap_or("from RUST_LOG")
+            );
+        }
+        Err(e) => error!(
+            "Failed to reload the log filter from {:?}: {}",
+            config_path, e
+        ),
+    }
+}
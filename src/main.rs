@@ -1,4 +1,3 @@
-use env_logger::Builder;
 use std::{backtrace::Backtrace, error::Error};
 
 /// Geyser service
@@ -38,10 +37,64 @@ mod transaction_manager;
 /// Crossbar client
 mod crossbar;
 
+/// Prometheus metrics
+mod metrics;
+
+/// Jupiter swaps for the rebalancer
+mod jupiter;
+
+/// Pyth pull oracle price updates
+mod pyth;
+
+/// Snapshots of the tracked accounts
+mod state;
+
+/// Discord and telegram alerts
+mod notifications;
+
+/// Priority fee estimation from the recent slots
+mod fee_estimator;
+
+/// Rpc endpoints failover
+mod rpc_pool;
+
+/// Rate limit of the rpc requests, shared by every component
+mod rpc_limiter;
+
+/// Log of the submitted transactions and their outcome
+mod tx_log;
+
+/// Profit and loss of the liquidations and rebalances
+mod pnl;
+
+/// Logger with a filter reloadable at runtime
+mod logger;
+
+/// Watch of the signer SOL balance
+mod sol_balance;
+
+/// Recording of the geyser updates and their replay
+mod replay;
+
+/// Live status of the liquidator, served as JSON
+mod status;
+
+/// Cooldown of the liquidations failing repeatedly
+mod cooldown;
+
+/// Decoding of the marginfi program errors
+mod marginfi_errors;
+
+/// Admin commands served on a unix socket
+mod admin;
+
+/// Cap of the fees and tips spent over a rolling hour
+mod spend_budget;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     // Assemble logger, with INFO as default log level
-    Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    crate::logger::init();
 
     std::panic::set_hook(Box::new(|panic_info| {
         eprintln!("Panic occurred: {:#?}", panic_info);
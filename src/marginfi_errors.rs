@@ -0,0 +1,93 @@
+use marginfi::errors::MarginfiError;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+/// Marginfi errors worth decoding, the custom error code of each is derived
+/// from the program error enum so it can't drift from the deployed program
+const MARGINFI_ERRORS: &[MarginfiError] = &[
+    MarginfiError::MathError,
+    MarginfiError::BankNotFound,
+    MarginfiError::LendingAccountBalanceNotFound,
+    MarginfiError::BankAssetCapacityExceeded,
+    MarginfiError::InvalidTransfer,
+    MarginfiError::MissingPythOrBankAccount,
+    MarginfiError::MissingPythAccount,
+    MarginfiError::InvalidOracleAccount,
+    MarginfiError::MissingBankAccount,
+    MarginfiError::InvalidBankAccount,
+    MarginfiError::RiskEngineInitRejected,
+    MarginfiError::LendingAccountBalanceSlotsFull,
+    MarginfiError::BankAlreadyExists,
+    MarginfiError::IllegalLiquidation,
+    MarginfiError::AccountNotBankrupt,
+    MarginfiError::BalanceNotBadDebt,
+    MarginfiError::InvalidConfig,
+    MarginfiError::StaleOracle,
+    MarginfiError::BankPaused,
+    MarginfiError::BankReduceOnly,
+    MarginfiError::BankAccountNotFound,
+    MarginfiError::OperationDepositOnly,
+    MarginfiError::OperationWithdrawOnly,
+    MarginfiError::OperationBorrowOnly,
+    MarginfiError::OperationRepayOnly,
+    MarginfiError::NoAssetFound,
+    MarginfiError::NoLiabilityFound,
+    MarginfiError::InvalidOracleSetup,
+    MarginfiError::IllegalUtilizationRatio,
+    MarginfiError::BankLiabilityCapacityExceeded,
+    MarginfiError::InvalidPrice,
+    MarginfiError::IsolatedAccountIllegalState,
+    MarginfiError::EmissionsAlreadySetup,
+    MarginfiError::OracleNotSetup,
+    MarginfiError::InvalidSwitchboardDecimalConversion,
+    MarginfiError::CannotCloseOutstandingEmissions,
+    MarginfiError::EmissionsUpdateError,
+    MarginfiError::AccountDisabled,
+    MarginfiError::AccountTempActiveBalanceLimitExceeded,
+    MarginfiError::AccountInFlashloan,
+    MarginfiError::IllegalFlashloan,
+    MarginfiError::IllegalFlag,
+    MarginfiError::IllegalBalanceState,
+    MarginfiError::IllegalAccountAuthorityTransfer,
+    MarginfiError::Unauthorized,
+    MarginfiError::IllegalAction,
+];
+
+/// Returns the name and message of a marginfi custom error code, e.g. `6017` is
+/// `StaleOracle: Stale oracle data`
+pub fn decode_error_code(code: u32) -> Option<String> {
+    MARGINFI_ERRORS
+        .iter()
+        .find(|error| u32::from(**error) == code)
+        .map(|error| format!("{:?}: {}", error, error))
+}
+
+/// Decodes the custom program error of a failed transaction. Only marginfi and the
+/// compute budget program are called by the liquidations, a custom error is marginfi's
+pub fn decode_transaction_error(err: &TransactionError) -> Option<String> {
+    match err {
+        TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+            decode_error_code(*code)
+                .map(|decoded| format!("instruction {} failed with {}", index, decoded))
+        }
+        _ => None,
+    }
+}
+
+/// Decodes the custom program error found in an error message,
+/// like the simulation failures reported by the block engine
+pub fn decode_error_message(msg: &str) -> Option<String> {
+    let (_, code) = msg.split_once("custom program error: 0x")?;
+    let code = code
+        .split(|c: char| !c.is_ascii_hexdigit())
+        .next()
+        .and_then(|code| u32::from_str_radix(code, 16).ok())?;
+    decode_error_code(code)
+}
+
+/// Formats a transaction error along its decoded marginfi error, when there is one
+pub fn describe_transaction_error(err: &TransactionError) -> String {
+    match decode_transaction_error(err) {
+        Some(decoded) => format!("{} ({})", err, decoded),
+        None => err.to_string(),
+    }
+}
@@ -1,26 +1,25 @@
+use crate::wrappers::bank::BankWrapper;
 use anchor_lang::{system_program, InstructionData, Key, ToAccountMetas};
 
 use anchor_spl::token_2022;
 use log::trace;
+use marginfi::state::marginfi_group::BankVaultType;
 use solana_sdk::instruction::AccountMeta;
-use solana_sdk::{
-    instruction::Instruction,
-    pubkey::Pubkey,
-    signature::{Keypair, Signer},
-};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
 
+/// Creates the `marginfi_account` owned by the signer, the keypair
+/// of the new account must sign the transaction along the signer
 pub fn make_initialize_ix(
     marginfi_program_id: Pubkey,
     marginfi_group: Pubkey,
+    marginfi_account: Pubkey,
     signer: Pubkey,
 ) -> Instruction {
-    let marginfi_account_key = Keypair::new();
-
     Instruction {
         program_id: marginfi_program_id,
         accounts: marginfi::accounts::MarginfiAccountInitialize {
             marginfi_group,
-            marginfi_account: marginfi_account_key.pubkey(),
+            marginfi_account,
             system_program: system_program::ID,
             authority: signer,
             fee_payer: signer,
@@ -146,54 +145,163 @@ pub fn make_withdraw_ix(
     }
 }
 
-pub fn make_liquidate_ix(
+pub fn make_withdraw_emissions_ix(
     marginfi_program_id: Pubkey,
     marginfi_group: Pubkey,
     marginfi_account: Pubkey,
-    asset_bank: Pubkey,
-    liab_bank: Pubkey,
     signer: Pubkey,
-    liquidatee_marginfi_account: Pubkey,
-    bank_liquidity_vault_authority: Pubkey,
-    bank_liquidity_vault: Pubkey,
-    bank_insurance_vault: Pubkey,
+    bank: Pubkey,
+    emissions_mint: Pubkey,
+    emissions_auth: Pubkey,
+    emissions_vault: Pubkey,
+    destination_account: Pubkey,
     token_program: Pubkey,
-    liquidator_observation_accounts: Vec<Pubkey>,
-    liquidatee_observation_accounts: Vec<Pubkey>,
-    asset_bank_oracle: Pubkey,
-    liab_bank_oracle: Pubkey,
-    liab_mint: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: marginfi_program_id,
+        accounts: marginfi::accounts::LendingAccountWithdrawEmissions {
+            marginfi_group,
+            marginfi_account,
+            signer,
+            bank,
+            emissions_mint,
+            emissions_auth,
+            emissions_vault,
+            destination_account,
+            token_program,
+        }
+        .to_account_metas(Some(true)),
+        data: marginfi::instruction::LendingAccountWithdrawEmissions.data(),
+    }
+}
+
+pub fn make_close_balance_ix(
+    marginfi_program_id: Pubkey,
+    marginfi_group: Pubkey,
+    marginfi_account: Pubkey,
+    signer: Pubkey,
+    bank: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: marginfi_program_id,
+        accounts: marginfi::accounts::LendingAccountCloseBalance {
+            marginfi_group,
+            marginfi_account,
+            signer,
+            bank,
+        }
+        .to_account_metas(Some(true)),
+        data: marginfi::instruction::LendingAccountCloseBalance.data(),
+    }
+}
+
+/// Every account of a liquidation instruction, in the order marginfi reads them
+pub struct LiquidateAccounts {
+    pub marginfi_group: Pubkey,
+    pub liquidator_marginfi_account: Pubkey,
+    pub signer: Pubkey,
+    pub liquidatee_marginfi_account: Pubkey,
+    pub asset_bank: Pubkey,
+    pub liab_bank: Pubkey,
+    pub bank_liquidity_vault_authority: Pubkey,
+    pub bank_liquidity_vault: Pubkey,
+    pub bank_insurance_vault: Pubkey,
+    pub token_program: Pubkey,
+    /// Mint of the liability bank, only passed for the token-2022 mints
+    pub liab_mint: Pubkey,
+    pub asset_bank_oracle: Pubkey,
+    pub liab_bank_oracle: Pubkey,
+    /// Accounts required by the program after the oracles and before the observation
+    /// accounts, for the marginfi versions reading more than the current layout
+    pub extra_accounts: Vec<AccountMeta>,
+    pub liquidator_observation_accounts: Vec<Pubkey>,
+    pub liquidatee_observation_accounts: Vec<Pubkey>,
+}
+
+impl LiquidateAccounts {
+    /// Resolves the accounts of a liquidation from the banks: the liquidity
+    /// vault and its authority, and the insurance vault of the liability bank
+    #[allow(clippy::too_many_arguments)]
+    pub fn resolve(
+        marginfi_program_id: &Pubkey,
+        marginfi_group: Pubkey,
+        liquidator_marginfi_account: Pubkey,
+        signer: Pubkey,
+        liquidatee_marginfi_account: Pubkey,
+        asset_bank: &BankWrapper,
+        liab_bank: &BankWrapper,
+        liquidator_observation_accounts: Vec<Pubkey>,
+        liquidatee_observation_accounts: Vec<Pubkey>,
+    ) -> Self {
+        let (bank_liquidity_vault_authority, _) = crate::utils::find_bank_vault_authority_pda(
+            &liab_bank.address,
+            BankVaultType::Liquidity,
+            marginfi_program_id,
+        );
+
+        Self {
+            marginfi_group,
+            liquidator_marginfi_account,
+            signer,
+            liquidatee_marginfi_account,
+            asset_bank: asset_bank.address,
+            liab_bank: liab_bank.address,
+            bank_liquidity_vault_authority,
+            bank_liquidity_vault: liab_bank.bank.liquidity_vault,
+            bank_insurance_vault: liab_bank.bank.insurance_vault,
+            token_program: liab_bank.token_program,
+            liab_mint: liab_bank.bank.mint,
+            asset_bank_oracle: asset_bank.oracle_adapter.address,
+            liab_bank_oracle: liab_bank.oracle_adapter.address,
+            extra_accounts: vec![],
+            liquidator_observation_accounts,
+            liquidatee_observation_accounts,
+        }
+    }
+}
+
+pub fn make_liquidate_ix(
+    marginfi_program_id: Pubkey,
+    liquidate_accounts: &LiquidateAccounts,
     asset_amount: u64,
 ) -> Instruction {
     let mut accounts = marginfi::accounts::LendingAccountLiquidate {
-        marginfi_group,
-        liquidator_marginfi_account: marginfi_account,
-        signer,
-        liquidatee_marginfi_account,
-        bank_liquidity_vault_authority,
-        bank_liquidity_vault,
-        bank_insurance_vault,
-        token_program,
-        asset_bank,
-        liab_bank,
+        marginfi_group: liquidate_accounts.marginfi_group,
+        liquidator_marginfi_account: liquidate_accounts.liquidator_marginfi_account,
+        signer: liquidate_accounts.signer,
+        liquidatee_marginfi_account: liquidate_accounts.liquidatee_marginfi_account,
+        bank_liquidity_vault_authority: liquidate_accounts.bank_liquidity_vault_authority,
+        bank_liquidity_vault: liquidate_accounts.bank_liquidity_vault,
+        bank_insurance_vault: liquidate_accounts.bank_insurance_vault,
+        token_program: liquidate_accounts.token_program,
+        asset_bank: liquidate_accounts.asset_bank,
+        liab_bank: liquidate_accounts.liab_bank,
     }
     .to_account_metas(Some(true));
 
-    maybe_add_bank_mint(&mut accounts, liab_mint, &token_program);
+    maybe_add_bank_mint(
+        &mut accounts,
+        liquidate_accounts.liab_mint,
+        &liquidate_accounts.token_program,
+    );
 
     accounts.extend([
-        AccountMeta::new_readonly(asset_bank_oracle, false),
-        AccountMeta::new_readonly(liab_bank_oracle, false),
+        AccountMeta::new_readonly(liquidate_accounts.asset_bank_oracle, false),
+        AccountMeta::new_readonly(liquidate_accounts.liab_bank_oracle, false),
     ]);
 
+    accounts.extend(liquidate_accounts.extra_accounts.iter().cloned());
+
     accounts.extend(
-        liquidator_observation_accounts
+        liquidate_accounts
+            .liquidator_observation_accounts
             .iter()
             .map(|a| AccountMeta::new_readonly(a.key(), false)),
     );
 
     accounts.extend(
-        liquidatee_observation_accounts
+        liquidate_accounts
+            .liquidatee_observation_accounts
             .iter()
             .map(|a| AccountMeta::new_readonly(a.key(), false)),
     );
@@ -210,3 +318,309 @@ fn maybe_add_bank_mint(accounts: &mut Vec<AccountMeta>, mint: Pubkey, token_prog
         accounts.push(AccountMeta::new_readonly(mint, false));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_spl::token;
+    use solana_sdk::hash::hash;
+
+    /// Asserts the accounts of the instruction, as (address, writable, signer)
+    fn assert_accounts(ix: &Instruction, expected: &[(Pubkey, bool, bool)]) {
+        let accounts = ix
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_writable, meta.is_signer))
+            .collect::<Vec<_>>();
+        assert_eq!(accounts, expected);
+    }
+
+    /// Asserts the anchor discriminator of the instruction, the first 8 bytes
+    /// of the sha256 of its name in the global namespace
+    fn assert_discriminator(ix: &Instruction, name: &str) {
+        let preimage = format!("global:{}", name);
+        assert_eq!(ix.data[..8], hash(preimage.as_bytes()).to_bytes()[..8]);
+    }
+
+    #[test]
+    fn initialize_ix() {
+        let (program, group, account, signer) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let ix = make_initialize_ix(program, group, account, signer);
+
+        assert_eq!(ix.program_id, program);
+        assert_accounts(
+            &ix,
+            &[
+                (group, false, false),
+                (account, true, true),
+                (signer, false, true),
+                (signer, true, true),
+                (system_program::ID, false, false),
+            ],
+        );
+        assert_discriminator(&ix, "marginfi_account_initialize");
+    }
+
+    #[test]
+    fn deposit_ix() {
+        let (program, group, account, signer, bank, token_account, vault, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let ix = make_deposit_ix(
+            program,
+            group,
+            account,
+            signer,
+            bank,
+            token_account,
+            vault,
+            token::ID,
+            mint,
+            42,
+        );
+
+        // The mint is only passed for the token-2022 banks
+        assert_accounts(
+            &ix,
+            &[
+                (group, false, false),
+                (account, true, false),
+                (signer, false, true),
+                (bank, true, false),
+                (token_account, true, false),
+                (vault, true, false),
+                (token::ID, false, false),
+            ],
+        );
+        assert_discriminator(&ix, "lending_account_deposit");
+        assert_eq!(ix.data[8..16], 42u64.to_le_bytes());
+    }
+
+    #[test]
+    fn repay_ix() {
+        let (program, group, account, signer, bank, token_account, vault, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let ix = make_repay_ix(
+            program,
+            group,
+            account,
+            signer,
+            bank,
+            token_account,
+            vault,
+            token_2022::ID,
+            mint,
+            42,
+            Some(true),
+        );
+
+        assert_accounts(
+            &ix,
+            &[
+                (group, false, false),
+                (account, true, false),
+                (signer, false, true),
+                (bank, true, false),
+                (token_account, true, false),
+                (vault, true, false),
+                (token_2022::ID, false, false),
+                (mint, false, false),
+            ],
+        );
+        assert_discriminator(&ix, "lending_account_repay");
+        assert_eq!(ix.data[8..16], 42u64.to_le_bytes());
+    }
+
+    #[test]
+    fn withdraw_ix() {
+        let (program, group, account, signer, bank, token_account, authority, vault, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let observation_accounts = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let ix = make_withdraw_ix(
+            program,
+            group,
+            account,
+            signer,
+            bank,
+            token_account,
+            authority,
+            vault,
+            token::ID,
+            observation_accounts.clone(),
+            mint,
+            42,
+            None,
+        );
+
+        assert_accounts(
+            &ix,
+            &[
+                (group, false, false),
+                (account, true, false),
+                (signer, false, true),
+                (bank, true, false),
+                (token_account, true, false),
+                (authority, true, false),
+                (vault, true, false),
+                (token::ID, false, false),
+                (observation_accounts[0], false, false),
+                (observation_accounts[1], false, false),
+            ],
+        );
+        assert_discriminator(&ix, "lending_account_withdraw");
+        assert_eq!(ix.data[8..16], 42u64.to_le_bytes());
+    }
+
+    #[test]
+    fn withdraw_emissions_ix() {
+        let (program, group, account, signer, bank) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (emissions_mint, emissions_auth, emissions_vault, destination) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let ix = make_withdraw_emissions_ix(
+            program,
+            group,
+            account,
+            signer,
+            bank,
+            emissions_mint,
+            emissions_auth,
+            emissions_vault,
+            destination,
+            token::ID,
+        );
+
+        assert_accounts(
+            &ix,
+            &[
+                (group, false, false),
+                (account, true, false),
+                (signer, false, true),
+                (bank, true, false),
+                (emissions_mint, false, false),
+                (emissions_auth, false, false),
+                (emissions_vault, true, false),
+                (destination, true, false),
+                (token::ID, false, false),
+            ],
+        );
+        assert_discriminator(&ix, "lending_account_withdraw_emissions");
+    }
+
+    #[test]
+    fn close_balance_ix() {
+        let (program, group, account, signer, bank) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let ix = make_close_balance_ix(program, group, account, signer, bank);
+
+        assert_accounts(
+            &ix,
+            &[
+                (group, false, false),
+                (account, true, false),
+                (signer, false, true),
+                (bank, true, false),
+            ],
+        );
+        assert_discriminator(&ix, "lending_account_close_balance");
+    }
+
+    #[test]
+    fn liquidate_ix() {
+        let extra_account = AccountMeta::new(Pubkey::new_unique(), false);
+        let liquidate_accounts = LiquidateAccounts {
+            marginfi_group: Pubkey::new_unique(),
+            liquidator_marginfi_account: Pubkey::new_unique(),
+            signer: Pubkey::new_unique(),
+            liquidatee_marginfi_account: Pubkey::new_unique(),
+            asset_bank: Pubkey::new_unique(),
+            liab_bank: Pubkey::new_unique(),
+            bank_liquidity_vault_authority: Pubkey::new_unique(),
+            bank_liquidity_vault: Pubkey::new_unique(),
+            bank_insurance_vault: Pubkey::new_unique(),
+            token_program: token_2022::ID,
+            liab_mint: Pubkey::new_unique(),
+            asset_bank_oracle: Pubkey::new_unique(),
+            liab_bank_oracle: Pubkey::new_unique(),
+            extra_accounts: vec![extra_account.clone()],
+            liquidator_observation_accounts: vec![Pubkey::new_unique()],
+            liquidatee_observation_accounts: vec![Pubkey::new_unique()],
+        };
+        let accounts = &liquidate_accounts;
+
+        let ix = make_liquidate_ix(Pubkey::new_unique(), accounts, 42);
+
+        assert_accounts(
+            &ix,
+            &[
+                (accounts.marginfi_group, false, false),
+                (accounts.asset_bank, true, false),
+                (accounts.liab_bank, true, false),
+                (accounts.liquidator_marginfi_account, true, false),
+                (accounts.signer, false, true),
+                (accounts.liquidatee_marginfi_account, true, false),
+                (accounts.bank_liquidity_vault_authority, true, false),
+                (accounts.bank_liquidity_vault, true, false),
+                (accounts.bank_insurance_vault, true, false),
+                (token_2022::ID, false, false),
+                (accounts.liab_mint, false, false),
+                (accounts.asset_bank_oracle, false, false),
+                (accounts.liab_bank_oracle, false, false),
+                (extra_account.pubkey, true, false),
+                (accounts.liquidator_observation_accounts[0], false, false),
+                (accounts.liquidatee_observation_accounts[0], false, false),
+            ],
+        );
+        assert_discriminator(&ix, "lending_account_liquidate");
+        assert_eq!(ix.data[8..16], 42u64.to_le_bytes());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::transaction_manager::BundleOutcome;
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use prometheus::{
+    register_gauge_vec, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Encoder, GaugeVec, Histogram, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, TextEncoder, TEXT_FORMAT,
+};
+use std::{convert::Infallible, net::SocketAddr};
+
+lazy_static! {
+    pub static ref LIQUIDATIONS_ATTEMPTED: IntCounter = register_int_counter!(
+        "eva01_liquidations_attempted_total",
+        "Number of liquidations attempted"
+    )
+    .unwrap();
+    pub static ref LIQUIDATIONS_SUCCEEDED: IntCounter = register_int_counter!(
+        "eva01_liquidations_succeeded_total",
+        "Number of liquidations whose bundle landed"
+    )
+    .unwrap();
+    pub static ref LIQUIDATIONS_FAILED: IntCounter = register_int_counter!(
+        "eva01_liquidations_failed_total",
+        "Number of liquidations that failed to be built or whose bundle didn't land"
+    )
+    .unwrap();
+    pub static ref LIQUIDATIONS_UNCONFIRMED: IntCounter = register_int_counter!(
+        "eva01_liquidations_unconfirmed_total",
+        "Number of liquidations whose bundle was accepted without being seen landing"
+    )
+    .unwrap();
+    pub static ref BUNDLES: IntCounterVec = register_int_counter_vec!(
+        "eva01_bundles_total",
+        "Number of bundles sent to the jito block engine, by outcome",
+        &["outcome"]
+    )
+    .unwrap();
+    pub static ref BUNDLES_REJECTED: IntCounterVec = register_int_counter_vec!(
+        "eva01_bundles_rejected_total",
+        "Number of bundles rejected by the jito block engine, by reason",
+        &["reason"]
+    )
+    .unwrap();
+    pub static ref GEYSER_UPDATES: IntCounter = register_int_counter!(
+        "eva01_geyser_updates_total",
+        "Number of updates received from geyser"
+    )
+    .unwrap();
+    pub static ref GEYSER_STALLS: IntCounter = register_int_counter!(
+        "eva01_geyser_stalls_total",
+        "Number of geyser streams subscribed again after staying silent"
+    )
+    .unwrap();
+    pub static ref GEYSER_UPDATES_MERGED: IntCounter = register_int_counter!(
+        "eva01_geyser_updates_merged_total",
+        "Number of pending geyser updates replaced by a newer update of the same account"
+    )
+    .unwrap();
+    pub static ref LIQUIDATION_LATENCY: Histogram = register_histogram!(
+        "eva01_liquidation_latency_seconds",
+        "Time spent building a liquidation"
+    )
+    .unwrap();
+    pub static ref OPPORTUNITY_TO_SUBMIT: Histogram = register_histogram!(
+        "eva01_opportunity_to_submit_seconds",
+        "Time from the creation of a transaction to the submission of its bundle"
+    )
+    .unwrap();
+    pub static ref LIQUIDATOR_READY: IntGauge = register_int_gauge!(
+        "eva01_liquidator_ready",
+        "Whether the liquidator finished its warm-up and evaluates the accounts"
+    )
+    .unwrap();
+    pub static ref SIGNER_SOL_BALANCE: GaugeVec = register_gauge_vec!(
+        "eva01_signer_sol_balance",
+        "Native SOL balance of each signer, paying its fees and jito tips",
+        &["signer"]
+    )
+    .unwrap();
+    pub static ref PNL_USD: GaugeVec = register_gauge_vec!(
+        "eva01_pnl_usd",
+        "Realized profit and loss since startup in USD, by component",
+        &["component"]
+    )
+    .unwrap();
+    pub static ref RPC_THROTTLED: IntCounterVec = register_int_counter_vec!(
+        "eva01_rpc_throttled_total",
+        "Number of rpc requests delayed by the rate limiter, by priority",
+        &["priority"]
+    )
+    .unwrap();
+    pub static ref RPC_THROTTLE_WAIT: HistogramVec = register_histogram_vec!(
+        "eva01_rpc_throttle_wait_seconds",
+        "Time the delayed rpc requests waited for the rate limiter, by priority",
+        &["priority"]
+    )
+    .unwrap();
+}
+
+/// Records the outcome of a bundle, and the reason when it was rejected
+pub fn record_bundle_outcome(outcome: &BundleOutcome) {
+    let label = match outcome {
+        BundleOutcome::Accepted { .. } => "accepted",
+        BundleOutcome::Landed { .. } => "landed",
+        BundleOutcome::Rejected(_) => {
+            let reason = outcome.rejection_reason().unwrap_or("unknown");
+            BUNDLES_REJECTED.with_label_values(&[reason]).inc();
+            "rejected"
+        }
+        BundleOutcome::Dropped => "dropped",
+        BundleOutcome::Finalized => "finalized",
+    };
+
+    BUNDLES.with_label_values(&[label]).inc();
+}
+
+/// Serves the prometheus metrics on the given port, and the status of the liquidator on `/status`
+pub async fn serve_metrics(port: u16) -> anyhow::Result<()> {
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(metrics_handler)) });
+
+    info!("Serving metrics on {}", address);
+
+    Server::try_bind(&address)?.serve(make_service).await?;
+
+    Ok(())
+}
+
+/// Sets the profit and loss gauges from the running totals
+fn update_pnl() {
+    let pnl = crate::pnl::summary().total;
+    for (component, value) in [
+        ("gross_profit", pnl.gross_profit()),
+        ("fees", pnl.fees),
+        ("swap_slippage", pnl.swap_slippage),
+        ("net_profit", pnl.net_profit()),
+    ] {
+        PNL_USD
+            .with_label_values(&[component])
+            .set(value.to_num::<f64>());
+    }
+}
+
+async fn metrics_handler(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() == "/status" {
+        let mut response = Response::new(Body::from(crate::status::status().to_string()));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        return Ok(response);
+    }
+
+    update_pnl();
+
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode the metrics: {:?}", e);
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT));
+
+    Ok(response)
+}
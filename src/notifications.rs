@@ -0,0 +1,161 @@
+use crate::config::GeneralConfig;
+use futures::future::BoxFuture;
+use log::{error, warn};
+use std::sync::OnceLock;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Maximum length of a discord message
+const DISCORD_MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Maximum length of a telegram message
+const TELEGRAM_MAX_MESSAGE_LENGTH: usize = 4096;
+
+static NOTIFICATIONS_TX: OnceLock<UnboundedSender<String>> = OnceLock::new();
+
+/// Channel where the alerts are delivered
+pub trait Notifier: Send + Sync {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// Posts the alerts to a discord webhook
+pub struct DiscordNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl DiscordNotifier {
+    pub fn new(webhook_url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhook_url,
+        }
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.client
+                .post(&self.webhook_url)
+                .json(&serde_json::json!({
+                    "content": truncate(message, DISCORD_MAX_MESSAGE_LENGTH),
+                }))
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                // The url holds the secret of the channel, it is kept out of the logs
+                .map_err(reqwest::Error::without_url)?;
+            Ok(())
+        })
+    }
+}
+
+/// Sends the alerts to a telegram chat through a bot
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot_token: String, chat_id: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            bot_token,
+            chat_id,
+        }
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn send<'a>(&'a self, message: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.client
+                .post(format!(
+                    "https://api.telegram.org/bot{}/sendMessage",
+                    self.bot_token
+                ))
+                .json(&serde_json::json!({
+                    "chat_id": self.chat_id,
+                    "text": truncate(message, TELEGRAM_MAX_MESSAGE_LENGTH),
+                }))
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                // The url holds the secret of the channel, it is kept out of the logs
+                .map_err(reqwest::Error::without_url)?;
+            Ok(())
+        })
+    }
+}
+
+/// Queues an alert, dropped when no notifier is configured
+pub fn notify(message: String) {
+    if let Some(tx) = NOTIFICATIONS_TX.get() {
+        let _ = tx.send(message);
+    }
+}
+
+/// Builds the notifiers from the config and delivers the alerts until every
+/// alert is sent. The alerts received within `notification_interval_secs`
+/// are batched into a single message, so a burst doesn't spam the channels
+pub async fn serve_notifications(config: &GeneralConfig) {
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![];
+    if let Some(webhook_url) = &config.discord_webhook_url {
+        notifiers.push(Box::new(DiscordNotifier::new(webhook_url.clone())));
+    }
+    match (&config.telegram_bot_token, &config.telegram_chat_id) {
+        (Some(bot_token), Some(chat_id)) => notifiers.push(Box::new(TelegramNotifier::new(
+            bot_token.clone(),
+            chat_id.clone(),
+        ))),
+        (None, None) => {}
+        _ => warn!("Telegram alerts need both a bot token and a chat id"),
+    }
+
+    if notifiers.is_empty() {
+        return;
+    }
+
+    let (tx, rx) = unbounded_channel();
+    if NOTIFICATIONS_TX.set(tx).is_err() {
+        warn!("Notifications are already served");
+        return;
+    }
+
+    deliver(
+        notifiers,
+        rx,
+        std::time::Duration::from_secs(config.notification_interval_secs),
+    )
+    .await;
+}
+
+async fn deliver(
+    notifiers: Vec<Box<dyn Notifier>>,
+    mut rx: UnboundedReceiver<String>,
+    interval: std::time::Duration,
+) {
+    while let Some(message) = rx.recv().await {
+        let mut messages = vec![message];
+
+        tokio::time::sleep(interval).await;
+        while let Ok(message) = rx.try_recv() {
+            messages.push(message);
+        }
+
+        let message = messages.join("\n");
+        for notifier in &notifiers {
+            if let Err(e) = notifier.send(&message).await {
+                error!("Failed to send the alert: {:?}", e);
+            }
+        }
+    }
+}
+
+fn truncate(message: &str, max_length: usize) -> &str {
+    match message.char_indices().nth(max_length) {
+        Some((index, _)) => &message[..index],
+        None => message,
+    }
+}
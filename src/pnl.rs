@@ -0,0 +1,206 @@
+use fixed::types::I80F48;
+use lazy_static::lazy_static;
+use log::info;
+use marginfi::constants::LIQUIDATION_LIQUIDATOR_FEE;
+use solana_program::pubkey::Pubkey;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+lazy_static! {
+    static ref PNL: Mutex<PnlSummary> = Mutex::new(PnlSummary::default());
+}
+
+/// Profit and loss of a transaction, estimated when it is built
+/// and recorded once its bundle landed
+#[derive(Debug, Clone)]
+pub enum PnlEntry {
+    Liquidation(LiquidationPnl),
+    Swap(SwapPnl),
+}
+
+/// A liquidation, valued at the oracle prices it was built with
+#[derive(Debug, Clone)]
+pub struct LiquidationPnl {
+    pub asset_mint: Pubkey,
+    /// Collateral seized, in native units
+    pub asset_amount: u64,
+    /// Oracle price of the seized collateral, in USD per token
+    pub asset_price: I80F48,
+    /// Value of the seized collateral, in USD
+    pub collateral_value: I80F48,
+    /// Price of a lamport in USD, to value the tip and the transaction fees
+    pub lamport_price: I80F48,
+    /// Slippage expected selling the seized collateral, in USD
+    pub expected_swap_slippage: I80F48,
+}
+
+impl LiquidationPnl {
+    /// Value of the liability taken over, in USD. Marginfi hands the liquidator
+    /// the collateral at a discount of the liquidator fee, the insurance fee is
+    /// paid by the liquidatee
+    pub fn liability_value(&self) -> I80F48 {
+        self.collateral_value * (I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE)
+    }
+}
+
+/// A swap of the rebalancer, valued at the oracle prices it was quoted with
+#[derive(Debug, Clone)]
+pub struct SwapPnl {
+    pub input_mint: Pubkey,
+    /// Oracle price of the tokens sold, in USD per token
+    pub input_price: I80F48,
+    /// Value of the tokens sold, in USD
+    pub input_value: I80F48,
+    /// Value of the tokens quoted to be bought, in USD
+    pub output_value: I80F48,
+    /// Price of a lamport in USD, to value the tip and the transaction fees
+    pub lamport_price: I80F48,
+}
+
+/// Running totals of the liquidations and swaps of an asset, in USD
+#[derive(Debug, Clone, Default)]
+pub struct AssetPnl {
+    pub liquidations: u64,
+    /// Collateral seized, in native units
+    pub seized_amount: u128,
+    pub collateral_value: I80F48,
+    pub liability_value: I80F48,
+    /// Jito tips, signature and priority fees
+    pub fees: I80F48,
+    /// Value lost selling the asset below its oracle price
+    pub swap_slippage: I80F48,
+}
+
+impl AssetPnl {
+    /// Profit of the liquidator fee, before any cost
+    pub fn gross_profit(&self) -> I80F48 {
+        self.collateral_value - self.liability_value
+    }
+
+    pub fn net_profit(&self) -> I80F48 {
+        self.gross_profit() - self.fees - self.swap_slippage
+    }
+
+    fn add(&mut self, other: &AssetPnl) {
+        self.liquidations += other.liquidations;
+        self.seized_amount += other.seized_amount;
+        self.collateral_value += other.collateral_value;
+        self.liability_value += other.liability_value;
+        self.fees += other.fees;
+        self.swap_slippage += other.swap_slippage;
+    }
+}
+
+/// Realized profit and loss since startup, by asset mint and in total
+#[derive(Debug, Clone, Default)]
+pub struct PnlSummary {
+    pub by_asset: HashMap<Pubkey, AssetPnl>,
+    pub total: AssetPnl,
+}
+
+impl PnlSummary {
+    fn record(&mut self, mint: Pubkey, pnl: AssetPnl) {
+        self.by_asset.entry(mint).or_default().add(&pnl);
+        self.total.add(&pnl);
+    }
+}
+
+/// Records the entries of a landed bundle. The fees of the bundle are split
+/// between its liquidations on their gross profit, as it sizes the jito tip,
+/// or evenly between its swaps when there is no liquidation
+pub fn record_landed(entries: Vec<PnlEntry>, fees_lamports: u64) {
+    if entries.is_empty() {
+        return;
+    }
+
+    let weights = entries
+        .iter()
+        .map(|entry| match entry {
+            PnlEntry::Liquidation(liquidation) => {
+                liquidation.collateral_value - liquidation.liability_value()
+            }
+            PnlEntry::Swap(_) => I80F48::ZERO,
+        })
+        .collect::<Vec<_>>();
+    let total_weight: I80F48 = weights.iter().sum();
+    let entries_count = I80F48::from_num(entries.len());
+
+    let mut summary = PNL.lock().unwrap();
+    for (entry, weight) in entries.into_iter().zip(weights) {
+        let fees_share = if total_weight > I80F48::ZERO {
+            weight / total_weight
+        } else {
+            I80F48::ONE / entries_count
+        };
+        let fees =
+            |lamport_price: I80F48| I80F48::from_num(fees_lamports) * fees_share * lamport_price;
+
+        match entry {
+            PnlEntry::Liquidation(liquidation) => summary.record(
+                liquidation.asset_mint,
+                AssetPnl {
+                    liquidations: 1,
+                    seized_amount: liquidation.asset_amount as u128,
+                    collateral_value: liquidation.collateral_value,
+                    liability_value: liquidation.liability_value(),
+                    fees: fees(liquidation.lamport_price),
+                    swap_slippage: I80F48::ZERO,
+                },
+            ),
+            PnlEntry::Swap(swap) => summary.record(
+                swap.input_mint,
+                AssetPnl {
+                    fees: fees(swap.lamport_price),
+                    swap_slippage: swap.input_value - swap.output_value,
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+}
+
+/// Realized profit and loss since startup
+pub fn summary() -> PnlSummary {
+    PNL.lock().unwrap().clone()
+}
+
+/// Logs the profit and loss summary every `interval`, and a last time when cancelled
+pub async fn serve_pnl_summary(interval: Duration, cancellation_token: CancellationToken) {
+    let mut summary_interval = tokio::time::interval(interval);
+    // The first tick completes immediately, there is nothing to log yet
+    summary_interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => {
+                log_summary();
+                return;
+            }
+            _ = summary_interval.tick() => log_summary(),
+        }
+    }
+}
+
+fn log_summary() {
+    let summary = summary();
+    for (mint, pnl) in &summary.by_asset {
+        info!(
+            "PnL of {}: {} liquidations, seized {} (${:.2}) for ${:.2} of liabilities, fees: ${:.2}, swap slippage: ${:.2}, net: ${:.2}",
+            mint,
+            pnl.liquidations,
+            pnl.seized_amount,
+            pnl.collateral_value,
+            pnl.liability_value,
+            pnl.fees,
+            pnl.swap_slippage,
+            pnl.net_profit()
+        );
+    }
+    info!(
+        "PnL: {} liquidations, gross: ${:.2}, fees: ${:.2}, swap slippage: ${:.2}, net: ${:.2}",
+        summary.total.liquidations,
+        summary.total.gross_profit(),
+        summary.total.fees,
+        summary.total.swap_slippage,
+        summary.total.net_profit()
+    );
+}
@@ -0,0 +1,551 @@
+use crate::transaction_manager::RawTransaction;
+use anchor_lang::{system_program, AnchorSerialize};
+use fixed::types::I80F48;
+use futures::{SinkExt, StreamExt};
+use lazy_static::lazy_static;
+use log::{error, info};
+use marginfi::{
+    constants::{CONF_INTERVAL_MULTIPLE, MAX_CONF_INTERVAL},
+    state::price::{OraclePriceType, PriceBias},
+};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    rent::Rent,
+    system_instruction,
+};
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_util::sync::CancellationToken;
+
+/// Wormhole core bridge used by the pyth receiver to verify the VAAs
+const WORMHOLE_CORE_BRIDGE_PROGRAM_ID: Pubkey =
+    pubkey!("HDwcJBJXjL9FpJ7UBsYBtaDjsBUhuLCUYoz3zr8SWWaQ");
+
+/// Pyth solana receiver, owner of the price update config and treasuries
+const PYTH_SOLANA_RECEIVER_PROGRAM_ID: Pubkey =
+    pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// Pyth push oracle, owner of the price feed accounts read by marginfi
+const PYTH_PUSH_ORACLE_PROGRAM_ID: Pubkey = pubkey!("pythWSnswVUd12oZpeFP8e9CVaEqJg25g1Vtc2biRsT");
+
+/// Size of an encoded VAA account before the VAA bytes
+const ENCODED_VAA_HEADER_SIZE: usize = 46;
+
+/// Bytes of the VAA written per transaction, keeping the transactions under the size limit
+const VAA_CHUNK_SIZE: usize = 700;
+
+/// Price feed updates per transaction, keeping the transactions under the size limit
+const PRICE_UPDATES_PER_TRANSACTION: usize = 2;
+
+/// Treasury receiving the price update fees
+const TREASURY_ID: u8 = 0;
+
+/// Magic of the accumulator updates served by hermes
+const ACCUMULATOR_MAGIC: &[u8; 4] = b"PNAU";
+
+/// Proof type of the accumulator updates verified through a wormhole merkle root
+const WORMHOLE_MERKLE_PROOF_TYPE: u8 = 0;
+
+/// Wait before reconnecting to the hermes websocket
+const HERMES_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub type FeedId = [u8; 32];
+
+lazy_static! {
+    static ref STREAMED_PRICES: RwLock<HashMap<FeedId, StreamedPrice>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Pyth price streamed by hermes, usually ahead of the price posted on chain
+#[derive(Debug, Clone)]
+pub struct StreamedPrice {
+    pub price: I80F48,
+    pub conf: I80F48,
+    pub ema_price: I80F48,
+    pub ema_conf: I80F48,
+    /// Unix time at which the price was published
+    pub publish_time: i64,
+}
+
+impl StreamedPrice {
+    /// Biases the price with its confidence interval, as marginfi does for pyth oracles
+    pub fn get_price_of_type(
+        &self,
+        oracle_type: OraclePriceType,
+        price_bias: Option<PriceBias>,
+    ) -> I80F48 {
+        let (price, conf) = match oracle_type {
+            OraclePriceType::RealTime => (self.price, self.conf),
+            OraclePriceType::TimeWeighted => (self.ema_price, self.ema_conf),
+        };
+        let conf = (conf * CONF_INTERVAL_MULTIPLE).min(price * MAX_CONF_INTERVAL);
+
+        match price_bias {
+            None => price,
+            Some(PriceBias::Low) => price - conf,
+            Some(PriceBias::High) => price + conf,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct HermesPrice {
+    price: String,
+    conf: String,
+    expo: i32,
+    publish_time: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct HermesPriceFeed {
+    id: String,
+    price: HermesPrice,
+    ema_price: HermesPrice,
+}
+
+impl HermesPriceFeed {
+    fn parse(&self) -> anyhow::Result<(FeedId, StreamedPrice)> {
+        let feed_id = hex::decode(self.id.trim_start_matches("0x"))?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid feed id {}", self.id))?;
+        let scale = |value: &str, expo: i32| -> anyhow::Result<I80F48> {
+            Ok(I80F48::from_num(
+                value.parse::<i64>()? as f64 * 10f64.powi(expo),
+            ))
+        };
+
+        Ok((
+            feed_id,
+            StreamedPrice {
+                price: scale(&self.price.price, self.price.expo)?,
+                conf: scale(&self.price.conf, self.price.expo)?,
+                ema_price: scale(&self.ema_price.price, self.ema_price.expo)?,
+                ema_conf: scale(&self.ema_price.conf, self.ema_price.expo)?,
+                publish_time: self.price.publish_time,
+            },
+        ))
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum HermesWsMessage {
+    Response {
+        status: String,
+        error: Option<String>,
+    },
+    PriceUpdate {
+        price_feed: HermesPriceFeed,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Latest price of the feed streamed by hermes, none until the stream received one
+pub fn get_streamed_price(feed_id: &FeedId) -> Option<StreamedPrice> {
+    STREAMED_PRICES.read().unwrap().get(feed_id).cloned()
+}
+
+/// Streams the prices of the feeds from the hermes websocket, reconnecting
+/// when the connection drops, until the cancellation token is cancelled
+pub async fn serve_hermes_price_stream(
+    url: String,
+    feed_ids: Vec<FeedId>,
+    cancellation_token: CancellationToken,
+) {
+    if feed_ids.is_empty() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => return,
+            result = stream_hermes_prices(&url, &feed_ids) => match result {
+                Ok(()) => error!("Hermes price stream closed, reconnecting"),
+                Err(e) => error!("Hermes price stream failed, reconnecting: {:?}", e),
+            },
+        }
+
+        tokio::select! {
+            _ = cancellation_token.cancelled() => return,
+            _ = tokio::time::sleep(HERMES_RECONNECT_DELAY) => {}
+        }
+    }
+}
+
+async fn stream_hermes_prices(url: &str, feed_ids: &[FeedId]) -> anyhow::Result<()> {
+    let (mut socket, _) = connect_async(url).await?;
+
+    let subscription = serde_json::json!({
+        "type": "subscribe",
+        "ids": feed_ids.iter().map(hex::encode).collect::<Vec<_>>(),
+    });
+    socket.send(Message::Text(subscription.to_string())).await?;
+    info!(
+        "Streaming the prices of {} pyth feeds from hermes",
+        feed_ids.len()
+    );
+
+    while let Some(message) = socket.next().await {
+        let Message::Text(text) = message? else {
+            continue;
+        };
+
+        match serde_json::from_str::<HermesWsMessage>(&text)? {
+            HermesWsMessage::PriceUpdate { price_feed } => {
+                let (feed_id, price) = price_feed.parse()?;
+                STREAMED_PRICES.write().unwrap().insert(feed_id, price);
+            }
+            HermesWsMessage::Response { status, error } if status != "success" => {
+                return Err(anyhow::anyhow!(
+                    "Hermes rejected the subscription: {}",
+                    error.unwrap_or(status)
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct HermesBinaryUpdate {
+    data: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct HermesPriceUpdates {
+    binary: HermesBinaryUpdate,
+}
+
+/// Price update of a single feed, proven against the merkle root signed in the VAA
+#[derive(Clone, AnchorSerialize)]
+pub struct MerklePriceUpdate {
+    pub message: Vec<u8>,
+    pub proof: Vec<[u8; 20]>,
+}
+
+impl MerklePriceUpdate {
+    /// Price feed messages start with their type, followed by the feed id
+    fn feed_id(&self) -> Option<&[u8]> {
+        self.message.get(1..33)
+    }
+}
+
+/// Accumulator update served by hermes, a VAA and the price updates it proves
+pub struct AccumulatorUpdate {
+    pub vaa: Vec<u8>,
+    pub updates: Vec<MerklePriceUpdate>,
+}
+
+#[derive(AnchorSerialize)]
+struct WriteEncodedVaaArgs {
+    index: u32,
+    data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize)]
+struct PostUpdateParams {
+    merkle_price_update: MerklePriceUpdate,
+    treasury_id: u8,
+}
+
+/// Client of the hermes api, serving the pyth pull oracle price updates
+pub struct HermesClient {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HermesClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    /// Fetches the latest price updates of the feeds, proven by a single VAA
+    pub async fn get_latest_price_updates(
+        &self,
+        feed_ids: &[FeedId],
+    ) -> anyhow::Result<AccumulatorUpdate> {
+        let mut query = feed_ids
+            .iter()
+            .map(|feed_id| ("ids[]", hex::encode(feed_id)))
+            .collect::<Vec<_>>();
+        query.push(("encoding", "hex".to_string()));
+
+        let response = self
+            .client
+            .get(format!("{}/v2/updates/price/latest", self.url))
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<HermesPriceUpdates>()
+            .await?;
+
+        let data = response
+            .binary
+            .data
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Hermes returned no price update"))?;
+
+        parse_accumulator_update(&hex::decode(data)?)
+    }
+}
+
+/// Parses an accumulator update, encoded in big endian
+fn parse_accumulator_update(data: &[u8]) -> anyhow::Result<AccumulatorUpdate> {
+    let mut cursor = data;
+
+    if take(&mut cursor, 4)? != ACCUMULATOR_MAGIC {
+        return Err(anyhow::anyhow!("Invalid accumulator update magic"));
+    }
+
+    // Major and minor versions
+    take(&mut cursor, 2)?;
+
+    let trailing_header_size = take(&mut cursor, 1)?[0] as usize;
+    take(&mut cursor, trailing_header_size)?;
+
+    if take(&mut cursor, 1)?[0] != WORMHOLE_MERKLE_PROOF_TYPE {
+        return Err(anyhow::anyhow!("Unsupported accumulator proof type"));
+    }
+
+    let vaa_size = u16::from_be_bytes(take(&mut cursor, 2)?.try_into()?) as usize;
+    let vaa = take(&mut cursor, vaa_size)?.to_vec();
+
+    let updates_count = take(&mut cursor, 1)?[0] as usize;
+    let mut updates = Vec::with_capacity(updates_count);
+    for _ in 0..updates_count {
+        let message_size = u16::from_be_bytes(take(&mut cursor, 2)?.try_into()?) as usize;
+        let message = take(&mut cursor, message_size)?.to_vec();
+
+        let proof_size = take(&mut cursor, 1)?[0] as usize;
+        let mut proof = Vec::with_capacity(proof_size);
+        for _ in 0..proof_size {
+            proof.push(take(&mut cursor, 20)?.try_into()?);
+        }
+
+        updates.push(MerklePriceUpdate { message, proof });
+    }
+
+    Ok(AccumulatorUpdate { vaa, updates })
+}
+
+/// Takes the next `len` bytes of the cursor
+fn take<'a>(cursor: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if cursor.len() < len {
+        return Err(anyhow::anyhow!("Accumulator update is truncated"));
+    }
+    let (bytes, rest) = cursor.split_at(len);
+    *cursor = rest;
+    Ok(bytes)
+}
+
+/// Finds the price feed account of the pyth push oracle for the shard and feed id
+fn find_price_feed_address(shard_id: u16, feed_id: &FeedId) -> Pubkey {
+    Pubkey::find_program_address(
+        &[&shard_id.to_le_bytes(), feed_id],
+        &PYTH_PUSH_ORACLE_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Builds the transactions posting the price updates to the pyth push oracle feeds:
+/// the VAA is written and verified in an encoded VAA account, the feeds are updated
+/// and the encoded VAA account is closed to reclaim its rent
+pub fn make_price_update_transactions(
+    payer: Pubkey,
+    feeds: &[(u16, FeedId)],
+    accumulator_update: AccumulatorUpdate,
+) -> anyhow::Result<Vec<RawTransaction>> {
+    let AccumulatorUpdate { vaa, updates } = accumulator_update;
+
+    // Guardian set index, after the VAA version
+    let guardian_set_index = u32::from_be_bytes(
+        vaa.get(1..5)
+            .ok_or_else(|| anyhow::anyhow!("VAA is truncated"))?
+            .try_into()?,
+    );
+
+    // The encoded VAA account is derived from the payer, so no other signer is needed
+    let seed = format!(
+        "{:x}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()
+    );
+    let encoded_vaa = Pubkey::create_with_seed(&payer, &seed, &WORMHOLE_CORE_BRIDGE_PROGRAM_ID)?;
+    let encoded_vaa_size = ENCODED_VAA_HEADER_SIZE + vaa.len();
+
+    let mut transactions = vec![];
+    for (index, chunk) in vaa.chunks(VAA_CHUNK_SIZE).enumerate() {
+        let mut ixs = vec![];
+        if index == 0 {
+            ixs.push(system_instruction::create_account_with_seed(
+                &payer,
+                &encoded_vaa,
+                &payer,
+                &seed,
+                Rent::default().minimum_balance(encoded_vaa_size),
+                encoded_vaa_size as u64,
+                &WORMHOLE_CORE_BRIDGE_PROGRAM_ID,
+            ));
+            ixs.push(make_init_encoded_vaa_ix(payer, encoded_vaa));
+        }
+        ixs.push(make_write_encoded_vaa_ix(
+            payer,
+            encoded_vaa,
+            (index * VAA_CHUNK_SIZE) as u32,
+            chunk.to_vec(),
+        )?);
+        transactions.push(ixs);
+    }
+
+    if let Some(ixs) = transactions.last_mut() {
+        ixs.push(make_verify_encoded_vaa_ix(
+            payer,
+            encoded_vaa,
+            guardian_set_index,
+        ));
+    }
+
+    let mut update_ixs = vec![];
+    for (shard_id, feed_id) in feeds {
+        let update = updates
+            .iter()
+            .find(|update| update.feed_id() == Some(feed_id.as_slice()))
+            .ok_or_else(|| anyhow::anyhow!("No price update for feed {}", hex::encode(feed_id)))?;
+
+        update_ixs.push(make_update_price_feed_ix(
+            payer,
+            encoded_vaa,
+            *shard_id,
+            *feed_id,
+            update.clone(),
+        )?);
+    }
+
+    for chunk in update_ixs.chunks(PRICE_UPDATES_PER_TRANSACTION) {
+        transactions.push(chunk.to_vec());
+    }
+
+    if let Some(ixs) = transactions.last_mut() {
+        ixs.push(make_close_encoded_vaa_ix(payer, encoded_vaa));
+    }
+
+    Ok(transactions.into_iter().map(RawTransaction::new).collect())
+}
+
+/// Anchor instruction data, the discriminator followed by the serialized arguments
+fn make_instruction_data(name: &str, args: &[u8]) -> Vec<u8> {
+    let mut data = hash(format!("global:{}", name).as_bytes()).to_bytes()[..8].to_vec();
+    data.extend_from_slice(args);
+    data
+}
+
+fn make_init_encoded_vaa_ix(write_authority: Pubkey, encoded_vaa: Pubkey) -> Instruction {
+    Instruction {
+        program_id: WORMHOLE_CORE_BRIDGE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(write_authority, true),
+            AccountMeta::new(encoded_vaa, false),
+        ],
+        data: make_instruction_data("init_encoded_vaa", &[]),
+    }
+}
+
+fn make_write_encoded_vaa_ix(
+    write_authority: Pubkey,
+    encoded_vaa: Pubkey,
+    index: u32,
+    data: Vec<u8>,
+) -> anyhow::Result<Instruction> {
+    Ok(Instruction {
+        program_id: WORMHOLE_CORE_BRIDGE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(write_authority, true),
+            AccountMeta::new(encoded_vaa, false),
+        ],
+        data: make_instruction_data(
+            "write_encoded_vaa",
+            &WriteEncodedVaaArgs { index, data }.try_to_vec()?,
+        ),
+    })
+}
+
+fn make_verify_encoded_vaa_ix(
+    write_authority: Pubkey,
+    encoded_vaa: Pubkey,
+    guardian_set_index: u32,
+) -> Instruction {
+    let (guardian_set, _) = Pubkey::find_program_address(
+        &[b"GuardianSet", &guardian_set_index.to_be_bytes()],
+        &WORMHOLE_CORE_BRIDGE_PROGRAM_ID,
+    );
+
+    Instruction {
+        program_id: WORMHOLE_CORE_BRIDGE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(write_authority, true),
+            AccountMeta::new(encoded_vaa, false),
+            AccountMeta::new_readonly(guardian_set, false),
+        ],
+        data: make_instruction_data("verify_encoded_vaa_v1", &[]),
+    }
+}
+
+fn make_close_encoded_vaa_ix(write_authority: Pubkey, encoded_vaa: Pubkey) -> Instruction {
+    Instruction {
+        program_id: WORMHOLE_CORE_BRIDGE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(write_authority, true),
+            AccountMeta::new(encoded_vaa, false),
+        ],
+        data: make_instruction_data("close_encoded_vaa", &[]),
+    }
+}
+
+fn make_update_price_feed_ix(
+    payer: Pubkey,
+    encoded_vaa: Pubkey,
+    shard_id: u16,
+    feed_id: FeedId,
+    merkle_price_update: MerklePriceUpdate,
+) -> anyhow::Result<Instruction> {
+    let (config, _) = Pubkey::find_program_address(&[b"config"], &PYTH_SOLANA_RECEIVER_PROGRAM_ID);
+    let (treasury, _) = Pubkey::find_program_address(
+        &[b"treasury", &[TREASURY_ID]],
+        &PYTH_SOLANA_RECEIVER_PROGRAM_ID,
+    );
+
+    let mut args = PostUpdateParams {
+        merkle_price_update,
+        treasury_id: TREASURY_ID,
+    }
+    .try_to_vec()?;
+    args.extend_from_slice(&shard_id.to_le_bytes());
+    args.extend_from_slice(&feed_id);
+
+    Ok(Instruction {
+        program_id: PYTH_PUSH_ORACLE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(PYTH_SOLANA_RECEIVER_PROGRAM_ID, false),
+            AccountMeta::new_readonly(encoded_vaa, false),
+            AccountMeta::new_readonly(config, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new(find_price_feed_address(shard_id, &feed_id), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: make_instruction_data("update_price_feed", &args),
+    })
+}
@@ -1,44 +1,37 @@
 use crate::{
-    config::{GeneralConfig, RebalancerCfg},
+    config::{GeneralConfig, PostLiquidationStrategy, RebalancerCfg},
     crossbar::CrossbarMaintainer,
     geyser::{AccountType, GeyserUpdate},
-    sender::{SenderCfg, TransactionSender},
+    jupiter::JupiterSwapper,
+    pnl::{PnlEntry, SwapPnl},
+    rpc_pool::RpcPool,
     token_account_manager::TokenAccountManager,
-    transaction_manager::{BatchTransactions, RawTransaction},
+    transaction_manager::{BatchTransactions, InFlightLiquidations, RawTransaction},
     utils::{
         accessor, batch_get_multiple_accounts, calc_weighted_assets_new, calc_weighted_liabs_new,
-        BankAccountWithPriceFeedEva,
     },
     wrappers::{
         bank::BankWrapper, liquidator_account::LiquidatorAccount,
-        marginfi_account::MarginfiAccountWrapper, token_account::TokenAccountWrapper,
+        token_account::TokenAccountWrapper,
     },
 };
-use anyhow::anyhow;
 use crossbeam::channel::{Receiver, Sender};
 use fixed::types::I80F48;
 use fixed_macro::types::I80F48;
-use jupiter_swap_api_client::{
-    quote::QuoteRequest,
-    swap::SwapRequest,
-    transaction_config::{ComputeUnitPriceMicroLamports, TransactionConfig},
-    JupiterSwapApiClient,
-};
 use log::{debug, info, warn};
 use marginfi::{
-    constants::EXP_10_I80F48,
+    constants::{
+        EMISSIONS_FLAG_BORROW_ACTIVE, EMISSIONS_FLAG_LENDING_ACTIVE, EXP_10_I80F48,
+        SECONDS_PER_YEAR,
+    },
     state::{
-        marginfi_account::{BalanceSide, MarginfiAccount, RequirementType},
+        marginfi_account::{Balance, BalanceSide, MarginfiAccount, RequirementType},
         price::{OraclePriceFeedAdapter, OracleSetup, PriceBias, SwitchboardPullPriceFeed},
     },
 };
-use solana_client::{
-    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
-};
 use solana_program::pubkey::Pubkey;
 use solana_sdk::{
     account_info::IntoAccountInfo, clock::Clock, commitment_config::CommitmentConfig,
-    signature::read_keypair_file, transaction::VersionedTransaction,
 };
 use std::{
     cmp::min,
@@ -48,7 +41,7 @@ use std::{
 };
 use switchboard_on_demand::PullFeedAccountData;
 use switchboard_on_demand_client::QueueAccountData;
-use switchboard_on_demand_client::{FetchUpdateManyParams, Gateway, PullFeed};
+use tokio_util::sync::CancellationToken;
 /// The rebalancer is responsible to keep the liquidator account
 /// "rebalanced" -> Document this better
 pub struct Rebalancer {
@@ -58,7 +51,7 @@ pub struct Rebalancer {
     token_accounts: HashMap<Pubkey, TokenAccountWrapper>,
     banks: HashMap<Pubkey, BankWrapper>,
     token_account_manager: TokenAccountManager,
-    rpc_client: Arc<RpcClient>,
+    rpc_pool: Arc<RpcPool>,
     mint_to_bank: HashMap<Pubkey, Pubkey>,
     oracle_to_bank: HashMap<Pubkey, Pubkey>,
     preferred_mints: HashSet<Pubkey>,
@@ -66,6 +59,12 @@ pub struct Rebalancer {
     geyser_receiver: Receiver<GeyserUpdate>,
     stop_liquidations: Arc<AtomicBool>,
     crossbar_client: CrossbarMaintainer,
+    jupiter_swapper: JupiterSwapper,
+    cancellation_token: CancellationToken,
+    /// When the emissions were last claimed, startup until the first claim
+    last_emissions_claim: std::time::Instant,
+    /// Banks of the empty balances being closed, so they are closed only once
+    closing_balances: HashSet<Pubkey>,
 }
 
 impl Rebalancer {
@@ -75,13 +74,14 @@ impl Rebalancer {
         transaction_tx: Sender<BatchTransactions>,
         geyser_receiver: Receiver<GeyserUpdate>,
         stop_liquidation: Arc<AtomicBool>,
+        cancellation_token: CancellationToken,
+        rpc_pool: Arc<RpcPool>,
     ) -> anyhow::Result<Self> {
-        let rpc_client = Arc::new(RpcClient::new(general_config.rpc_url.clone()));
-        let token_account_manager = TokenAccountManager::new(rpc_client.clone())?;
+        let token_account_manager =
+            TokenAccountManager::new(rpc_pool.clone(), general_config.get_sender_config())?;
 
         let liquidator_account = LiquidatorAccount::new(
-            RpcClient::new(general_config.rpc_url.clone()),
-            general_config.liquidator_account,
+            rpc_pool.clone(),
             transaction_tx.clone(),
             general_config.clone(),
         )
@@ -89,6 +89,8 @@ impl Rebalancer {
 
         let preferred_mints = config.preferred_mints.iter().cloned().collect();
 
+        let jupiter_swapper = JupiterSwapper::from_config(&config, rpc_pool.clone());
+
         Ok(Rebalancer {
             config,
             general_config,
@@ -96,7 +98,7 @@ impl Rebalancer {
             token_accounts: HashMap::new(),
             banks: HashMap::new(),
             token_account_manager,
-            rpc_client,
+            rpc_pool,
             mint_to_bank: HashMap::new(),
             oracle_to_bank: HashMap::new(),
             preferred_mints,
@@ -104,9 +106,24 @@ impl Rebalancer {
             geyser_receiver,
             stop_liquidations: stop_liquidation,
             crossbar_client: CrossbarMaintainer::new(),
+            jupiter_swapper,
+            cancellation_token,
+            last_emissions_claim: std::time::Instant::now(),
+            closing_balances: HashSet::new(),
         })
     }
 
+    /// Holds the batches of the rebalancer until the liquidations in flight
+    /// resolve, so it never moves collateral which isn't seized yet
+    pub fn with_liquidation_dependencies(
+        mut self,
+        in_flight_liquidations: InFlightLiquidations,
+    ) -> Self {
+        self.liquidator_account
+            .set_liquidation_dependencies(in_flight_liquidations);
+        self
+    }
+
     pub async fn load_data(
         &mut self,
         banks_and_map: (HashMap<Pubkey, BankWrapper>, HashMap<Pubkey, Pubkey>),
@@ -130,12 +147,8 @@ impl Rebalancer {
             .token_account_manager
             .get_mints_and_token_account_addresses();
 
-        self.liquidator_account
-            .load_initial_data(&self.rpc_client, mints.clone())
-            .await?;
-
         let accounts = batch_get_multiple_accounts(
-            self.rpc_client.clone(),
+            self.rpc_pool.rpc(),
             &token_account_addresses,
             crate::utils::BatchLoadingConfig::DEFAULT,
         )?;
@@ -179,14 +192,64 @@ impl Rebalancer {
             .get_bank_for_mint(&self.config.swap_mint)
             .map(|bank| bank.address);
 
+        self.seed_deposits()?;
+
+        Ok(())
+    }
+
+    /// Deposits the configured seed amounts from the signer token accounts into the
+    /// liquidator account, skipping the ones its token balances can't cover.
+    /// Done once the token accounts are loaded, so not again when the rebalancer restarts
+    fn seed_deposits(&self) -> anyhow::Result<()> {
+        for seed_deposit in &self.config.seed_deposits {
+            let Some(token_account) = self.token_accounts.get(&seed_deposit.mint) else {
+                warn!(
+                    "Skipping the seed deposit of mint {}, no bank of the group holds it",
+                    seed_deposit.mint
+                );
+                continue;
+            };
+
+            if token_account.balance < seed_deposit.amount {
+                warn!(
+                    "Skipping the seed deposit of {} of mint {}, the token account only holds {}",
+                    seed_deposit.amount, seed_deposit.mint, token_account.balance
+                );
+                continue;
+            }
+
+            let bank = self.banks.get(&token_account.bank_address).unwrap();
+            if !bank.accepts_increases() {
+                warn!(
+                    "Skipping the seed deposit of mint {}, the bank {} is {:?}",
+                    seed_deposit.mint, bank.address, bank.bank.config.operational_state
+                );
+                continue;
+            }
+
+            info!(
+                "Depositing {} of mint {} into the liquidator account",
+                seed_deposit.amount, seed_deposit.mint
+            );
+            self.liquidator_account
+                .deposit(bank, token_account.address, seed_deposit.amount)?;
+        }
+
         Ok(())
     }
 
     pub async fn start(&mut self) -> anyhow::Result<()> {
         let max_duration = std::time::Duration::from_secs(10);
         loop {
+            if self.cancellation_token.is_cancelled() {
+                info!("Rebalancer stopped");
+                return Ok(());
+            }
             let start = std::time::Instant::now().checked_sub(max_duration).unwrap();
             while let Ok(mut msg) = self.geyser_receiver.recv() {
+                if self.cancellation_token.is_cancelled() {
+                    break;
+                }
                 debug!("Received message {:?}", msg);
                 match msg.account_type {
                     AccountType::OracleAccount => {
@@ -234,23 +297,46 @@ impl Rebalancer {
                         }
                     }
                     AccountType::MarginfiAccount => {
-                        if msg.address == self.general_config.liquidator_account {
+                        if msg.address == self.liquidator_account.account_wrapper.address {
                             let marginfi_account =
                                 bytemuck::from_bytes::<MarginfiAccount>(&msg.account.data[8..]);
 
-                            self.liquidator_account.account_wrapper.account = *marginfi_account;
+                            self.liquidator_account.refresh_account(*marginfi_account);
+
+                            // Balances emptied by a withdraw all or a repay all are closed
+                            // once the account update confirms they are empty
+                            self.close_empty_balances();
                         }
                     }
                     AccountType::TokenAccount => {
+                        // A closed token account, like an unwrapped wSOL account, has no data left
+                        if msg.account.data.is_empty() {
+                            if let Some(token_account) = self
+                                .token_accounts
+                                .values_mut()
+                                .find(|token_account| token_account.address == msg.address)
+                            {
+                                token_account.balance = 0;
+                            }
+                            continue;
+                        }
+
                         let mint = accessor::mint(&msg.account.data);
                         let balance = accessor::amount(&msg.account.data);
 
-                        let token_to_update = self.token_accounts.get_mut(&mint).unwrap();
-
-                        token_to_update.balance = balance;
+                        // The token accounts of the other signers are tracked as well
+                        if let Some(token_to_update) = self
+                            .token_accounts
+                            .get_mut(&mint)
+                            .filter(|token_account| token_account.address == msg.address)
+                        {
+                            token_to_update.balance = balance;
+                        }
                     }
                 }
 
+                self.maybe_claim_emissions();
+
                 if start.elapsed() > max_duration && self.needs_to_be_relanced().await {
                     if let Err(e) = self.rebalance_accounts().await {
                         info!("Failed to rebalance account: {:?}", e);
@@ -284,49 +370,78 @@ impl Rebalancer {
 
         self.should_stop_liquidations().await.unwrap();
 
-        self.has_tokens_in_token_accounts()
-            || self.has_non_preferred_deposits()
-            || self.has_liabilities()
+        let value_to_rebalance = match self.get_value_to_rebalance() {
+            Ok(value) => value,
+            Err(e) => {
+                warn!("Failed to value the positions to rebalance: {:?}", e);
+                return false;
+            }
+        };
+
+        // The liquidator account is deleveraged right away when its own health is low,
+        // or can't be told
+        if self.is_below_health_buffer().unwrap_or(true) {
+            warn!("The liquidator account is below its health buffer, deleveraging it");
+            return true;
+        }
+
+        if value_to_rebalance.is_zero() {
+            return false;
+        }
+
+        if value_to_rebalance < self.config.min_rebalance_usd {
+            debug!(
+                "Skipping rebalance of ${}, below the minimum of ${}",
+                value_to_rebalance, self.config.min_rebalance_usd
+            );
+            return false;
+        }
+
+        true
     }
 
     async fn rebalance_accounts(&mut self) -> anyhow::Result<()> {
         let active_banks = self.liquidator_account.account_wrapper.get_active_banks();
 
-        let active_swb_oracles: Vec<Pubkey> = active_banks
-            .iter()
-            .filter_map(|&bank_pk| {
-                self.banks.get(&bank_pk).and_then(|bank| {
-                    if bank.oracle_adapter.is_switchboard_pull() {
-                        Some(bank.oracle_adapter.address)
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect();
-
-        if !active_swb_oracles.is_empty() {
-            if let Ok((ix, lut)) = PullFeed::fetch_update_many_ix(
-                &self.liquidator_account.non_blocking_rpc_client,
-                FetchUpdateManyParams {
-                    feeds: active_swb_oracles,
-                    payer: self.general_config.signer_pubkey,
-                    gateway: self.liquidator_account.swb_gateway.clone(),
-                    num_signatures: Some(1),
-                    ..Default::default()
-                },
-            )
+        match self
+            .liquidator_account
+            .build_oracle_updates(&active_banks, &self.banks)
             .await
-            {
-                self.liquidator_account
-                    .transaction_tx
-                    .send(vec![RawTransaction::new(vec![ix]).with_lookup_tables(lut)])
-                    .unwrap();
+        {
+            Ok(oracle_updates) => {
+                let transactions = oracle_updates.into_transactions();
+                if !transactions.is_empty() {
+                    self.liquidator_account
+                        .transaction_tx
+                        .send(transactions)
+                        .unwrap();
+                }
             }
+            Err(e) => warn!("Failed to build the oracle updates: {:?}", e),
         }
         debug!("Rebalancing accounts");
-        self.sell_non_preferred_deposits().await?;
-        self.repay_liabilities().await?;
+        // Below its health buffer the liquidator account is deleveraged right away,
+        // whatever the strategy, the collateral being sold to repay the liabilities
+        let strategy = if self.is_below_health_buffer()? {
+            PostLiquidationStrategy::WithdrawAndSell
+        } else {
+            self.config.post_liquidation_strategy
+        };
+        match strategy {
+            PostLiquidationStrategy::HoldAsMarginfiPosition => {}
+            PostLiquidationStrategy::WithdrawAndSell => {
+                self.sell_non_preferred_deposits().await?;
+                self.repay_liabilities().await?;
+            }
+            PostLiquidationStrategy::RepayThenWithdraw => {
+                // The collateral is sold by a next rebalance, once the repayments landed
+                if self.has_liabilities_to_repay()? {
+                    self.repay_liabilities().await?;
+                } else {
+                    self.sell_non_preferred_deposits().await?;
+                }
+            }
+        }
         self.handle_tokens_in_token_accounts().await?;
         self.deposit_preferred_tokens().await?;
 
@@ -336,10 +451,10 @@ impl Rebalancer {
     // If our margin is at 50% or lower, we should stop liquidations and await until the account
     // is fully rebalanced
     pub async fn should_stop_liquidations(&self) -> anyhow::Result<()> {
-        let (assets, liabs) = self.calc_health(
-            &self.liquidator_account.account_wrapper,
-            RequirementType::Initial,
-        );
+        let (assets, liabs) = self
+            .liquidator_account
+            .account_wrapper
+            .calc_health(&self.banks, RequirementType::Initial)?;
 
         if assets.is_zero() {
             warn!("Assets are zero, stopping liquidations");
@@ -350,7 +465,8 @@ impl Rebalancer {
             return Ok(());
         }
 
-        if (assets - liabs) / assets <= 0.5 {
+        // No new collateral is seized until the liquidator account is deleveraged
+        if (assets - liabs) / assets <= 0.5 || self.is_below_health_buffer()? {
             self.stop_liquidations
                 .store(true, std::sync::atomic::Ordering::Relaxed);
         } else {
@@ -390,6 +506,9 @@ impl Rebalancer {
         }
 
         for (_, bank_pk) in non_preferred_deposits {
+            if self.is_dust_position(&bank_pk)? {
+                continue;
+            }
             self.withdraw_and_sell_deposit(&bank_pk).await?;
         }
         Ok(())
@@ -402,12 +521,30 @@ impl Rebalancer {
             .get_liabilities_shares();
 
         for (_, bank_pk) in liabilities {
+            if self.is_dust_position(&bank_pk)? {
+                continue;
+            }
             let _ = self.repay_liability(bank_pk).await;
         }
 
         Ok(())
     }
 
+    /// Whether the liquidator account has liabilities left to repay, dust aside
+    fn has_liabilities_to_repay(&self) -> anyhow::Result<bool> {
+        for (_, bank_pk) in self
+            .liquidator_account
+            .account_wrapper
+            .get_liabilities_shares()
+        {
+            if !self.is_dust_position(&bank_pk)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
     /// Repay a liability for a given bank
     ///
     /// - Find any bank tokens in token accounts
@@ -417,8 +554,15 @@ impl Rebalancer {
     /// - Withdraw USDC
     /// - Swap USDC for bank tokens
     /// - Repay liability
+    ///
+    /// The withdraw, the swap and the repay are sent in a single bundle, which
+    /// is waited for so the next step sees the tokens it moved
     async fn repay_liability(&mut self, bank_pk: Pubkey) -> anyhow::Result<()> {
         let bank = self.banks.get(&bank_pk).unwrap();
+        if bank.is_paused() {
+            debug!("Skipping the repayment of bank {}, it is paused", bank_pk);
+            return Ok(());
+        }
 
         // Get the balance for the liability and check if it's a valide balance
 
@@ -463,6 +607,8 @@ impl Rebalancer {
 
         let token_balance_to_withdraw = required_swap_token - swap_token_balance;
 
+        let mut bundle = vec![];
+
         let withdraw_amount = if token_balance_to_withdraw.is_positive() {
             let (max_withdraw_amount, withdraw_all) =
                 self.get_max_withdraw_for_bank(&self.swap_mint_bank_pk.unwrap())?;
@@ -471,51 +617,75 @@ impl Rebalancer {
 
             let bank = self.banks.get(&self.swap_mint_bank_pk.unwrap()).unwrap();
 
-            self.liquidator_account.withdraw(
-                bank,
-                self.token_account_manager
-                    .get_address_for_mint(bank.bank.mint)
-                    .unwrap(),
-                withdraw_amount.to_num(),
-                Some(withdraw_all),
-                &self.banks,
-            )?;
+            if withdraw_amount.is_positive() {
+                let (withdraw_tx, withdrawn_amount) = self.liquidator_account.build_withdraw(
+                    bank,
+                    self.token_account_manager
+                        .get_address_for_mint(bank.bank.mint)
+                        .unwrap(),
+                    withdraw_amount.to_num(),
+                    Some(withdraw_all),
+                    false,
+                    &self.banks,
+                )?;
+                bundle.push(withdraw_tx);
 
-            withdraw_amount
+                I80F48::from_num(withdrawn_amount)
+            } else {
+                warn!(
+                    "Skipping withdraw from bank {}, it would bring the liquidator account below its health buffer",
+                    bank.address
+                );
+
+                I80F48::ZERO
+            }
         } else {
             I80F48::ZERO
         };
 
-        let amount_to_swap = min(liab_balance + withdraw_amount, required_swap_token);
+        let amount_to_swap = min(swap_token_balance + withdraw_amount, required_swap_token);
 
-        if amount_to_swap.is_positive() {
-            self.swap(
-                amount_to_swap.to_num(),
-                &self.swap_mint_bank_pk.unwrap(),
-                &bank_pk,
-            )
-            .await?;
+        let mut repay_amount = token_balance;
+        let mut swap_out_amount = 0;
 
-            self.refresh_token_account(&bank_pk).await?;
+        if amount_to_swap.is_positive() {
+            if let Some((swap_tx, min_out_amount)) = self
+                .swap_transaction(
+                    amount_to_swap.to_num(),
+                    &self.swap_mint_bank_pk.unwrap(),
+                    &bank_pk,
+                )
+                .await?
+            {
+                bundle.push(swap_tx);
+                swap_out_amount = min_out_amount;
+                repay_amount += I80F48::from_num(min_out_amount);
+            }
         }
 
-        let token_balance = self
-            .get_token_balance_for_bank(&bank_pk)?
-            .unwrap_or_default();
+        if repay_amount.is_zero() {
+            return Ok(());
+        }
 
-        let repay_all = token_balance >= liab_balance;
+        let repay_all = repay_amount >= liab_balance;
 
         let bank = self.banks.get(&bank_pk).unwrap();
 
-        self.liquidator_account.repay(
-            bank,
-            &self
-                .token_account_manager
-                .get_address_for_mint(bank.bank.mint)
-                .unwrap(),
-            token_balance.to_num(),
-            Some(repay_all),
-        )?;
+        bundle.push(
+            self.liquidator_account.build_repay(
+                bank,
+                &self
+                    .token_account_manager
+                    .get_address_for_mint(bank.bank.mint)
+                    .unwrap(),
+                repay_amount.to_num(),
+                Some(repay_all),
+                swap_out_amount,
+            )?,
+        );
+
+        self.send_and_wait(bundle, &[self.swap_mint_bank_pk.unwrap(), bank_pk])
+            .await?;
 
         Ok(())
     }
@@ -534,6 +704,13 @@ impl Rebalancer {
         }
 
         let bank = self.banks.get(&self.swap_mint_bank_pk.unwrap()).unwrap();
+        if !bank.accepts_increases() {
+            debug!(
+                "Keeping the preferred tokens in the token account, the bank {} is {:?}",
+                bank.address, bank.bank.config.operational_state
+            );
+            return Ok(());
+        }
         let token_address = self
             .token_account_manager
             .get_address_for_mint(bank.bank.mint)
@@ -545,17 +722,155 @@ impl Rebalancer {
         Ok(())
     }
 
-    fn has_tokens_in_token_accounts(&self) -> bool {
-        let has_tokens_in_tas = self.token_accounts.values().any(|account| {
-            let bank = self.banks.get(&account.bank_address).unwrap();
-            let value = account.get_value(bank).unwrap();
-            value > self.config.token_account_dust_threshold
-        });
-        has_tokens_in_tas
+    /// Closes the balances of the liquidator account left empty, which
+    /// would otherwise fill up its balance slots over time
+    fn close_empty_balances(&mut self) {
+        let empty_balances = self.liquidator_account.account_wrapper.get_empty_balances();
+
+        // The balances no longer empty were closed, or received a new position
+        self.closing_balances
+            .retain(|bank_pk| empty_balances.contains(bank_pk));
+
+        let banks_to_close = empty_balances
+            .into_iter()
+            .filter(|bank_pk| !self.closing_balances.contains(bank_pk))
+            .collect::<Vec<_>>();
+
+        if banks_to_close.is_empty() {
+            return;
+        }
+
+        info!("Closing the empty balances of banks {:?}", banks_to_close);
+        if let Err(e) = self.liquidator_account.close_balances(&banks_to_close) {
+            warn!("Failed to close the empty balances: {:?}", e);
+            return;
+        }
+
+        self.closing_balances.extend(banks_to_close);
+    }
+
+    /// Claims the emissions accrued by the liquidator positions, worth at least
+    /// `min_claim_emissions_usd`, once every `claim_emissions_interval_secs`
+    fn maybe_claim_emissions(&mut self) {
+        let interval = std::time::Duration::from_secs(self.config.claim_emissions_interval_secs);
+        if !self.config.claim_emissions || self.last_emissions_claim.elapsed() < interval {
+            return;
+        }
+        self.last_emissions_claim = std::time::Instant::now();
+
+        let mut banks_to_claim = vec![];
+        for balance in self
+            .liquidator_account
+            .account_wrapper
+            .account
+            .lending_account
+            .balances
+            .iter()
+            .filter(|balance| balance.active)
+        {
+            let bank = match self.banks.get(&balance.bank_pk) {
+                Some(bank) => bank,
+                None => continue,
+            };
+            if bank.bank.emissions_mint == Pubkey::default() {
+                continue;
+            }
+
+            let emissions_active = bank.bank.flags
+                & (EMISSIONS_FLAG_BORROW_ACTIVE | EMISSIONS_FLAG_LENDING_ACTIVE)
+                != 0;
+            let emissions_outstanding = I80F48::from(balance.emissions_outstanding);
+            if !emissions_active && emissions_outstanding.is_zero() {
+                continue;
+            }
+
+            // Emissions which can't be valued are only claimed when every claim is wanted
+            let value = self.get_emissions_value(bank, balance);
+            let min_value = self.config.min_claim_emissions_usd;
+            if min_value.is_positive() && !value.is_some_and(|value| value >= min_value) {
+                debug!(
+                    "Skipping the emissions of bank {}, worth {:?} USD",
+                    bank.address, value
+                );
+                continue;
+            }
+
+            info!(
+                "Claiming the emissions of bank {}, worth {:?} USD",
+                bank.address, value
+            );
+            banks_to_claim.push(bank);
+        }
+
+        if banks_to_claim.is_empty() {
+            return;
+        }
+        if let Err(e) = self.liquidator_account.withdraw_emissions(&banks_to_claim) {
+            warn!(
+                "Failed to claim the emissions of {} banks: {:?}",
+                banks_to_claim.len(),
+                e
+            );
+        }
+    }
+
+    /// Value in USD of the emissions of the balance, the outstanding ones along with
+    /// those accrued since its last update, as the program settles them on the claim.
+    /// None when no bank prices the emissions mint
+    fn get_emissions_value(&self, bank: &BankWrapper, balance: &Balance) -> Option<I80F48> {
+        let emissions_bank = self
+            .banks
+            .values()
+            .find(|emissions_bank| emissions_bank.bank.mint == bank.bank.emissions_mint)?;
+
+        let balance_amount = match balance.get_side() {
+            Some(BalanceSide::Assets) if bank.bank.flags & EMISSIONS_FLAG_LENDING_ACTIVE != 0 => {
+                bank.bank
+                    .get_asset_amount(balance.asset_shares.into())
+                    .ok()?
+            }
+            Some(BalanceSide::Liabilities)
+                if bank.bank.flags & EMISSIONS_FLAG_BORROW_ACTIVE != 0 =>
+            {
+                bank.bank
+                    .get_liability_amount(balance.liability_shares.into())
+                    .ok()?
+            }
+            _ => I80F48::ZERO,
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        let period = I80F48::from_num(now.saturating_sub(balance.last_update));
+        let accrued_emissions = period
+            * (balance_amount * I80F48::from_num(bank.bank.emissions_rate)
+                / EXP_10_I80F48[bank.bank.mint_decimals as usize])
+            / SECONDS_PER_YEAR;
+
+        emissions_bank
+            .calc_value(
+                I80F48::from(balance.emissions_outstanding) + accrued_emissions,
+                BalanceSide::Assets,
+                RequirementType::Equity,
+            )
+            .ok()
     }
 
-    fn has_non_preferred_deposits(&self) -> bool {
-        let has_non_preferred_deposits = self
+    /// Value of everything that has to be rebalanced: tokens sitting in token accounts,
+    /// non preferred deposits and liabilities. Dust is left out
+    fn get_value_to_rebalance(&self) -> anyhow::Result<I80F48> {
+        let mut value = I80F48::ZERO;
+
+        for account in self.token_accounts.values() {
+            let bank = self.banks.get(&account.bank_address).unwrap();
+            let account_value = account.get_value(bank)?;
+            if account_value > self.config.token_account_dust_threshold {
+                value += account_value;
+            }
+        }
+
+        for balance in self
             .liquidator_account
             .account_wrapper
             .account
@@ -563,22 +878,58 @@ impl Rebalancer {
             .balances
             .iter()
             .filter(|balance| balance.active)
-            .any(|balance| {
-                let mint = self
-                    .banks
-                    .get(&balance.bank_pk)
-                    .map(|bank| bank.bank.mint)
-                    .unwrap();
+        {
+            let mint = self.banks.get(&balance.bank_pk).unwrap().bank.mint;
+
+            // The held positions are left as they are
+            let needs_rebalance = match balance.get_side() {
+                _ if self.config.post_liquidation_strategy
+                    == PostLiquidationStrategy::HoldAsMarginfiPosition =>
+                {
+                    false
+                }
+                Some(BalanceSide::Assets) => !self.preferred_mints.contains(&mint),
+                Some(BalanceSide::Liabilities) => true,
+                None => false,
+            };
+
+            if needs_rebalance {
+                let position_value = self.get_position_value(&balance.bank_pk)?;
+                if position_value >= self.config.dust_threshold_usd {
+                    value += position_value;
+                }
+            }
+        }
+
+        Ok(value)
+    }
 
-                matches!(balance.get_side(), Some(BalanceSide::Assets))
-                    && !self.preferred_mints.contains(&mint)
-            });
+    /// Whether the liquidator account position in the bank is below the dust threshold
+    fn is_dust_position(&self, bank_pk: &Pubkey) -> anyhow::Result<bool> {
+        let value = self.get_position_value(bank_pk)?;
 
-        has_non_preferred_deposits
+        if value < self.config.dust_threshold_usd {
+            debug!(
+                "Skipping dust position in bank {}, worth ${} only",
+                bank_pk, value
+            );
+            return Ok(true);
+        }
+
+        Ok(false)
     }
 
-    fn has_liabilities(&self) -> bool {
-        self.liquidator_account.account_wrapper.has_liabs()
+    /// Unweighted value of the liquidator account position in the bank
+    fn get_position_value(&self, bank_pk: &Pubkey) -> anyhow::Result<I80F48> {
+        let balance = self
+            .liquidator_account
+            .account_wrapper
+            .get_balance_for_bank(bank_pk, self.banks.get(bank_pk).unwrap())?;
+
+        match balance {
+            Some((amount, side)) => self.get_value(amount, bank_pk, RequirementType::Equity, side),
+            None => Ok(I80F48::ZERO),
+        }
     }
 
     async fn handle_tokens_in_token_accounts(&mut self) -> anyhow::Result<()> {
@@ -602,14 +953,11 @@ impl Rebalancer {
             .collect();
 
         // Step 2: Iterate over the collected data
+        let swap_mint_bank_pk = self.swap_mint_bank_pk.unwrap();
         for (value, amount, bank_address, _) in accounts_data {
             if value > self.config.token_account_dust_threshold {
-                self.swap(
-                    amount.to_num(),
-                    &bank_address,
-                    &self.swap_mint_bank_pk.unwrap(),
-                )
-                .await?;
+                self.swap(amount.to_num(), &bank_address, &swap_mint_bank_pk)
+                    .await?;
             }
         }
 
@@ -618,6 +966,11 @@ impl Rebalancer {
 
     /// Withdraw and sells a given asset
     async fn withdraw_and_sell_deposit(&mut self, bank_pk: &Pubkey) -> anyhow::Result<()> {
+        if self.banks.get(bank_pk).is_some_and(BankWrapper::is_paused) {
+            debug!("Skipping the withdraw from bank {}, it is paused", bank_pk);
+            return Ok(());
+        }
+
         let balance = self
             .liquidator_account
             .account_wrapper
@@ -629,21 +982,53 @@ impl Rebalancer {
 
         let (withdraw_amount, withdrawl_all) = self.get_max_withdraw_for_bank(bank_pk)?;
 
+        if !withdraw_amount.is_positive() {
+            warn!(
+                "Skipping withdraw from bank {}, it would bring the liquidator account below its health buffer",
+                bank_pk
+            );
+            return Ok(());
+        }
+
         let amount = withdraw_amount.to_num::<u64>();
 
         let bank = self.banks.get(bank_pk).unwrap();
 
-        self.liquidator_account.withdraw(
+        // The withdrawn SOL is kept as native SOL to fund the tips and fees, instead of being sold
+        let unwrap_sol =
+            self.config.auto_unwrap_sol && bank.bank.mint == spl_token::native_mint::ID;
+
+        let (withdraw_tx, withdrawn_amount) = self.liquidator_account.build_withdraw(
             bank,
             self.token_account_manager
                 .get_address_for_mint(bank.bank.mint)
                 .unwrap(),
             amount,
             Some(withdrawl_all),
+            unwrap_sol,
             &self.banks,
         )?;
 
-        self.swap(amount, bank_pk, &self.swap_mint_bank_pk.unwrap())
+        if unwrap_sol {
+            info!(
+                "Unwrapping {} lamports withdrawn from bank {}",
+                withdrawn_amount, bank_pk
+            );
+            self.liquidator_account.send_batch(vec![withdraw_tx])?;
+            return Ok(());
+        }
+
+        // The withdrawn tokens are sold in the same bundle
+        let swap_mint_bank_pk = self.swap_mint_bank_pk.unwrap();
+        let mut bundle = vec![withdraw_tx];
+        if let Some((swap_tx, _)) = self
+            .swap_transaction(withdrawn_amount, bank_pk, &swap_mint_bank_pk)
+            .await?
+        {
+            bundle.push(swap_tx);
+        }
+
+        self.send_and_wait(bundle, &[*bank_pk, swap_mint_bank_pk])
             .await?;
 
         Ok(())
@@ -655,60 +1040,126 @@ impl Rebalancer {
         src_bank: &Pubkey,
         dst_bank: &Pubkey,
     ) -> anyhow::Result<()> {
-        let src_mint = {
-            let bank = self.banks.get(src_bank).unwrap();
+        if let Some((swap_tx, _)) = self.swap_transaction(amount, src_bank, dst_bank).await? {
+            self.send_and_wait(vec![swap_tx], &[*src_bank, *dst_bank])
+                .await?;
+        }
+
+        Ok(())
+    }
 
-            bank.bank.mint
+    /// Sends the batch and waits for the transaction manager to be done with it, then
+    /// refreshes the liquidator account and the token accounts of the banks, so the
+    /// next step doesn't swap or repay the same tokens again. Whether the batch landed
+    async fn send_and_wait(
+        &mut self,
+        mut batch: BatchTransactions,
+        banks: &[Pubkey],
+    ) -> anyhow::Result<bool> {
+        let Some(last_transaction) = batch.pop() else {
+            return Ok(false);
         };
+        let (last_transaction, outcome) = last_transaction.with_outcome();
+        batch.push(last_transaction);
 
-        let dst_mint = {
-            let bank = self.banks.get(dst_bank).unwrap();
+        self.liquidator_account.send_batch(batch)?;
 
-            bank.bank.mint
+        let landed = tokio::select! {
+            landed = outcome.landed() => landed,
+            _ = self.cancellation_token.cancelled() => return Ok(false),
         };
+        if !landed {
+            warn!("The rebalancing batch didn't land");
+        }
 
-        let jup_swap_client = JupiterSwapApiClient::new(self.config.jup_swap_api_url.clone());
+        self.liquidator_account.reload_account().await?;
+        for bank_pk in banks {
+            self.refresh_token_account(bank_pk).await?;
+        }
 
-        let quote_response = jup_swap_client
-            .quote(&QuoteRequest {
-                input_mint: src_mint,
-                output_mint: dst_mint,
-                amount,
-                slippage_bps: self.config.slippage_bps,
-                ..Default::default()
-            })
-            .await?;
+        Ok(landed)
+    }
 
-        let swap = jup_swap_client
-            .swap(&SwapRequest {
-                user_public_key: self.general_config.signer_pubkey,
-                quote_response,
-                config: TransactionConfig {
-                    wrap_and_unwrap_sol: false,
-                    compute_unit_price_micro_lamports: self
-                        .config
-                        .compute_unit_price_micro_lamports
-                        .map(ComputeUnitPriceMicroLamports::MicroLamports),
-                    ..Default::default()
-                },
-            })
-            .await?;
+    /// Reads the balance of the token account of the bank from the rpc, ahead of its
+    /// geyser update. A closed or missing token account holds no tokens
+    async fn refresh_token_account(&mut self, bank_pk: &Pubkey) -> anyhow::Result<()> {
+        let mint = self.banks.get(bank_pk).unwrap().bank.mint;
+        let Some(token_account) = self.token_accounts.get_mut(&mint) else {
+            return Ok(());
+        };
 
-        let mut tx = bincode::deserialize::<VersionedTransaction>(&swap.swap_transaction)
-            .map_err(|_| anyhow!("Failed to deserialize"))?;
+        let account = self
+            .rpc_pool
+            .non_blocking_rpc()
+            .get_account_with_commitment(&token_account.address, CommitmentConfig::confirmed())
+            .await?
+            .value;
+        token_account.balance = account
+            .filter(|account| !account.data.is_empty())
+            .map_or(0, |account| accessor::amount(&account.data));
 
-        tx = VersionedTransaction::try_new(
-            tx.message,
-            &[&read_keypair_file(&self.general_config.keypair_path).unwrap()],
+        Ok(())
+    }
+
+    /// Builds the Jupiter swap transaction, along with the minimum amount of
+    /// tokens it buys. Swaps worth less than `min_swap_usd` are skipped, swaps
+    /// with too much price impact may sell only a part of the amount
+    async fn swap_transaction(
+        &self,
+        amount: u64,
+        src_bank: &Pubkey,
+        dst_bank: &Pubkey,
+    ) -> anyhow::Result<Option<(RawTransaction, u64)>> {
+        let value = self.get_value(
+            I80F48::from_num(amount),
+            src_bank,
+            RequirementType::Equity,
+            BalanceSide::Assets,
         )?;
 
-        TransactionSender::aggressive_send_tx(self.rpc_client.clone(), &tx, SenderCfg::DEFAULT)
-            .map_err(|_| anyhow!("Failed to send swap transaction"))?;
+        if value < self.config.min_swap_usd {
+            debug!(
+                "Skipping swap of {} tokens from bank {}, worth ${} only",
+                amount, src_bank, value
+            );
+            return Ok(None);
+        }
+
+        let src_mint = self.banks.get(src_bank).unwrap().bank.mint;
+        let dst_mint = self.banks.get(dst_bank).unwrap().bank.mint;
 
-        self.refresh_token_account(src_bank).await?;
-        self.refresh_token_account(dst_bank).await?;
+        let swap = self
+            .jupiter_swapper
+            .swap_transaction(
+                self.general_config.signer_pubkey,
+                src_mint,
+                dst_mint,
+                amount,
+            )
+            .await?;
 
-        Ok(())
+        // The slippage is measured against the oracle prices, not the quote
+        let swap_tx = swap.transaction.with_pnl(PnlEntry::Swap(SwapPnl {
+            input_mint: src_mint,
+            input_price: self
+                .banks
+                .get(src_bank)
+                .unwrap()
+                .get_price(BalanceSide::Assets, RequirementType::Equity)?,
+            input_value: self.banks.get(src_bank).unwrap().calc_value(
+                I80F48::from_num(swap.in_amount),
+                BalanceSide::Assets,
+                RequirementType::Equity,
+            )?,
+            output_value: self.banks.get(dst_bank).unwrap().calc_value(
+                I80F48::from_num(swap.out_amount),
+                BalanceSide::Assets,
+                RequirementType::Equity,
+            )?,
+            lamport_price: crate::utils::lamports_to_usd(1, &self.banks).unwrap_or(I80F48::ZERO),
+        }));
+
+        Ok(Some((swap_tx, swap.min_out_amount)))
     }
 
     pub fn get_max_withdraw_for_bank(&self, bank_pk: &Pubkey) -> anyhow::Result<(I80F48, bool)> {
@@ -729,33 +1180,48 @@ impl Rebalancer {
                 let max_withdraw = value.min(free_collateral);
 
                 let amount = self.get_amount(max_withdraw, bank_pk, Some(PriceBias::Low))?;
+                let safe_amount = amount.min(self.get_max_withdraw_for_health_buffer(bank_pk)?);
 
-                (amount, value <= free_collateral)
+                (
+                    safe_amount,
+                    value <= free_collateral && safe_amount == amount,
+                )
             }
             _ => (I80F48!(0), false),
         })
     }
 
-    pub async fn refresh_token_account(&mut self, bank_pk: &Pubkey) -> anyhow::Result<()> {
-        let mint = self.banks.get(bank_pk).unwrap().bank.mint;
+    /// Whether the maintenance health factor of the liquidator account
+    /// is below 1 + `min_health_buffer`
+    fn is_below_health_buffer(&self) -> anyhow::Result<bool> {
+        let (assets, liabs) = self
+            .liquidator_account
+            .account_wrapper
+            .calc_health(&self.banks, RequirementType::Maintenance)?;
 
-        let token_account_addresses = self
-            .token_account_manager
-            .get_address_for_mint(mint)
-            .unwrap();
+        Ok(!liabs.is_zero()
+            && assets < liabs * I80F48::from_num(1.0 + self.config.min_health_buffer))
+    }
 
-        let account = self
-            .rpc_client
-            .get_account_with_commitment(&token_account_addresses, CommitmentConfig::confirmed())?
-            .value
-            .ok_or_else(|| anyhow::anyhow!("Token account not found"))?;
+    /// Maximum amount of the bank that can be withdrawn while the maintenance health
+    /// factor of the liquidator account stays above 1 + `min_health_buffer`, so the
+    /// rebalancer never brings the liquidator account close to its own liquidation
+    fn get_max_withdraw_for_health_buffer(&self, bank_pk: &Pubkey) -> anyhow::Result<I80F48> {
+        let (assets, liabs) = self
+            .liquidator_account
+            .account_wrapper
+            .calc_health(&self.banks, RequirementType::Maintenance)?;
 
-        let mint = accessor::mint(&account.data);
-        let balance = accessor::amount(&account.data);
+        let bank = self.banks.get(bank_pk).unwrap();
+        let asset_weight: I80F48 = bank.bank.config.asset_weight_maint.into();
+        if liabs.is_zero() || asset_weight.is_zero() {
+            return Ok(I80F48::MAX);
+        }
 
-        self.token_accounts.get_mut(&mint).unwrap().balance = balance;
+        let min_assets = liabs * I80F48::from_num(1.0 + self.config.min_health_buffer);
+        let withdrawable_value = (assets - min_assets).max(I80F48::ZERO) / asset_weight;
 
-        Ok(())
+        self.get_amount(withdrawable_value, bank_pk, Some(PriceBias::Low))
     }
 
     pub fn get_value(
@@ -778,10 +1244,10 @@ impl Rebalancer {
     }
 
     fn get_free_collateral(&self) -> anyhow::Result<I80F48> {
-        let (assets, liabs) = self.calc_health(
-            &self.liquidator_account.account_wrapper,
-            RequirementType::Initial,
-        );
+        let (assets, liabs) = self
+            .liquidator_account
+            .account_wrapper
+            .calc_health(&self.banks, RequirementType::Initial)?;
         if assets > liabs {
             Ok(assets - liabs)
         } else {
@@ -789,27 +1255,6 @@ impl Rebalancer {
         }
     }
 
-    /// Calculates the health of a given account
-    fn calc_health(
-        &self,
-        account: &MarginfiAccountWrapper,
-        requirement_type: RequirementType,
-    ) -> (I80F48, I80F48) {
-        let baws =
-            BankAccountWithPriceFeedEva::load(&account.account.lending_account, self.banks.clone())
-                .unwrap();
-
-        baws.iter().fold(
-            (I80F48::ZERO, I80F48::ZERO),
-            |(total_assets, total_liabs), baw| {
-                let (assets, liabs) = baw
-                    .calc_weighted_assets_and_liabilities_values(requirement_type)
-                    .unwrap();
-                (total_assets + assets, total_liabs + liabs)
-            },
-        )
-    }
-
     fn get_token_balance_for_bank(&self, bank_pk: &Pubkey) -> anyhow::Result<Option<I80F48>> {
         let mint = self.banks.get(bank_pk).unwrap().bank.mint;
 
@@ -0,0 +1,114 @@
+use crate::geyser::{AccountType, GeyserUpdate};
+use log::{error, warn};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+
+/// Interval between two flushes of the recording to disk
+const RECORDING_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+static RECORDING_TX: OnceLock<UnboundedSender<RecordedUpdate>> = OnceLock::new();
+
+/// Geyser update as recorded, along with the slot it was written at
+/// and when it was received, so the replay can follow the same timing
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct RecordedUpdate {
+    pub slot: u64,
+    /// Unix timestamp at which the update was received, in milliseconds
+    pub timestamp_ms: u64,
+    pub account_type: AccountType,
+    pub address: Pubkey,
+    pub account: Account,
+}
+
+impl From<RecordedUpdate> for GeyserUpdate {
+    fn from(record: RecordedUpdate) -> Self {
+        GeyserUpdate {
+            account_type: record.account_type,
+            address: record.address,
+            account: record.account,
+        }
+    }
+}
+
+/// Records a geyser update, dropped when the recording is disabled
+pub fn record_update(slot: u64, update: &GeyserUpdate) {
+    if let Some(tx) = RECORDING_TX.get() {
+        let _ = tx.send(RecordedUpdate {
+            slot,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_millis() as u64)
+                .unwrap_or_default(),
+            account_type: update.account_type.clone(),
+            address: update.address,
+            account: update.account.clone(),
+        });
+    }
+}
+
+/// Appends the geyser updates distributed to the liquidator and the
+/// rebalancer to the file at `path`, until cancelled
+pub async fn serve_recording(path: PathBuf, cancellation_token: CancellationToken) {
+    let mut writer = match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            error!("Failed to open the geyser recording {:?}: {:?}", path, e);
+            return;
+        }
+    };
+
+    let (tx, mut rx) = unbounded_channel();
+    if RECORDING_TX.set(tx).is_err() {
+        warn!("The geyser updates are already recorded");
+        return;
+    }
+
+    let mut flush_interval = tokio::time::interval(RECORDING_FLUSH_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => break,
+            Some(record) = rx.recv() => {
+                if let Err(e) = bincode::serialize_into(&mut writer, &record) {
+                    error!("Failed to record a geyser update: {:?}", e);
+                }
+            }
+            _ = flush_interval.tick() => {
+                if let Err(e) = writer.flush() {
+                    error!("Failed to flush the geyser recording: {:?}", e);
+                }
+            }
+        }
+    }
+
+    if let Err(e) = writer.flush() {
+        error!("Failed to flush the geyser recording: {:?}", e);
+    }
+}
+
+/// Reads the updates of a recording in the order they were received.
+/// A record truncated by a crash ends the recording
+pub fn read_recording(path: &Path) -> anyhow::Result<Vec<RecordedUpdate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut records = vec![];
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(record) => records.push(record),
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => break,
+                _ => return Err(e.into()),
+            },
+        }
+    }
+
+    Ok(records)
+}
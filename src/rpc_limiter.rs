@@ -0,0 +1,182 @@
+use crate::metrics::{RPC_THROTTLED, RPC_THROTTLE_WAIT};
+use async_trait::async_trait;
+use solana_client::{
+    http_sender::HttpSender,
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client_api::client_error::Result as ClientResult;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Longest wait between two attempts to take a token, so a request preempted
+/// by a higher priority notices quickly when the bucket is available again
+const MAX_TOKEN_WAIT: Duration = Duration::from_millis(50);
+
+/// Priority of an rpc request, a request waits while requests of a higher
+/// priority are waiting for a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcPriority {
+    /// Blockhashes, fees, sends and confirmations of the transactions in flight
+    Critical = 0,
+    /// Everything else
+    Normal = 1,
+    /// Bulk loads and periodic polls, which can wait
+    Background = 2,
+}
+
+impl RpcPriority {
+    const COUNT: usize = 3;
+
+    pub fn of(request: &RpcRequest) -> Self {
+        match request {
+            RpcRequest::GetLatestBlockhash
+            | RpcRequest::GetFeeForMessage
+            | RpcRequest::GetRecentPrioritizationFees
+            | RpcRequest::GetSignatureStatuses
+            | RpcRequest::SendTransaction
+            | RpcRequest::SimulateTransaction
+            | RpcRequest::IsBlockhashValid => RpcPriority::Critical,
+            RpcRequest::GetProgramAccounts
+            | RpcRequest::GetTokenAccountsByOwner
+            | RpcRequest::GetBalance => RpcPriority::Background,
+            _ => RpcPriority::Normal,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            RpcPriority::Critical => "critical",
+            RpcPriority::Normal => "normal",
+            RpcPriority::Background => "background",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token bucket shared by every rpc client, refilled at `requests_per_second`
+/// up to `burst` tokens. Each request takes a token, waiting for one when the
+/// bucket is empty, so the provider rate limits are never tripped by the bot itself
+pub struct RpcRateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+    /// Requests waiting for a token, by priority
+    waiting: [AtomicUsize; RpcPriority::COUNT],
+}
+
+/// Counts a request as waiting until it took its token or was dropped
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        Self(waiting)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl RpcRateLimiter {
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        Self {
+            requests_per_second: requests_per_second as f64,
+            burst: burst as f64,
+            bucket: Mutex::new(Bucket {
+                tokens: burst as f64,
+                refilled_at: Instant::now(),
+            }),
+            waiting: Default::default(),
+        }
+    }
+
+    /// Waits until a token is available and no request of a higher priority is waiting
+    pub async fn acquire(&self, priority: RpcPriority) {
+        let tier = priority as usize;
+        let _waiting = WaitingGuard::new(&self.waiting[tier]);
+        let mut throttled_at = None;
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                bucket.tokens = (bucket.tokens
+                    + now.duration_since(bucket.refilled_at).as_secs_f64()
+                        * self.requests_per_second)
+                    .min(self.burst);
+                bucket.refilled_at = now;
+
+                let preempted = self.waiting[..tier]
+                    .iter()
+                    .any(|waiting| waiting.load(Ordering::Relaxed) > 0);
+                if !preempted && bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    break;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens).max(0.0) / self.requests_per_second)
+                    .min(MAX_TOKEN_WAIT)
+            };
+
+            if throttled_at.is_none() {
+                throttled_at = Some(Instant::now());
+                RPC_THROTTLED.with_label_values(&[priority.label()]).inc();
+            }
+            tokio::time::sleep(wait).await;
+        }
+
+        if let Some(throttled_at) = throttled_at {
+            RPC_THROTTLE_WAIT
+                .with_label_values(&[priority.label()])
+                .observe(throttled_at.elapsed().as_secs_f64());
+        }
+    }
+}
+
+/// Http sender taking a token of the shared limiter before each request
+pub struct RateLimitedSender {
+    sender: HttpSender,
+    limiter: Arc<RpcRateLimiter>,
+}
+
+impl RateLimitedSender {
+    pub fn new(url: String, limiter: Arc<RpcRateLimiter>) -> Self {
+        Self {
+            sender: HttpSender::new(url),
+            limiter,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSender for RateLimitedSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        self.limiter.acquire(RpcPriority::of(&request)).await;
+        self.sender.send(request, params).await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.sender.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.sender.url()
+    }
+}
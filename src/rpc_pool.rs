@@ -0,0 +1,168 @@
+use crate::{
+    config::GeneralConfig,
+    rpc_limiter::{RateLimitedSender, RpcRateLimiter},
+};
+use futures::future::join_all;
+use log::{error, warn};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient,
+    rpc_client::{RpcClient, RpcClientConfig},
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Maximum time an endpoint has to answer a health check
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct RpcEndpoint {
+    url: String,
+    rpc_client: Arc<RpcClient>,
+    non_blocking_rpc_client: Arc<NonBlockingRpcClient>,
+}
+
+/// Rpc clients of the configured endpoints, everything goes through the current
+/// one, which is swapped for the next healthy endpoint when it lags or times out.
+/// The requests of every endpoint share the rate limiter, when configured
+pub struct RpcPool {
+    endpoints: Vec<RpcEndpoint>,
+    current: AtomicUsize,
+    /// Slots an endpoint can lag behind the most advanced one before being unhealthy
+    max_slot_lag: u64,
+}
+
+impl RpcPool {
+    pub fn new(config: &GeneralConfig) -> Self {
+        let limiter = config.rpc_requests_per_second.map(|requests_per_second| {
+            Arc::new(RpcRateLimiter::new(requests_per_second, config.rpc_burst))
+        });
+
+        let endpoints = config
+            .get_rpc_urls()
+            .into_iter()
+            .map(|url| match &limiter {
+                Some(limiter) => RpcEndpoint {
+                    rpc_client: Arc::new(RpcClient::new_sender(
+                        RateLimitedSender::new(url.clone(), limiter.clone()),
+                        RpcClientConfig::with_commitment(config.commitment),
+                    )),
+                    non_blocking_rpc_client: Arc::new(NonBlockingRpcClient::new_sender(
+                        RateLimitedSender::new(url.clone(), limiter.clone()),
+                        RpcClientConfig::with_commitment(config.commitment),
+                    )),
+                    url,
+                },
+                None => RpcEndpoint {
+                    rpc_client: Arc::new(RpcClient::new_with_commitment(
+                        url.clone(),
+                        config.commitment,
+                    )),
+                    non_blocking_rpc_client: Arc::new(NonBlockingRpcClient::new_with_commitment(
+                        url.clone(),
+                        config.commitment,
+                    )),
+                    url,
+                },
+            })
+            .collect();
+
+        Self {
+            endpoints,
+            current: AtomicUsize::new(0),
+            max_slot_lag: config.max_rpc_slot_lag,
+        }
+    }
+
+    fn current(&self) -> &RpcEndpoint {
+        &self.endpoints[self.current.load(Ordering::Relaxed)]
+    }
+
+    /// Blocking client of the current endpoint
+    pub fn rpc(&self) -> Arc<RpcClient> {
+        self.current().rpc_client.clone()
+    }
+
+    /// Non blocking client of the current endpoint
+    pub fn non_blocking_rpc(&self) -> Arc<NonBlockingRpcClient> {
+        self.current().non_blocking_rpc_client.clone()
+    }
+
+    /// Url of the current endpoint
+    pub fn url(&self) -> String {
+        self.current().url.clone()
+    }
+
+    /// Checks the health of every endpoint, the current one is swapped for the
+    /// next healthy endpoint when it times out or lags behind the others
+    pub async fn check_health(&self) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+
+        let slots = join_all(self.endpoints.iter().map(|endpoint| async move {
+            match tokio::time::timeout(
+                HEALTH_CHECK_TIMEOUT,
+                endpoint.non_blocking_rpc_client.get_slot(),
+            )
+            .await
+            {
+                Ok(Ok(slot)) => Some(slot),
+                Ok(Err(e)) => {
+                    warn!("Rpc {} failed the health check: {:?}", endpoint.url, e);
+                    None
+                }
+                Err(_) => {
+                    warn!("Rpc {} timed out on the health check", endpoint.url);
+                    None
+                }
+            }
+        }))
+        .await;
+
+        let max_slot = match slots.iter().flatten().max() {
+            Some(slot) => *slot,
+            None => {
+                error!("No rpc endpoint is healthy");
+                return;
+            }
+        };
+        let is_healthy = |index: usize| {
+            slots[index].is_some_and(|slot| max_slot.saturating_sub(slot) <= self.max_slot_lag)
+        };
+
+        let current = self.current.load(Ordering::Relaxed);
+        if is_healthy(current) {
+            return;
+        }
+
+        if let Some(next) = (1..self.endpoints.len())
+            .map(|offset| (current + offset) % self.endpoints.len())
+            .find(|index| is_healthy(*index))
+        {
+            warn!(
+                "Switching from rpc {} to {}",
+                self.endpoints[current].url, self.endpoints[next].url
+            );
+            self.current.store(next, Ordering::Relaxed);
+        }
+    }
+
+    /// Checks the health of the endpoints every `interval` until cancelled
+    pub async fn serve_health_checks(
+        self: Arc<Self>,
+        interval: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        loop {
+            tokio::select! {
+                _ = cancellation_token.cancelled() => return,
+                _ = tokio::time::sleep(interval) => self.check_health().await,
+            }
+        }
+    }
+}
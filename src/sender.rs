@@ -1,5 +1,8 @@
-use crate::wrappers::marginfi_account::TxConfig;
-use log::{error, info};
+use crate::{
+    fee_estimator::writable_accounts, marginfi_errors::describe_transaction_error,
+    wrappers::marginfi_account::TxConfig,
+};
+use log::{error, info, warn};
 use serde::Deserialize;
 use solana_client::rpc_client::{RpcClient, SerializableTransaction};
 use solana_client::rpc_config::{RpcSendTransactionConfig, RpcSimulateTransactionConfig};
@@ -7,14 +10,15 @@ use solana_sdk::signature::Signature;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
     instruction::Instruction,
     signature::{Keypair, Signer},
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{error::Error, sync::Arc};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct SenderCfg {
     #[serde(default = "SenderCfg::default_spam_times")]
     spam_times: u64,
@@ -24,6 +28,17 @@ pub struct SenderCfg {
     timeout: Duration,
     #[serde(default = "SenderCfg::default_transaction_type")]
     transaction_type: TransactionType,
+    #[serde(default = "SenderCfg::default_max_resign_attempts")]
+    max_resign_attempts: u64,
+    #[serde(default = "SenderCfg::default_resign_backoff")]
+    resign_backoff: Duration,
+    /// Commitment awaited when confirming the transactions
+    ///
+    /// `processed` is the fastest but the transaction can still be dropped with its fork,
+    /// `finalized` can't be rolled back but takes ~13 seconds,
+    /// `confirmed` is a middle ground, voted by a supermajority of the cluster
+    #[serde(default = "SenderCfg::default_commitment")]
+    commitment: CommitmentConfig,
 }
 
 impl SenderCfg {
@@ -32,6 +47,9 @@ impl SenderCfg {
         skip_preflight: false,
         timeout: Duration::from_secs(45),
         transaction_type: TransactionType::Aggressive,
+        max_resign_attempts: 3,
+        resign_backoff: Duration::from_millis(500),
+        commitment: CommitmentConfig::confirmed(),
     };
 
     pub const PASSIVE: SenderCfg = SenderCfg {
@@ -39,6 +57,9 @@ impl SenderCfg {
         skip_preflight: false,
         timeout: Duration::from_secs(45),
         transaction_type: TransactionType::Passive,
+        max_resign_attempts: 1,
+        resign_backoff: Duration::from_secs(1),
+        commitment: CommitmentConfig::confirmed(),
     };
 
     pub const fn default_spam_times() -> u64 {
@@ -49,18 +70,88 @@ impl SenderCfg {
         Self::DEFAULT.skip_preflight
     }
 
-    const fn default_timeout() -> Duration {
+    pub const fn default_timeout() -> Duration {
         Self::DEFAULT.timeout
     }
 
     const fn default_transaction_type() -> TransactionType {
         TransactionType::Aggressive
     }
+
+    pub const fn default_max_resign_attempts() -> u64 {
+        Self::DEFAULT.max_resign_attempts
+    }
+
+    pub const fn default_resign_backoff() -> Duration {
+        Self::DEFAULT.resign_backoff
+    }
+
+    const fn default_commitment() -> CommitmentConfig {
+        Self::DEFAULT.commitment
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn with_spam_times(mut self, spam_times: u64) -> Self {
+        self.spam_times = spam_times;
+        self
+    }
+
+    pub fn with_skip_preflight(mut self, skip_preflight: bool) -> Self {
+        self.skip_preflight = skip_preflight;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_resign_attempts(mut self, max_resign_attempts: u64) -> Self {
+        self.max_resign_attempts = max_resign_attempts;
+        self
+    }
+
+    pub fn with_resign_backoff(mut self, resign_backoff: Duration) -> Self {
+        self.resign_backoff = resign_backoff;
+        self
+    }
+}
+
+/// Interval between two polls of the status of a sent transaction
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Outcome of a transaction sent through the rpc
+#[derive(Debug, Clone)]
+pub enum SendOutcome {
+    /// The transaction landed and succeeded
+    Confirmed(Signature),
+    /// The transaction landed but reverted
+    Failed(Signature, TransactionError),
+    /// The transaction wasn't confirmed before the timeout
+    Timeout(Signature),
+}
+
+impl SendOutcome {
+    pub fn signature(&self) -> &Signature {
+        match self {
+            SendOutcome::Confirmed(signature)
+            | SendOutcome::Failed(signature, _)
+            | SendOutcome::Timeout(signature) => signature,
+        }
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self, SendOutcome::Confirmed(_))
+    }
 }
 
 pub struct TransactionSender;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub enum TransactionType {
     Aggressive,
     Passive,
@@ -82,7 +173,8 @@ impl TransactionSender {
             let mut compute_budget_price_ix =
                 ComputeBudgetInstruction::set_compute_unit_price(1000);
 
-            if let Some(price) = config.compute_unit_price_micro_lamports {
+            if let Some(priority_fee) = config.priority_fee {
+                let price = priority_fee.resolve_blocking(&rpc_client, &writable_accounts(&ixs));
                 compute_budget_price_ix = ComputeBudgetInstruction::set_compute_unit_price(price);
             }
 
@@ -123,8 +215,12 @@ impl TransactionSender {
                 },
             )?;
 
-            if res.value.err.is_some() {
-                error!("Failed to simulate transaction: {:#?}", res.value);
+            if let Some(err) = &res.value.err {
+                error!(
+                    "Failed to simulate transaction: {}\n{:#?}",
+                    describe_transaction_error(err),
+                    res.value
+                );
                 return Err("Transaction simulation failed".into());
             }
         }
@@ -133,18 +229,20 @@ impl TransactionSender {
 
         let blockhash = transaction.get_recent_blockhash();
 
-        rpc.confirm_transaction_with_spinner(&signature, blockhash, CommitmentConfig::confirmed())?;
+        rpc.confirm_transaction_with_spinner(&signature, blockhash, cfg.commitment)?;
 
         info!("Confirmed transaction: {}", signature.to_string());
 
         Ok(signature)
     }
 
+    /// Spams the transaction and polls its status until it is confirmed
+    /// with the configured commitment, reverts or the timeout is reached
     pub fn aggressive_send_tx(
         rpc: Arc<RpcClient>,
         transaction: &impl SerializableTransaction,
         cfg: SenderCfg,
-    ) -> Result<Signature, Box<dyn Error>> {
+    ) -> Result<SendOutcome, Box<dyn Error>> {
         let signature = *transaction.get_signature();
 
         info!("Sending transaction: {}", signature.to_string());
@@ -158,8 +256,12 @@ impl TransactionSender {
                 },
             )?;
 
-            if res.value.err.is_some() {
-                error!("Failed to simulate transaction: {:#?}", res.value);
+            if let Some(err) = &res.value.err {
+                error!(
+                    "Failed to simulate transaction: {}\n{:#?}",
+                    describe_transaction_error(err),
+                    res.value
+                );
                 return Err("Transaction simulation failed".into());
             }
         }
@@ -175,12 +277,232 @@ impl TransactionSender {
             Ok::<_, Box<dyn Error>>(())
         })?;
 
-        let blockhash = transaction.get_recent_blockhash();
+        let outcome = Self::wait_for_outcome(&rpc, signature, &cfg)?;
+
+        match &outcome {
+            SendOutcome::Confirmed(_) => info!("Confirmed transaction: {}", signature),
+            SendOutcome::Failed(_, err) => error!(
+                "Transaction {} failed: {}",
+                signature,
+                describe_transaction_error(err)
+            ),
+            SendOutcome::Timeout(_) => warn!(
+                "Transaction {} not confirmed after {:?}",
+                signature, cfg.timeout
+            ),
+        }
 
-        rpc.confirm_transaction_with_spinner(&signature, blockhash, CommitmentConfig::confirmed())?;
+        Ok(outcome)
+    }
 
-        info!("Confirmed transaction: {}", signature.to_string());
+    /// Polls the status of the transaction until it reaches the configured commitment
+    /// or the timeout is reached
+    fn wait_for_outcome(
+        rpc: &RpcClient,
+        signature: Signature,
+        cfg: &SenderCfg,
+    ) -> Result<SendOutcome, Box<dyn Error>> {
+        let start = Instant::now();
+
+        while start.elapsed() < cfg.timeout {
+            if let Some(outcome) = Self::poll_outcome(rpc, signature, cfg)? {
+                return Ok(outcome);
+            }
 
-        Ok(signature)
+            std::thread::sleep(STATUS_POLL_INTERVAL);
+        }
+
+        Ok(SendOutcome::Timeout(signature))
+    }
+
+    /// Keeps polling the status of the transaction until the block height passes
+    /// `last_valid_block_height`, after which it can't land anymore.
+    /// Returns `None` once it expired.
+    fn wait_for_expiry(
+        rpc: &RpcClient,
+        signature: Signature,
+        last_valid_block_height: u64,
+        cfg: &SenderCfg,
+    ) -> Result<Option<SendOutcome>, Box<dyn Error>> {
+        loop {
+            // Read before the status, so a transaction landing in the last valid block is seen
+            let block_height = rpc.get_block_height()?;
+
+            if let Some(outcome) = Self::poll_outcome(rpc, signature, cfg)? {
+                return Ok(Some(outcome));
+            }
+
+            if block_height > last_valid_block_height {
+                return Ok(None);
+            }
+
+            std::thread::sleep(STATUS_POLL_INTERVAL);
+        }
+    }
+
+    /// Outcome of the transaction, if it reverted or reached the configured commitment
+    fn poll_outcome(
+        rpc: &RpcClient,
+        signature: Signature,
+        cfg: &SenderCfg,
+    ) -> Result<Option<SendOutcome>, Box<dyn Error>> {
+        let status = rpc
+            .get_signature_statuses(&[signature])?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+
+        Ok(status.and_then(|status| {
+            if let Some(err) = status.err {
+                Some(SendOutcome::Failed(signature, err))
+            } else if status.satisfies_commitment(cfg.commitment) {
+                Some(SendOutcome::Confirmed(signature))
+            } else {
+                None
+            }
+        }))
+    }
+
+    /// Sends the transaction built by `build_tx`, re-signing it with a fresh
+    /// blockhash when it wasn't confirmed before its blockhash expired.
+    /// An expired transaction can't land anymore, so resending is safe.
+    /// A reverted transaction is not sent again.
+    pub fn aggressive_send_with_resign<T: SerializableTransaction>(
+        rpc: Arc<RpcClient>,
+        build_tx: impl Fn(Hash) -> Result<T, Box<dyn Error>>,
+        cfg: SenderCfg,
+    ) -> Result<SendOutcome, Box<dyn Error>> {
+        let mut attempt = 0;
+        loop {
+            let (blockhash, last_valid_block_height) =
+                rpc.get_latest_blockhash_with_commitment(rpc.commitment())?;
+            let transaction = build_tx(blockhash)?;
+
+            let outcome = Self::aggressive_send_tx(rpc.clone(), &transaction, cfg.clone())?;
+            if !matches!(outcome, SendOutcome::Timeout(_)) || attempt >= cfg.max_resign_attempts {
+                return Ok(outcome);
+            }
+
+            // The transaction can still land until its blockhash expires,
+            // re-signing it before would risk sending it twice
+            if let Some(outcome) = Self::wait_for_expiry(
+                &rpc,
+                *transaction.get_signature(),
+                last_valid_block_height,
+                &cfg,
+            )? {
+                return Ok(outcome);
+            }
+
+            attempt += 1;
+            warn!(
+                "Blockhash expired before confirmation, re-signing transaction (attempt {}/{})",
+                attempt, cfg.max_resign_attempts
+            );
+            std::thread::sleep(cfg.resign_backoff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::{
+        rpc_client::Mocks,
+        rpc_request::RpcRequest,
+        rpc_response::{Response, RpcBlockhash, RpcResponseContext},
+    };
+    use solana_sdk::system_instruction;
+    use std::cell::{Cell, RefCell};
+
+    /// Mocked rpc whose first blockhash is valid until the block height 1000,
+    /// while the current block height is 1234 and no transaction ever lands
+    fn expiring_rpc() -> Arc<RpcClient> {
+        let blockhash = serde_json::to_value(Response {
+            context: RpcResponseContext {
+                slot: 1,
+                api_version: None,
+            },
+            value: RpcBlockhash {
+                blockhash: Hash::new_unique().to_string(),
+                last_valid_block_height: 1_000,
+            },
+        })
+        .unwrap();
+
+        Arc::new(RpcClient::new_mock_with_mocks(
+            "sig_not_found",
+            Mocks::from([(RpcRequest::GetLatestBlockhash, blockhash)]),
+        ))
+    }
+
+    fn cfg() -> SenderCfg {
+        SenderCfg::DEFAULT
+            .with_spam_times(1)
+            .with_skip_preflight(true)
+            .with_timeout(Duration::ZERO)
+            .with_resign_backoff(Duration::ZERO)
+            .with_max_resign_attempts(1)
+    }
+
+    #[test]
+    fn resign_once_the_blockhash_expired() {
+        let signer = Keypair::new();
+        let signed_blockhashes = RefCell::new(Vec::new());
+
+        let outcome = TransactionSender::aggressive_send_with_resign(
+            expiring_rpc(),
+            |blockhash| {
+                signed_blockhashes.borrow_mut().push(blockhash);
+
+                Ok(Transaction::new_signed_with_payer(
+                    &[system_instruction::transfer(
+                        &signer.pubkey(),
+                        &signer.pubkey(),
+                        1,
+                    )],
+                    Some(&signer.pubkey()),
+                    &[&signer],
+                    blockhash,
+                ))
+            },
+            cfg(),
+        )
+        .unwrap();
+
+        let signed_blockhashes = signed_blockhashes.into_inner();
+        assert!(matches!(outcome, SendOutcome::Timeout(_)));
+        assert_eq!(signed_blockhashes.len(), 2);
+        assert_ne!(signed_blockhashes[0], signed_blockhashes[1]);
+    }
+
+    #[test]
+    fn landed_transaction_is_not_resigned() {
+        let signer = Keypair::new();
+        let signatures = Cell::new(0);
+
+        let outcome = TransactionSender::aggressive_send_with_resign(
+            Arc::new(RpcClient::new_mock("succeeds")),
+            |blockhash| {
+                signatures.set(signatures.get() + 1);
+
+                Ok(Transaction::new_signed_with_payer(
+                    &[system_instruction::transfer(
+                        &signer.pubkey(),
+                        &signer.pubkey(),
+                        1,
+                    )],
+                    Some(&signer.pubkey()),
+                    &[&signer],
+                    blockhash,
+                ))
+            },
+            cfg(),
+        )
+        .unwrap();
+
+        assert!(outcome.is_confirmed());
+        assert_eq!(signatures.get(), 1);
     }
 }
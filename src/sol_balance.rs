@@ -0,0 +1,126 @@
+use crate::{metrics::SIGNER_SOL_BALANCE, notifications::notify, rpc_pool::RpcPool};
+use log::{error, info, warn};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::native_token::{lamports_to_sol, sol_to_lamports};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Latest SOL balance of each watched signer
+static SOL_BALANCES: Mutex<BTreeMap<Pubkey, SolBalance>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone, Copy)]
+struct SolBalance {
+    sol: f64,
+    /// Whether the balance is below `min_sol_balance`
+    low: bool,
+}
+
+/// Whether the signer is low on SOL, the remaining balance is
+/// then kept for the most profitable liquidations
+pub fn is_sol_balance_low(signer_pubkey: &Pubkey) -> bool {
+    SOL_BALANCES
+        .lock()
+        .unwrap()
+        .get(signer_pubkey)
+        .is_some_and(|balance| balance.low)
+}
+
+/// The SOL balance of every watched signer, served in the status
+pub fn sol_balances() -> serde_json::Value {
+    SOL_BALANCES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(signer_pubkey, balance)| {
+            (
+                signer_pubkey.to_string(),
+                serde_json::json!({ "sol": balance.sol, "low": balance.low }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Checks the SOL balance of the signer every `interval` until cancelled,
+/// alerting when it drops below `min_sol_balance` and when it recovers.
+/// Every signer is watched by its own task
+pub async fn serve_sol_balance_watch(
+    rpc_pool: Arc<RpcPool>,
+    signer_pubkey: Pubkey,
+    min_sol_balance: f64,
+    interval: Duration,
+    cancellation_token: CancellationToken,
+) {
+    let min_lamports = sol_to_lamports(min_sol_balance);
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+
+        let lamports = match rpc_pool
+            .non_blocking_rpc()
+            .get_balance(&signer_pubkey)
+            .await
+        {
+            Ok(lamports) => lamports,
+            Err(e) => {
+                error!(
+                    "Failed to get the SOL balance of the signer {}: {:?}",
+                    signer_pubkey, e
+                );
+                continue;
+            }
+        };
+        SIGNER_SOL_BALANCE
+            .with_label_values(&[&signer_pubkey.to_string()])
+            .set(lamports_to_sol(lamports));
+
+        let low = lamports < min_lamports;
+        let was_low = SOL_BALANCES
+            .lock()
+            .unwrap()
+            .insert(
+                signer_pubkey,
+                SolBalance {
+                    sol: lamports_to_sol(lamports),
+                    low,
+                },
+            )
+            .is_some_and(|balance| balance.low);
+        if low == was_low {
+            continue;
+        }
+        if low {
+            warn!(
+                "The SOL balance of the signer {} is {} SOL, below the minimum of {} SOL, pausing its jito tips",
+                signer_pubkey,
+                lamports_to_sol(lamports),
+                min_sol_balance
+            );
+            notify(format!(
+                "The signer {} is low on SOL: {} SOL left, below the minimum of {} SOL. \
+                 Only the most profitable liquidations are sent, without jito tips, until it is topped up",
+                signer_pubkey,
+                lamports_to_sol(lamports),
+                min_sol_balance
+            ));
+        } else {
+            info!(
+                "The SOL balance of the signer {} is back to {} SOL, resuming its jito tips",
+                signer_pubkey,
+                lamports_to_sol(lamports)
+            );
+            notify(format!(
+                "The signer {} was topped up to {} SOL",
+                signer_pubkey,
+                lamports_to_sol(lamports)
+            ));
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::notifications::notify;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Window over which the fees and tips are capped
+const SPEND_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Lamports that can be spent over the window, uncapped when not set
+static MAX_SPEND_PER_HOUR: OnceLock<u64> = OnceLock::new();
+
+/// Set while the budget of the window is exhausted
+static EXHAUSTED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Lamports spent on the fees and tips of each submission, within the window
+    static ref SPENDS: Mutex<VecDeque<(Instant, u64)>> = Mutex::new(VecDeque::new());
+}
+
+pub fn set_max_spend_per_hour(max_spend_per_hour: Option<u64>) {
+    if let Some(max_spend_per_hour) = max_spend_per_hour {
+        let _ = MAX_SPEND_PER_HOUR.set(max_spend_per_hour);
+    }
+}
+
+/// Records the fees and tips paid for a submission
+pub fn record_spend(lamports: u64) {
+    if MAX_SPEND_PER_HOUR.get().is_none() {
+        return;
+    }
+
+    SPENDS.lock().unwrap().push_back((Instant::now(), lamports));
+    update_exhausted();
+}
+
+/// Lamports left to spend in the window, none when the spending is uncapped
+pub fn remaining() -> Option<u64> {
+    let max_spend_per_hour = *MAX_SPEND_PER_HOUR.get()?;
+
+    let mut spends = SPENDS.lock().unwrap();
+    while spends
+        .front()
+        .map_or(false, |(spent_at, _)| spent_at.elapsed() > SPEND_WINDOW)
+    {
+        spends.pop_front();
+    }
+    let spent = spends
+        .iter()
+        .map(|(_, lamports)| *lamports)
+        .fold(0u64, u64::saturating_add);
+
+    Some(max_spend_per_hour.saturating_sub(spent))
+}
+
+/// Whether the budget of the window is exhausted, the submissions are
+/// paused until the window rolls over while the accounts are still tracked
+pub fn is_exhausted() -> bool {
+    update_exhausted()
+}
+
+/// Alerts when the budget gets exhausted and when it is available again
+fn update_exhausted() -> bool {
+    let Some(remaining) = remaining() else {
+        return false;
+    };
+
+    let exhausted = remaining == 0;
+    if exhausted != EXHAUSTED.swap(exhausted, Ordering::Relaxed) {
+        if exhausted {
+            let message = format!(
+                "The fee and tip budget of {} lamports per hour is exhausted, pausing the submissions",
+                MAX_SPEND_PER_HOUR.get().copied().unwrap_or_default()
+            );
+            warn!("{}", message);
+            notify(message);
+        } else {
+            let message = format!(
+                "The fee and tip budget rolled over, {} lamports available, resuming the submissions",
+                remaining
+            );
+            info!("{}", message);
+            notify(message);
+        }
+    }
+
+    exhausted
+}
@@ -0,0 +1,153 @@
+use crate::{
+    utils::{batch_get_multiple_accounts, BatchLoadingConfig},
+    wrappers::marginfi_account::MarginfiAccountWrapper,
+};
+use log::{info, warn};
+use marginfi::state::marginfi_account::MarginfiAccount;
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use std::{
+    collections::HashMap,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Snapshot of the marginfi accounts tracked by the liquidator, reloaded on
+/// startup so only the accounts missing from it are fetched from the rpc
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct StateSnapshot {
+    /// Unix timestamp at which the snapshot was saved
+    pub saved_at: u64,
+    pub marginfi_group: Pubkey,
+    /// Raw marginfi accounts, by address
+    pub accounts: Vec<(Pubkey, Vec<u8>)>,
+}
+
+impl StateSnapshot {
+    pub fn new(
+        marginfi_group: Pubkey,
+        accounts: &HashMap<Pubkey, MarginfiAccountWrapper>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            marginfi_group,
+            accounts: accounts
+                .iter()
+                .map(|(address, account)| (*address, bytemuck::bytes_of(&account.account).to_vec()))
+                .collect(),
+        })
+    }
+
+    /// Saves the snapshot, through a temporary file so a crash
+    /// never leaves a truncated snapshot behind
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+
+        let mut file = BufWriter::new(std::fs::File::create(&tmp_path)?);
+        bincode::serialize_into(&mut file, self)?;
+        // The data must be on disk before the rename, or a crash could leave an empty snapshot
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+
+        info!(
+            "Saved the state of {} accounts to {:?}",
+            self.accounts.len(),
+            path
+        );
+
+        Ok(())
+    }
+
+    /// Loads the snapshot of the marginfi group, ignoring it when it is
+    /// missing, unreadable, of another group or older than `max_age_secs`
+    pub fn load(path: &Path, marginfi_group: &Pubkey, max_age_secs: u64) -> Option<Self> {
+        if !path.exists() {
+            return None;
+        }
+
+        let snapshot: Self = match std::fs::File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(bincode::deserialize_from(BufReader::new(file))?))
+        {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Failed to load the state from {:?}: {:?}", path, e);
+                return None;
+            }
+        };
+
+        if snapshot.marginfi_group != *marginfi_group {
+            warn!("Ignoring the state of another marginfi group");
+            return None;
+        }
+
+        let age = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs()
+            .saturating_sub(snapshot.saved_at);
+        if age > max_age_secs {
+            warn!("Ignoring the state saved {}s ago", age);
+            return None;
+        }
+
+        Some(snapshot)
+    }
+
+    /// Returns the marginfi accounts of the snapshot, skipping the malformed ones
+    pub fn into_accounts(self) -> HashMap<Pubkey, MarginfiAccountWrapper> {
+        self.accounts
+            .into_iter()
+            .filter_map(|(address, data)| {
+                let account = bytemuck::try_pod_read_unaligned::<MarginfiAccount>(&data).ok()?;
+                Some((address, MarginfiAccountWrapper::new(address, account)))
+            })
+            .collect()
+    }
+}
+
+/// Marginfi accounts of a snapshot refetched in the background, as geyser only
+/// streams the changes made after the subscription, not those made since the
+/// snapshot was saved
+#[derive(Clone)]
+pub struct SnapshotRefresh(Arc<OnceLock<Vec<(Pubkey, Account)>>>);
+
+impl SnapshotRefresh {
+    pub fn spawn(rpc_client: Arc<RpcClient>, addresses: Vec<Pubkey>) -> Self {
+        let accounts = Arc::new(OnceLock::new());
+
+        let refreshed_accounts = accounts.clone();
+        tokio::task::spawn_blocking(move || {
+            let fetched_accounts = match batch_get_multiple_accounts(
+                rpc_client,
+                &addresses,
+                BatchLoadingConfig::DEFAULT,
+            ) {
+                Ok(fetched_accounts) => addresses
+                    .into_iter()
+                    .zip(fetched_accounts)
+                    .filter_map(|(address, account)| Some((address, account?)))
+                    .collect(),
+                Err(e) => {
+                    warn!("Failed to refresh the accounts of the state: {:?}", e);
+                    vec![]
+                }
+            };
+            info!(
+                "Refreshed {} marginfi accounts of the state",
+                fetched_accounts.len()
+            );
+            let _ = refreshed_accounts.set(fetched_accounts);
+        });
+
+        Self(accounts)
+    }
+
+    /// The refetched accounts, once they all are
+    pub fn accounts(&self) -> Option<&[(Pubkey, Account)]> {
+        self.0.get().map(Vec::as_slice)
+    }
+}
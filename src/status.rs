@@ -0,0 +1,117 @@
+use crate::config::Eva01Config;
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Config fields holding a secret, such as an api key in an url, never shown in the status
+const REDACTED_FIELDS: [&str; 7] = [
+    "rpc_url",
+    "rpc_standby_urls",
+    "yellowstone_x_token",
+    "x_token",
+    "discord_webhook_url",
+    "telegram_bot_token",
+    "telegram_chat_id",
+];
+
+static TRACKED_ACCOUNTS: AtomicUsize = AtomicUsize::new(0);
+
+static LAST_GEYSER_SLOT: AtomicU64 = AtomicU64::new(0);
+
+static PENDING_BUNDLES: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref LAST_LIQUIDATION: Mutex<Option<LastLiquidation>> = Mutex::new(None);
+    static ref CONFIG: Mutex<Option<Value>> = Mutex::new(None);
+}
+
+/// Outcome of the last liquidation sent
+#[derive(Clone, serde::Serialize)]
+pub struct LastLiquidation {
+    pub description: String,
+    pub signature: String,
+    pub expected_profit_lamports: u64,
+    pub landed: bool,
+    /// Unix timestamp at which its outcome was known
+    pub timestamp: u64,
+}
+
+pub fn set_tracked_accounts(count: usize) {
+    TRACKED_ACCOUNTS.store(count, Ordering::Relaxed);
+}
+
+pub fn set_last_geyser_slot(slot: u64) {
+    LAST_GEYSER_SLOT.fetch_max(slot, Ordering::Relaxed);
+}
+
+pub fn set_pending_bundles(count: usize) {
+    PENDING_BUNDLES.store(count, Ordering::Relaxed);
+}
+
+pub fn set_last_liquidation(
+    description: String,
+    signature: String,
+    expected_profit_lamports: u64,
+    landed: bool,
+) {
+    *LAST_LIQUIDATION.lock().unwrap() = Some(LastLiquidation {
+        description,
+        signature,
+        expected_profit_lamports,
+        landed,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default(),
+    });
+}
+
+/// Keeps the config shown in the status, with its secrets redacted
+pub fn set_config(config: &Eva01Config) {
+    *CONFIG.lock().unwrap() = Some(redacted_config(config));
+}
+
+/// The config with its secrets redacted, safe to show or log
+pub fn redacted_config(config: &Eva01Config) -> Value {
+    let mut config = serde_json::to_value(config).unwrap_or_default();
+    redact(&mut config);
+    config
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields.iter_mut() {
+                if REDACTED_FIELDS.contains(&field.as_str()) {
+                    if !value.is_null() {
+                        *value = Value::from("<redacted>");
+                    }
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// What the liquidator is doing right now, served as JSON on `/status`
+pub fn status() -> Value {
+    serde_json::json!({
+        "tracked_accounts": TRACKED_ACCOUNTS.load(Ordering::Relaxed),
+        "last_geyser_slot": LAST_GEYSER_SLOT.load(Ordering::Relaxed),
+        "pending_bundles": PENDING_BUNDLES.load(Ordering::Relaxed),
+        "last_liquidation": LAST_LIQUIDATION.lock().unwrap().clone(),
+        "signer_sol_balances": crate::sol_balance::sol_balances(),
+        "paused": crate::admin::is_paused(),
+        "remaining_spend_budget_lamports": crate::spend_budget::remaining(),
+        "config": CONFIG.lock().unwrap().clone(),
+    })
+}
@@ -7,11 +7,12 @@ use anchor_spl::associated_token;
 use log::{debug, error, info};
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use sha2::{Digest, Sha256};
-use solana_client::rpc_client::RpcClient;
 use solana_sdk::{account::Account, pubkey::Pubkey, signature::Keypair, signer::Signer};
 
 use crate::{
-    sender::{SenderCfg, TransactionSender},
+    rpc_pool::RpcPool,
+    sender::{SendOutcome, SenderCfg, TransactionSender},
+    tx_log::{log_submission, TxMetadata},
     utils::{batch_get_multiple_accounts, BatchLoadingConfig},
 };
 
@@ -27,14 +28,19 @@ pub enum TokenAccountManagerError {
 #[derive(Clone)]
 pub struct TokenAccountManager {
     mint_to_account: Arc<RwLock<HashMap<Pubkey, (Pubkey, Pubkey)>>>,
-    rpc_client: Arc<RpcClient>,
+    rpc_pool: Arc<RpcPool>,
+    sender_config: SenderCfg,
 }
 
 impl TokenAccountManager {
-    pub fn new(rpc_client: Arc<RpcClient>) -> Result<Self, TokenAccountManagerError> {
+    pub fn new(
+        rpc_pool: Arc<RpcPool>,
+        sender_config: SenderCfg,
+    ) -> Result<Self, TokenAccountManagerError> {
         Ok(Self {
             mint_to_account: Arc::new(RwLock::new(HashMap::new())),
-            rpc_client,
+            rpc_pool,
+            sender_config,
         })
     }
 
@@ -43,18 +49,15 @@ impl TokenAccountManager {
         mints: &[Pubkey],
         signer: Pubkey,
     ) -> Result<(), TokenAccountManagerError> {
-        let mint_owners = batch_get_multiple_accounts(
-            self.rpc_client.clone(),
-            mints,
-            BatchLoadingConfig::DEFAULT,
-        )
-        .map_err(|e| {
-            error!("Failed to load mint accounts: {:?}", e);
-            TokenAccountManagerError::SetupFailed("Failed to find missing accounts")
-        })?
-        .iter()
-        .map(|a| a.as_ref().unwrap().owner)
-        .collect::<Vec<_>>();
+        let mint_owners =
+            batch_get_multiple_accounts(self.rpc_pool.rpc(), mints, BatchLoadingConfig::DEFAULT)
+                .map_err(|e| {
+                    error!("Failed to load mint accounts: {:?}", e);
+                    TokenAccountManagerError::SetupFailed("Failed to find missing accounts")
+                })?
+                .iter()
+                .map(|a| a.as_ref().unwrap().owner)
+                .collect::<Vec<_>>();
 
         let mut mint_to_account = self.mint_to_account.write().unwrap();
 
@@ -99,7 +102,7 @@ impl TokenAccountManager {
             .copied()
             .collect::<Vec<_>>();
 
-        let rpc_client = self.rpc_client.clone();
+        let rpc_client = self.rpc_pool.rpc();
 
         let tas = mints
             .iter()
@@ -187,11 +190,21 @@ impl TokenAccountManager {
                         recent_blockhash,
                     );
 
-                    let sig = TransactionSender::aggressive_send_tx(rpc, &tx, SenderCfg::DEFAULT)
-                        .map_err(|e| {
-                        error!("Failed to send transaction: {:?}", e);
-                        TokenAccountManagerError::SetupFailed("Failed to send transaction")
-                    })?;
+                    let outcome =
+                        TransactionSender::aggressive_send_tx(rpc, &tx, self.sender_config.clone())
+                            .map_err(|e| {
+                                error!("Failed to send transaction: {:?}", e);
+                                TokenAccountManagerError::SetupFailed("Failed to send transaction")
+                            })?;
+
+                    let SendOutcome::Confirmed(sig) = outcome else {
+                        error!("Token accounts were not created: {:?}", outcome);
+                        return Err(TokenAccountManagerError::SetupFailed(
+                            "Failed to create token accounts",
+                        ));
+                    };
+
+                    log_submission(sig, TxMetadata::default());
 
                     debug!("Token accounts created {:?}", sig);
 
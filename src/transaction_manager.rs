@@ -1,55 +1,397 @@
-use crate::config::GeneralConfig;
-use crossbeam::channel::Receiver;
-use jito_protos::searcher::{
-    searcher_service_client::SearcherServiceClient, GetTipAccountsRequest,
-    NextScheduledLeaderRequest, SubscribeBundleResultsRequest,
+use crate::{
+    config::GeneralConfig,
+    cooldown::LiquidationCooldowns,
+    metrics::{
+        LIQUIDATIONS_FAILED, LIQUIDATIONS_SUCCEEDED, LIQUIDATIONS_UNCONFIRMED,
+        OPPORTUNITY_TO_SUBMIT,
+    },
+    notifications::notify,
+    pnl::{record_landed, PnlEntry},
+    rpc_pool::RpcPool,
+    sender::{SendOutcome, SenderCfg, TransactionSender},
+    tx_log::{log_submission, TxAccounting, TxMetadata},
 };
-use jito_searcher_client::{get_searcher_client_no_auth, send_bundle_with_confirmation};
-use log::{debug, error};
+use crossbeam::channel::{Receiver, Sender};
+use fixed::types::I80F48;
+use futures::FutureExt;
+use jito_protos::{
+    auth::{auth_service_client::AuthServiceClient, Role},
+    bundle::{bundle_result, rejected::Reason, Bundle, BundleResult, SimulationFailure},
+    convert::proto_packet_from_versioned_tx,
+    searcher::{
+        searcher_service_client::SearcherServiceClient, GetTipAccountsRequest,
+        NextScheduledLeaderRequest, NextScheduledLeaderResponse, SendBundleRequest,
+        SubscribeBundleResultsRequest,
+    },
+};
+use jito_searcher_client::{create_grpc_channel, token_authenticator::ClientInterceptor};
+use log::{debug, error, info, warn};
 use solana_address_lookup_table_program::state::AddressLookupTable;
 use solana_client::{
-    nonblocking::rpc_client::RpcClient, rpc_client::RpcClient as NonBlockRpc,
-    rpc_client::SerializableTransaction, rpc_config::RpcSimulateTransactionConfig,
+    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient,
+    nonce_utils::nonblocking::{data_from_account, get_account_with_commitment},
+    rpc_client::RpcClient as NonBlockRpc,
+    rpc_client::SerializableTransaction,
+    rpc_config::RpcSimulateTransactionConfig,
 };
 use solana_sdk::{
     address_lookup_table_account::AddressLookupTableAccount,
     commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
     instruction::Instruction,
     message::{v0, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
     pubkey::Pubkey,
     signature::{read_keypair_file, Keypair, Signature, Signer},
-    system_instruction::transfer,
+    system_instruction::{advance_nonce_account, transfer},
     transaction::VersionedTransaction,
 };
+use std::collections::HashSet;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, Weak,
 };
 use std::{error::Error, str::FromStr};
-use tonic::transport::Channel;
+use tonic::{
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::Channel,
+    Request, Status, Streaming,
+};
+
+/// Maximum number of transactions the jito block engine accepts in a bundle
+pub const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+/// Duration of a slot, the leader polls are paced on the distance to the leader slot
+const SLOT_DURATION: std::time::Duration = std::time::Duration::from_millis(400);
+
+/// Shortest wait between two polls of the next jito leader
+const MIN_LEADER_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Longest wait between two polls of the next jito leader
+const MAX_LEADER_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Longest random delay added to a wait between two polls, so the bundles
+/// waiting for a leader don't poll the block engine in lockstep
+const LEADER_POLL_JITTER: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Polls of the next jito leader before a bundle is given up
+const MAX_LEADER_POLLS: u32 = 100;
+
+/// The maximum time to wait for a terminal result of a sent bundle
+const BUNDLE_RESULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The compute unit limit used when a transaction doesn't specify one
+const DEFAULT_COMPUTE_UNIT_LIMIT: u32 = 1_000_000;
+
+/// Smallest tip transfer accepted by the jito block engine
+const MIN_TIP_LAMPORTS: u64 = 1_000;
+
+/// Accounts a transaction can lock
+const MAX_ACCOUNT_LOCKS: usize = 64;
+
+/// Fee paid for each signature of a transaction
+const SIGNATURE_FEE_LAMPORTS: u64 = 5_000;
+
+/// Wait between two attempts to connect to a block engine unreachable at startup
+const BLOCK_ENGINE_RECONNECT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Searcher client for the jito block engine, authenticated or not
+type SearcherClient = SearcherServiceClient<InterceptedService<Channel, SearcherInterceptor>>;
+
+/// Interceptor of the searcher client, when an auth keypair is provided
+/// the requests are authenticated against the block engine
+#[derive(Clone)]
+pub enum SearcherInterceptor {
+    NoAuth,
+    Auth(ClientInterceptor),
+}
+
+impl Interceptor for SearcherInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        match self {
+            SearcherInterceptor::NoAuth => Ok(request),
+            SearcherInterceptor::Auth(interceptor) => interceptor.call(request),
+        }
+    }
+}
+
+/// Outcome of a bundle sent to the jito block engine
+#[derive(Debug, Clone)]
+pub enum BundleOutcome {
+    /// The bundle was accepted and forwarded to the leader, without
+    /// landing as far as the block engine and the rpc tell
+    Accepted { slot: u64 },
+    /// The bundle landed in a block
+    Landed { slot: u64 },
+    /// The bundle was rejected by the block engine
+    Rejected(Option<Reason>),
+    /// The bundle was dropped and won't land
+    Dropped,
+    /// The bundle landed and was finalized
+    Finalized,
+}
+
+impl BundleOutcome {
+    fn from_bundle_result(result: bundle_result::Result) -> Option<Self> {
+        match result {
+            bundle_result::Result::Accepted(accepted) => Some(BundleOutcome::Accepted {
+                slot: accepted.slot,
+            }),
+            bundle_result::Result::Rejected(rejected) => {
+                Some(BundleOutcome::Rejected(rejected.reason))
+            }
+            bundle_result::Result::Dropped(_) => Some(BundleOutcome::Dropped),
+            bundle_result::Result::Finalized(_) => Some(BundleOutcome::Finalized),
+            bundle_result::Result::Processed(processed) => Some(BundleOutcome::Landed {
+                slot: processed.slot,
+            }),
+        }
+    }
+
+    /// Whether the transactions of the bundle landed
+    pub fn landed(&self) -> bool {
+        matches!(
+            self,
+            BundleOutcome::Landed { .. } | BundleOutcome::Finalized
+        )
+    }
+
+    /// Whether no further result is expected for the bundle
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, BundleOutcome::Accepted { .. })
+    }
+
+    /// Whether the bundle can land when it is sent again with a fresh blockhash:
+    /// it lost an auction or was dropped, a failed simulation would fail again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BundleOutcome::Dropped => true,
+            BundleOutcome::Rejected(Some(reason)) => matches!(
+                reason,
+                Reason::StateAuctionBidRejected(_)
+                    | Reason::WinningBatchBidRejected(_)
+                    | Reason::InternalError(_)
+                    | Reason::DroppedBundle(_)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Label of the reason the bundle was rejected, none when it wasn't
+    pub fn rejection_reason(&self) -> Option<&'static str> {
+        match self {
+            BundleOutcome::Rejected(reason) => Some(match reason {
+                Some(Reason::StateAuctionBidRejected(_)) => "state_auction_bid_rejected",
+                Some(Reason::WinningBatchBidRejected(_)) => "winning_batch_bid_rejected",
+                Some(Reason::SimulationFailure(_)) => "simulation_failure",
+                Some(Reason::InternalError(_)) => "internal_error",
+                Some(Reason::DroppedBundle(_)) => "dropped_bundle",
+                None => "unknown",
+            }),
+            _ => None,
+        }
+    }
+
+    /// Describes the outcome. A rejection tells whether the tip was outbid or the
+    /// transactions are invalid, along the marginfi error of a failed simulation
+    pub fn describe(&self) -> String {
+        let BundleOutcome::Rejected(Some(reason)) = self else {
+            return format!("{:?}", self);
+        };
+
+        let details = match reason {
+            Reason::StateAuctionBidRejected(rejected) => format!(
+                "outbid in the state auction {} with a bid of {} lamports, the tip is too low: {}",
+                rejected.auction_id,
+                rejected.simulated_bid_lamports,
+                rejected.msg.as_deref().unwrap_or("no message")
+            ),
+            Reason::WinningBatchBidRejected(rejected) => format!(
+                "outbid in the winning batch of the auction {} with a bid of {} lamports, the tip is too low: {}",
+                rejected.auction_id,
+                rejected.simulated_bid_lamports,
+                rejected.msg.as_deref().unwrap_or("no message")
+            ),
+            Reason::SimulationFailure(failure) => {
+                let msg = failure.msg.as_deref().unwrap_or("no message");
+                match failure
+                    .msg
+                    .as_deref()
+                    .and_then(crate::marginfi_errors::decode_error_message)
+                {
+                    Some(decoded) => format!(
+                        "transaction {} failed the simulation, it is invalid: {} ({})",
+                        failure.tx_signature, msg, decoded
+                    ),
+                    None => format!(
+                        "transaction {} failed the simulation, it is invalid: {}",
+                        failure.tx_signature, msg
+                    ),
+                }
+            }
+            Reason::InternalError(error) => {
+                format!("internal error of the block engine: {}", error.msg)
+            }
+            Reason::DroppedBundle(dropped) => {
+                format!("dropped by the block engine: {}", dropped.msg)
+            }
+        };
 
-/// The leadership threshold related to the jito block engine
-const LEADERSHIP_THRESHOLD: u64 = 2;
+        format!(
+            "Rejected ({}): {}",
+            self.rejection_reason().unwrap_or("unknown"),
+            details
+        )
+    }
+}
 
-/// The sleep duration for the transaction manager
-/// to wait before checking for the next leader
-const SLEEP_DURATION: std::time::Duration = std::time::Duration::from_millis(500);
+/// A bundle handed back to the transaction manager to be rebuilt and sent again
+struct BundleRetry {
+    raw_transactions: BatchTransactions,
+    /// Retries of the bundle, this one included
+    attempt: u64,
+    delay: std::time::Duration,
+    retry_tx: Sender<(BatchTransactions, u64)>,
+}
 
 /// Manages transactions for the liquidator and rebalancer
+///
+/// The batches are taken in the order they are received, and the transactions of
+/// a batch are sent in order: atomically as a bundle, or one by one through the
+/// rpc, stopping at the first one which isn't confirmed. Up to `max_in_flight_bundles`
+/// batches await their outcome at once, with no ordering between them, except for
+/// the batches depending on liquidations, which are held until these resolve and
+/// dropped unless all of them landed
 #[allow(dead_code)]
 pub struct TransactionManager {
     rx: Receiver<BatchTransactions>,
-    keypair: Keypair,
-    rpc: Arc<RpcClient>,
-    non_block_rpc: NonBlockRpc,
-    /// The searcher client for the jito block engine
-    searcher_client: SearcherServiceClient<Channel>,
+    keypair: Arc<Keypair>,
+    /// Rpc clients of the current healthy endpoint
+    rpc_pool: Arc<RpcPool>,
+    /// The searcher client for the jito block engine, none while it is
+    /// unreachable, the batches are then sent through the rpc
+    searcher_client: Option<SearcherClient>,
+    /// Receives the searcher client and the tip accounts once the block engine,
+    /// unreachable at startup, is connected in the background
+    block_engine_rx: Option<tokio::sync::oneshot::Receiver<(SearcherClient, Vec<Pubkey>)>>,
     /// Atomic boolean to check if the current node is the jito leader
     is_jito_leader: AtomicBool,
     /// The tip accounts of the jito block engine
     tip_accounts: Vec<Pubkey>,
+    /// Round-robin cursor over the tip accounts
+    tip_account_cursor: AtomicUsize,
     lookup_tables: Vec<AddressLookupTableAccount>,
+    /// Strategy used to size the jito tip
+    tip_strategy: TipStrategy,
+    /// Maximum time to wait for a jito leader before dropping the bundle
+    max_leader_wait: std::time::Duration,
+    /// Slots before a jito leader from which its bundles are sent
+    leader_slot_threshold: u64,
+    /// Number of tip accounts the tip of a bundle is spread across
+    tip_split: usize,
+    /// Resend the transactions through the rpc when a bundle doesn't land
+    fallback_to_rpc: bool,
+    /// Batches awaiting their outcome at once
+    max_in_flight_bundles: usize,
+    /// Logs the transactions instead of sending them
+    dry_run: bool,
+    /// Logs the dry run estimates as JSON, one per line
+    dry_run_json: bool,
+    /// Commitment used by the rpc clients and awaited on confirmations
+    commitment: CommitmentConfig,
+    /// Retries and timeouts of the transactions sent through the rpc fallback
+    sender_config: SenderCfg,
+    /// Times a bundle rejected for a transient reason is sent again
+    max_bundle_retries: u64,
+    /// Wait before a rejected bundle is sent again
+    bundle_retry_delay: std::time::Duration,
+    /// Raise of the tip on each retry of a bundle, in basis points
+    retry_tip_bump_bps: u16,
+    /// Durable nonce account the single transaction bundles are built on
+    nonce_account: Option<Pubkey>,
+    /// Bundles to send again, along with their number of retries
+    retry_tx: Sender<(BatchTransactions, u64)>,
+    retry_rx: Receiver<(BatchTransactions, u64)>,
+}
+
+/// Accounts with a liquidation handed to the transaction manager and not resolved yet
+pub type PendingLiquidations = Arc<Mutex<HashSet<Pubkey>>>;
+
+/// Liquidations of a signer still in flight, the batches of its rebalancer depend on them
+pub type InFlightLiquidations = Arc<Mutex<Vec<Weak<LiquidationGuard>>>>;
+
+/// Outcome of a transaction, a liquidation or one awaited by the rebalancer,
+/// resolved once the transaction manager is done with its batch
+#[derive(Clone)]
+pub struct TransactionOutcome(tokio::sync::watch::Receiver<Option<bool>>);
+
+impl TransactionOutcome {
+    /// Whether the transaction landed, a transaction dropped
+    /// without an outcome, e.g. while paused, didn't
+    pub async fn landed(mut self) -> bool {
+        match self.0.wait_for(Option::is_some).await {
+            Ok(outcome) => outcome.unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Marks an account as pending liquidation until dropped, which happens once
+/// the transaction manager is done with its bundle, whether it landed or not
+pub struct LiquidationGuard {
+    pending_liquidations: PendingLiquidations,
+    address: Pubkey,
+    /// Cooldowns the outcome of the liquidation is recorded to, along with its banks
+    cooldown: Option<(LiquidationCooldowns, Pubkey, Pubkey)>,
+    /// Resolves the outcome awaited by the batches depending on the liquidation
+    outcome: tokio::sync::watch::Sender<Option<bool>>,
+}
+
+impl LiquidationGuard {
+    /// Marks the account as pending, none when it already is
+    pub fn new(pending_liquidations: &PendingLiquidations, address: Pubkey) -> Option<Self> {
+        pending_liquidations
+            .lock()
+            .unwrap()
+            .insert(address)
+            .then(|| Self {
+                pending_liquidations: pending_liquidations.clone(),
+                address,
+                cooldown: None,
+                outcome: tokio::sync::watch::Sender::new(None),
+            })
+    }
+
+    pub fn with_cooldown(
+        mut self,
+        cooldowns: LiquidationCooldowns,
+        asset_bank: Pubkey,
+        liab_bank: Pubkey,
+    ) -> Self {
+        self.cooldown = Some((cooldowns, asset_bank, liab_bank));
+        self
+    }
+
+    pub fn record_outcome(&self, landed: bool) {
+        if let Some((cooldowns, asset_bank, liab_bank)) = &self.cooldown {
+            cooldowns.record_outcome((self.address, *asset_bank, *liab_bank), landed);
+        }
+        self.outcome.send_replace(Some(landed));
+    }
+
+    pub fn outcome(&self) -> TransactionOutcome {
+        TransactionOutcome(self.outcome.subscribe())
+    }
+}
+
+impl Drop for LiquidationGuard {
+    fn drop(&mut self) {
+        self.pending_liquidations
+            .lock()
+            .unwrap()
+            .remove(&self.address);
+    }
 }
 
 // Type alias for a batch of transactions
@@ -58,9 +400,32 @@ pub struct TransactionManager {
 // The outer vector represents a batch of transactions
 pub type BatchTransactions = Vec<RawTransaction>;
 
+#[derive(Clone)]
 pub struct RawTransaction {
     pub instructions: Vec<Instruction>,
     pub lookup_tables: Option<Vec<AddressLookupTableAccount>>,
+    /// Expected profit of the transaction in lamports, used to size the jito tip
+    pub expected_profit_lamports: Option<u64>,
+    /// Compute unit limit of the transaction, defaults to `DEFAULT_COMPUTE_UNIT_LIMIT`
+    pub compute_unit_limit: Option<u32>,
+    /// Compute unit price of the transaction in micro lamports, none when not set
+    pub compute_unit_price_micro_lamports: Option<u64>,
+    /// When the transaction was created, used to measure the time until its submission
+    pub created_at: std::time::Instant,
+    /// What the transaction does, used in the alerts
+    pub description: Option<String>,
+    /// What the transaction does, recorded in the transaction log
+    pub metadata: TxMetadata,
+    /// Profit and loss of the transaction, recorded once it landed
+    pub pnl: Option<PnlEntry>,
+    /// Keeps the liquidated account pending until the transaction is resolved
+    pub liquidation_guard: Option<Arc<LiquidationGuard>>,
+    /// Liquidations which must land before the transaction is sent
+    pub depends_on: Vec<TransactionOutcome>,
+    /// Resolves the outcome awaited by the sender of the batch
+    pub outcome: Option<Arc<tokio::sync::watch::Sender<Option<bool>>>>,
+    /// Whether the transaction tops up the signer with the lamports of the tip
+    pub funds_tip: bool,
 }
 
 impl RawTransaction {
@@ -68,6 +433,17 @@ impl RawTransaction {
         Self {
             instructions,
             lookup_tables: None,
+            expected_profit_lamports: None,
+            compute_unit_limit: None,
+            compute_unit_price_micro_lamports: None,
+            created_at: std::time::Instant::now(),
+            description: None,
+            metadata: TxMetadata::default(),
+            pnl: None,
+            liquidation_guard: None,
+            depends_on: vec![],
+            outcome: None,
+            funds_tip: false,
         }
     }
 
@@ -75,28 +451,243 @@ impl RawTransaction {
         self.lookup_tables = Some(lookup_tables);
         self
     }
+
+    pub fn with_expected_profit(mut self, expected_profit_lamports: Option<u64>) -> Self {
+        self.expected_profit_lamports = expected_profit_lamports;
+        self
+    }
+
+    pub fn with_compute_unit_limit(mut self, compute_unit_limit: u32) -> Self {
+        self.compute_unit_limit = Some(compute_unit_limit);
+        self
+    }
+
+    pub fn with_compute_unit_price(mut self, compute_unit_price_micro_lamports: u64) -> Self {
+        self.compute_unit_price_micro_lamports = Some(compute_unit_price_micro_lamports);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: TxMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_description(mut self, description: String) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    pub fn with_pnl(mut self, pnl: PnlEntry) -> Self {
+        self.pnl = Some(pnl);
+        self
+    }
+
+    pub fn with_liquidation_guard(mut self, liquidation_guard: Arc<LiquidationGuard>) -> Self {
+        self.liquidation_guard = Some(liquidation_guard);
+        self
+    }
+
+    /// Resolves the returned outcome once the transaction manager is done with the batch
+    pub fn with_outcome(mut self) -> (Self, TransactionOutcome) {
+        let (outcome_tx, outcome_rx) = tokio::sync::watch::channel(None);
+        self.outcome = Some(Arc::new(outcome_tx));
+        (self, TransactionOutcome(outcome_rx))
+    }
+
+    pub fn with_tip_funding(mut self) -> Self {
+        self.funds_tip = true;
+        self
+    }
 }
 
-impl TransactionManager {
-    /// Creates a new transaction manager
-    pub async fn new(rx: Receiver<BatchTransactions>, config: GeneralConfig) -> Self {
-        let keypair = read_keypair_file(&config.keypair_path).unwrap();
-        let mut searcher_client = get_searcher_client_no_auth(&config.block_engine_url)
-            .await
-            .unwrap();
+/// Everything needed to resend a batch of transactions through the rpc,
+/// used when a bundle fails to land
+struct RpcFallback {
+    raw_transactions: BatchTransactions,
+    rpc: Arc<NonBlockRpc>,
+    keypair: Arc<Keypair>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    sender_config: SenderCfg,
+    /// Durable nonce account and blockhash the bundle was built on
+    nonce: Option<(Pubkey, Hash)>,
+}
+
+impl RpcFallback {
+    /// Sends each transaction of the batch through the rpc, without the jito tip,
+    /// stopping at the first one which isn't confirmed
+    fn send(self) -> anyhow::Result<Vec<SendOutcome>> {
+        let mut nonce = self.nonce;
+        let mut outcomes = Vec::new();
+        for raw_transaction in self.raw_transactions {
+            // No tip is paid by the transactions sent through the rpc
+            let accounting = TxAccounting::new(
+                raw_transaction.pnl.as_ref(),
+                TransactionManager::raw_transaction_fees_lamports(&raw_transaction),
+                0,
+            );
+
+            let mut ixs = raw_transaction.instructions;
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(
+                raw_transaction
+                    .compute_unit_limit
+                    .unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT),
+            ));
+            if let Some(price) = raw_transaction.compute_unit_price_micro_lamports {
+                ixs.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+            }
+
+            let lookup_tables = raw_transaction
+                .lookup_tables
+                .as_ref()
+                .unwrap_or(&self.lookup_tables);
+
+            let compile = |ixs: &[Instruction],
+                           blockhash: Hash|
+             -> Result<VersionedTransaction, Box<dyn Error>> {
+                Ok(VersionedTransaction::try_new(
+                    VersionedMessage::V0(v0::Message::try_compile(
+                        &self.keypair.pubkey(),
+                        ixs,
+                        lookup_tables,
+                        blockhash,
+                    )?),
+                    &[self.keypair.as_ref()],
+                )?)
+            };
+
+            let outcome = match nonce.take() {
+                // The bundle transaction doesn't expire. Built on the same nonce, whichever
+                // of the two lands first advances it and invalidates the other. This one
+                // doesn't expire either, so it isn't re-signed
+                Some((nonce_account, blockhash)) => {
+                    ixs.insert(
+                        0,
+                        advance_nonce_account(&nonce_account, &self.keypair.pubkey()),
+                    );
+                    compile(&ixs, blockhash).and_then(|transaction| {
+                        TransactionSender::aggressive_send_tx(
+                            self.rpc.clone(),
+                            &transaction,
+                            self.sender_config.clone(),
+                        )
+                    })
+                }
+                None => TransactionSender::aggressive_send_with_resign(
+                    self.rpc.clone(),
+                    |blockhash| compile(&ixs, blockhash),
+                    self.sender_config.clone(),
+                ),
+            }
+            .map_err(|e| anyhow::anyhow!("Failed to send transaction through rpc: {:?}", e))?;
+
+            log_submission(
+                *outcome.signature(),
+                raw_transaction.metadata.with_accounting(accounting),
+            );
+
+            let confirmed = outcome.is_confirmed();
+            outcomes.push(outcome);
+            if !confirmed {
+                break;
+            }
+        }
 
-        let rpc = Arc::new(RpcClient::new_with_commitment(
-            config.rpc_url.clone(),
-            CommitmentConfig::confirmed(),
-        ));
+        Ok(outcomes)
+    }
+}
+
+/// Strategy used to size the jito tip of a transaction
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub enum TipStrategy {
+    /// Always tips the same amount of lamports
+    Fixed(u64),
+    /// Tips a percentage (in basis points) of the expected profit,
+    /// clamped between `min` and `max` lamports
+    PercentOfProfit { bps: u16, min: u64, max: u64 },
+}
+
+impl TipStrategy {
+    /// Computes the tip in lamports for the given expected profit,
+    /// falls back to the minimum tip when the profit is unknown
+    pub fn tip_lamports(&self, expected_profit_lamports: Option<u64>) -> u64 {
+        match self {
+            TipStrategy::Fixed(lamports) => *lamports,
+            TipStrategy::PercentOfProfit { bps, min, max } => {
+                let tip = expected_profit_lamports
+                    .map(|profit| (profit as u128 * *bps as u128 / 10_000) as u64)
+                    .unwrap_or(*min);
+
+                tip.max(*min).min(*max)
+            }
+        }
+    }
+}
+
+/// Region of the jito block engine
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub enum JitoRegion {
+    Amsterdam,
+    Frankfurt,
+    NewYork,
+    Tokyo,
+    SaltLakeCity,
+}
 
-        let non_block_rpc = NonBlockRpc::new(config.rpc_url.clone());
+impl JitoRegion {
+    /// Mainnet block engine url of the region
+    pub fn block_engine_url(&self) -> &'static str {
+        match self {
+            JitoRegion::Amsterdam => "https://amsterdam.mainnet.block-engine.jito.wtf",
+            JitoRegion::Frankfurt => "https://frankfurt.mainnet.block-engine.jito.wtf",
+            JitoRegion::NewYork => "https://ny.mainnet.block-engine.jito.wtf",
+            JitoRegion::Tokyo => "https://tokyo.mainnet.block-engine.jito.wtf",
+            JitoRegion::SaltLakeCity => "https://slc.mainnet.block-engine.jito.wtf",
+        }
+    }
+}
+
+impl TransactionManager {
+    /// Creates a new transaction manager
+    pub async fn new(
+        rx: Receiver<BatchTransactions>,
+        config: GeneralConfig,
+        rpc_pool: Arc<RpcPool>,
+    ) -> anyhow::Result<Self> {
+        let keypair = Arc::new(config.keypair.load()?);
+        let block_engine_url = Self::select_block_engine_url(&config).await;
+        // The rpc keeps the liquidator running through a block engine maintenance
+        let (searcher_client, tip_accounts, block_engine_rx) = match Self::connect_block_engine(
+            &config,
+            &block_engine_url,
+        )
+        .await
+        {
+            Ok((searcher_client, tip_accounts)) => (Some(searcher_client), tip_accounts, None),
+            Err(e) => {
+                warn!(
+                        "The jito block engine {} is unreachable, sending through the rpc until it is back: {:?}",
+                        block_engine_url, e
+                    );
+                notify(format!(
+                        "The jito block engine {} is unreachable, the transactions are sent through the rpc without tip until it is back",
+                        block_engine_url
+                    ));
+                let (block_engine_tx, block_engine_rx) = tokio::sync::oneshot::channel();
+                tokio::spawn(Self::reconnect_block_engine(
+                    config.clone(),
+                    block_engine_url,
+                    block_engine_tx,
+                ));
+                (None, vec![], Some(block_engine_rx))
+            }
+        };
 
         // Loads the Address Lookup Table's accounts
+        let rpc = rpc_pool.non_blocking_rpc();
         let mut lookup_tables = vec![];
         for table_address in &config.address_lookup_tables {
-            let raw_account = rpc.get_account(table_address).await.unwrap();
-            let address_lookup_table = AddressLookupTable::deserialize(&raw_account.data).unwrap();
+            let raw_account = rpc.get_account(table_address).await?;
+            let address_lookup_table = AddressLookupTable::deserialize(&raw_account.data)?;
             let lookup_table = AddressLookupTableAccount {
                 key: *table_address,
                 addresses: address_lookup_table.addresses.to_vec(),
@@ -104,96 +695,827 @@ impl TransactionManager {
             lookup_tables.push(lookup_table);
         }
 
-        let tip_accounts = Self::get_tip_accounts(&mut searcher_client).await.unwrap();
+        let (retry_tx, retry_rx) = crossbeam::channel::unbounded();
 
-        Self {
+        Ok(Self {
             rx,
             keypair,
-            rpc,
-            non_block_rpc,
+            rpc_pool,
             searcher_client,
+            block_engine_rx,
             is_jito_leader: AtomicBool::new(false),
             tip_accounts,
+            tip_account_cursor: AtomicUsize::new(0),
             lookup_tables,
+            tip_strategy: config.tip_strategy,
+            max_leader_wait: std::time::Duration::from_millis(config.max_leader_wait_ms),
+            leader_slot_threshold: config.jito_leader_slot_threshold,
+            tip_split: config.jito_tip_split,
+            fallback_to_rpc: config.fallback_to_rpc,
+            max_in_flight_bundles: config.max_in_flight_bundles,
+            dry_run: config.dry_run,
+            dry_run_json: config.dry_run_json,
+            commitment: config.commitment,
+            sender_config: config.get_sender_config(),
+            max_bundle_retries: config.jito_max_retries,
+            bundle_retry_delay: std::time::Duration::from_millis(config.jito_retry_delay_ms),
+            retry_tip_bump_bps: config.jito_retry_tip_bump_bps,
+            nonce_account: config.nonce_account,
+            retry_tx,
+            retry_rx,
+        })
+    }
+
+    /// Picks the block engine of the configured region with the lowest latency,
+    /// measured on a tip accounts request, defaults to the `block_engine_url`
+    async fn select_block_engine_url(config: &GeneralConfig) -> String {
+        let mut fastest: Option<(JitoRegion, std::time::Duration)> = None;
+        for region in &config.block_engine_regions {
+            let start = std::time::Instant::now();
+            let ping = async {
+                let channel = create_grpc_channel(region.block_engine_url()).await?;
+                let mut client =
+                    SearcherServiceClient::with_interceptor(channel, SearcherInterceptor::NoAuth);
+                Self::get_tip_accounts(&mut client).await
+            };
+
+            match ping.await {
+                Ok(_) => {
+                    let latency = start.elapsed();
+                    info!("Jito block engine {:?} latency: {:?}", region, latency);
+                    if fastest.map_or(true, |(_, fastest_latency)| latency < fastest_latency) {
+                        fastest = Some((*region, latency));
+                    }
+                }
+                Err(e) => warn!(
+                    "Failed to reach the jito block engine {:?}: {:?}",
+                    region, e
+                ),
+            }
+        }
+
+        match fastest {
+            Some((region, _)) => {
+                info!("Using the jito block engine {:?}", region);
+                region.block_engine_url().to_string()
+            }
+            None => config.block_engine_url.clone(),
+        }
+    }
+
+    /// Connects to the block engine and loads its tip accounts
+    async fn connect_block_engine(
+        config: &GeneralConfig,
+        block_engine_url: &str,
+    ) -> anyhow::Result<(SearcherClient, Vec<Pubkey>)> {
+        let mut searcher_client = Self::get_searcher_client(config, block_engine_url).await?;
+        let tip_accounts = Self::get_tip_accounts(&mut searcher_client).await?;
+        Ok((searcher_client, tip_accounts))
+    }
+
+    /// Retries to connect to the block engine until it succeeds,
+    /// or the transaction manager is dropped
+    async fn reconnect_block_engine(
+        config: GeneralConfig,
+        block_engine_url: String,
+        block_engine_tx: tokio::sync::oneshot::Sender<(SearcherClient, Vec<Pubkey>)>,
+    ) {
+        loop {
+            tokio::time::sleep(BLOCK_ENGINE_RECONNECT_INTERVAL).await;
+            if block_engine_tx.is_closed() {
+                return;
+            }
+            match Self::connect_block_engine(&config, &block_engine_url).await {
+                Ok(block_engine) => {
+                    let _ = block_engine_tx.send(block_engine);
+                    return;
+                }
+                Err(e) => warn!(
+                    "The jito block engine {} is still unreachable: {:?}",
+                    block_engine_url, e
+                ),
+            }
         }
     }
 
-    /// Starts the transaction manager
+    /// Switches to the block engine once it is connected in the background
+    fn poll_block_engine(&mut self) {
+        let Some(block_engine_rx) = &mut self.block_engine_rx else {
+            return;
+        };
+        match block_engine_rx.try_recv() {
+            Ok((searcher_client, tip_accounts)) => {
+                info!("Connected to the jito block engine, sending bundles again");
+                notify(
+                    "The jito block engine is back, the transactions are sent as bundles again"
+                        .to_string(),
+                );
+                self.searcher_client = Some(searcher_client);
+                self.tip_accounts = tip_accounts;
+                self.block_engine_rx = None;
+            }
+            Err(tokio::sync::oneshot::error::TryRecvError::Empty) => {}
+            Err(tokio::sync::oneshot::error::TryRecvError::Closed) => self.block_engine_rx = None,
+        }
+    }
+
+    /// Creates the searcher client for the jito block engine,
+    /// authenticated with the auth keypair when it is configured
+    async fn get_searcher_client(
+        config: &GeneralConfig,
+        block_engine_url: &str,
+    ) -> anyhow::Result<SearcherClient> {
+        let interceptor = match &config.auth_keypair_path {
+            Some(auth_keypair_path) => {
+                let auth_keypair = Arc::new(
+                    read_keypair_file(auth_keypair_path)
+                        .map_err(|e| anyhow::anyhow!("Failed to read auth keypair: {:?}", e))?,
+                );
+                let auth_channel = create_grpc_channel(block_engine_url).await?;
+                let client_interceptor = ClientInterceptor::new(
+                    AuthServiceClient::new(auth_channel),
+                    &auth_keypair,
+                    Role::Searcher,
+                )
+                .await?;
+
+                SearcherInterceptor::Auth(client_interceptor)
+            }
+            None => SearcherInterceptor::NoAuth,
+        };
+
+        let searcher_channel = create_grpc_channel(block_engine_url).await?;
+
+        Ok(SearcherServiceClient::with_interceptor(
+            searcher_channel,
+            interceptor,
+        ))
+    }
+
+    /// Starts the transaction manager, returns once every sender is dropped
+    /// and the in flight bundles are done
     pub async fn start(&mut self) {
-        for instructions in self.rx.clone().iter() {
-            let transactions = match self.configure_instructions(instructions).await {
+        let mut in_flight = tokio::task::JoinSet::new();
+        let rx = self.rx.clone();
+        let retry_rx = self.retry_rx.clone();
+        loop {
+            let (mut instructions, attempt) = crossbeam::channel::select! {
+                recv(rx) -> instructions => match instructions {
+                    Ok(instructions) => (instructions, 0),
+                    Err(_) => break,
+                },
+                recv(retry_rx) -> retry => match retry {
+                    Ok(retry) => retry,
+                    Err(_) => continue,
+                },
+            };
+
+            // Reap the bundles that are already done
+            while let Some(Some(_)) = in_flight.join_next().now_or_never() {}
+            if in_flight.len() >= self.max_in_flight_bundles {
+                debug!(
+                    "{} bundles in flight, waiting for one of them to resolve",
+                    in_flight.len()
+                );
+                while in_flight.len() >= self.max_in_flight_bundles {
+                    in_flight.join_next().await;
+                }
+            }
+            crate::status::set_pending_bundles(in_flight.len());
+
+            if self.dry_run {
+                self.log_dry_run(&instructions);
+                continue;
+            }
+            // The batch comes back through the retries once its dependencies landed
+            let depends_on = instructions
+                .iter_mut()
+                .flat_map(|raw_transaction| std::mem::take(&mut raw_transaction.depends_on))
+                .collect::<Vec<_>>();
+            if !depends_on.is_empty() {
+                let retry_tx = self.retry_tx.clone();
+                tokio::spawn(async move {
+                    let outcomes = futures::future::join_all(
+                        depends_on.into_iter().map(TransactionOutcome::landed),
+                    )
+                    .await;
+                    if outcomes.into_iter().all(|landed| landed) {
+                        let _ = retry_tx.send((instructions, attempt));
+                    } else {
+                        info!(
+                            "Dropping a batch of {} transactions, a liquidation it depends on didn't land",
+                            instructions.len()
+                        );
+                    }
+                });
+                continue;
+            }
+            // Dropping the batch releases the accounts of its liquidations
+            if crate::admin::is_paused() {
+                warn!(
+                    "Submissions are paused, dropping a batch of {} transactions",
+                    instructions.len()
+                );
+                continue;
+            }
+            if crate::spend_budget::is_exhausted() {
+                warn!(
+                    "The fee and tip budget is exhausted, dropping a batch of {} transactions",
+                    instructions.len()
+                );
+                continue;
+            }
+            self.poll_block_engine();
+            // While the signer is low on SOL, or the block engine is unreachable,
+            // the batch is sent through the rpc, without jito tip, unless it funds its tip
+            let funds_tip = instructions
+                .iter()
+                .any(|raw_transaction| raw_transaction.funds_tip);
+            let searcher_client = self.searcher_client.clone().filter(|_| {
+                funds_tip || !crate::sol_balance::is_sol_balance_low(&self.keypair.pubkey())
+            });
+            if searcher_client.is_some() {
+                debug!("Waiting for Jito leader...");
+                if let Err(e) = self.wait_for_leader().await {
+                    error!("Failed to wait for the jito leader: {:?}", e);
+                    continue;
+                }
+            }
+            // Only the liquidations carry an expected profit
+            let liquidation_indexes = instructions
+                .iter()
+                .enumerate()
+                .filter(|(_, raw_transaction)| raw_transaction.expected_profit_lamports.is_some())
+                .map(|(index, raw_transaction)| {
+                    (
+                        index,
+                        raw_transaction.description.clone().unwrap_or_default(),
+                        raw_transaction.expected_profit_lamports.unwrap_or_default(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let liquidations = liquidation_indexes.len() as u64;
+            let created_at = instructions
+                .iter()
+                .map(|raw_transaction| raw_transaction.created_at)
+                .min();
+            // The tip is paid by the last transaction of the bundle
+            let tip_lamports = searcher_client
+                .as_ref()
+                .map(|_| self.bundle_tip_lamports(&instructions, attempt))
+                .unwrap_or_default();
+            let metadata = instructions
+                .iter()
+                .enumerate()
+                .map(|(index, raw_transaction)| {
+                    let tip_lamports = if index + 1 == instructions.len() {
+                        tip_lamports
+                    } else {
+                        0
+                    };
+                    raw_transaction
+                        .metadata
+                        .clone()
+                        .with_accounting(TxAccounting::new(
+                            raw_transaction.pnl.as_ref(),
+                            Self::raw_transaction_fees_lamports(raw_transaction),
+                            tip_lamports,
+                        ))
+                })
+                .collect::<Vec<_>>();
+            let pnl_entries = instructions
+                .iter()
+                .filter_map(|raw_transaction| raw_transaction.pnl.clone())
+                .collect::<Vec<_>>();
+            let fees_lamports = if searcher_client.is_none() {
+                Self::transaction_fees_lamports(&instructions)
+            } else {
+                self.bundle_fees_lamports(&instructions, attempt)
+            };
+            let liquidation_guards = instructions
+                .iter()
+                .filter_map(|raw_transaction| raw_transaction.liquidation_guard.clone())
+                .collect::<Vec<_>>();
+            let outcomes = instructions
+                .iter()
+                .filter_map(|raw_transaction| raw_transaction.outcome.clone())
+                .collect::<Vec<_>>();
+            let Some(searcher_client) = searcher_client else {
+                // The fees of the transactions sent through the rpc are paid even when they revert
+                crate::spend_budget::record_spend(fees_lamports);
+                let rpc_fallback = RpcFallback {
+                    raw_transactions: instructions,
+                    rpc: self.rpc_pool.rpc(),
+                    keypair: self.keypair.clone(),
+                    lookup_tables: self.lookup_tables.clone(),
+                    sender_config: self.sender_config.clone(),
+                    nonce: None,
+                };
+                in_flight.spawn(async move {
+                    let landed =
+                        match tokio::task::spawn_blocking(move || rpc_fallback.send()).await {
+                            Ok(Ok(outcomes)) => {
+                                info!("Sent transactions through the rpc: {:?}", outcomes);
+                                outcomes.iter().all(SendOutcome::is_confirmed)
+                            }
+                            Ok(Err(e)) => {
+                                error!("Failed to send transactions through the rpc: {:?}", e);
+                                false
+                            }
+                            Err(e) => {
+                                error!("Failed to send transactions through the rpc: {:?}", e);
+                                false
+                            }
+                        };
+                    if landed {
+                        LIQUIDATIONS_SUCCEEDED.inc_by(liquidations);
+                        record_landed(pnl_entries, fees_lamports);
+                    } else {
+                        LIQUIDATIONS_FAILED.inc_by(liquidations);
+                    }
+                    for liquidation_guard in &liquidation_guards {
+                        liquidation_guard.record_outcome(landed);
+                    }
+                    drop(liquidation_guards);
+                    for outcome in outcomes {
+                        outcome.send_replace(Some(landed));
+                    }
+                });
+                continue;
+            };
+            let nonce = match self.bundle_nonce(instructions.len()).await {
+                Ok(nonce) => nonce,
+                Err(e) => {
+                    error!("Failed to fetch the durable nonce: {:?}", e);
+                    continue;
+                }
+            };
+            let rpc_fallback = self.fallback_to_rpc.then(|| RpcFallback {
+                raw_transactions: instructions.clone(),
+                rpc: self.rpc_pool.rpc(),
+                keypair: self.keypair.clone(),
+                lookup_tables: self.lookup_tables.clone(),
+                sender_config: self.sender_config.clone(),
+                nonce,
+            });
+            let retry = (attempt < self.max_bundle_retries).then(|| BundleRetry {
+                raw_transactions: instructions.clone(),
+                attempt: attempt + 1,
+                delay: self.bundle_retry_delay,
+                retry_tx: self.retry_tx.clone(),
+            });
+            // The transactions are only built once the leader is close,
+            // so the blockhash is fresh when the bundle is sent
+            let transactions = match self
+                .configure_instructions(instructions, attempt, nonce)
+                .await
+            {
                 Ok(txs) => txs,
                 Err(e) => {
                     error!("Failed to configure instructions: {:?}", e);
                     continue;
                 }
             };
-            debug!("Waiting for Jito leader...");
-            loop {
-                let next_leader = match self
-                    .searcher_client
-                    .get_next_scheduled_leader(NextScheduledLeaderRequest {})
-                    .await
-                {
-                    Ok(response) => response.into_inner(),
+            if let Some(created_at) = created_at {
+                OPPORTUNITY_TO_SUBMIT.observe(created_at.elapsed().as_secs_f64());
+            }
+            let liquidation_alerts = liquidation_indexes
+                .into_iter()
+                .map(|(index, description, expected_profit_lamports)| {
+                    (
+                        description,
+                        expected_profit_lamports,
+                        transactions[index].signatures[0],
+                    )
+                })
+                .collect::<Vec<_>>();
+            for (transaction, metadata) in transactions.iter().zip(metadata) {
+                log_submission(transaction.signatures[0], metadata);
+            }
+            let transaction = Self::send_transaction_with_fallback(
+                transactions,
+                searcher_client,
+                rpc_fallback,
+                retry,
+                self.rpc_pool.non_blocking_rpc(),
+            );
+            in_flight.spawn(async move {
+                let (landed, accepted, outcome) = match transaction.await {
+                    // The bundle was handed back to be sent again
+                    Ok(None) => return,
+                    Ok(Some((outcome, landed_through_rpc))) => {
+                        if outcome.rejection_reason().is_some() {
+                            warn!("Bundle outcome: {}", outcome.describe());
+                        } else {
+                            info!("Bundle outcome: {}", outcome.describe());
+                        }
+                        crate::metrics::record_bundle_outcome(&outcome);
+                        (
+                            landed_through_rpc || outcome.landed(),
+                            matches!(outcome, BundleOutcome::Accepted { .. }),
+                            outcome.describe(),
+                        )
+                    }
                     Err(e) => {
-                        error!("Failed to get next scheduled leader: {:?}", e);
-                        continue;
+                        error!("Failed to send transaction: {:?}", e);
+                        (false, false, format!("{:?}", e))
                     }
                 };
+                if landed {
+                    LIQUIDATIONS_SUCCEEDED.inc_by(liquidations);
+                    // A bundle is only charged when it lands
+                    crate::spend_budget::record_spend(fees_lamports);
+                    record_landed(pnl_entries, fees_lamports);
+                } else if accepted {
+                    // Neither counted as a success nor as a failure, it may still land
+                    LIQUIDATIONS_UNCONFIRMED.inc_by(liquidations);
+                } else {
+                    LIQUIDATIONS_FAILED.inc_by(liquidations);
+                }
+                for (description, expected_profit_lamports, signature) in liquidation_alerts {
+                    crate::status::set_last_liquidation(
+                        description.clone(),
+                        signature.to_string(),
+                        expected_profit_lamports,
+                        landed,
+                    );
+                    if landed {
+                        notify(format!(
+                            "Liquidated {}, expected profit: {} lamports, signature: {}",
+                            description, expected_profit_lamports, signature
+                        ));
+                    } else {
+                        notify(format!(
+                            "Liquidation of {} failed: {}",
+                            description, outcome
+                        ));
+                    }
+                }
+                // The liquidated accounts can be evaluated again
+                for liquidation_guard in &liquidation_guards {
+                    liquidation_guard.record_outcome(landed);
+                }
+                drop(liquidation_guards);
+                for outcome in outcomes {
+                    outcome.send_replace(Some(landed));
+                }
+            });
+            crate::status::set_pending_bundles(in_flight.len());
+        }
 
-                let num_slots = next_leader.next_leader_slot - next_leader.current_slot;
+        info!("Waiting for {} in flight bundles", in_flight.len());
+        while in_flight.join_next().await.is_some() {}
+        info!("Transaction manager stopped");
+    }
 
-                if num_slots <= LEADERSHIP_THRESHOLD {
-                    debug!("Sending bundle");
-                    break;
-                }
+    /// Logs the costs of the transactions that would have been sent, used in dry run
+    /// mode. The estimates only depend on the batch and the config so runs over the
+    /// same accounts can be compared. The jito tip is paid by the last transaction
+    fn log_dry_run(&self, raw_transactions: &BatchTransactions) {
+        let bundle_tip_lamports = self.bundle_tip_lamports(raw_transactions, 0);
+        // Any entry of the batch values the lamports, the rebalancer swaps included
+        let lamport_price = raw_transactions
+            .iter()
+            .find_map(|raw_transaction| match raw_transaction.pnl.as_ref()? {
+                PnlEntry::Liquidation(liquidation) => Some(liquidation.lamport_price),
+                PnlEntry::Swap(swap) => Some(swap.lamport_price),
+            })
+            .unwrap_or(I80F48::ZERO);
+
+        for (index, raw_transaction) in raw_transactions.iter().enumerate() {
+            let compute_unit_limit = raw_transaction
+                .compute_unit_limit
+                .unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT);
+            let compute_unit_price = raw_transaction
+                .compute_unit_price_micro_lamports
+                .unwrap_or_default();
+            let priority_fee_lamports =
+                Self::raw_transaction_fees_lamports(raw_transaction) - SIGNATURE_FEE_LAMPORTS;
+            let tip_lamports = if index + 1 == raw_transactions.len() {
+                bundle_tip_lamports
+            } else {
+                0
+            };
+
+            let (gross_profit, swap_slippage) = match raw_transaction.pnl.as_ref() {
+                Some(PnlEntry::Liquidation(liquidation)) => (
+                    liquidation.collateral_value - liquidation.liability_value(),
+                    liquidation.expected_swap_slippage,
+                ),
+                Some(PnlEntry::Swap(swap)) => (I80F48::ZERO, swap.input_value - swap.output_value),
+                None => (I80F48::ZERO, I80F48::ZERO),
+            };
+            let fees =
+                I80F48::from_num(SIGNATURE_FEE_LAMPORTS + priority_fee_lamports + tip_lamports)
+                    * lamport_price;
+            let net_profit = gross_profit - swap_slippage - fees;
 
-                tokio::time::sleep(SLEEP_DURATION).await;
+            if self.dry_run_json {
+                let estimate = serde_json::json!({
+                    "description": raw_transaction.description,
+                    "compute_unit_limit": compute_unit_limit,
+                    "compute_unit_price_micro_lamports": compute_unit_price,
+                    "priority_fee_lamports": priority_fee_lamports,
+                    "signature_fee_lamports": SIGNATURE_FEE_LAMPORTS,
+                    "tip_lamports": tip_lamports,
+                    "expected_profit_lamports": raw_transaction.expected_profit_lamports,
+                    "gross_profit_usd": gross_profit.to_num::<f64>(),
+                    "swap_slippage_usd": swap_slippage.to_num::<f64>(),
+                    "fees_usd": fees.to_num::<f64>(),
+                    "net_profit_usd": net_profit.to_num::<f64>(),
+                });
+                info!("{}", estimate);
+            } else {
+                info!(
+                    "[Dry run] Skipping transaction {}: compute unit limit: {}, compute unit price: {} micro lamports, priority fee: {} lamports, signature fee: {} lamports, tip: {} lamports, gross profit: ${:.4}, swap slippage: ${:.4}, fees: ${:.4}, net profit: ${:.4}",
+                    raw_transaction.description.as_deref().unwrap_or("unknown"),
+                    compute_unit_limit,
+                    compute_unit_price,
+                    priority_fee_lamports,
+                    SIGNATURE_FEE_LAMPORTS,
+                    tip_lamports,
+                    gross_profit,
+                    swap_slippage,
+                    fees,
+                    net_profit
+                );
             }
-            let transaction = Self::send_transactions(
-                transactions,
-                self.searcher_client.clone(),
-                self.rpc.clone(),
+            debug!(
+                "[Dry run] Instructions: {:#?}",
+                raw_transaction.instructions
             );
-            tokio::spawn(async move {
-                if let Err(e) = transaction.await {
-                    error!("Failed to send transaction: {:?}", e);
+        }
+    }
+
+    /// Waits until the next jito leader is within the leadership threshold,
+    /// fails if no leader shows up within `max_leader_wait` or `MAX_LEADER_POLLS`.
+    /// The block engine errors are retried with an exponential backoff
+    async fn wait_for_leader(&mut self) -> anyhow::Result<()> {
+        let start = std::time::Instant::now();
+        let mut error_backoff = MIN_LEADER_POLL_INTERVAL;
+        for _ in 0..MAX_LEADER_POLLS {
+            if start.elapsed() > self.max_leader_wait {
+                return Err(anyhow::anyhow!(
+                    "No jito leader found within {:?}",
+                    self.max_leader_wait
+                ));
+            }
+
+            let searcher_client = self
+                .searcher_client
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("The jito block engine is unreachable"))?;
+            let next_leader = match searcher_client
+                .get_next_scheduled_leader(NextScheduledLeaderRequest {})
+                .await
+            {
+                Ok(response) => response.into_inner(),
+                Err(e) => {
+                    error!("Failed to get next scheduled leader: {:?}", e);
+                    tokio::time::sleep(with_jitter(error_backoff)).await;
+                    error_backoff = (error_backoff * 2).min(MAX_LEADER_POLL_INTERVAL);
+                    continue;
                 }
-            });
+            };
+            error_backoff = MIN_LEADER_POLL_INTERVAL;
+
+            let poll_interval =
+                match Self::is_leader_window(&next_leader, self.leader_slot_threshold) {
+                    Some(true) => {
+                        debug!("Sending bundle");
+                        return Ok(());
+                    }
+                    Some(false) => self.leader_poll_interval(&next_leader),
+                    None => {
+                        debug!("Jito leader slot already passed, waiting for the next one");
+                        MIN_LEADER_POLL_INTERVAL
+                    }
+                };
+
+            tokio::time::sleep(with_jitter(poll_interval)).await;
         }
+
+        Err(anyhow::anyhow!(
+            "No jito leader found within {} polls",
+            MAX_LEADER_POLLS
+        ))
     }
 
     /// Sends a transaction/bundle of transactions to the jito
-    /// block engine and waits for confirmation
+    /// block engine and waits for its outcome
     async fn send_transactions(
         transactions: Vec<VersionedTransaction>,
-        mut searcher_client: SearcherServiceClient<Channel>,
-        rpc: Arc<RpcClient>,
-    ) -> anyhow::Result<()> {
+        mut searcher_client: SearcherClient,
+    ) -> anyhow::Result<BundleOutcome> {
         let mut bundle_results_subscription = searcher_client
             .subscribe_bundle_results(SubscribeBundleResultsRequest {})
             .await?
             .into_inner();
 
-        if let Err(e) = send_bundle_with_confirmation(
-            &transactions,
-            &rpc,
-            &mut searcher_client,
-            &mut bundle_results_subscription,
-        )
-        .await
+        let bundle_id = searcher_client
+            .send_bundle(SendBundleRequest {
+                bundle: Some(Bundle {
+                    header: None,
+                    packets: transactions
+                        .iter()
+                        .map(proto_packet_from_versioned_tx)
+                        .collect(),
+                }),
+            })
+            .await?
+            .into_inner()
+            .uuid;
+
+        debug!("Sent bundle {}", bundle_id);
+
+        Self::wait_for_bundle_outcome(&bundle_id, &mut bundle_results_subscription).await
+    }
+
+    /// Sends the bundle to the jito block engine. When it is rejected for a transient
+    /// reason and retries are left, it is handed back to the transaction manager to be
+    /// rebuilt with a fresh blockhash, none is returned then. Otherwise when it doesn't
+    /// land and the rpc fallback is enabled, the same instructions are sent through the rpc,
+    /// whether they were all confirmed is returned along the bundle outcome
+    async fn send_transaction_with_fallback(
+        transactions: Vec<VersionedTransaction>,
+        searcher_client: SearcherClient,
+        rpc_fallback: Option<RpcFallback>,
+        retry: Option<BundleRetry>,
+        rpc: Arc<NonBlockingRpcClient>,
+    ) -> anyhow::Result<Option<(BundleOutcome, bool)>> {
+        let mut result = Self::send_transactions(transactions.clone(), searcher_client).await;
+
+        if let Ok(BundleOutcome::Rejected(Some(Reason::SimulationFailure(failure)))) = &result {
+            Self::log_simulation_failure(&rpc, &transactions, failure).await;
+        }
+
+        // The block engine doesn't always report the landing of an accepted bundle, and
+        // a bundle whose result timed out may have landed too. Its last transaction only
+        // lands along the others, a bundle which landed is never sent through the rpc
+        if matches!(result, Ok(BundleOutcome::Accepted { .. }) | Err(_)) {
+            if let Some(slot) = Self::landed_slot(&rpc, transactions.last()).await {
+                if let Err(e) = &result {
+                    warn!("The bundle landed in slot {} despite: {:?}", slot, e);
+                }
+                result = Ok(BundleOutcome::Landed { slot });
+            }
+        }
+
+        // An accepted bundle can still land, it isn't sent again
+        if matches!(&result, Ok(outcome) if outcome.landed())
+            || matches!(result, Ok(BundleOutcome::Accepted { .. }))
         {
-            return Err(anyhow::anyhow!("Failed to send transaction: {:?}", e));
+            return result.map(|outcome| Some((outcome, false)));
         }
 
-        Ok(())
+        if let (Ok(outcome), Some(retry)) = (&result, retry) {
+            if outcome.is_retryable() {
+                warn!(
+                    "Bundle outcome: {}, sending it again (retry {})",
+                    outcome.describe(),
+                    retry.attempt
+                );
+                crate::metrics::record_bundle_outcome(outcome);
+                tokio::time::sleep(retry.delay).await;
+                retry
+                    .retry_tx
+                    .send((retry.raw_transactions, retry.attempt))?;
+                return Ok(None);
+            }
+        }
+
+        let mut landed_through_rpc = false;
+        if let Some(rpc_fallback) = rpc_fallback {
+            warn!(
+                "Bundle didn't land ({}), falling back to the rpc",
+                match &result {
+                    Ok(outcome) => outcome.describe(),
+                    Err(e) => format!("{:?}", e),
+                }
+            );
+            let outcomes = tokio::task::spawn_blocking(move || rpc_fallback.send()).await??;
+            info!("Sent transactions through the rpc: {:?}", outcomes);
+            landed_through_rpc = outcomes.iter().all(SendOutcome::is_confirmed);
+        }
+
+        result.map(|outcome| Some((outcome, landed_through_rpc)))
+    }
+
+    /// Slot where the transaction landed, at the confirmed commitment, none
+    /// when it didn't land or its status can't be fetched
+    async fn landed_slot(
+        rpc: &NonBlockingRpcClient,
+        transaction: Option<&VersionedTransaction>,
+    ) -> Option<u64> {
+        let signature = transaction?.signatures[0];
+        let status = rpc
+            .get_signature_statuses(&[signature])
+            .await
+            .map_err(|e| {
+                warn!(
+                    "Failed to fetch the status of the transaction {}: {:?}",
+                    signature, e
+                )
+            })
+            .ok()?
+            .value
+            .pop()
+            .flatten()?;
+
+        (status.err.is_none() && status.satisfies_commitment(CommitmentConfig::confirmed()))
+            .then_some(status.slot)
+    }
+
+    /// Logs the program logs of the transaction whose simulation failed the bundle. The block
+    /// engine only reports its signature, so it is simulated again through the rpc, alone:
+    /// the logs can differ when it depends on the previous transactions of the bundle
+    async fn log_simulation_failure(
+        rpc: &NonBlockingRpcClient,
+        transactions: &[VersionedTransaction],
+        failure: &SimulationFailure,
+    ) {
+        let Some(transaction) = transactions
+            .iter()
+            .find(|transaction| transaction.signatures[0].to_string() == failure.tx_signature)
+        else {
+            warn!(
+                "The failed transaction {} isn't part of the bundle",
+                failure.tx_signature
+            );
+            return;
+        };
+
+        match rpc
+            .simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(CommitmentConfig::processed()),
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            Ok(simulation) => error!(
+                "Transaction {} failed the bundle simulation: {}, program logs:\n{}",
+                failure.tx_signature,
+                failure.msg.as_deref().unwrap_or("no message"),
+                simulation.value.logs.unwrap_or_default().join("\n")
+            ),
+            Err(e) => warn!(
+                "Failed to fetch the program logs of the transaction {}: {:?}",
+                failure.tx_signature, e
+            ),
+        }
+    }
+
+    /// Consumes the bundle results until a terminal outcome is received for the
+    /// given bundle, on timeout the last non terminal outcome is returned
+    async fn wait_for_bundle_outcome(
+        bundle_id: &str,
+        bundle_results_subscription: &mut Streaming<BundleResult>,
+    ) -> anyhow::Result<BundleOutcome> {
+        let start = std::time::Instant::now();
+        let mut last_outcome = None;
+
+        while let Some(remaining) = BUNDLE_RESULT_TIMEOUT.checked_sub(start.elapsed()) {
+            let bundle_result = match tokio::time::timeout(
+                remaining,
+                bundle_results_subscription.message(),
+            )
+            .await
+            {
+                Ok(Ok(Some(bundle_result))) => bundle_result,
+                Ok(Ok(None)) | Err(_) => break,
+                Ok(Err(e)) => return Err(anyhow::anyhow!("Bundle results stream failed: {:?}", e)),
+            };
+
+            if bundle_result.bundle_id != bundle_id {
+                continue;
+            }
+
+            if let Some(outcome) = bundle_result
+                .result
+                .and_then(BundleOutcome::from_bundle_result)
+            {
+                debug!("Bundle {} result: {:?}", bundle_id, outcome);
+                if outcome.is_terminal() {
+                    return Ok(outcome);
+                }
+                last_outcome = Some(outcome);
+            }
+        }
+
+        last_outcome
+            .ok_or_else(|| anyhow::anyhow!("Timed out waiting for the bundle {}", bundle_id))
     }
 
     /// Implements a alternative solution to jito transactions
     /// Sends a transaction to the network and waits for confirmation (non-jito)
     fn send_agressive_tx(&self, mut ixs: Vec<Instruction>) -> Result<Signature, Box<dyn Error>> {
-        let recent_blockhash = self.non_block_rpc.get_latest_blockhash()?;
+        let non_block_rpc = self.rpc_pool.rpc();
+        let recent_blockhash = non_block_rpc.get_latest_blockhash()?;
 
         ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(500_000));
 
@@ -209,7 +1531,7 @@ impl TransactionManager {
 
         let signature = *transaction.get_signature();
 
-        let simulation = self.non_block_rpc.simulate_transaction_with_config(
+        let simulation = non_block_rpc.simulate_transaction_with_config(
             &transaction,
             RpcSimulateTransactionConfig {
                 commitment: Some(CommitmentConfig::processed()),
@@ -222,77 +1544,357 @@ impl TransactionManager {
         }
 
         (0..12).try_for_each(|_| {
-            self.non_block_rpc.send_transaction(&transaction)?;
+            non_block_rpc.send_transaction(&transaction)?;
             Ok::<_, Box<dyn Error>>(())
         })?;
 
         let blockhash = transaction.get_recent_blockhash();
 
-        self.non_block_rpc.confirm_transaction_with_spinner(
-            &signature,
-            blockhash,
-            CommitmentConfig::confirmed(),
-        )?;
+        non_block_rpc.confirm_transaction_with_spinner(&signature, blockhash, self.commitment)?;
 
         Ok(signature)
     }
 
     /// Configures the instructions
-    /// Adds the compute budget instruction to each instruction
+    /// Adds the compute budget instruction to each instruction,
+    /// the jito tip to the last one of the bundle
     /// and compiles the instructions into transactions
     /// Returns a vector of transactions
     async fn configure_instructions(
         &self,
         instructions: BatchTransactions,
+        attempt: u64,
+        nonce: Option<(Pubkey, Hash)>,
     ) -> anyhow::Result<Vec<VersionedTransaction>> {
-        let blockhash = self.rpc.get_latest_blockhash().await?;
+        Self::check_bundle_limits(instructions.len())?;
 
+        let blockhash = match nonce {
+            Some((_, blockhash)) => blockhash,
+            None => {
+                self.rpc_pool
+                    .non_blocking_rpc()
+                    .get_latest_blockhash()
+                    .await?
+            }
+        };
+
+        // The tip is sized on the expected profit of the whole bundle
+        let expected_profit_lamports = instructions
+            .iter()
+            .filter_map(|raw_transaction| raw_transaction.expected_profit_lamports)
+            .reduce(u64::saturating_add);
+
+        let transactions_count = instructions.len();
         let mut txs = Vec::new();
-        for mut raw_transaction in instructions {
+        for (index, raw_transaction) in instructions.into_iter().enumerate() {
+            let description = raw_transaction.description.clone().unwrap_or_default();
             let mut ixs = raw_transaction.instructions;
-            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(1_000_000));
-            ixs.push(transfer(
-                &self.keypair.pubkey(),
-                &self.tip_accounts[0],
-                10_000,
+            // Advancing the nonce must be the first instruction of the transaction
+            if let Some((nonce_account, _)) = nonce {
+                ixs.insert(
+                    0,
+                    advance_nonce_account(&nonce_account, &self.keypair.pubkey()),
+                );
+            }
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(
+                raw_transaction
+                    .compute_unit_limit
+                    .unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT),
             ));
-            let transaction = VersionedTransaction::try_new(
-                VersionedMessage::V0(v0::Message::try_compile(
-                    &self.keypair.pubkey(),
-                    &ixs,
-                    if raw_transaction.lookup_tables.is_some() {
-                        raw_transaction.lookup_tables.as_ref().unwrap()
-                    } else {
-                        &self.lookup_tables
-                    },
-                    blockhash,
-                )?),
-                &[&self.keypair],
-            )?;
+            if let Some(price) = raw_transaction.compute_unit_price_micro_lamports {
+                ixs.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+            }
+            let lookup_tables = raw_transaction
+                .lookup_tables
+                .as_ref()
+                .unwrap_or(&self.lookup_tables);
+            let compile = |ixs: &[Instruction]| -> anyhow::Result<VersionedTransaction> {
+                Ok(VersionedTransaction::try_new(
+                    VersionedMessage::V0(v0::Message::try_compile(
+                        &self.keypair.pubkey(),
+                        ixs,
+                        lookup_tables,
+                        blockhash,
+                    )?),
+                    &[&self.keypair],
+                )?)
+            };
+
+            let transaction = if index == transactions_count - 1 {
+                let tip_lamports = self.tip_lamports(expected_profit_lamports, attempt);
+                let split_transaction = self
+                    .split_tip_instructions(tip_lamports)?
+                    .and_then(|tip_ixs| {
+                        compile(&[ixs.as_slice(), tip_ixs.as_slice()].concat())
+                            .ok()
+                            .map(|transaction| (transaction, tip_ixs.len()))
+                    })
+                    .filter(|(transaction, _)| Self::fits_in_transaction_limits(transaction));
+
+                match split_transaction {
+                    // The tip accounts of the split are only used up once it is kept
+                    Some((transaction, tip_accounts)) => {
+                        self.advance_tip_cursor(tip_accounts);
+                        transaction
+                    }
+                    // The tip falls back to a single transfer when it can't be split
+                    None => {
+                        ixs.push(transfer(
+                            &self.keypair.pubkey(),
+                            &self.next_tip_account()?,
+                            tip_lamports,
+                        ));
+                        compile(&ixs)?
+                    }
+                }
+            } else {
+                compile(&ixs)?
+            };
+            // An oversized transaction is rejected by the block engine without a reason
+            Self::check_transaction_limits(&transaction).map_err(|e| {
+                anyhow::anyhow!("Transaction {} of the bundle {}: {}", index, description, e)
+            })?;
             txs.push(transaction);
         }
         Ok(txs)
     }
 
+    /// Durable nonce account the bundle is built on, with its blockhash.
+    /// The nonce is advanced by the first transaction of a bundle, so
+    /// only the bundles of a single transaction can be built on it
+    async fn bundle_nonce(
+        &self,
+        transactions_count: usize,
+    ) -> anyhow::Result<Option<(Pubkey, Hash)>> {
+        match self.nonce_account.filter(|_| transactions_count == 1) {
+            Some(nonce_account) => Ok(Some((
+                nonce_account,
+                self.get_nonce_blockhash(&nonce_account).await?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Blockhash stored in the durable nonce account, valid until the nonce is advanced
+    async fn get_nonce_blockhash(&self, nonce_account: &Pubkey) -> anyhow::Result<Hash> {
+        let account = get_account_with_commitment(
+            &self.rpc_pool.non_blocking_rpc(),
+            nonce_account,
+            self.commitment,
+        )
+        .await?;
+        Ok(data_from_account(&account)?.blockhash())
+    }
+
+    /// Spreads the tip across `tip_split` distinct tip accounts, following the jito
+    /// recommendation against tip concentration. None when the tip isn't split,
+    /// or is too low for every transfer to reach the minimum tip.
+    /// The tip accounts are picked from the cursor without advancing it
+    fn split_tip_instructions(
+        &self,
+        tip_lamports: u64,
+    ) -> anyhow::Result<Option<Vec<Instruction>>> {
+        let split = self.tip_split.min(self.tip_accounts.len());
+        if split <= 1 || tip_lamports / (split as u64) < MIN_TIP_LAMPORTS {
+            return Ok(None);
+        }
+
+        let share = tip_lamports / split as u64;
+        let remainder = tip_lamports % split as u64;
+
+        (0..split)
+            .map(|index| {
+                let lamports = if index == 0 { share + remainder } else { share };
+                Ok(transfer(
+                    &self.keypair.pubkey(),
+                    &self.peek_tip_account(index)?,
+                    lamports,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    /// Whether the transaction fits in a packet and within the account locks limit
+    fn fits_in_transaction_limits(transaction: &VersionedTransaction) -> bool {
+        Self::check_transaction_limits(transaction).is_ok()
+    }
+
+    /// Fails with the size and the account count of the transaction when it doesn't
+    /// fit in a packet or exceeds the account locks limit
+    pub(crate) fn check_transaction_limits(
+        transaction: &VersionedTransaction,
+    ) -> anyhow::Result<()> {
+        let accounts = transaction.message.static_account_keys().len()
+            + transaction
+                .message
+                .address_table_lookups()
+                .unwrap_or_default()
+                .iter()
+                .map(|lookup| lookup.writable_indexes.len() + lookup.readonly_indexes.len())
+                .sum::<usize>();
+
+        let size = bincode::serialized_size(transaction)?;
+
+        if size > PACKET_DATA_SIZE as u64 {
+            return Err(anyhow::anyhow!(
+                "{} bytes serialized, over the {} bytes packet limit, \
+                 its accounts should be moved to an address lookup table",
+                size,
+                PACKET_DATA_SIZE
+            ));
+        }
+        if accounts > MAX_ACCOUNT_LOCKS {
+            return Err(anyhow::anyhow!(
+                "{} accounts, over the limit of {} account locks",
+                accounts,
+                MAX_ACCOUNT_LOCKS
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fails when the bundle is empty or holds more transactions than the block engine accepts
+    fn check_bundle_limits(transactions_count: usize) -> anyhow::Result<()> {
+        if transactions_count == 0 {
+            return Err(anyhow::anyhow!("The bundle has no transaction"));
+        }
+        if transactions_count > MAX_BUNDLE_TRANSACTIONS {
+            return Err(anyhow::anyhow!(
+                "{} transactions, over the limit of {} transactions in a bundle",
+                transactions_count,
+                MAX_BUNDLE_TRANSACTIONS
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fees paid by a bundle once landed, in lamports: the jito tip,
+    /// the signature fees and the priority fees of its transactions
+    fn bundle_fees_lamports(&self, instructions: &BatchTransactions, attempt: u64) -> u64 {
+        self.bundle_tip_lamports(instructions, attempt)
+            + Self::transaction_fees_lamports(instructions)
+    }
+
+    /// Jito tip of a bundle, sized on the expected profit of its liquidations
+    fn bundle_tip_lamports(&self, instructions: &BatchTransactions, attempt: u64) -> u64 {
+        let expected_profit_lamports = instructions
+            .iter()
+            .filter_map(|raw_transaction| raw_transaction.expected_profit_lamports)
+            .reduce(u64::saturating_add);
+
+        self.tip_lamports(expected_profit_lamports, attempt)
+    }
+
+    /// Signature and priority fees of the transactions, without the jito tip
+    fn transaction_fees_lamports(instructions: &BatchTransactions) -> u64 {
+        instructions
+            .iter()
+            .map(Self::raw_transaction_fees_lamports)
+            .sum::<u64>()
+    }
+
+    /// Signature and priority fees of a transaction
+    pub fn raw_transaction_fees_lamports(raw_transaction: &RawTransaction) -> u64 {
+        let compute_unit_limit = raw_transaction
+            .compute_unit_limit
+            .unwrap_or(DEFAULT_COMPUTE_UNIT_LIMIT) as u64;
+        let priority_fees_lamports = raw_transaction
+            .compute_unit_price_micro_lamports
+            .unwrap_or_default()
+            .saturating_mul(compute_unit_limit)
+            / 1_000_000;
+
+        SIGNATURE_FEE_LAMPORTS + priority_fees_lamports
+    }
+
+    /// Tip of the bundle, raised by `retry_tip_bump_bps` on each of its retries
+    fn tip_lamports(&self, expected_profit_lamports: Option<u64>, attempt: u64) -> u64 {
+        let tip_lamports = self.tip_strategy.tip_lamports(expected_profit_lamports);
+        let bump_bps = (self.retry_tip_bump_bps as u64).saturating_mul(attempt);
+
+        tip_lamports.saturating_add(tip_lamports.saturating_mul(bump_bps) / 10_000)
+    }
+
     /// Listen for the next leader and update the AtomicBool accordingly
     async fn listen_for_leader(&mut self) -> anyhow::Result<()> {
         loop {
             let next_leader = self
                 .searcher_client
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("The jito block engine is unreachable"))?
                 .get_next_scheduled_leader(NextScheduledLeaderRequest {})
                 .await?
                 .into_inner();
 
-            let num_slots = next_leader.next_leader_slot - next_leader.current_slot;
-
+            let is_leader_window = Self::is_leader_window(&next_leader, self.leader_slot_threshold);
             self.is_jito_leader
-                .store(num_slots <= LEADERSHIP_THRESHOLD, Ordering::Relaxed);
+                .store(is_leader_window.unwrap_or(false), Ordering::Relaxed);
+
+            let poll_interval = match is_leader_window {
+                Some(false) => self.leader_poll_interval(&next_leader),
+                _ => MIN_LEADER_POLL_INTERVAL,
+            };
+            tokio::time::sleep(with_jitter(poll_interval)).await;
         }
     }
 
-    async fn get_tip_accounts(
-        searcher_client: &mut SearcherServiceClient<Channel>,
-    ) -> anyhow::Result<Vec<Pubkey>> {
+    /// Wait before polling the next leader again, half of the time left until
+    /// the leader window opens, so the polls tighten as the window nears
+    fn leader_poll_interval(
+        &self,
+        next_leader: &NextScheduledLeaderResponse,
+    ) -> std::time::Duration {
+        let slots_to_window = next_leader
+            .next_leader_slot
+            .saturating_sub(next_leader.current_slot)
+            .saturating_sub(self.leader_slot_threshold);
+
+        (SLOT_DURATION.saturating_mul(slots_to_window.try_into().unwrap_or(u32::MAX)) / 2)
+            .clamp(MIN_LEADER_POLL_INTERVAL, MAX_LEADER_POLL_INTERVAL)
+    }
+
+    /// Whether the next jito leader is at most `leader_slot_threshold` slots away,
+    /// none when its slot is already behind the current one, which a stale or
+    /// skewed response can report, the next leader has to be fetched again then
+    fn is_leader_window(
+        next_leader: &NextScheduledLeaderResponse,
+        leader_slot_threshold: u64,
+    ) -> Option<bool> {
+        let num_slots = next_leader
+            .next_leader_slot
+            .checked_sub(next_leader.current_slot)?;
+
+        Some(num_slots <= leader_slot_threshold)
+    }
+
+    /// Picks the next tip account in a round-robin fashion, so the tips
+    /// are not concentrated on a single account
+    fn next_tip_account(&self) -> anyhow::Result<Pubkey> {
+        let tip_account = self.peek_tip_account(0)?;
+        self.advance_tip_cursor(1);
+
+        Ok(tip_account)
+    }
+
+    /// Tip account `offset` positions after the cursor, which isn't advanced
+    fn peek_tip_account(&self, offset: usize) -> anyhow::Result<Pubkey> {
+        if self.tip_accounts.is_empty() {
+            return Err(anyhow::anyhow!("No jito tip accounts available"));
+        }
+
+        let index = self.tip_account_cursor.load(Ordering::Relaxed) + offset;
+
+        Ok(self.tip_accounts[index % self.tip_accounts.len()])
+    }
+
+    fn advance_tip_cursor(&self, count: usize) {
+        self.tip_account_cursor.fetch_add(count, Ordering::Relaxed);
+    }
+
+    async fn get_tip_accounts(searcher_client: &mut SearcherClient) -> anyhow::Result<Vec<Pubkey>> {
         let tip_accounts = searcher_client
             .get_tip_accounts(GetTipAccountsRequest {})
             .await?
@@ -307,3 +1909,49 @@ impl TransactionManager {
         Ok(tip_accounts)
     }
 }
+
+/// Adds a random delay of up to `LEADER_POLL_JITTER` to the wait
+fn with_jitter(wait: std::time::Duration) -> std::time::Duration {
+    wait + LEADER_POLL_JITTER.mul_f64(rand::random::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next_leader(current_slot: u64, next_leader_slot: u64) -> NextScheduledLeaderResponse {
+        NextScheduledLeaderResponse {
+            current_slot,
+            next_leader_slot,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn leader_behind_the_current_slot() {
+        assert_eq!(
+            TransactionManager::is_leader_window(&next_leader(1_000, 999), 5),
+            None
+        );
+    }
+
+    #[test]
+    fn leader_within_the_window() {
+        assert_eq!(
+            TransactionManager::is_leader_window(&next_leader(1_000, 1_000), 5),
+            Some(true)
+        );
+        assert_eq!(
+            TransactionManager::is_leader_window(&next_leader(1_000, 1_005), 5),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn leader_beyond_the_window() {
+        assert_eq!(
+            TransactionManager::is_leader_window(&next_leader(1_000, 1_006), 5),
+            Some(false)
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use crate::{
+    pnl::PnlEntry,
+    rpc_pool::RpcPool,
+    utils::{from_vec_str_to_pubkey, vec_pubkey_to_str},
+};
+use fixed::types::I80F48;
+use log::{error, warn};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+
+/// Interval between two polls of the pending signature statuses
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time after which a signature still unknown to the rpc is recorded as expired,
+/// its blockhash can't be valid anymore
+const PENDING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Maximum number of signatures accepted by `getSignatureStatuses`
+const MAX_SIGNATURES_PER_REQUEST: usize = 256;
+
+static TX_LOG_TX: OnceLock<UnboundedSender<(Signature, TxMetadata)>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxType {
+    Liquidate,
+    Deposit,
+    Repay,
+    Withdraw,
+    Swap,
+    #[default]
+    Other,
+}
+
+/// What a transaction does, recorded along with its signature
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TxMetadata {
+    pub tx_type: TxType,
+    #[serde(
+        serialize_with = "vec_pubkey_to_str",
+        deserialize_with = "from_vec_str_to_pubkey"
+    )]
+    pub banks: Vec<Pubkey>,
+    /// Amount of tokens moved, in native units
+    pub amount: Option<u64>,
+    /// Costs and value of the transaction, set when it is submitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accounting: Option<TxAccounting>,
+}
+
+impl TxMetadata {
+    pub fn new(tx_type: TxType, banks: Vec<Pubkey>, amount: Option<u64>) -> Self {
+        Self {
+            tx_type,
+            banks,
+            amount,
+            accounting: None,
+        }
+    }
+
+    pub fn with_accounting(mut self, accounting: TxAccounting) -> Self {
+        self.accounting = Some(accounting);
+        self
+    }
+}
+
+/// Costs and value of a transaction, at the oracle prices it was built with
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TxAccounting {
+    /// Signature and priority fees
+    pub fees_lamports: u64,
+    /// Jito tip, paid by the last transaction of a bundle
+    pub tip_lamports: u64,
+    /// Oracle price of the seized or sold asset, in USD per token
+    pub asset_price_usd: Option<f64>,
+    /// Value of the seized or sold asset, in USD
+    pub asset_value_usd: Option<f64>,
+    /// Value of the liability taken over or of the tokens bought, in USD
+    pub counter_value_usd: Option<f64>,
+    /// Fees and tip, in USD
+    pub fees_usd: Option<f64>,
+    /// Profit of the transaction net of its fees and tip, in USD
+    pub pnl_usd: Option<f64>,
+}
+
+impl TxAccounting {
+    pub fn new(pnl: Option<&PnlEntry>, fees_lamports: u64, tip_lamports: u64) -> Self {
+        let mut accounting = Self {
+            fees_lamports,
+            tip_lamports,
+            ..Default::default()
+        };
+
+        // A liquidation earns the liquidator fee, a swap loses its slippage
+        let (asset_price, asset_value, counter_value, gross_profit, lamport_price) = match pnl {
+            Some(PnlEntry::Liquidation(liquidation)) => (
+                liquidation.asset_price,
+                liquidation.collateral_value,
+                liquidation.liability_value(),
+                liquidation.collateral_value - liquidation.liability_value(),
+                liquidation.lamport_price,
+            ),
+            Some(PnlEntry::Swap(swap)) => (
+                swap.input_price,
+                swap.input_value,
+                swap.output_value,
+                swap.output_value - swap.input_value,
+                swap.lamport_price,
+            ),
+            None => return accounting,
+        };
+        let fees = I80F48::from_num(fees_lamports.saturating_add(tip_lamports)) * lamport_price;
+
+        accounting.asset_price_usd = Some(asset_price.to_num());
+        accounting.asset_value_usd = Some(asset_value.to_num());
+        accounting.counter_value_usd = Some(counter_value.to_num());
+        accounting.fees_usd = Some(fees.to_num());
+        accounting.pnl_usd = Some((gross_profit - fees).to_num());
+        accounting
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum TxLogRecord {
+    Submitted {
+        signature: String,
+        timestamp: u64,
+        #[serde(flatten)]
+        metadata: TxMetadata,
+    },
+    Confirmed {
+        signature: String,
+        timestamp: u64,
+        slot: u64,
+    },
+    Failed {
+        signature: String,
+        timestamp: u64,
+        slot: u64,
+        error: String,
+    },
+    Expired {
+        signature: String,
+        timestamp: u64,
+    },
+}
+
+/// Records a submitted transaction, dropped when the log is disabled
+pub fn log_submission(signature: Signature, metadata: TxMetadata) {
+    if let Some(tx) = TX_LOG_TX.get() {
+        let _ = tx.send((signature, metadata));
+    }
+}
+
+/// Appends the submitted transactions to the JSONL file at `path`, followed by
+/// their outcome once the rpc reports it, until cancelled
+pub async fn serve_tx_log(
+    path: PathBuf,
+    rpc_pool: Arc<RpcPool>,
+    cancellation_token: CancellationToken,
+) {
+    let mut writer = match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            error!("Failed to open the transaction log {:?}: {:?}", path, e);
+            return;
+        }
+    };
+
+    let (tx, mut rx) = unbounded_channel();
+    if TX_LOG_TX.set(tx).is_err() {
+        warn!("The transaction log is already served");
+        return;
+    }
+
+    let mut pending: HashMap<Signature, Instant> = HashMap::new();
+    let mut poll_interval = tokio::time::interval(STATUS_POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => return,
+            Some((signature, metadata)) = rx.recv() => {
+                write_record(
+                    &mut writer,
+                    &TxLogRecord::Submitted {
+                        signature: signature.to_string(),
+                        timestamp: now(),
+                        metadata,
+                    },
+                );
+                pending.insert(signature, Instant::now());
+            }
+            _ = poll_interval.tick() => {
+                if let Err(e) = poll_statuses(&mut writer, &rpc_pool, &mut pending).await {
+                    warn!("Failed to poll the transaction statuses: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Records the outcome of the pending signatures the rpc confirmed or failed,
+/// and the expiry of the ones it never saw
+async fn poll_statuses(
+    writer: &mut BufWriter<File>,
+    rpc_pool: &RpcPool,
+    pending: &mut HashMap<Signature, Instant>,
+) -> anyhow::Result<()> {
+    let signatures = pending.keys().copied().collect::<Vec<_>>();
+    for chunk in signatures.chunks(MAX_SIGNATURES_PER_REQUEST) {
+        let statuses = rpc_pool
+            .non_blocking_rpc()
+            .get_signature_statuses(chunk)
+            .await?
+            .value;
+
+        for (signature, status) in chunk.iter().zip(statuses) {
+            let record = match status {
+                Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => {
+                    match status.err {
+                        None => TxLogRecord::Confirmed {
+                            signature: signature.to_string(),
+                            timestamp: now(),
+                            slot: status.slot,
+                        },
+                        Some(err) => TxLogRecord::Failed {
+                            signature: signature.to_string(),
+                            timestamp: now(),
+                            slot: status.slot,
+                            error: err.to_string(),
+                        },
+                    }
+                }
+                Some(_) => continue,
+                None if pending[signature].elapsed() > PENDING_TIMEOUT => TxLogRecord::Expired {
+                    signature: signature.to_string(),
+                    timestamp: now(),
+                },
+                None => continue,
+            };
+
+            write_record(writer, &record);
+            pending.remove(signature);
+        }
+    }
+
+    Ok(())
+}
+
+/// Columns of the CSV export of the transaction log
+const CSV_HEADER: &str = "timestamp,signature,tx_type,banks,amount,slot,asset_price_usd,asset_value_usd,counter_value_usd,fees_lamports,tip_lamports,fees_usd,pnl_usd";
+
+/// Writes a CSV row for each transaction of the log at `path` confirmed between the
+/// `from` and `to` unix timestamps, along with what was recorded when it was submitted.
+/// Returns the number of rows written
+pub fn export_csv(
+    path: &Path,
+    from: u64,
+    to: u64,
+    writer: &mut impl Write,
+) -> anyhow::Result<usize> {
+    let file = File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open the transaction log {:?}: {}", path, e))?;
+
+    writeln!(writer, "{}", CSV_HEADER)?;
+
+    let mut submitted: HashMap<String, TxMetadata> = HashMap::new();
+    let mut rows = 0;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // A line cut short by a crash doesn't prevent the export of the others
+        let record = match serde_json::from_str::<TxLogRecord>(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!(
+                    "Skipping line {} of the transaction log: {:?}",
+                    index + 1,
+                    e
+                );
+                continue;
+            }
+        };
+
+        match record {
+            TxLogRecord::Submitted {
+                signature,
+                metadata,
+                ..
+            } => {
+                submitted.insert(signature, metadata);
+            }
+            TxLogRecord::Confirmed {
+                signature,
+                timestamp,
+                slot,
+            } => {
+                let Some(metadata) = submitted.remove(&signature) else {
+                    continue;
+                };
+                if timestamp < from || timestamp > to {
+                    continue;
+                }
+                write_csv_row(writer, &signature, timestamp, slot, &metadata)?;
+                rows += 1;
+            }
+            TxLogRecord::Failed { signature, .. } | TxLogRecord::Expired { signature, .. } => {
+                submitted.remove(&signature);
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(rows)
+}
+
+fn write_csv_row(
+    writer: &mut impl Write,
+    signature: &str,
+    timestamp: u64,
+    slot: u64,
+    metadata: &TxMetadata,
+) -> anyhow::Result<()> {
+    // Transactions logged before the accounting was recorded leave its columns empty
+    let accounting = metadata.accounting.as_ref();
+    let column = |value: Option<String>| value.unwrap_or_default();
+    let usd = |value: fn(&TxAccounting) -> Option<f64>| {
+        column(accounting.and_then(value).map(|value| value.to_string()))
+    };
+    let tx_type = serde_json::to_value(metadata.tx_type)?;
+
+    let row = [
+        chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .map(|datetime| datetime.to_rfc3339())
+            .unwrap_or_default(),
+        signature.to_string(),
+        tx_type.as_str().unwrap_or_default().to_string(),
+        // The banks are separated by semicolons, a comma would split the column
+        metadata
+            .banks
+            .iter()
+            .map(|bank| bank.to_string())
+            .collect::<Vec<_>>()
+            .join(";"),
+        metadata
+            .amount
+            .map(|amount| amount.to_string())
+            .unwrap_or_default(),
+        slot.to_string(),
+        usd(|accounting| accounting.asset_price_usd),
+        usd(|accounting| accounting.asset_value_usd),
+        usd(|accounting| accounting.counter_value_usd),
+        column(accounting.map(|accounting| accounting.fees_lamports.to_string())),
+        column(accounting.map(|accounting| accounting.tip_lamports.to_string())),
+        usd(|accounting| accounting.fees_usd),
+        usd(|accounting| accounting.pnl_usd),
+    ];
+
+    writeln!(writer, "{}", row.join(","))?;
+
+    Ok(())
+}
+
+fn write_record(writer: &mut BufWriter<File>, record: &TxLogRecord) {
+    let result = serde_json::to_string(record)
+        .map_err(anyhow::Error::from)
+        .and_then(|line| {
+            writeln!(writer, "{}", line)?;
+            writer.flush()?;
+            Ok(())
+        });
+
+    if let Err(e) = result {
+        error!("Failed to write to the transaction log: {:?}", e);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
@@ -1,20 +1,30 @@
+use anchor_lang::Discriminator;
 use anyhow::{anyhow, Result};
 use backoff::ExponentialBackoff;
 use fixed::types::I80F48;
 use marginfi::{
     bank_authority_seed, bank_seed,
-    constants::{PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID, PYTH_PUSH_PYTH_SPONSORED_SHARD_ID},
+    constants::{
+        EMISSIONS_AUTH_SEED, EMISSIONS_TOKEN_ACCOUNT_SEED, EXP_10_I80F48,
+        PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID, PYTH_PUSH_PYTH_SPONSORED_SHARD_ID,
+    },
     prelude::MarginfiResult,
     state::{
-        marginfi_account::{calc_value, Balance, BalanceSide, LendingAccount, RequirementType},
+        marginfi_account::{
+            calc_value, Balance, BalanceSide, LendingAccount, MarginfiAccount, RequirementType,
+        },
         marginfi_group::{Bank, BankConfig, BankVaultType, RiskTier},
-        price::{PriceBias, PythPushOraclePriceFeed},
+        price::{OraclePriceType, OracleSetup, PriceBias, PythPushOraclePriceFeed},
     },
 };
 use rayon::{iter::ParallelIterator, slice::ParallelSlice};
-use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serializer};
-use solana_account_decoder::UiAccountEncoding;
-use solana_client::rpc_config::RpcAccountInfoConfig;
+use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+};
 use solana_program::pubkey::Pubkey;
 use solana_sdk::{
     account::Account,
@@ -192,6 +202,15 @@ where
     Pubkey::from_str(&s).map_err(serde::de::Error::custom)
 }
 
+pub(crate) fn from_option_pubkey_string<'de, D>(deserializer: D) -> Result<Option<Pubkey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    s.map(|s| Pubkey::from_str(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
 pub(crate) fn from_option_vec_pubkey_string<'de, D>(
     deserializer: D,
 ) -> Result<Option<Vec<Pubkey>>, D::Error>
@@ -233,6 +252,16 @@ where
     serializer.serialize_str(&p.to_string())
 }
 
+pub(crate) fn option_pubkey_to_str<S>(p: &Option<Pubkey>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match p {
+        Some(pubkey) => serializer.serialize_str(&pubkey.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
 // TODO: The next functions can be done better
 
 pub(crate) fn vec_pubkey_to_str<S>(ps: &Vec<Pubkey>, serializer: S) -> Result<S::Ok, S::Error>
@@ -277,6 +306,38 @@ where
         .collect()
 }
 
+pub(crate) fn pubkey_map_to_str_map<S, V>(
+    map: &HashMap<Pubkey, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    serializer.collect_map(
+        map.iter()
+            .map(|(pubkey, value)| (pubkey.to_string(), value)),
+    )
+}
+
+pub(crate) fn from_str_map_to_pubkey_map<'de, D, V>(
+    deserializer: D,
+) -> Result<HashMap<Pubkey, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    let s: HashMap<String, V> = Deserialize::deserialize(deserializer)?;
+    s.into_iter()
+        .map(|(s, value)| {
+            Ok((
+                Pubkey::from_str(&s).map_err(serde::de::Error::custom)?,
+                value,
+            ))
+        })
+        .collect()
+}
+
 pub struct BankAccountWithPriceFeedEva<'a> {
     bank: BankWrapper,
     balance: &'a Balance,
@@ -415,6 +476,36 @@ pub fn find_bank_vault_authority_pda(
     Pubkey::find_program_address(bank_authority_seed!(vault_type, bank_pk), program_id)
 }
 
+pub fn find_emissions_auth_pda(
+    bank_pk: &Pubkey,
+    emissions_mint: &Pubkey,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            EMISSIONS_AUTH_SEED.as_bytes(),
+            bank_pk.as_ref(),
+            emissions_mint.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+pub fn find_emissions_vault_pda(
+    bank_pk: &Pubkey,
+    emissions_mint: &Pubkey,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            EMISSIONS_TOKEN_ACCOUNT_SEED.as_bytes(),
+            bank_pk.as_ref(),
+            emissions_mint.as_ref(),
+        ],
+        program_id,
+    )
+}
+
 pub fn calc_weighted_assets_new(
     bank: &BankWrapper,
     amount: I80F48,
@@ -553,6 +644,34 @@ pub fn calc_weighted_liabs(
     )?)
 }
 
+/// Returns the price of SOL in USD, taken from the bank of the native mint
+pub fn get_sol_price(banks: &HashMap<Pubkey, BankWrapper>) -> Option<I80F48> {
+    let sol_bank = banks
+        .values()
+        .find(|bank| bank.bank.mint == spl_token::native_mint::ID)?;
+
+    let sol_price = sol_bank
+        .oracle_adapter
+        .get_price_of_type(OraclePriceType::RealTime, None)
+        .ok()?;
+
+    if sol_price.is_zero() {
+        return None;
+    }
+
+    Some(sol_price)
+}
+
+/// Converts a USD value into lamports, using the price of the SOL bank
+pub fn usd_to_lamports(value: I80F48, banks: &HashMap<Pubkey, BankWrapper>) -> Option<u64> {
+    (value / get_sol_price(banks)? * EXP_10_I80F48[9]).checked_to_num()
+}
+
+/// Converts lamports into a USD value, using the price of the SOL bank
+pub fn lamports_to_usd(lamports: u64, banks: &HashMap<Pubkey, BankWrapper>) -> Option<I80F48> {
+    Some(I80F48::from_num(lamports) / EXP_10_I80F48[9] * get_sol_price(banks)?)
+}
+
 pub fn find_oracle_keys(bank_config: &BankConfig) -> Vec<Pubkey> {
     match bank_config.oracle_setup {
         marginfi::state::price::OracleSetup::PythPushOracle => {
@@ -574,6 +693,61 @@ pub fn find_oracle_keys(bank_config: &BankConfig) -> Vec<Pubkey> {
     }
 }
 
+/// Offset of the aggregate publish slot in a pyth legacy price account
+const PYTH_LEGACY_PUB_SLOT_OFFSET: usize = 232;
+
+/// Offset of the latest confirmed round open slot in a switchboard v2 aggregator account
+const SWITCHBOARD_V2_ROUND_OPEN_SLOT_OFFSET: usize = 350;
+
+/// Offset of the verification level in a pyth push oracle price update account
+const PYTH_PUSH_VERIFICATION_LEVEL_OFFSET: usize = 8 + 32;
+
+/// Size of the price message in a pyth push oracle price update account
+const PYTH_PUSH_PRICE_MESSAGE_SIZE: usize = 32 + 8 + 8 + 4 + 8 + 8 + 8 + 8;
+
+/// Offset of the publish time in the price message of a pyth push oracle
+const PYTH_PUSH_PUBLISH_TIME_OFFSET: usize = 32 + 8 + 8 + 4;
+
+/// Reads the slot at which the oracle price was published from the raw oracle account
+pub fn get_oracle_publish_slot(oracle_setup: OracleSetup, data: &[u8]) -> Option<u64> {
+    let read_u64 = |offset: usize| {
+        data.get(offset..offset + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    match oracle_setup {
+        OracleSetup::PythLegacy => read_u64(PYTH_LEGACY_PUB_SLOT_OFFSET),
+        OracleSetup::SwitchboardV2 => read_u64(SWITCHBOARD_V2_ROUND_OPEN_SLOT_OFFSET),
+        OracleSetup::PythPushOracle => {
+            read_u64(pyth_push_price_message_offset(data)? + PYTH_PUSH_PRICE_MESSAGE_SIZE)
+        }
+        OracleSetup::SwitchboardPull => {
+            let mut offsets_data = [0u8; std::mem::size_of::<PullFeedAccountData>()];
+            offsets_data
+                .copy_from_slice(data.get(8..std::mem::size_of::<PullFeedAccountData>() + 8)?);
+            let swb_feed = load_swb_pull_account_from_bytes(&offsets_data).ok()?;
+            Some(swb_feed.result.slot)
+        }
+        _ => None,
+    }
+}
+
+/// Reads the unix time at which the price of a pyth push oracle was published
+/// from the raw price update account
+pub fn get_pyth_push_publish_time(data: &[u8]) -> Option<i64> {
+    let offset = pyth_push_price_message_offset(data)? + PYTH_PUSH_PUBLISH_TIME_OFFSET;
+    data.get(offset..offset + 8)
+        .map(|bytes| i64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn pyth_push_price_message_offset(data: &[u8]) -> Option<usize> {
+    // The partial verification level carries the number of signatures
+    match data.get(PYTH_PUSH_VERIFICATION_LEVEL_OFFSET)? {
+        0 => Some(PYTH_PUSH_VERIFICATION_LEVEL_OFFSET + 2),
+        _ => Some(PYTH_PUSH_VERIFICATION_LEVEL_OFFSET + 1),
+    }
+}
+
 pub fn load_swb_pull_account(account_info: &AccountInfo) -> anyhow::Result<PullFeedAccountData> {
     let bytes = &account_info.data.borrow().to_vec()[8..std::mem::size_of::<PullFeedAccountData>()];
 
@@ -639,3 +813,63 @@ pub fn ask_keypair_until_valid() -> anyhow::Result<(PathBuf, Keypair)> {
         }
     }
 }
+
+/// Marginfi accounts of the authority in the group, sorted by address
+/// so their order doesn't change between two calls
+pub fn marginfi_accounts_by_authority(
+    rpc_client: &RpcClient,
+    marginfi_program_id: &Pubkey,
+    marginfi_group: &Pubkey,
+    authority: &Pubkey,
+) -> anyhow::Result<Vec<Pubkey>> {
+    let marginfi_account_address = rpc_client.get_program_accounts_with_config(
+        marginfi_program_id,
+        RpcProgramAccountsConfig {
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                data_slice: Some(UiDataSliceConfig {
+                    offset: 0,
+                    length: 0,
+                }),
+                ..Default::default()
+            },
+            filters: Some(vec![
+                #[allow(deprecated)]
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 0,
+                    #[allow(deprecated)]
+                    bytes: MemcmpEncodedBytes::Base58(
+                        bs58::encode(MarginfiAccount::DISCRIMINATOR).into_string(),
+                    ),
+                    #[allow(deprecated)]
+                    encoding: None,
+                }),
+                #[allow(deprecated)]
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 8,
+                    #[allow(deprecated)]
+                    bytes: MemcmpEncodedBytes::Base58(marginfi_group.to_string()),
+                    #[allow(deprecated)]
+                    encoding: None,
+                }),
+                #[allow(deprecated)]
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 8 + 32,
+                    #[allow(deprecated)]
+                    bytes: MemcmpEncodedBytes::Base58(authority.to_string()),
+                    #[allow(deprecated)]
+                    encoding: None,
+                }),
+            ]),
+            with_context: Some(false),
+        },
+    )?;
+
+    let mut marginfi_account_pubkeys: Vec<Pubkey> = marginfi_account_address
+        .iter()
+        .map(|(pubkey, _)| *pubkey)
+        .collect();
+    marginfi_account_pubkeys.sort();
+
+    Ok(marginfi_account_pubkeys)
+}
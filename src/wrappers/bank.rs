@@ -1,25 +1,122 @@
 use super::oracle::OracleWrapper;
+use crate::pyth::FeedId;
 use fixed::types::I80F48;
-use marginfi::state::{
-    marginfi_account::{calc_amount, calc_value, BalanceSide, RequirementType},
-    marginfi_group::Bank,
-    price::{OraclePriceType, PriceAdapter, PriceBias},
+use marginfi::{
+    constants::{PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID, PYTH_PUSH_PYTH_SPONSORED_SHARD_ID},
+    state::{
+        marginfi_account::{calc_amount, calc_value, BalanceSide, RequirementType},
+        marginfi_group::{Bank, BankOperationalState},
+        price::{OraclePriceType, OracleSetup, PriceAdapter, PriceBias, PythPushOraclePriceFeed},
+    },
 };
 use solana_program::pubkey::Pubkey;
 
+/// Update posted ahead of the transactions reading a pull oracle,
+/// marginfi rejects their price as stale otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OracleUpdate {
+    /// Price update of a pyth pull oracle (shard and feed id), posted from hermes
+    PythPull(u16, FeedId),
+    /// Crank of a switchboard on-demand feed, fetched from its gateway
+    SwitchboardPull(Pubkey),
+}
+
 #[derive(Clone)]
 pub struct BankWrapper {
     pub address: Pubkey,
     pub bank: Bank,
     pub oracle_adapter: OracleWrapper,
+    /// Token program owning the bank mint (spl-token or token-2022)
+    pub token_program: Pubkey,
 }
 
 impl BankWrapper {
-    pub fn new(address: Pubkey, bank: Bank, oracle_adapter_wrapper: OracleWrapper) -> Self {
+    pub fn new(
+        address: Pubkey,
+        bank: Bank,
+        oracle_adapter_wrapper: OracleWrapper,
+        token_program: Pubkey,
+    ) -> Self {
         Self {
             address,
             bank,
             oracle_adapter: oracle_adapter_wrapper,
+            token_program,
+        }
+    }
+
+    /// Whether the bank accepts operations raising the deposits or the liabilities
+    /// of an account: deposits, borrows and seizing collateral in a liquidation.
+    /// Reduce-only and paused banks reject them
+    pub fn accepts_increases(&self) -> bool {
+        matches!(
+            self.bank.config.operational_state,
+            BankOperationalState::Operational
+        )
+    }
+
+    /// Whether the bank is paused, rejecting every operation, withdrawals and repayments included
+    pub fn is_paused(&self) -> bool {
+        matches!(
+            self.bank.config.operational_state,
+            BankOperationalState::Paused
+        )
+    }
+
+    /// Whether the oracle price is older than `max_age_slots`, marginfi rejects
+    /// liquidations against banks with stale oracles. Pull oracles are updated
+    /// in the liquidation bundle, so they are never stale
+    pub fn is_oracle_stale(&self, current_slot: u64, max_age_slots: u64) -> bool {
+        if matches!(
+            self.bank.config.oracle_setup,
+            OracleSetup::SwitchboardPull | OracleSetup::PythPushOracle
+        ) {
+            return false;
+        }
+
+        self.oracle_adapter
+            .publish_slot
+            .map_or(false, |publish_slot| {
+                current_slot.saturating_sub(publish_slot) > max_age_slots
+            })
+    }
+
+    /// Returns the shard and feed id of the bank pyth pull oracle, which has to be
+    /// updated in the liquidation bundle
+    pub fn get_pyth_push_oracle_feed(&self) -> Option<(u16, FeedId)> {
+        if !matches!(self.bank.config.oracle_setup, OracleSetup::PythPushOracle) {
+            return None;
+        }
+
+        let feed_id = *self.bank.config.get_pyth_push_oracle_feed_id()?;
+
+        let marginfi_sponsored_address = PythPushOraclePriceFeed::find_oracle_address(
+            PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID,
+            &feed_id,
+        )
+        .0;
+
+        let shard_id = if self.oracle_adapter.address == marginfi_sponsored_address {
+            PYTH_PUSH_MARGINFI_SPONSORED_SHARD_ID
+        } else {
+            PYTH_PUSH_PYTH_SPONSORED_SHARD_ID
+        };
+
+        Some((shard_id, feed_id))
+    }
+
+    /// Returns the update to post before a transaction reading the bank oracle, based
+    /// on its oracle setup. Pyth legacy and switchboard v2 oracles are pushed by their
+    /// publishers, they need none
+    pub fn get_oracle_update(&self) -> Option<OracleUpdate> {
+        match self.bank.config.oracle_setup {
+            OracleSetup::PythPushOracle => self
+                .get_pyth_push_oracle_feed()
+                .map(|(shard_id, feed_id)| OracleUpdate::PythPull(shard_id, feed_id)),
+            OracleSetup::SwitchboardPull => {
+                Some(OracleUpdate::SwitchboardPull(self.oracle_adapter.address))
+            }
+            _ => None,
         }
     }
 
@@ -53,6 +150,19 @@ impl BankWrapper {
         }
     }
 
+    /// Oracle price of a token, in USD, as used to value it on the side
+    pub fn get_price(
+        &self,
+        side: BalanceSide,
+        requirement_type: RequirementType,
+    ) -> anyhow::Result<I80F48> {
+        let (_, price_bias, oracle_type) = self.get_pricing_params(side, requirement_type);
+
+        Ok(self
+            .oracle_adapter
+            .get_price_of_type(oracle_type, price_bias)?)
+    }
+
     pub fn calc_amount(
         &self,
         value: I80F48,
@@ -1,49 +1,135 @@
-use super::{bank::BankWrapper, marginfi_account::MarginfiAccountWrapper};
+use super::{
+    bank::{BankWrapper, OracleUpdate},
+    marginfi_account::{MarginfiAccountWrapper, TxConfig},
+};
 use crate::{
+    cli::setup::initialize::initialize_marginfi_account,
     config::GeneralConfig,
-    marginfi_ixs::{make_deposit_ix, make_liquidate_ix, make_repay_ix, make_withdraw_ix},
-    transaction_manager::{BatchTransactions, RawTransaction},
+    fee_estimator::writable_accounts,
+    marginfi_ixs::{
+        make_close_balance_ix, make_deposit_ix, make_liquidate_ix, make_repay_ix,
+        make_withdraw_emissions_ix, make_withdraw_ix, LiquidateAccounts,
+    },
+    pnl::{LiquidationPnl, PnlEntry},
+    pyth::{make_price_update_transactions, HermesClient},
+    rpc_pool::RpcPool,
+    transaction_manager::{
+        BatchTransactions, InFlightLiquidations, RawTransaction, TransactionManager,
+    },
+    tx_log::{TxMetadata, TxType},
+    utils::marginfi_accounts_by_authority,
 };
+use anyhow::bail;
 use crossbeam::channel::Sender;
-use marginfi::state::{marginfi_account::MarginfiAccount, marginfi_group::BankVaultType};
-use solana_client::{
-    nonblocking::rpc_client::RpcClient as NonBlockingRpcClient, rpc_client::RpcClient,
+use fixed::types::I80F48;
+use log::{debug, info, warn};
+use marginfi::state::{
+    marginfi_account::{BalanceSide, MarginfiAccount, RequirementType},
+    marginfi_group::BankVaultType,
 };
+use solana_address_lookup_table_program::state::AddressLookupTable;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::{
-    signature::{read_keypair_file, Keypair},
+    address_lookup_table_account::AddressLookupTableAccount,
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    signature::Keypair,
     signer::Signer,
+    transaction::VersionedTransaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, Mutex},
 };
-use std::{collections::HashMap, str::FromStr, sync::Arc};
 use switchboard_on_demand_client::{FetchUpdateManyParams, Gateway, PullFeed, QueueAccountData};
 
+/// Base compute units of a liquidation, without the observation accounts
+const LIQUIDATION_BASE_COMPUTE_UNITS: u32 = 100_000;
+
+/// Compute units added to a liquidation for each observation account
+const COMPUTE_UNITS_PER_OBSERVATION_ACCOUNT: u32 = 25_000;
+
+/// Maximum compute units a transaction can request
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Transactions updating the pull oracles read by a transaction
+#[derive(Default)]
+pub struct OracleUpdates {
+    /// Pyth pull price updates, spanning several transactions
+    pub price_update_transactions: BatchTransactions,
+    /// Switchboard on-demand crank, with its lookup tables
+    pub crank: Option<(Instruction, Vec<AddressLookupTableAccount>)>,
+}
+
+impl OracleUpdates {
+    /// The update transactions, to be sent before the ones reading the oracles
+    pub fn into_transactions(self) -> BatchTransactions {
+        let mut transactions = self.price_update_transactions;
+        if let Some((crank_ix, crank_lut)) = self.crank {
+            transactions.push(RawTransaction::new(vec![crank_ix]).with_lookup_tables(crank_lut));
+        }
+        transactions
+    }
+}
+
+/// Observation accounts of the liquidator account, keyed by the bank of each
+/// of its balance slots and the banks included and excluded
+type ObservationAccountsCache =
+    HashMap<(Vec<Option<Pubkey>>, Vec<Pubkey>, Vec<Pubkey>), Vec<Pubkey>>;
+
+/// Tuned compute unit limits of the liquidations, keyed by their asset bank,
+/// liability bank and number of observation accounts
+type ComputeUnitLimitCache = HashMap<(Pubkey, Pubkey, usize), u32>;
+
 /// Wraps the liquidator account into a dedicated strecture
 pub struct LiquidatorAccount {
     pub account_wrapper: MarginfiAccountWrapper,
     pub signer_keypair: Arc<Keypair>,
     program_id: Pubkey,
-    token_program_per_mint: HashMap<Pubkey, Pubkey>,
     group: Pubkey,
     pub transaction_tx: Sender<BatchTransactions>,
     pub swb_gateway: Gateway,
-    pub non_blocking_rpc_client: NonBlockingRpcClient,
+    /// Rpc clients of the current healthy endpoint
+    pub rpc_pool: Arc<RpcPool>,
+    tx_config: TxConfig,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    hermes_client: HermesClient,
+    /// The liquidator account changes rarely, its observation accounts are
+    /// reused until its balance slots change
+    observation_accounts_cache: Mutex<ObservationAccountsCache>,
+    /// Liquidations of the same shape consume about the same compute units,
+    /// they are only simulated once to tune their limit
+    compute_unit_limit_cache: Mutex<ComputeUnitLimitCache>,
+    /// Liquidations the batches sent through `send_batch` wait for, so the
+    /// rebalancer doesn't move collateral which isn't seized yet
+    liquidation_dependencies: Option<InFlightLiquidations>,
 }
 
 impl LiquidatorAccount {
     pub async fn new(
-        rpc_client: RpcClient,
-        liquidator_pubkey: Pubkey,
+        rpc_pool: Arc<RpcPool>,
         transaction_tx: Sender<BatchTransactions>,
         config: GeneralConfig,
     ) -> anyhow::Result<Self> {
-        let signer_keypair = Arc::new(read_keypair_file(&config.keypair_path).unwrap());
+        let liquidator_pubkey = Self::resolve_address(&rpc_pool, &config, false)?;
+        let signer_keypair = Arc::new(config.keypair.load()?);
 
+        let rpc_client = rpc_pool.rpc();
         let account = rpc_client.get_account(&liquidator_pubkey)?;
         let marginfi_account = bytemuck::from_bytes::<MarginfiAccount>(&account.data[8..]);
         let account_wrapper = MarginfiAccountWrapper::new(liquidator_pubkey, *marginfi_account);
         let group = account_wrapper.account.group;
 
-        let non_blocking_rpc_client = NonBlockingRpcClient::new(config.rpc_url.clone());
+        let non_blocking_rpc_client = rpc_pool.non_blocking_rpc();
 
         let queue = QueueAccountData::load(
             &non_blocking_rpc_client,
@@ -57,63 +143,297 @@ impl LiquidatorAccount {
             .unwrap()[0]
             .clone();
 
+        let mut lookup_tables = vec![];
+        for table_address in &config.address_lookup_tables {
+            let raw_account = rpc_client.get_account(table_address)?;
+            let address_lookup_table = AddressLookupTable::deserialize(&raw_account.data)?;
+            lookup_tables.push(AddressLookupTableAccount {
+                key: *table_address,
+                addresses: address_lookup_table.addresses.to_vec(),
+            });
+        }
+
         Ok(Self {
             account_wrapper,
             signer_keypair,
             program_id: config.marginfi_program_id,
             group,
             transaction_tx,
-            token_program_per_mint: HashMap::new(),
             swb_gateway,
-            non_blocking_rpc_client,
+            rpc_pool,
+            tx_config: config.get_tx_config(),
+            lookup_tables,
+            hermes_client: HermesClient::new(config.hermes_url.clone()),
+            observation_accounts_cache: Mutex::new(HashMap::new()),
+            compute_unit_limit_cache: Mutex::new(HashMap::new()),
+            liquidation_dependencies: None,
         })
     }
 
-    pub async fn load_initial_data(
-        &mut self,
-        rpc_client: &RpcClient,
-        mints: Vec<Pubkey>,
-    ) -> anyhow::Result<()> {
-        let token_program_per_mint = rpc_client
-            .get_multiple_accounts(&mints)
-            .unwrap()
-            .iter()
-            .zip(mints)
-            .map(|(account, mint)| (mint, account.as_ref().unwrap().owner))
-            .collect();
+    /// Marginfi account of the signer: the configured one, else the one at
+    /// `liquidator_account_index` among the accounts of the signer in the group.
+    /// A signer without any account gets one created when `create_if_missing` is set,
+    /// which is never the case of a dry run
+    pub fn resolve_address(
+        rpc_pool: &RpcPool,
+        config: &GeneralConfig,
+        create_if_missing: bool,
+    ) -> anyhow::Result<Pubkey> {
+        if let Some(liquidator_account) = config.liquidator_account {
+            return Ok(liquidator_account);
+        }
 
-        self.token_program_per_mint = token_program_per_mint;
+        let accounts = marginfi_accounts_by_authority(
+            &rpc_pool.rpc(),
+            &config.marginfi_program_id,
+            &config.marginfi_group_address,
+            &config.signer_pubkey,
+        )?;
+        if let Some(liquidator_account) = accounts.get(config.liquidator_account_index) {
+            info!(
+                "Using the marginfi account {} of the signer {}, {} of {}",
+                liquidator_account,
+                config.signer_pubkey,
+                config.liquidator_account_index + 1,
+                accounts.len()
+            );
+            return Ok(*liquidator_account);
+        }
+        if !accounts.is_empty() {
+            bail!(
+                "`liquidator_account_index` is {} but the signer {} has {} marginfi accounts in the group {}",
+                config.liquidator_account_index,
+                config.signer_pubkey,
+                accounts.len(),
+                config.marginfi_group_address
+            );
+        }
+        if !create_if_missing || config.dry_run {
+            bail!(
+                "The signer {} has no marginfi account in the group {}, one is created by the `run` command outside of a dry run",
+                config.signer_pubkey,
+                config.marginfi_group_address
+            );
+        }
+
+        let (liquidator_account, signature) = initialize_marginfi_account(
+            rpc_pool.rpc(),
+            Arc::new(config.keypair.load()?),
+            config.marginfi_program_id,
+            config.marginfi_group_address,
+            config.get_sender_config(),
+        )?;
+        info!(
+            "Created the marginfi account {} of the signer {}: {}",
+            liquidator_account, config.signer_pubkey, signature
+        );
+
+        Ok(liquidator_account)
+    }
+
+    /// Observation accounts of the liquidator account, cached until its balance slots change
+    fn get_observation_accounts(
+        &self,
+        banks_to_include: &[Pubkey],
+        banks_to_exclude: &[Pubkey],
+        banks: &HashMap<Pubkey, BankWrapper>,
+    ) -> Vec<Pubkey> {
+        let balance_slots = self.account_wrapper.get_balance_slots();
+
+        let mut cache = self.observation_accounts_cache.lock().unwrap();
+        if cache
+            .keys()
+            .next()
+            .is_some_and(|(cached_balance_slots, _, _)| *cached_balance_slots != balance_slots)
+        {
+            cache.clear();
+        }
+
+        cache
+            .entry((
+                balance_slots,
+                banks_to_include.to_vec(),
+                banks_to_exclude.to_vec(),
+            ))
+            .or_insert_with(|| {
+                self.account_wrapper.get_observation_accounts(
+                    banks_to_include,
+                    banks_to_exclude,
+                    banks,
+                )
+            })
+            .clone()
+    }
+
+    /// Replaces the state of the liquidator account with an update of it,
+    /// dropping the observation accounts cached for the previous state
+    pub fn refresh_account(&mut self, account: MarginfiAccount) {
+        self.account_wrapper.account = account;
+        self.observation_accounts_cache.lock().unwrap().clear();
+    }
+
+    /// Reads the liquidator account from the rpc, ahead of its geyser update
+    pub async fn reload_account(&mut self) -> anyhow::Result<()> {
+        let account = self
+            .rpc_pool
+            .non_blocking_rpc()
+            .get_account_with_commitment(
+                &self.account_wrapper.address,
+                CommitmentConfig::confirmed(),
+            )
+            .await?
+            .value
+            .ok_or_else(|| anyhow::anyhow!("Liquidator account not found"))?;
+        let marginfi_account = bytemuck::from_bytes::<MarginfiAccount>(&account.data[8..]);
+        self.refresh_account(*marginfi_account);
 
         Ok(())
     }
 
-    pub async fn liquidate(
+    /// Makes the batches sent through `send_batch` wait for the liquidations in flight
+    pub fn set_liquidation_dependencies(&mut self, in_flight_liquidations: InFlightLiquidations) {
+        self.liquidation_dependencies = Some(in_flight_liquidations);
+    }
+
+    /// Sends the batch to the transaction manager, held until the
+    /// liquidations in flight resolve when it depends on them
+    pub fn send_batch(&self, mut batch: BatchTransactions) -> anyhow::Result<()> {
+        if let Some(in_flight_liquidations) = &self.liquidation_dependencies {
+            let depends_on = in_flight_liquidations
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|liquidation_guard| liquidation_guard.upgrade())
+                .map(|liquidation_guard| liquidation_guard.outcome())
+                .collect::<Vec<_>>();
+            if let Some(first_transaction) = batch.first_mut() {
+                first_transaction.depends_on.extend(depends_on);
+            }
+        }
+
+        self.transaction_tx.send(batch)?;
+
+        Ok(())
+    }
+
+    /// Withdraws the lamports from a SOL deposit of the liquidator account and unwraps
+    /// them to the signer, so it can pay the tip of the bundle. None when no SOL
+    /// deposit is large enough. The account is reloaded first, and `batch_banks` are
+    /// the banks it opened in the bundles sent before, which it doesn't reflect yet
+    pub async fn build_tip_funding(
+        &mut self,
+        lamports: u64,
+        banks: &HashMap<Pubkey, BankWrapper>,
+        batch_banks: &[Pubkey],
+    ) -> anyhow::Result<Option<RawTransaction>> {
+        self.reload_account().await?;
+
+        let mut sol_bank = None;
+        for bank in banks
+            .values()
+            .filter(|bank| bank.bank.mint == spl_token::native_mint::ID)
+        {
+            if let Some((deposit, BalanceSide::Assets)) = self
+                .account_wrapper
+                .get_balance_for_bank(&bank.address, bank)?
+            {
+                if deposit >= I80F48::from_num(lamports) {
+                    sol_bank = Some(bank);
+                    break;
+                }
+            }
+        }
+        let Some(sol_bank) = sol_bank else {
+            return Ok(None);
+        };
+
+        let signer_pk = self.signer_keypair.pubkey();
+        let mint = sol_bank.bank.mint;
+        let token_program = sol_bank.token_program;
+        let token_account =
+            get_associated_token_address_with_program_id(&signer_pk, &mint, &token_program);
+
+        let withdraw_ix = make_withdraw_ix(
+            self.program_id,
+            self.group,
+            self.account_wrapper.address,
+            signer_pk,
+            sol_bank.address,
+            token_account,
+            crate::utils::find_bank_vault_authority_pda(
+                &sol_bank.address,
+                BankVaultType::Liquidity,
+                &self.program_id,
+            )
+            .0,
+            sol_bank.bank.liquidity_vault,
+            token_program,
+            self.get_observation_accounts(batch_banks, &[], banks),
+            mint,
+            lamports,
+            None,
+        );
+
+        // Closing the wSOL account hands the withdrawn lamports to the signer
+        let ixs = vec![
+            create_associated_token_account_idempotent(
+                &signer_pk,
+                &signer_pk,
+                &mint,
+                &token_program,
+            ),
+            withdraw_ix,
+            spl_token::instruction::close_account(
+                &token_program,
+                &token_account,
+                &signer_pk,
+                &signer_pk,
+                &[],
+            )?,
+        ];
+
+        Ok(Some(
+            RawTransaction::new(ixs)
+                .with_metadata(TxMetadata::new(
+                    TxType::Withdraw,
+                    vec![sol_bank.address],
+                    Some(lamports),
+                ))
+                .with_description(format!("tip funding of {} lamports", lamports))
+                .with_tip_funding(),
+        ))
+    }
+
+    /// Builds the transactions of a liquidation, the pyth pull oracle price updates
+    /// and the switchboard crank (when needed) followed by the liquidation itself.
+    /// `batch_banks` are the banks of the liquidator account opened by the previous
+    /// liquidations of the batch, which aren't reflected by its state yet
+    #[allow(clippy::too_many_arguments)]
+    pub async fn build_liquidation(
         &mut self,
         liquidate_account: &MarginfiAccountWrapper,
         asset_bank: &BankWrapper,
         liab_bank: &BankWrapper,
         asset_amount: u64,
         banks: &HashMap<Pubkey, BankWrapper>,
-    ) -> anyhow::Result<()> {
+        profit: I80F48,
+        batch_banks: &[Pubkey],
+    ) -> anyhow::Result<BatchTransactions> {
         let liquidator_account_address = self.account_wrapper.address;
         let liquidatee_account_address = liquidate_account.address;
         let signer_pk = self.signer_keypair.pubkey();
-        let liab_mint = liab_bank.bank.mint;
-
-        let (bank_liquidaity_vault_authority, _) = crate::utils::find_bank_vault_authority_pda(
-            &liab_bank.address,
-            BankVaultType::Liquidity,
-            &self.program_id,
-        );
-
-        let bank_liquidaity_vault = liab_bank.bank.liquidity_vault;
-        let bank_insurante_vault = liab_bank.bank.insurance_vault;
 
-        let liquidator_observation_accounts = self.account_wrapper.get_observation_accounts(
-            &[liab_bank.address, asset_bank.address],
-            &[],
-            banks,
-        );
+        // Only the banks read by the health checks are observed: marginfi loads the bank
+        // and oracle of every active balance of both accounts, and the liquidator opens
+        // the balances of the liquidated pair. The banks opened earlier in the batch
+        // come first, they take the free slots first
+        let banks_to_include = batch_banks
+            .iter()
+            .chain([&liab_bank.address, &asset_bank.address])
+            .copied()
+            .collect::<Vec<_>>();
+        let liquidator_observation_accounts =
+            self.get_observation_accounts(&banks_to_include, &[], banks);
 
         let liquidatee_observation_accounts =
             liquidate_account.get_observation_accounts(&[], &[], banks);
@@ -124,24 +444,182 @@ impl LiquidatorAccount {
             .cloned()
             .collect::<Vec<_>>();
 
-        let observation_swb_oracles = joined_observation_accounts
+        // Pull oracles are only read by marginfi, their price has to be posted
+        // in the same bundle for the liquidation not to revert on a stale price
+        let oracle_updates = self
+            .build_oracle_updates(&joined_observation_accounts, banks)
+            .await?;
+
+        let liquidation_shape = (
+            asset_bank.address,
+            liab_bank.address,
+            joined_observation_accounts.len(),
+        );
+        let tune_compute_unit_limit =
+            self.tx_config.tune_compute_unit_limit && self.tx_config.compute_unit_limit.is_none();
+        let tuned_compute_unit_limit = if tune_compute_unit_limit {
+            self.compute_unit_limit_cache
+                .lock()
+                .unwrap()
+                .get(&liquidation_shape)
+                .copied()
+        } else {
+            None
+        };
+        let mut compute_unit_limit = self
+            .tx_config
+            .compute_unit_limit
+            .or(tuned_compute_unit_limit)
+            .unwrap_or_else(|| {
+                Self::estimate_compute_unit_limit(joined_observation_accounts.len())
+            });
+        let tune_compute_unit_limit = tune_compute_unit_limit && tuned_compute_unit_limit.is_none();
+
+        let liquidate_accounts = LiquidateAccounts::resolve(
+            &self.program_id,
+            self.group,
+            liquidator_account_address,
+            signer_pk,
+            liquidatee_account_address,
+            asset_bank,
+            liab_bank,
+            liquidator_observation_accounts,
+            liquidatee_observation_accounts,
+        );
+        let liquidate_ix = make_liquidate_ix(self.program_id, &liquidate_accounts, asset_amount);
+
+        self.check_liquidation_size(&liquidate_ix).map_err(|e| {
+            anyhow::anyhow!(
+                "Liquidation of account {} with {} observation accounts doesn't fit in a transaction: {}",
+                liquidatee_account_address,
+                joined_observation_accounts.len(),
+                e
+            )
+        })?;
+
+        // The price updates span several transactions, a liquidation depending
+        // on them can only be checked once they landed
+        if (self.tx_config.simulate || tune_compute_unit_limit)
+            && oracle_updates.price_update_transactions.is_empty()
+        {
+            let mut ixs = vec![];
+            let mut lookup_tables = self.lookup_tables.clone();
+            if let Some((crank_ix, crank_lut)) = &oracle_updates.crank {
+                ixs.push(crank_ix.clone());
+                lookup_tables.extend(crank_lut.iter().cloned());
+            }
+            ixs.push(liquidate_ix.clone());
+
+            let units_consumed = self.simulate(ixs, &lookup_tables)?;
+
+            // The crank is simulated along, so the tuned limit errs on the high side
+            if let Some(units_consumed) = units_consumed.filter(|_| tune_compute_unit_limit) {
+                compute_unit_limit = self.tune_compute_unit_limit(units_consumed);
+                debug!(
+                    "Compute unit limit of the liquidations of {} against {} with {} observation accounts tuned to {}, {} consumed",
+                    asset_bank.address,
+                    liab_bank.address,
+                    joined_observation_accounts.len(),
+                    compute_unit_limit,
+                    units_consumed
+                );
+                self.compute_unit_limit_cache
+                    .lock()
+                    .unwrap()
+                    .insert(liquidation_shape, compute_unit_limit);
+            }
+        }
+
+        let mut transactions = oracle_updates.into_transactions();
+        let mut liquidate_transaction = RawTransaction::new(vec![liquidate_ix])
+            .with_expected_profit(crate::utils::usd_to_lamports(profit, banks))
+            .with_compute_unit_limit(compute_unit_limit)
+            .with_metadata(TxMetadata::new(
+                TxType::Liquidate,
+                vec![asset_bank.address, liab_bank.address],
+                Some(asset_amount),
+            ))
+            .with_description(format!(
+                "account {} (asset bank: {}, liability bank: {})",
+                liquidatee_account_address, asset_bank.address, liab_bank.address
+            ))
+            .with_pnl(PnlEntry::Liquidation(LiquidationPnl {
+                asset_mint: asset_bank.bank.mint,
+                asset_amount,
+                asset_price: asset_bank.get_price(BalanceSide::Assets, RequirementType::Equity)?,
+                collateral_value: asset_bank.calc_value(
+                    I80F48::from_num(asset_amount),
+                    BalanceSide::Assets,
+                    RequirementType::Equity,
+                )?,
+                lamport_price: crate::utils::lamports_to_usd(1, banks).unwrap_or(I80F48::ZERO),
+                expected_swap_slippage: I80F48::ZERO,
+            }));
+        // The price is resolved last, as close as possible to the submission
+        if let Some(priority_fee) = &self.tx_config.priority_fee {
+            let price = priority_fee
+                .resolve(
+                    &self.rpc_pool.non_blocking_rpc(),
+                    &writable_accounts(&liquidate_transaction.instructions),
+                )
+                .await;
+            liquidate_transaction = liquidate_transaction
+                .with_compute_unit_price(self.tx_config.clamp_compute_unit_price(price));
+        }
+        transactions.push(liquidate_transaction);
+
+        Ok(transactions)
+    }
+
+    /// Builds the updates of the pull oracles of the given banks, detected from their
+    /// oracle setup: the pyth price updates posted from hermes and a single switchboard
+    /// crank for all the on-demand feeds. Keys which aren't banks are ignored
+    pub async fn build_oracle_updates(
+        &self,
+        bank_pks: &[Pubkey],
+        banks: &HashMap<Pubkey, BankWrapper>,
+    ) -> anyhow::Result<OracleUpdates> {
+        // A bank can be observed for both accounts, each oracle is updated once
+        let mut updates = bank_pks
             .iter()
-            .filter_map(|&pk| {
-                banks.get(&pk).and_then(|bank| {
-                    if bank.oracle_adapter.is_switchboard_pull() {
-                        Some(bank.oracle_adapter.address)
-                    } else {
-                        None
-                    }
-                })
-            })
+            .filter_map(|pk| banks.get(pk)?.get_oracle_update())
             .collect::<Vec<_>>();
+        updates.sort();
+        updates.dedup();
 
-        let crank_data = if !observation_swb_oracles.is_empty() {
-            if let Ok((ix, luts)) = PullFeed::fetch_update_many_ix(
-                &self.non_blocking_rpc_client,
+        let mut pyth_feeds = vec![];
+        let mut swb_feeds = vec![];
+        for update in updates {
+            match update {
+                OracleUpdate::PythPull(shard_id, feed_id) => pyth_feeds.push((shard_id, feed_id)),
+                OracleUpdate::SwitchboardPull(feed) => swb_feeds.push(feed),
+            }
+        }
+
+        let price_update_transactions = if !pyth_feeds.is_empty() {
+            let feed_ids = pyth_feeds
+                .iter()
+                .map(|(_, feed_id)| *feed_id)
+                .collect::<Vec<_>>();
+            let accumulator_update = self
+                .hermes_client
+                .get_latest_price_updates(&feed_ids)
+                .await?;
+
+            make_price_update_transactions(
+                self.signer_keypair.pubkey(),
+                &pyth_feeds,
+                accumulator_update,
+            )?
+        } else {
+            vec![]
+        };
+
+        let crank = if !swb_feeds.is_empty() {
+            let crank = PullFeed::fetch_update_many_ix(
+                &self.rpc_pool.non_blocking_rpc(),
                 FetchUpdateManyParams {
-                    feeds: observation_swb_oracles,
+                    feeds: swb_feeds,
                     payer: self.signer_keypair.pubkey(),
                     gateway: self.swb_gateway.clone(),
                     num_signatures: Some(1),
@@ -149,44 +627,102 @@ impl LiquidatorAccount {
                 },
             )
             .await
-            {
-                Some((ix, luts))
-            } else {
-                return Err(anyhow::anyhow!("Failed to fetch crank data"));
-            }
+            .map_err(|e| anyhow::anyhow!("Failed to fetch crank data: {:?}", e))?;
+            Some(crank)
         } else {
             None
         };
 
-        let liquidate_ix = make_liquidate_ix(
-            self.program_id,
-            self.group,
-            liquidator_account_address,
-            asset_bank.address,
-            liab_bank.address,
-            signer_pk,
-            liquidatee_account_address,
-            bank_liquidaity_vault_authority,
-            bank_liquidaity_vault,
-            bank_insurante_vault,
-            *self.token_program_per_mint.get(&liab_mint).unwrap(),
-            liquidator_observation_accounts,
-            liquidatee_observation_accounts,
-            asset_bank.oracle_adapter.address,
-            liab_bank.oracle_adapter.address,
-            liab_mint,
-            asset_amount,
-        );
+        Ok(OracleUpdates {
+            price_update_transactions,
+            crank,
+        })
+    }
 
-        let mut bundle = vec![];
-        if let Some((crank_ix, crank_lut)) = crank_data {
-            bundle.push(RawTransaction::new(vec![crank_ix]).with_lookup_tables(crank_lut));
-        }
-        bundle.push(RawTransaction::new(vec![liquidate_ix]));
+    /// Estimates the compute units of a liquidation from the number of
+    /// observation accounts it has to load
+    fn estimate_compute_unit_limit(observation_accounts: usize) -> u32 {
+        (observation_accounts as u32)
+            .saturating_mul(COMPUTE_UNITS_PER_OBSERVATION_ACCOUNT)
+            .saturating_add(LIQUIDATION_BASE_COMPUTE_UNITS)
+            .min(MAX_COMPUTE_UNIT_LIMIT)
+    }
 
-        self.transaction_tx.send(bundle)?;
+    /// Compute unit limit covering the simulated consumption with the configured margin
+    fn tune_compute_unit_limit(&self, units_consumed: u64) -> u32 {
+        let margin = units_consumed * self.tx_config.compute_unit_margin_bps as u64 / 10_000;
+        units_consumed
+            .saturating_add(margin)
+            .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+    }
 
-        Ok(())
+    /// Fails when the liquidation, with its compute budget, doesn't fit in a transaction
+    fn check_liquidation_size(&self, liquidate_ix: &Instruction) -> anyhow::Result<()> {
+        let ixs = [
+            liquidate_ix.clone(),
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            ComputeBudgetInstruction::set_compute_unit_price(0),
+        ];
+
+        let transaction = VersionedTransaction::try_new(
+            VersionedMessage::V0(v0::Message::try_compile(
+                &self.signer_keypair.pubkey(),
+                &ixs,
+                &self.lookup_tables,
+                Hash::default(),
+            )?),
+            &[self.signer_keypair.as_ref()],
+        )?;
+
+        TransactionManager::check_transaction_limits(&transaction)
+    }
+
+    /// Simulates the instructions in a single transaction, failing with
+    /// the program logs when the simulation doesn't succeed.
+    /// Returns the compute units consumed, when reported by the rpc
+    fn simulate(
+        &self,
+        mut ixs: Vec<Instruction>,
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> anyhow::Result<Option<u64>> {
+        ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(
+            MAX_COMPUTE_UNIT_LIMIT,
+        ));
+
+        // The blockhash is replaced by the rpc during the simulation
+        let transaction = VersionedTransaction::try_new(
+            VersionedMessage::V0(v0::Message::try_compile(
+                &self.signer_keypair.pubkey(),
+                &ixs,
+                lookup_tables,
+                Hash::default(),
+            )?),
+            &[self.signer_keypair.as_ref()],
+        )?;
+
+        let simulation = self
+            .rpc_pool
+            .rpc()
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(CommitmentConfig::processed()),
+                    ..Default::default()
+                },
+            )?
+            .value;
+
+        if let Some(err) = simulation.err {
+            return Err(anyhow::anyhow!(
+                "Liquidation simulation failed: {}\nLogs: {:#?}",
+                crate::marginfi_errors::describe_transaction_error(&err),
+                simulation.logs.unwrap_or_default()
+            ));
+        }
+
+        Ok(simulation.units_consumed)
     }
 
     pub fn withdraw(
@@ -195,24 +731,72 @@ impl LiquidatorAccount {
         token_account: Pubkey,
         amount: u64,
         withdraw_all: Option<bool>,
+        unwrap_sol: bool,
         banks: &HashMap<Pubkey, BankWrapper>,
     ) -> anyhow::Result<()> {
+        let (tx, _) =
+            self.build_withdraw(bank, token_account, amount, withdraw_all, unwrap_sol, banks)?;
+
+        self.send_batch(vec![tx])?;
+
+        Ok(())
+    }
+
+    /// Builds the withdraw transaction, to be bundled with the transactions using
+    /// the withdrawn tokens, along with the amount withdrawn, which is lower than
+    /// the requested one when the liquidity vault can't cover it
+    pub fn build_withdraw(
+        &self,
+        bank: &BankWrapper,
+        token_account: Pubkey,
+        amount: u64,
+        withdraw_all: Option<bool>,
+        unwrap_sol: bool,
+        banks: &HashMap<Pubkey, BankWrapper>,
+    ) -> anyhow::Result<(RawTransaction, u64)> {
         let marginfi_account = self.account_wrapper.address;
 
         let signer_pk = self.signer_keypair.pubkey();
 
+        // A withdraw the liquidity vault can't cover reverts, it is downgraded
+        // to a partial withdraw of the available liquidity instead
+        let available_liquidity = self.get_token_balance(&bank.bank.liquidity_vault);
+        let (amount, withdraw_all) = if available_liquidity == 0 {
+            return Err(anyhow::anyhow!(
+                "Bank {} has no liquidity left to withdraw",
+                bank.address
+            ));
+        } else if withdraw_all.unwrap_or(false) {
+            let deposit = match self
+                .account_wrapper
+                .get_balance_for_bank(&bank.address, bank)?
+            {
+                Some((deposit, BalanceSide::Assets)) => deposit.to_num::<u64>(),
+                _ => amount,
+            };
+            if deposit > available_liquidity {
+                warn!(
+                    "Bank {} holds {} tokens, not enough to withdraw all of the deposit of {}, withdrawing them only",
+                    bank.address, available_liquidity, deposit
+                );
+                (available_liquidity, Some(false))
+            } else {
+                (amount, withdraw_all)
+            }
+        } else {
+            (amount.min(available_liquidity), withdraw_all)
+        };
+
         let banks_to_exclude = if withdraw_all.unwrap_or(false) {
             vec![bank.address]
         } else {
             vec![]
         };
 
-        let observation_accounts =
-            self.account_wrapper
-                .get_observation_accounts(&[], &banks_to_exclude, banks);
+        let observation_accounts = self.get_observation_accounts(&[], &banks_to_exclude, banks);
 
         let mint = bank.bank.mint;
-        let token_program = *self.token_program_per_mint.get(&mint).unwrap();
+        let token_program = bank.token_program;
 
         let withdraw_ix = make_withdraw_ix(
             self.program_id,
@@ -235,10 +819,29 @@ impl LiquidatorAccount {
             withdraw_all,
         );
 
-        self.transaction_tx
-            .send(vec![RawTransaction::new(vec![withdraw_ix])])?;
+        let mut ixs = self.maybe_create_token_account_ix(&token_account, &mint, &token_program)?;
+        ixs.push(withdraw_ix);
 
-        Ok(())
+        // Closing the wSOL account sends all of its lamports, the withdrawn
+        // amount included, back to the signer as native SOL
+        if unwrap_sol && mint == spl_token::native_mint::ID {
+            ixs.push(spl_token::instruction::close_account(
+                &token_program,
+                &token_account,
+                &signer_pk,
+                &signer_pk,
+                &[],
+            )?);
+        }
+
+        Ok((
+            RawTransaction::new(ixs).with_metadata(TxMetadata::new(
+                TxType::Withdraw,
+                vec![bank.address],
+                Some(amount),
+            )),
+            amount,
+        ))
     }
 
     pub fn repay(
@@ -248,12 +851,62 @@ impl LiquidatorAccount {
         amount: u64,
         repay_all: Option<bool>,
     ) -> anyhow::Result<()> {
+        let tx = self.build_repay(bank, token_account, amount, repay_all, 0)?;
+
+        self.send_batch(vec![tx])?;
+
+        Ok(())
+    }
+
+    /// Builds the repay transaction, to be bundled with the swap
+    /// that buys the repaid tokens, `incoming_amount` being the tokens
+    /// it delivers to the token account before the repay
+    pub fn build_repay(
+        &self,
+        bank: &BankWrapper,
+        token_account: &Pubkey,
+        amount: u64,
+        repay_all: Option<bool>,
+        incoming_amount: u64,
+    ) -> anyhow::Result<RawTransaction> {
+        // A repay the token account can't cover reverts, it is downgraded
+        // to a partial repay of the available tokens instead
+        let available = self
+            .get_token_balance(token_account)
+            .saturating_add(incoming_amount);
+        let (amount, repay_all) = if available == 0 {
+            return Err(anyhow::anyhow!(
+                "No tokens of bank {} to repay with in {}",
+                bank.address,
+                token_account
+            ));
+        } else if repay_all.unwrap_or(false) {
+            let liability = match self
+                .account_wrapper
+                .get_balance_for_bank(&bank.address, bank)?
+            {
+                Some((liability, BalanceSide::Liabilities)) => liability.ceil().to_num::<u64>(),
+                _ => amount,
+            };
+            if liability > available {
+                warn!(
+                    "Only {} tokens available to repay all of the liability of {} to bank {}, repaying them only",
+                    available, liability, bank.address
+                );
+                (available, Some(false))
+            } else {
+                (amount, repay_all)
+            }
+        } else {
+            (amount.min(available), repay_all)
+        };
+
         let marginfi_account = self.account_wrapper.address;
 
         let signer_pk = self.signer_keypair.pubkey();
 
         let mint = bank.bank.mint;
-        let token_program = *self.token_program_per_mint.get(&mint).unwrap();
+        let token_program = bank.token_program;
 
         let repay_ix = make_repay_ix(
             self.program_id,
@@ -269,10 +922,14 @@ impl LiquidatorAccount {
             repay_all,
         );
 
-        self.transaction_tx
-            .send(vec![RawTransaction::new(vec![repay_ix])])?;
+        let mut ixs = self.maybe_create_token_account_ix(token_account, &mint, &token_program)?;
+        ixs.push(repay_ix);
 
-        Ok(())
+        Ok(RawTransaction::new(ixs).with_metadata(TxMetadata::new(
+            TxType::Repay,
+            vec![bank.address],
+            Some(amount),
+        )))
     }
 
     pub fn deposit(
@@ -286,7 +943,7 @@ impl LiquidatorAccount {
         let signer_pk = self.signer_keypair.pubkey();
 
         let mint = bank.bank.mint;
-        let token_program = *self.token_program_per_mint.get(&mint).unwrap();
+        let token_program = bank.token_program;
 
         let deposit_ix = make_deposit_ix(
             self.program_id,
@@ -301,9 +958,139 @@ impl LiquidatorAccount {
             amount,
         );
 
-        self.transaction_tx
-            .send(vec![RawTransaction::new(vec![deposit_ix])])?;
+        let mut ixs = self.maybe_create_token_account_ix(&token_account, &mint, &token_program)?;
+
+        // The native SOL sent to the wSOL account only counts once synced
+        if mint == spl_token::native_mint::ID {
+            ixs.push(spl_token::instruction::sync_native(
+                &token_program,
+                &token_account,
+            )?);
+        }
+        ixs.push(deposit_ix);
+
+        self.send_batch(vec![RawTransaction::new(ixs).with_metadata(
+            TxMetadata::new(TxType::Deposit, vec![bank.address], Some(amount)),
+        )])?;
+
+        Ok(())
+    }
+
+    /// Closes the empty balances of the banks, freeing their balance slots
+    pub fn close_balances(&self, banks: &[Pubkey]) -> anyhow::Result<()> {
+        let marginfi_account = self.account_wrapper.address;
+
+        let signer_pk = self.signer_keypair.pubkey();
+
+        let ixs = banks
+            .iter()
+            .map(|bank| {
+                make_close_balance_ix(
+                    self.program_id,
+                    self.group,
+                    marginfi_account,
+                    signer_pk,
+                    *bank,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        self.send_batch(vec![RawTransaction::new(ixs)])?;
 
         Ok(())
     }
+
+    /// Claims the emissions accrued by the positions in the banks, in a single
+    /// transaction, to the signer token accounts of the emissions mints
+    pub fn withdraw_emissions(&self, banks: &[&BankWrapper]) -> anyhow::Result<()> {
+        let marginfi_account = self.account_wrapper.address;
+
+        let signer_pk = self.signer_keypair.pubkey();
+
+        let mut ixs = vec![];
+        // Banks sharing an emissions mint share the destination account, created once
+        let mut destination_accounts = HashSet::new();
+        for bank in banks {
+            let emissions_mint = bank.bank.emissions_mint;
+            let token_program = self.rpc_pool.rpc().get_account(&emissions_mint)?.owner;
+            let destination_account = get_associated_token_address_with_program_id(
+                &signer_pk,
+                &emissions_mint,
+                &token_program,
+            );
+
+            if destination_accounts.insert(destination_account) {
+                ixs.extend(self.maybe_create_token_account_ix(
+                    &destination_account,
+                    &emissions_mint,
+                    &token_program,
+                )?);
+            }
+            ixs.push(make_withdraw_emissions_ix(
+                self.program_id,
+                self.group,
+                marginfi_account,
+                signer_pk,
+                bank.address,
+                emissions_mint,
+                crate::utils::find_emissions_auth_pda(
+                    &bank.address,
+                    &emissions_mint,
+                    &self.program_id,
+                )
+                .0,
+                crate::utils::find_emissions_vault_pda(
+                    &bank.address,
+                    &emissions_mint,
+                    &self.program_id,
+                )
+                .0,
+                destination_account,
+                token_program,
+            ));
+        }
+
+        self.send_batch(vec![RawTransaction::new(ixs)])?;
+
+        Ok(())
+    }
+
+    /// Token balance of the account, zero when it doesn't exist
+    fn get_token_balance(&self, token_account: &Pubkey) -> u64 {
+        self.rpc_pool
+            .rpc()
+            .get_token_account_balance(token_account)
+            .ok()
+            .and_then(|balance| balance.amount.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns a create associated token account instruction when the
+    /// signer token account doesn't exist yet, so it can be prepended
+    fn maybe_create_token_account_ix(
+        &self,
+        token_account: &Pubkey,
+        mint: &Pubkey,
+        token_program: &Pubkey,
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let token_account_exists = self
+            .rpc_pool
+            .rpc()
+            .get_account_with_commitment(token_account, CommitmentConfig::confirmed())?
+            .value
+            .is_some();
+
+        if token_account_exists {
+            return Ok(vec![]);
+        }
+
+        let signer_pk = self.signer_keypair.pubkey();
+
+        Ok(vec![create_associated_token_account_idempotent(
+            &signer_pk,
+            &signer_pk,
+            mint,
+            token_program,
+        )])
+    }
 }
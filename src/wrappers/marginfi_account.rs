@@ -1,12 +1,40 @@
 use super::bank::BankWrapper;
+use crate::{fee_estimator::PriorityFee, utils::BankAccountWithPriceFeedEva};
 use fixed::types::I80F48;
-use marginfi::state::marginfi_account::{BalanceSide, MarginfiAccount};
+use log::warn;
+use marginfi::{
+    constants::{LIQUIDATION_INSURANCE_FEE, LIQUIDATION_LIQUIDATOR_FEE},
+    state::marginfi_account::{BalanceSide, MarginfiAccount, RequirementType},
+};
 use solana_program::pubkey::Pubkey;
 use std::collections::HashMap;
 
 #[derive(Clone)]
 pub struct TxConfig {
-    pub compute_unit_price_micro_lamports: Option<u64>,
+    pub priority_fee: Option<PriorityFee>,
+    pub simulate: bool,
+    pub compute_unit_limit: Option<u32>,
+    /// Sizes the compute unit limit on the simulated consumption, plus a margin in bps
+    pub tune_compute_unit_limit: bool,
+    pub compute_unit_margin_bps: u16,
+    /// Bounds of every compute unit price sent, in micro lamports
+    pub min_compute_unit_price: u64,
+    pub max_compute_unit_price: u64,
+}
+
+impl TxConfig {
+    /// Keeps the compute unit price within its bounds, so a misconfigured
+    /// or runaway priority fee can't drain the signer on a single transaction
+    pub fn clamp_compute_unit_price(&self, price: u64) -> u64 {
+        let clamped_price = price.clamp(self.min_compute_unit_price, self.max_compute_unit_price);
+        if clamped_price != price {
+            warn!(
+                "Compute unit price of {} micro lamports clamped to {}",
+                price, clamped_price
+            );
+        }
+        clamped_price
+    }
 }
 
 #[derive(Clone)]
@@ -97,6 +125,109 @@ impl MarginfiAccountWrapper {
         Ok(balance)
     }
 
+    /// Computes the largest amount of the asset bank that can be liquidated,
+    /// and its value, without bringing the account back above its maintenance
+    /// requirement, which marginfi rejects as an over liquidation
+    pub fn max_liquidatable_amount(
+        &self,
+        asset_bank: &BankWrapper,
+        liab_bank: &BankWrapper,
+        banks: &HashMap<Pubkey, BankWrapper>,
+    ) -> anyhow::Result<(I80F48, I80F48)> {
+        let (assets, liabs) = self.calc_health(banks, RequirementType::Maintenance)?;
+        let maintenance_health = assets - liabs;
+
+        if maintenance_health >= I80F48::ZERO {
+            return Ok((I80F48::ZERO, I80F48::ZERO));
+        }
+
+        let asset_weight_maint: I80F48 = asset_bank.bank.config.asset_weight_maint.into();
+        let liab_weight_maint: I80F48 = liab_bank.bank.config.liability_weight_maint.into();
+
+        // The liabilities are repaid at a discount, the liquidator and insurance fees
+        let liquidation_discount =
+            I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE - LIQUIDATION_INSURANCE_FEE;
+
+        // Every seized dollar of asset lowers the weighted assets by its weight,
+        // and the weighted liabilities by the discounted liability weight
+        let health_improvement_per_value =
+            liab_weight_maint * liquidation_discount - asset_weight_maint;
+
+        if health_improvement_per_value <= I80F48::ZERO {
+            return Ok((I80F48::ZERO, I80F48::ZERO));
+        }
+
+        let underwater_maint_value = -maintenance_health / health_improvement_per_value;
+
+        let asset_amount = match self.get_balance_for_bank(&asset_bank.address, asset_bank)? {
+            Some((amount, BalanceSide::Assets)) => amount,
+            _ => I80F48::ZERO,
+        };
+        let liab_amount = match self.get_balance_for_bank(&liab_bank.address, liab_bank)? {
+            Some((amount, BalanceSide::Liabilities)) => amount,
+            _ => I80F48::ZERO,
+        };
+
+        let asset_value = asset_bank.calc_value(
+            asset_amount,
+            BalanceSide::Assets,
+            RequirementType::Maintenance,
+        )?;
+        let liab_value = liab_bank.calc_value(
+            liab_amount,
+            BalanceSide::Liabilities,
+            RequirementType::Maintenance,
+        )?;
+
+        let max_liquidatable_value = asset_value.min(liab_value).min(underwater_maint_value);
+
+        // Nothing to seize, and a zero priced asset has no amount for a value
+        if max_liquidatable_value <= I80F48::ZERO {
+            return Ok((I80F48::ZERO, I80F48::ZERO));
+        }
+
+        let max_liquidatable_asset_amount = asset_bank.calc_amount(
+            max_liquidatable_value,
+            BalanceSide::Assets,
+            RequirementType::Maintenance,
+        )?;
+
+        Ok((max_liquidatable_asset_amount, max_liquidatable_value))
+    }
+
+    /// Calculates the weighted assets and liabilities of the account
+    pub fn calc_health(
+        &self,
+        banks: &HashMap<Pubkey, BankWrapper>,
+        requirement_type: RequirementType,
+    ) -> anyhow::Result<(I80F48, I80F48)> {
+        let baws = BankAccountWithPriceFeedEva::load(&self.account.lending_account, banks.clone())?;
+
+        baws.iter().try_fold(
+            (I80F48::ZERO, I80F48::ZERO),
+            |(total_assets, total_liabs), baw| {
+                let (assets, liabs) =
+                    baw.calc_weighted_assets_and_liabilities_values(requirement_type)?;
+                Ok((total_assets + assets, total_liabs + liabs))
+            },
+        )
+    }
+
+    /// Calculates the maintenance health factor of the account, the ratio of its
+    /// weighted assets to its weighted liabilities. Below 1 it can be liquidated
+    pub fn calc_health_factor(
+        &self,
+        banks: &HashMap<Pubkey, BankWrapper>,
+    ) -> anyhow::Result<I80F48> {
+        let (assets, liabs) = self.calc_health(banks, RequirementType::Maintenance)?;
+
+        if liabs.is_zero() {
+            return Ok(I80F48::MAX);
+        }
+
+        Ok(assets / liabs)
+    }
+
     pub fn get_deposits_shares(&self) -> Vec<(I80F48, Pubkey)> {
         self.account
             .lending_account
@@ -141,31 +272,57 @@ impl MarginfiAccountWrapper {
             .collect::<Vec<_>>()
     }
 
+    /// Bank of each balance slot, None for the free slots
+    pub fn get_balance_slots(&self) -> Vec<Option<Pubkey>> {
+        self.account
+            .lending_account
+            .balances
+            .iter()
+            .map(|b| b.active.then_some(b.bank_pk))
+            .collect::<Vec<_>>()
+    }
+
+    /// Returns the banks of the active balances left without any asset or liability,
+    /// which still take a balance slot until they are closed
+    pub fn get_empty_balances(&self) -> Vec<Pubkey> {
+        self.account
+            .lending_account
+            .balances
+            .iter()
+            .filter(|b| {
+                b.active && b.is_empty(BalanceSide::Assets) && b.is_empty(BalanceSide::Liabilities)
+            })
+            .map(|b| b.bank_pk)
+            .collect::<Vec<_>>()
+    }
+
+    /// Bank and oracle of each balance, in the balance slot order marginfi checks them in.
+    /// The included banks without a balance take the first free slots, as marginfi
+    /// creates their balances there, the bank map is only used for lookups
     pub fn get_observation_accounts(
         &self,
         banks_to_include: &[Pubkey],
         banks_to_exclude: &[Pubkey],
         banks: &HashMap<Pubkey, BankWrapper>,
     ) -> Vec<Pubkey> {
-        let mut ordered_active_banks = self
-            .account
-            .lending_account
-            .balances
-            .iter()
-            .filter(|b| b.active && !banks_to_exclude.contains(&b.bank_pk))
-            .map(|b| b.bank_pk)
-            .collect::<Vec<_>>();
+        let mut slots = self.get_balance_slots();
 
         for bank_pk in banks_to_include {
-            if !ordered_active_banks.contains(bank_pk) {
-                ordered_active_banks.push(*bank_pk);
+            if slots.contains(&Some(*bank_pk)) {
+                continue;
+            }
+            match slots.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => *slot = Some(*bank_pk),
+                None => slots.push(Some(*bank_pk)),
             }
         }
 
-        let bank_accounts_and_oracles = ordered_active_banks
-            .iter()
+        let bank_accounts_and_oracles = slots
+            .into_iter()
+            .flatten()
+            .filter(|bank_pk| !banks_to_exclude.contains(bank_pk))
             .flat_map(|b| {
-                let bank = banks.get(b).unwrap();
+                let bank = banks.get(&b).unwrap();
 
                 vec![bank.address, bank.oracle_adapter.address]
             })
@@ -174,3 +331,247 @@ impl MarginfiAccountWrapper {
         bank_accounts_and_oracles
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wrappers::test_fixtures::{account, bank, banks_map, set_balance};
+    use marginfi::state::marginfi_group::RiskTier;
+
+    #[test]
+    fn health_factor_of_a_healthy_account() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let sol = bank(100.0, 0.75, 1.25);
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &sol, 0, 4);
+
+        // $750 of weighted collateral against $500 of weighted liabilities
+        let health_factor = account
+            .calc_health_factor(&banks_map(&[&usdc, &sol]))
+            .unwrap();
+
+        assert_eq!(health_factor, I80F48::from_num(1.5));
+    }
+
+    #[test]
+    fn health_factor_of_a_liquidatable_account() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let sol = bank(100.0, 0.75, 1.25);
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &sol, 0, 8);
+
+        // $750 of weighted collateral against $1000 of weighted liabilities
+        let health_factor = account
+            .calc_health_factor(&banks_map(&[&usdc, &sol]))
+            .unwrap();
+
+        assert_eq!(health_factor, I80F48::from_num(0.75));
+    }
+
+    #[test]
+    fn health_factor_without_liabilities() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let banks = banks_map(&[&usdc]);
+
+        assert_eq!(account().calc_health_factor(&banks).unwrap(), I80F48::MAX);
+
+        // A balance left empty weighs on neither side
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 0, 0);
+        assert_eq!(account.calc_health_factor(&banks).unwrap(), I80F48::MAX);
+    }
+
+    #[test]
+    fn isolated_bank_only_weighs_on_the_liabilities() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let mut isolated = bank(1.0, 0.75, 1.25);
+        isolated.bank.config.risk_tier = RiskTier::Isolated;
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &isolated, 0, 740);
+        let banks = banks_map(&[&usdc, &isolated]);
+
+        // $750 of weighted collateral against $925 of weighted liabilities
+        assert_eq!(
+            account
+                .calc_health(&banks, RequirementType::Maintenance)
+                .unwrap(),
+            (I80F48::from_num(750), I80F48::from_num(925))
+        );
+
+        // Each seized dollar improves the health by 1.25 * 0.95 - 0.75 = 0.4375,
+        // so ~$400 of the $175 shortfall are liquidatable
+        let (amount, value) = account
+            .max_liquidatable_amount(&usdc, &isolated, &banks)
+            .unwrap();
+        assert!((value - I80F48::from_num(400)).abs() < I80F48::from_num(0.001));
+        assert_eq!(amount, value * I80F48::from_num(1_000_000));
+
+        // A deposit in an isolated bank is no collateral
+        let mut account = self::account();
+        set_balance(&mut account, 0, &isolated, 1_000, 0);
+        assert_eq!(
+            account
+                .calc_health(&banks, RequirementType::Maintenance)
+                .unwrap(),
+            (I80F48::ZERO, I80F48::ZERO)
+        );
+    }
+
+    #[test]
+    fn zero_priced_oracle() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let worthless = bank(0.0, 0.75, 1.25);
+        let sol = bank(100.0, 0.75, 1.25);
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &worthless, 1_000, 0);
+        set_balance(&mut account, 2, &sol, 0, 8);
+        let banks = banks_map(&[&usdc, &worthless, &sol]);
+
+        // The zero priced deposit doesn't back the $1000 of weighted liabilities
+        assert_eq!(
+            account
+                .calc_health(&banks, RequirementType::Maintenance)
+                .unwrap(),
+            (I80F48::from_num(750), I80F48::from_num(1_000))
+        );
+
+        // There is nothing to seize in the zero priced bank
+        assert_eq!(
+            account
+                .max_liquidatable_amount(&worthless, &sol, &banks)
+                .unwrap(),
+            (I80F48::ZERO, I80F48::ZERO)
+        );
+        assert!(account
+            .max_liquidatable_amount(&usdc, &sol, &banks)
+            .unwrap()
+            .0
+            .is_positive());
+
+        // A zero priced liability weighs nothing
+        let mut account = self::account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &worthless, 0, 1_000);
+        assert_eq!(
+            account
+                .calc_health(&banks, RequirementType::Maintenance)
+                .unwrap(),
+            (I80F48::from_num(750), I80F48::ZERO)
+        );
+        assert_eq!(
+            account
+                .max_liquidatable_amount(&usdc, &worthless, &banks)
+                .unwrap(),
+            (I80F48::ZERO, I80F48::ZERO)
+        );
+    }
+
+    #[test]
+    fn asset_bank_at_its_deposit_limit() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let mut capped_usdc = usdc.clone();
+        capped_usdc.bank.config.deposit_limit = 2_000_000_000;
+        capped_usdc.bank.total_asset_shares = I80F48::from_num(2_000_000_000u64).into();
+        let sol = bank(100.0, 0.75, 1.25);
+        let mut account = account();
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &sol, 0, 8);
+        let banks = banks_map(&[&usdc, &sol]);
+        let capped_banks = banks_map(&[&capped_usdc, &sol]);
+
+        // The deposit limit caps new deposits, not the value of the existing ones
+        let health = account
+            .calc_health(&capped_banks, RequirementType::Maintenance)
+            .unwrap();
+        assert_eq!(health, (I80F48::from_num(750), I80F48::from_num(1_000)));
+
+        // Nor the liquidation, whose seized collateral bypasses the limit
+        let (amount, value) = account
+            .max_liquidatable_amount(&capped_usdc, &sol, &capped_banks)
+            .unwrap();
+        assert!(amount.is_positive());
+        assert_eq!(
+            (amount, value),
+            account
+                .max_liquidatable_amount(&usdc, &sol, &banks)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn observation_accounts_of_the_balances() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let sol = bank(100.0, 0.75, 1.25);
+        let banks = banks_map(&[&usdc, &sol]);
+        let mut account = account();
+
+        assert!(account
+            .get_observation_accounts(&[], &[], &banks)
+            .is_empty());
+
+        set_balance(&mut account, 0, &usdc, 1_000, 0);
+        set_balance(&mut account, 1, &sol, 0, 4);
+
+        assert_eq!(
+            account.get_observation_accounts(&[], &[], &banks),
+            vec![
+                usdc.address,
+                usdc.oracle_adapter.address,
+                sol.address,
+                sol.oracle_adapter.address,
+            ]
+        );
+    }
+
+    #[test]
+    fn liquidation_observes_only_the_balances_and_the_liquidated_pair() {
+        let usdc = bank(1.0, 0.75, 1.0);
+        let sol = bank(100.0, 0.75, 1.25);
+        let [bonk, jup] = std::array::from_fn(|_| bank(1.0, 0.75, 1.0));
+        let banks = banks_map(&[&usdc, &sol, &bonk, &jup]);
+        let mut liquidator = account();
+        set_balance(&mut liquidator, 0, &usdc, 1_000, 0);
+
+        // The other banks of the group aren't read by the health check
+        assert_eq!(
+            liquidator.get_observation_accounts(&[sol.address, usdc.address], &[], &banks),
+            vec![
+                usdc.address,
+                usdc.oracle_adapter.address,
+                sol.address,
+                sol.oracle_adapter.address,
+            ]
+        );
+    }
+
+    #[test]
+    fn observation_accounts_follow_the_balance_slots() {
+        let [a, b, c, d, e] = std::array::from_fn(|_| bank(1.0, 0.75, 1.0));
+        let banks = banks_map(&[&a, &b, &c, &d, &e]);
+        let mut account = account();
+        // Opened out of the bank order, with free slots 1 and 3 in between
+        set_balance(&mut account, 0, &c, 10, 0);
+        set_balance(&mut account, 2, &a, 10, 0);
+        set_balance(&mut account, 4, &b, 0, 5);
+
+        // The included banks without a balance take the free slots in order,
+        // the one already opened keeps its slot
+        let observation_accounts = account.get_observation_accounts(
+            &[d.address, a.address, e.address],
+            &[c.address],
+            &banks,
+        );
+
+        assert_eq!(
+            observation_accounts,
+            [&d, &a, &e, &b]
+                .iter()
+                .flat_map(|bank| [bank.address, bank.oracle_adapter.address])
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -3,3 +3,6 @@ pub mod liquidator_account;
 pub mod marginfi_account;
 pub mod oracle;
 pub mod token_account;
+
+#[cfg(test)]
+pub mod test_fixtures;
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use crate::pyth::StreamedPrice;
 use fixed::types::I80F48;
 use marginfi::state::price::{OraclePriceFeedAdapter, OraclePriceType, PriceAdapter, PriceBias};
 use solana_program::pubkey::Pubkey;
@@ -12,6 +13,12 @@ pub struct OracleWrapper {
     // Simulated price are only for swb pull oracles
     pub simulated_price: Option<f64>,
     pub swb_feed_hash: Option<String>,
+    /// Slot at which the oracle price was published, when the oracle layout is known
+    pub publish_slot: Option<u64>,
+    /// Unix time at which the price posted on chain was published, for pyth push oracles
+    pub publish_time: Option<i64>,
+    /// Price streamed by hermes, used while it is more recent than the price posted on chain
+    pub streamed_price: Option<StreamedPrice>,
 }
 
 impl OracleWrapper {
@@ -21,6 +28,9 @@ impl OracleWrapper {
             price_adapter,
             simulated_price: None,
             swb_feed_hash: None,
+            publish_slot: None,
+            publish_time: None,
+            streamed_price: None,
         }
     }
 
@@ -29,18 +39,24 @@ impl OracleWrapper {
         oracle_type: OraclePriceType,
         price_bias: Option<PriceBias>,
     ) -> anyhow::Result<I80F48> {
-        match self.simulated_price {
-            Some(price) => Ok(I80F48::from_num(price)),
-            None => Ok(self
-                .price_adapter
-                .get_price_of_type(oracle_type, price_bias)?),
+        if let Some(price) = self.simulated_price {
+            return Ok(I80F48::from_num(price));
         }
+
+        if let Some(streamed_price) = self.get_fresh_streamed_price() {
+            return Ok(streamed_price.get_price_of_type(oracle_type, price_bias));
+        }
+
+        Ok(self
+            .price_adapter
+            .get_price_of_type(oracle_type, price_bias)?)
     }
 
-    pub fn is_switchboard_pull(&self) -> bool {
-        matches!(
-            self.price_adapter,
-            OraclePriceFeedAdapter::SwitchboardPull(_)
-        )
+    /// The streamed price, when it was published after the price posted on chain
+    fn get_fresh_streamed_price(&self) -> Option<&StreamedPrice> {
+        let publish_time = self.publish_time?;
+        self.streamed_price
+            .as_ref()
+            .filter(|streamed_price| streamed_price.publish_time > publish_time)
     }
 }
@@ -0,0 +1,60 @@
+//! Hand built banks and accounts shared by the tests
+
+use super::{bank::BankWrapper, marginfi_account::MarginfiAccountWrapper, oracle::OracleWrapper};
+use fixed::types::I80F48;
+use marginfi::state::{
+    marginfi_group::{Bank, RiskTier},
+    price::{OraclePriceFeedAdapter, SwitchboardPullPriceFeed},
+};
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+use switchboard_on_demand::PullFeedAccountData;
+
+/// Collateral bank of a token of 6 decimals, one share per native token,
+/// priced at `price` through the simulated price of its oracle
+pub fn bank(price: f64, asset_weight_maint: f64, liability_weight_maint: f64) -> BankWrapper {
+    let mut bank: Bank = bytemuck::Zeroable::zeroed();
+    bank.mint_decimals = 6;
+    bank.asset_share_value = I80F48::ONE.into();
+    bank.liability_share_value = I80F48::ONE.into();
+    bank.config.asset_weight_maint = I80F48::from_num(asset_weight_maint).into();
+    bank.config.liability_weight_maint = I80F48::from_num(liability_weight_maint).into();
+    bank.config.risk_tier = RiskTier::Collateral;
+
+    let swb_feed: PullFeedAccountData = bytemuck::Zeroable::zeroed();
+    let mut oracle = OracleWrapper::new(
+        Pubkey::new_unique(),
+        OraclePriceFeedAdapter::SwitchboardPull(SwitchboardPullPriceFeed {
+            feed: Box::new((&swb_feed).into()),
+        }),
+    );
+    oracle.simulated_price = Some(price);
+
+    BankWrapper::new(Pubkey::new_unique(), bank, oracle, anchor_spl::token::ID)
+}
+
+pub fn banks_map(banks: &[&BankWrapper]) -> HashMap<Pubkey, BankWrapper> {
+    banks
+        .iter()
+        .map(|bank| (bank.address, (*bank).clone()))
+        .collect()
+}
+
+pub fn account() -> MarginfiAccountWrapper {
+    MarginfiAccountWrapper::new(Pubkey::new_unique(), bytemuck::Zeroable::zeroed())
+}
+
+/// Opens a balance of the bank in the slot, in whole tokens
+pub fn set_balance(
+    account: &mut MarginfiAccountWrapper,
+    slot: usize,
+    bank: &BankWrapper,
+    assets: u64,
+    liabilities: u64,
+) {
+    let balance = &mut account.account.lending_account.balances[slot];
+    balance.active = true;
+    balance.bank_pk = bank.address;
+    balance.asset_shares = I80F48::from_num(assets * 1_000_000).into();
+    balance.liability_shares = I80F48::from_num(liabilities * 1_000_000).into();
+}